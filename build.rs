@@ -0,0 +1,6 @@
+fn main() {
+    // Node loads the addon into its own process, so the N-API symbols are
+    // resolved at load time; this sets up the linker flags that allows.
+    #[cfg(feature = "node")]
+    napi_build::setup();
+}
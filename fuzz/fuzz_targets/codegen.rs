@@ -0,0 +1,25 @@
+//! Every program that passes semantic analysis must generate Brainfuck that
+//! is well formed, and running it must not panic the interpreter.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rusted_brains::analysis::Analyzer;
+use rusted_brains::ast::Program;
+use rusted_brains::bf::{Interpreter, RuntimeError};
+use rusted_brains::codegen::BrainfuckGenerator;
+use rusted_brains::ir;
+
+fuzz_target!(|program: Program| {
+    if Analyzer::new().analyze(&program).has_errors() {
+        return;
+    }
+    let code = BrainfuckGenerator::new()
+        .generate(&program)
+        .expect("code generation failed");
+    ir::parse(&code).expect("generated unbalanced brackets");
+
+    // Loops may legitimately never finish, so bound the run.
+    let result = Interpreter::new(code).with_max_steps(100_000).run();
+    assert!(!matches!(result, Err(RuntimeError::UnmatchedBracket { .. })));
+});
@@ -0,0 +1,10 @@
+//! Any text must lex to tokens or diagnostics, never a panic.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rusted_brains::lexer::Lexer;
+
+fuzz_target!(|source: &str| {
+    let _ = Lexer::new(source).tokenize();
+});
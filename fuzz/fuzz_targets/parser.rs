@@ -0,0 +1,12 @@
+//! Any token sequence must parse to a program or diagnostics, and error
+//! recovery must terminate.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rusted_brains::ast::SpannedToken;
+use rusted_brains::parser::Parser;
+
+fuzz_target!(|tokens: Vec<SpannedToken>| {
+    let _ = Parser::new(tokens).parse();
+});
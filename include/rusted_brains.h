@@ -0,0 +1,53 @@
+/* C interface to the RustedBrains transpiler; see src/ffi.rs.
+ * Regenerate with `cbindgen --config cbindgen.toml --output include/rusted_brains.h`
+ * after changing the bindings. */
+
+#ifndef RUSTED_BRAINS_H
+#define RUSTED_BRAINS_H
+
+#include <stdbool.h>
+#include <stddef.h>
+
+typedef enum Rust2BfSeverity {
+  RUST2BF_SEVERITY_ERROR = 0,
+  RUST2BF_SEVERITY_WARNING = 1,
+} Rust2BfSeverity;
+
+typedef struct Rust2BfDiagnostic {
+  char *message;
+  Rust2BfSeverity severity;
+  /* Whether `start`, `end`, `line` and `column` are meaningful. */
+  bool has_span;
+  /* Byte offsets into the source. */
+  size_t start;
+  size_t end;
+  /* 1-based line and column of `start`. */
+  size_t line;
+  size_t column;
+} Rust2BfDiagnostic;
+
+typedef struct Rust2BfOutput {
+  /* The generated Brainfuck, or NULL if compilation failed. */
+  char *code;
+  /* Errors (on failure) or warnings (on success). */
+  Rust2BfDiagnostic *diagnostics;
+  size_t diagnostic_count;
+} Rust2BfOutput;
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Compiles the NUL-terminated mini-rust `source` to Brainfuck. Returns NULL
+ * only if `source` is NULL or not valid UTF-8. Release the result with
+ * rust2bf_output_free. */
+Rust2BfOutput *rust2bf_compile(const char *source);
+
+/* Releases an output returned by rust2bf_compile. NULL is ignored. */
+void rust2bf_output_free(Rust2BfOutput *output);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RUSTED_BRAINS_H */
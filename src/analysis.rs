@@ -0,0 +1,1421 @@
+use crate::ast::{BinaryOp, Expr, Function, MatchArm, Program, Stmt};
+use crate::error::{Diagnostics, TranspilerError};
+use crate::lexer::RESERVED;
+use crate::ranges::{self, Ranges};
+use crate::span::Span;
+use crate::suggest;
+use crate::symbol_table::{Symbol, SymbolId, SymbolTable, Type};
+use crate::trace;
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// How a binding is used after its declaration.
+#[derive(Default)]
+struct Usage {
+    read: bool,
+    assigned: bool,
+}
+
+/// What a call to a top-level function is checked against.
+struct Signature {
+    /// The type written for each parameter, if any.
+    params: Vec<Option<Type>>,
+    returns: Option<Type>,
+    span: Span,
+}
+
+/// Semantic analysis: reports names that do not resolve and ill-typed
+/// expressions as errors, and code that is legal but probably not what the
+/// author meant as warnings.
+pub struct Analyzer {
+    symbols: SymbolTable,
+    usage: BTreeMap<SymbolId, Usage>,
+    /// Bindings assigned in each enclosing `while` body, innermost last.
+    loop_assignments: Vec<BTreeSet<SymbolId>>,
+    /// How many loops of any kind enclose the current statement.
+    loop_depth: usize,
+    /// Values of immutable bindings whose initializer is a constant.
+    constants: BTreeMap<SymbolId, u8>,
+    /// The values each variable can hold at the statement being visited.
+    ranges: Ranges,
+    /// The signature of each top-level function.
+    functions: BTreeMap<String, Signature>,
+    /// Bindings that are function parameters.
+    parameters: BTreeSet<SymbolId>,
+    /// Functions called from anywhere in the program.
+    called: BTreeSet<String>,
+    /// The function whose body is being visited, if any.
+    current_function: Option<String>,
+    /// Whether to report conditions rustc would reject, as with
+    /// `--strict-rust`.
+    strict: bool,
+    diagnostics: Diagnostics,
+    // Expressions carry no spans of their own, so they are reported at the
+    // statement containing them.
+    current_span: Span,
+}
+
+impl Default for Analyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Analyzer {
+    pub fn new() -> Self {
+        Self {
+            symbols: SymbolTable::new(),
+            usage: BTreeMap::new(),
+            loop_assignments: Vec::new(),
+            loop_depth: 0,
+            constants: BTreeMap::new(),
+            ranges: Ranges::new(),
+            functions: BTreeMap::new(),
+            parameters: BTreeSet::new(),
+            called: BTreeSet::new(),
+            current_function: None,
+            strict: false,
+            diagnostics: Diagnostics::new(),
+            current_span: Span::default(),
+        }
+    }
+
+    /// Also reports what rustc rejects but mini-rust allows: conditions that
+    /// are numbers rather than `bool`s, assigning to a binding that is not
+    /// `mut`, and arithmetic on constants that overflows.
+    pub fn with_strict_rust(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+
+    pub fn analyze(mut self, program: &Program) -> Diagnostics {
+        let _span = trace::span!("analyze");
+        // Functions may be called before they are defined.
+        for stmt in program {
+            if let Stmt::Function(function) = stmt {
+                self.declare_function(function);
+            }
+        }
+        for stmt in program {
+            match stmt {
+                Stmt::Function(function) => self.visit_function(function),
+                _ => self.visit_stmt(stmt),
+            }
+        }
+
+        for (name, signature) in &self.functions {
+            if !name.starts_with('_') && !self.called.contains(name) {
+                self.diagnostics.push(TranspilerError::warning(
+                    format!("function `{}` is never used", name),
+                    signature.span,
+                ));
+            }
+        }
+
+        for (id, symbol) in self.symbols.iter() {
+            // Following rustc, a leading underscore marks a binding as
+            // intentionally unused.
+            if symbol.name.starts_with('_') {
+                continue;
+            }
+            let usage = &self.usage[&id];
+            if !usage.read {
+                self.diagnostics.push(TranspilerError::warning(
+                    format!("unused variable: `{}`", symbol.name),
+                    symbol.span,
+                ));
+            }
+            if symbol.mutable && !usage.assigned {
+                self.diagnostics.push(TranspilerError::warning(
+                    format!("variable `{}` does not need to be mutable", symbol.name),
+                    symbol.span,
+                ));
+            }
+        }
+
+        self.diagnostics
+    }
+
+    fn visit_block(&mut self, body: &[Stmt]) {
+        self.symbols.enter_scope();
+        self.ranges.enter_scope();
+        self.visit_stmts(body);
+        self.ranges.exit_scope();
+        self.symbols.exit_scope();
+    }
+
+    fn visit_stmts(&mut self, body: &[Stmt]) {
+        for (i, stmt) in body.iter().enumerate() {
+            self.visit_stmt(stmt);
+            if let (
+                Stmt::Break { span } | Stmt::Continue { span } | Stmt::Return { span, .. },
+                Some(next),
+            ) = (stmt, body.get(i + 1))
+            {
+                self.diagnostics.push(
+                    TranspilerError::warning("unreachable statement", next.span())
+                        .with_related("any code after this is unreachable", *span),
+                );
+            }
+        }
+    }
+
+    /// Visits the body of a loop, where `break` and `continue` may appear.
+    fn visit_loop_body(&mut self, body: &[Stmt]) {
+        self.loop_depth += 1;
+        self.visit_block(body);
+        self.loop_depth -= 1;
+    }
+
+    fn declare_function(&mut self, function: &Function) {
+        if let Some(previous) = self.functions.get(&function.name).map(|f| f.span) {
+            self.diagnostics.push(
+                TranspilerError::with_span(
+                    format!("the function `{}` is defined more than once", function.name),
+                    function.span,
+                )
+                .with_related(
+                    format!("previous definition of `{}` here", function.name),
+                    previous,
+                ),
+            );
+            return;
+        }
+        self.functions.insert(
+            function.name.clone(),
+            Signature {
+                params: function.param_types.clone(),
+                returns: function.return_type,
+                span: function.span,
+            },
+        );
+    }
+
+    /// Visits a function body, which sees only its parameters and is not
+    /// inside any loop around the definition.
+    fn visit_function(&mut self, function: &Function) {
+        self.current_span = function.span;
+        self.symbols.enter_function();
+        for (i, param) in function.params.iter().enumerate() {
+            if function.params[..i].contains(param) {
+                self.diagnostics.push(TranspilerError::with_span(
+                    format!(
+                        "identifier `{}` is bound more than once in this parameter list",
+                        param
+                    ),
+                    function.span,
+                ));
+            }
+            let ty = function.param_types.get(i).copied().flatten();
+            let id = self.symbols.declare(
+                Symbol::new(param, false, function.span).with_type(ty.unwrap_or(Type::Int)),
+            );
+            self.usage.insert(id, Usage::default());
+            self.parameters.insert(id);
+        }
+
+        let loop_depth = core::mem::take(&mut self.loop_depth);
+        let loop_assignments = core::mem::take(&mut self.loop_assignments);
+        let ranges = core::mem::take(&mut self.ranges);
+        self.current_function = Some(function.name.clone());
+        self.visit_stmts(&function.body);
+        self.current_function = None;
+        self.ranges = ranges;
+        self.loop_assignments = loop_assignments;
+        self.loop_depth = loop_depth;
+        self.symbols.exit_function();
+    }
+
+    /// Resolves a use of `name`, reporting it if no binding is in scope.
+    fn resolve(&mut self, name: &str) -> Option<SymbolId> {
+        let id = self.symbols.resolve(name);
+        if id.is_none() {
+            let mut error = TranspilerError::with_span(
+                format!("undefined variable `{}`", name),
+                self.current_span,
+            );
+            if let Some(candidate) = suggest::best_match(name, self.symbols.visible_names()) {
+                error = error.with_note(format!("help: did you mean `{}`?", candidate));
+            }
+            self.diagnostics.push(error);
+        }
+        id
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        self.current_span = stmt.span();
+
+        match stmt {
+            Stmt::Let {
+                name,
+                mutable,
+                ty: annotation,
+                value,
+                span,
+            } => {
+                let found = self.visit_expr(value);
+                let ty = match annotation {
+                    Some(expected) => {
+                        self.check_type(*expected, found, *span);
+                        *expected
+                    }
+                    None => found.unwrap_or(Type::Int),
+                };
+                if RESERVED.contains(&name.as_str()) {
+                    self.diagnostics.push(
+                        TranspilerError::warning(
+                            format!("`{}` is a reserved keyword", name),
+                            *span,
+                        )
+                        .with_note(format!(
+                            "help: `{}` may become a keyword in a future version; rename it",
+                            name
+                        )),
+                    );
+                }
+                if let Some(previous) = self.symbols.resolve_in_current_scope(name) {
+                    // `let x = x + 1;` is the usual way to shadow on purpose;
+                    // anything else in the same scope is likely a mistake.
+                    if !mentions(value, name) {
+                        self.diagnostics.push(
+                            TranspilerError::warning(
+                                format!("`{}` is declared twice in the same scope", name),
+                                *span,
+                            )
+                            .with_related(
+                                format!("previous declaration of `{}` here", name),
+                                self.symbols.get(previous).span,
+                            )
+                            .with_note(format!(
+                                "help: if this is intentional, assign instead: `{} = ...`",
+                                name
+                            )),
+                        );
+                    }
+                }
+                // Folded before declaring, as the value may read a binding
+                // this one shadows.
+                let constant = self.constant(value);
+                self.ranges.declare(name, self.ranges.of(value));
+                let id = self
+                    .symbols
+                    .declare(Symbol::new(name, *mutable, *span).with_type(ty));
+                self.usage.insert(id, Usage::default());
+                if let Some(constant) = constant
+                    && !mutable
+                {
+                    self.constants.insert(id, constant);
+                }
+            }
+            Stmt::Assign { name, value, span } => {
+                if matches!(value, Expr::Variable(source) if source == name) {
+                    self.diagnostics.push(TranspilerError::warning(
+                        format!("assigning `{}` to itself has no effect", name),
+                        *span,
+                    ));
+                }
+                let found = self.visit_expr(value);
+                self.ranges.assign(name, self.ranges.of(value));
+                if let Some(id) = self.resolve(name) {
+                    self.usage.entry(id).or_default().assigned = true;
+                    if let Some(assigned) = self.loop_assignments.last_mut() {
+                        assigned.insert(id);
+                    }
+                    let symbol = self.symbols.get(id);
+                    let expected = symbol.ty;
+                    if self.strict && !symbol.mutable {
+                        let error = if self.parameters.contains(&id) {
+                            TranspilerError::with_span(
+                                format!("cannot assign to immutable argument `{}`", name),
+                                *span,
+                            )
+                            .with_note(format!(
+                                "help: copy it into a binding first: `let mut {} = {};`",
+                                name, name
+                            ))
+                        } else {
+                            TranspilerError::with_span(
+                                format!("cannot assign twice to immutable variable `{}`", name),
+                                *span,
+                            )
+                            .with_related(format!("first assignment to `{}`", name), symbol.span)
+                            .with_note(format!(
+                                "help: make the binding mutable: `let mut {}`",
+                                name
+                            ))
+                        };
+                        self.diagnostics.push(error);
+                    }
+                    if expected == Type::Str {
+                        // Its cells were sized for the first value.
+                        self.diagnostics.push(
+                            TranspilerError::with_span(
+                                format!("cannot assign to `{}`, which holds a string", name),
+                                *span,
+                            )
+                            .with_note("help: declare a new binding with `let` instead"),
+                        );
+                    } else {
+                        self.check_type(expected, found, *span);
+                    }
+                }
+            }
+            // A string prints each of its bytes in turn.
+            Stmt::Print { value, span } => {
+                self.visit_expr(value);
+                let byte = match value {
+                    Expr::Str(text) => text.bytes().find(|&byte| !printable(byte)),
+                    _ => self.constant(value),
+                };
+                if let Some(byte) = byte {
+                    self.check_printable(byte, matches!(value, Expr::Str(_)), *span);
+                }
+            }
+            Stmt::PrintNum { value, .. } => {
+                let ty = self.visit_expr(value);
+                self.check_not_str(ty, "print_num(c);");
+            }
+            Stmt::If {
+                condition,
+                body,
+                else_body,
+                span,
+            } => {
+                let ty = self.visit_expr(condition);
+                self.check_not_str(ty, "if c == 65 { ... }");
+                self.check_bool(ty);
+                if body.is_empty() && else_body.is_empty() {
+                    self.diagnostics.push(TranspilerError::warning(
+                        "`if` with an empty body has no effect",
+                        *span,
+                    ));
+                }
+                self.check_reachable(condition, body, *span);
+                if let (Some(1..), Some(first)) = (condition.constant(), else_body.first()) {
+                    self.diagnostics.push(
+                        TranspilerError::warning("unreachable statement", first.span())
+                            .with_related("this condition is always true", *span),
+                    );
+                }
+                let before = self.ranges.clone();
+                self.ranges.assume(condition, true);
+                self.visit_block(body);
+                let after_body = core::mem::replace(&mut self.ranges, before);
+                self.ranges.assume(condition, false);
+                self.visit_block(else_body);
+                self.ranges.join(&after_body);
+            }
+            Stmt::Match {
+                scrutinee,
+                arms,
+                span,
+            } => {
+                let ty = self.visit_expr(scrutinee);
+                self.check_not_str(ty, "match c { 65 => { ... } _ => {} }");
+                if arms.is_empty() {
+                    self.diagnostics.push(TranspilerError::warning(
+                        "`match` with no arms has no effect",
+                        *span,
+                    ));
+                }
+                self.check_arms_reachable(arms);
+                // No arm need run, so the ranges before the `match` still
+                // hold after it.
+                let before = self.ranges.clone();
+                for arm in arms {
+                    self.visit_block(&arm.body);
+                    let after_arm = core::mem::replace(&mut self.ranges, before.clone());
+                    self.ranges.join(&after_arm);
+                }
+            }
+            Stmt::While {
+                condition,
+                body,
+                span,
+            } => {
+                let ty = self.visit_expr(condition);
+                self.check_not_str(ty, "if c == 65 { ... }");
+                self.check_bool(ty);
+                self.check_reachable(condition, body, *span);
+                self.ranges.widen(body);
+                let before = self.ranges.clone();
+                self.ranges.assume(condition, true);
+                let assigned = self.visit_assigning_loop_body(body);
+                self.ranges = before;
+                self.assume_exited(condition, body);
+                self.check_condition_changes(condition, body, &assigned, *span);
+            }
+            Stmt::Loop { body, .. } => {
+                self.ranges.widen(body);
+                let before = self.ranges.clone();
+                self.visit_loop_body(body);
+                self.ranges = before;
+            }
+            // The condition is tested outside the body's scope.
+            Stmt::DoWhile {
+                body,
+                condition,
+                span,
+            } => {
+                self.ranges.widen(body);
+                let before = self.ranges.clone();
+                let assigned = self.visit_assigning_loop_body(body);
+                self.ranges = before;
+                self.current_span = *span;
+                let ty = self.visit_expr(condition);
+                self.check_not_str(ty, "if c == 65 { ... }");
+                self.check_bool(ty);
+                self.assume_exited(condition, body);
+                self.check_condition_changes(condition, body, &assigned, *span);
+            }
+            Stmt::For {
+                name,
+                iterable,
+                body,
+                span,
+            } => {
+                if let Some(found) = self.visit_expr(iterable).filter(|&ty| ty != Type::Str) {
+                    self.diagnostics.push(TranspilerError::with_span(
+                        format!("`for` loops over a string, found `{}`", found),
+                        *span,
+                    ));
+                }
+                // The byte is bound in the body's own scope.
+                self.symbols.enter_scope();
+                let id = self.symbols.declare(Symbol::new(name, false, *span));
+                self.usage.insert(id, Usage::default());
+                self.ranges.widen(body);
+                let before = self.ranges.clone();
+                self.ranges.enter_scope();
+                self.ranges.declare(name, ranges::bytes(iterable));
+                self.loop_depth += 1;
+                self.visit_stmts(body);
+                self.loop_depth -= 1;
+                self.ranges = before;
+                self.symbols.exit_scope();
+            }
+            Stmt::Function(function) => {
+                self.diagnostics.push(TranspilerError::with_span(
+                    "functions can only be defined at the top level",
+                    function.span,
+                ));
+            }
+            Stmt::Return { value, span } => {
+                if let Some(value) = value {
+                    let ty = self.visit_expr(value);
+                    self.check_not_str(ty, "print(c);");
+                    let declared = self.current_function.as_ref().and_then(|name| {
+                        self.functions
+                            .get(name)
+                            .and_then(|signature| signature.returns)
+                    });
+                    if let (Some(expected), Some(found)) = (declared, ty)
+                        && found != Type::Str
+                    {
+                        self.check_type(expected, Some(found), *span);
+                    }
+                }
+                if self.current_function.is_none() {
+                    self.diagnostics.push(TranspilerError::with_span(
+                        "`return` outside of a function",
+                        *span,
+                    ));
+                }
+            }
+            Stmt::Expr { value, .. } => {
+                self.visit_expr(value);
+            }
+            Stmt::Break { span } | Stmt::Continue { span } => {
+                if self.loop_depth == 0 {
+                    let keyword = match stmt {
+                        Stmt::Break { .. } => "break",
+                        _ => "continue",
+                    };
+                    self.diagnostics.push(TranspilerError::with_span(
+                        format!("`{}` outside of a loop", keyword),
+                        *span,
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Visits a loop body, returning the bindings it assigns to. They count
+    /// as assigned in any loop around this one as well.
+    fn visit_assigning_loop_body(&mut self, body: &[Stmt]) -> BTreeSet<SymbolId> {
+        self.loop_assignments.push(BTreeSet::new());
+        self.visit_loop_body(body);
+        let assigned = self.loop_assignments.pop().unwrap_or_default();
+        if let Some(outer) = self.loop_assignments.last_mut() {
+            outer.extend(assigned.iter().copied());
+        }
+        assigned
+    }
+
+    /// Narrows the ranges after a loop that only ends once `condition` is
+    /// false, unless `body` can leave it some other way.
+    fn assume_exited(&mut self, condition: &Expr, body: &[Stmt]) {
+        if !body.iter().any(Stmt::breaks) {
+            self.ranges.assume(condition, false);
+        }
+    }
+
+    /// Warns about a loop condition that `body`, which assigns to
+    /// `assigned`, can never change. A condition with no variables is
+    /// constant on purpose, but one whose variables the body never touches
+    /// is almost always a forgotten update, unless the loop breaks out or
+    /// the condition reads input.
+    fn check_condition_changes(
+        &mut self,
+        condition: &Expr,
+        body: &[Stmt],
+        assigned: &BTreeSet<SymbolId>,
+        span: Span,
+    ) {
+        let mut names = Vec::new();
+        variables(condition, &mut names);
+        let tested: BTreeSet<SymbolId> = names
+            .iter()
+            .filter_map(|name| self.symbols.resolve(name))
+            .collect();
+        if tested.is_empty()
+            || !tested.is_disjoint(assigned)
+            || body.iter().any(Stmt::breaks)
+            || condition.reads_input()
+        {
+            return;
+        }
+        let names: Vec<_> = names.iter().map(|name| format!("`{}`", name)).collect();
+        self.diagnostics.push(
+            TranspilerError::warning("`while` condition never changes inside the loop", span)
+                .with_note("once entered, this loop runs forever")
+                .with_note(format!(
+                    "help: assign to {} in the loop body",
+                    names.join(" or ")
+                )),
+        );
+    }
+
+    /// Reports a string where a single value is needed, suggesting a
+    /// `for` loop over its bytes ending in `usage`.
+    fn check_not_str(&mut self, ty: Option<Type>, usage: &str) {
+        if ty == Some(Type::Str) {
+            self.diagnostics.push(
+                TranspilerError::with_span(
+                    "mismatched types: expected a single value, found `str`",
+                    self.current_span,
+                )
+                .with_note(format!(
+                    "help: go through the string a byte at a time: `for c in s {{ {} }}`",
+                    usage
+                )),
+            );
+        }
+    }
+
+    /// In strict mode, reports a condition of type `ty` that is not a
+    /// `bool`.
+    fn check_bool(&mut self, ty: Option<Type>) {
+        if self.strict && ty == Some(Type::Int) {
+            self.diagnostics.push(
+                TranspilerError::with_span(
+                    "mismatched types: Rust needs a `bool` condition, found `int`",
+                    self.current_span,
+                )
+                .with_note("help: compare it with zero: `x != 0`"),
+            );
+        }
+    }
+
+    /// The value of `expr` if it only reads literals and constant bindings.
+    fn constant(&self, expr: &Expr) -> Option<u8> {
+        expr.constant_with(&|name| {
+            let id = self.symbols.resolve(name)?;
+            self.constants.get(&id).copied()
+        })
+    }
+
+    /// Warns when `print` is known to output a byte that shows nothing or
+    /// garbage on a terminal, which outside a string usually means a number
+    /// was meant.
+    fn check_printable(&mut self, byte: u8, text: bool, span: Span) {
+        if printable(byte) {
+            return;
+        }
+        let help = if text {
+            "help: `print` outputs the byte itself; printable characters are 32 to 126, \
+             and 10 is a newline"
+                .to_string()
+        } else {
+            format!(
+                "help: `print` outputs the byte itself; use `print_num(...)` to show it as \
+                 the number {}",
+                byte
+            )
+        };
+        self.diagnostics.push(
+            TranspilerError::warning(
+                format!(
+                    "`print` outputs byte {}, which is not a printable character",
+                    byte
+                ),
+                span,
+            )
+            .with_note(help),
+        );
+    }
+
+    /// Warns about `match` arms that an earlier arm always takes over:
+    /// ones after a `_`, and ones repeating a value. Code generation leaves
+    /// them out.
+    fn check_arms_reachable(&mut self, arms: &[MatchArm]) {
+        let mut seen: BTreeMap<u8, Span> = BTreeMap::new();
+        let mut wildcard = None;
+        for arm in arms {
+            let earlier = match (wildcard, arm.pattern.value()) {
+                (Some(span), _) => Some(("this pattern matches every value", span)),
+                (None, Some(value)) => seen
+                    .get(&value)
+                    .map(|&span| ("this pattern matches the same value", span)),
+                (None, None) => None,
+            };
+            if let Some((message, span)) = earlier {
+                self.diagnostics.push(
+                    TranspilerError::warning("unreachable pattern", arm.span)
+                        .with_related(message, span),
+                );
+                continue;
+            }
+            match arm.pattern.value() {
+                Some(value) => {
+                    seen.insert(value, arm.span);
+                }
+                None => wildcard = Some(arm.span),
+            }
+        }
+    }
+
+    /// Reports the body of an `if` or `while` whose condition is always
+    /// false. Code generation leaves such bodies out, as it does the `else`
+    /// of an `if` whose condition is always true.
+    fn check_reachable(&mut self, condition: &Expr, body: &[Stmt], header: Span) {
+        if let (Some(0), Some(first)) = (condition.constant(), body.first()) {
+            self.diagnostics.push(
+                TranspilerError::warning("unreachable statement", first.span())
+                    .with_related("this condition is always false", header),
+            );
+        }
+    }
+
+    /// Checks `expr` and returns its type, or `None` if it could not be
+    /// determined because of an error that has already been reported.
+    fn visit_expr(&mut self, expr: &Expr) -> Option<Type> {
+        match expr {
+            Expr::Number(value) => {
+                if !(0..=255).contains(value) {
+                    // Each value lives in one 8-bit cell, where anything
+                    // larger would silently wrap.
+                    self.diagnostics.push(
+                        TranspilerError::with_span(
+                            format!("literal out of range: `{}`", value),
+                            self.current_span,
+                        )
+                        .with_note(format!(
+                            "the literal `{}` does not fit in a cell, whose range is `0..=255`",
+                            value
+                        ))
+                        .with_note("help: multi-cell integers such as `u16` are not supported yet"),
+                    );
+                }
+                Some(Type::Int)
+            }
+            Expr::Bool(_) => Some(Type::Bool),
+            Expr::Str(_) => Some(Type::Str),
+            Expr::Variable(name) => {
+                let id = self.resolve(name)?;
+                self.usage.entry(id).or_default().read = true;
+                Some(self.symbols.get(id).ty)
+            }
+            Expr::Unary { operator, operand } => {
+                let operand = self.visit_expr(operand)?;
+                if operand != Type::Bool {
+                    self.diagnostics.push(TranspilerError::with_span(
+                        format!(
+                            "cannot apply `{}` to `{}` values",
+                            operator.symbol(),
+                            operand
+                        ),
+                        self.current_span,
+                    ));
+                }
+                Some(Type::Bool)
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                let left_ty = self.visit_expr(left);
+                let right_ty = self.visit_expr(right);
+                let ty = self.check_binary(operator, left_ty?, right_ty?);
+                self.check_overflow(left, operator, right);
+                ty
+            }
+            Expr::Call { name, args } => {
+                let types: Vec<Option<Type>> = args
+                    .iter()
+                    .map(|arg| {
+                        let ty = self.visit_expr(arg);
+                        self.check_not_str(ty, &format!("{}(c);", name));
+                        ty
+                    })
+                    .collect();
+                self.visit_call(name, &types)
+            }
+            Expr::Input => Some(Type::Int),
+        }
+    }
+
+    /// Checks a call to `name` with arguments of the given types, giving
+    /// the type of its result.
+    fn visit_call(&mut self, name: &str, args: &[Option<Type>]) -> Option<Type> {
+        let Some(signature) = self.functions.get(name) else {
+            let mut error = TranspilerError::with_span(
+                format!("cannot find function `{}`", name),
+                self.current_span,
+            );
+            // `prnt(c)` parses as a call, but `print` is what was meant.
+            let names = self.functions.keys().map(String::as_str);
+            if let Some(candidate) = suggest::best_match(name, names.chain(["print", "println"])) {
+                error = error.with_note(format!("help: did you mean `{}`?", candidate));
+            }
+            self.diagnostics.push(error);
+            return Some(Type::Int);
+        };
+        let (expected, supplied) = (signature.params.len(), args.len());
+        let returns = signature.returns.unwrap_or(Type::Int);
+        let mismatched: Vec<(Type, Type)> = signature
+            .params
+            .iter()
+            .zip(args)
+            .filter_map(|(&param, &arg)| Some((param?, arg?)))
+            .filter(|&(param, arg)| arg != param && arg != Type::Str)
+            .collect();
+        self.called.insert(name.to_string());
+        for (param, arg) in mismatched {
+            self.check_type(param, Some(arg), self.current_span);
+        }
+        if supplied != expected {
+            self.diagnostics.push(TranspilerError::with_span(
+                format!(
+                    "`{}` takes {} argument{} but {} {} supplied",
+                    name,
+                    expected,
+                    if expected == 1 { "" } else { "s" },
+                    supplied,
+                    if supplied == 1 { "was" } else { "were" }
+                ),
+                self.current_span,
+            ));
+        }
+        Some(returns)
+    }
+
+    /// Reports a value of type `found` where the code says `expected`.
+    fn check_type(&mut self, expected: Type, found: Option<Type>, span: Span) {
+        if let Some(found) = found.filter(|&found| found != expected) {
+            self.diagnostics.push(TranspilerError::with_span(
+                format!(
+                    "mismatched types: expected `{}`, found `{}`",
+                    expected, found
+                ),
+                span,
+            ));
+        }
+    }
+
+    /// Warns about arithmetic whose every result lies outside the cell's
+    /// range, so that it always wraps around. rustc rejects it outright if
+    /// the result is a single known value, and so does strict mode.
+    fn check_overflow(&mut self, left: &Expr, operator: &BinaryOp, right: &Expr) {
+        if !self.ranges.reachable() {
+            return;
+        }
+        let (left, right) = (self.ranges.of(left), self.ranges.of(right));
+        let Some((min, max)) = ranges::unwrapped(left, operator, right) else {
+            return;
+        };
+        let direction = if min > 255 {
+            "overflow"
+        } else if max < 0 {
+            "underflow"
+        } else {
+            return;
+        };
+        let result = if min == max {
+            format!("{}", min)
+        } else {
+            format!("{}..={}", min, max)
+        };
+        let message = format!("this arithmetic operation will {}", direction);
+        let diagnostic = if self.strict && min == max {
+            TranspilerError::with_span(message, self.current_span)
+        } else {
+            TranspilerError::warning(message, self.current_span)
+        };
+        self.diagnostics.push(diagnostic.with_note(format!(
+            "`{}` gives {} here, which wraps around to {}",
+            operator.symbol(),
+            result,
+            ranges::binary(left, operator, right)
+        )));
+    }
+
+    fn check_binary(&mut self, operator: &BinaryOp, left: Type, right: Type) -> Option<Type> {
+        let (operands_ok, result) = match operator {
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => {
+                (left == Type::Int && right == Type::Int, Type::Int)
+            }
+            BinaryOp::Less | BinaryOp::Greater | BinaryOp::LessEqual | BinaryOp::GreaterEqual => {
+                (left == Type::Int && right == Type::Int, Type::Bool)
+            }
+            BinaryOp::Equal | BinaryOp::NotEqual => {
+                (left == right && left != Type::Str, Type::Bool)
+            }
+            BinaryOp::And | BinaryOp::Or => (left == Type::Bool && right == Type::Bool, Type::Bool),
+        };
+
+        if operands_ok {
+            return Some(result);
+        }
+
+        let message = if left == right {
+            format!("cannot apply `{}` to `{}` values", operator.symbol(), left)
+        } else {
+            format!(
+                "mismatched types: cannot apply `{}` to `{}` and `{}`",
+                operator.symbol(),
+                left,
+                right
+            )
+        };
+        self.diagnostics
+            .push(TranspilerError::with_span(message, self.current_span));
+        // The operator determines the result type even when its operands are
+        // wrong, so checking can continue without cascading errors.
+        Some(result)
+    }
+}
+
+/// Whether a terminal shows `byte` as a character or as whitespace.
+fn printable(byte: u8) -> bool {
+    matches!(byte, b' '..=b'~' | b'\t' | b'\n' | b'\r')
+}
+
+/// Whether `expr` reads the variable `name`.
+fn mentions(expr: &Expr, name: &str) -> bool {
+    match expr {
+        Expr::Number(_) | Expr::Bool(_) | Expr::Str(_) | Expr::Input => false,
+        Expr::Variable(variable) => variable == name,
+        Expr::Unary { operand, .. } => mentions(operand, name),
+        Expr::Binary { left, right, .. } => mentions(left, name) || mentions(right, name),
+        Expr::Call { args, .. } => args.iter().any(|arg| mentions(arg, name)),
+    }
+}
+
+/// Appends the variables `expr` reads to `out`, once each, in order of
+/// appearance.
+fn variables<'a>(expr: &'a Expr, out: &mut Vec<&'a str>) {
+    match expr {
+        Expr::Number(_) | Expr::Bool(_) | Expr::Str(_) | Expr::Input => {}
+        Expr::Variable(name) if out.contains(&name.as_str()) => {}
+        Expr::Variable(name) => out.push(name),
+        Expr::Unary { operand, .. } => variables(operand, out),
+        Expr::Binary { left, right, .. } => {
+            variables(left, out);
+            variables(right, out);
+        }
+        Expr::Call { args, .. } => {
+            for arg in args {
+                variables(arg, out);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn warnings(source: &str) -> Vec<String> {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        Analyzer::new()
+            .analyze(&program)
+            .into_iter()
+            .map(|w| w.message)
+            .collect()
+    }
+
+    #[test]
+    fn test_unused_variable() {
+        assert_eq!(
+            warnings("let x = 1; let _y = 2; let z = 65; print(z);"),
+            vec!["unused variable: `x`"]
+        );
+    }
+
+    #[test]
+    fn test_unneeded_mut() {
+        assert_eq!(
+            warnings("let mut a = 1; let mut b = 2; b = a; print(b);"),
+            vec!["variable `a` does not need to be mutable"]
+        );
+    }
+
+    #[test]
+    fn test_shadowed_binding_tracked_separately() {
+        assert_eq!(
+            warnings("let x = 1; if x == 1 { let x = 65; print(x); }"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            warnings("let x = 65; let y = x; let x = 66; print(x); print(y);"),
+            vec!["`x` is declared twice in the same scope"]
+        );
+    }
+
+    #[test]
+    fn test_duplicate_declaration_reports_both_locations() {
+        let tokens = Lexer::new("let x = 1;\nlet x = 2;\nprint(x);")
+            .tokenize()
+            .unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        let diagnostics = Analyzer::new().analyze(&program);
+        let duplicate = diagnostics.iter().next().unwrap();
+
+        assert_eq!(duplicate.span, Some(Span::new(11, 21)));
+        assert_eq!(duplicate.related[0].1, Span::new(0, 10));
+    }
+
+    #[test]
+    fn test_intentional_shadowing_is_allowed() {
+        assert_eq!(
+            warnings("let x = 64; let x = x + 1; print(x);"),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_block_scoped_shadowing() {
+        assert_eq!(
+            warnings("let x = 65; if x == 65 { let x = 66; print(x); } print(x);"),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_undefined_variable_with_suggestion() {
+        let tokens = Lexer::new("let count = 1;\nprint(cuont);\nundef = 2;")
+            .tokenize()
+            .unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        let diagnostics = Analyzer::new().analyze(&program);
+        let errors: Vec<_> = diagnostics.iter().filter(|d| !d.is_warning()).collect();
+
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].message, "undefined variable `cuont`");
+        assert_eq!(errors[0].notes, vec!["help: did you mean `count`?"]);
+        assert_eq!(errors[1].message, "undefined variable `undef`");
+        assert!(errors[1].notes.is_empty());
+    }
+
+    #[test]
+    fn test_variable_out_of_scope() {
+        let tokens = Lexer::new("if 1 == 1 { let inner = 1; print(inner); } print(inner);")
+            .tokenize()
+            .unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+
+        assert!(Analyzer::new().analyze(&program).has_errors());
+    }
+
+    fn errors(source: &str) -> Vec<String> {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        Analyzer::new()
+            .analyze(&program)
+            .into_iter()
+            .filter(|d| !d.is_warning())
+            .map(|d| d.message)
+            .collect()
+    }
+
+    #[test]
+    fn test_type_errors() {
+        assert_eq!(
+            errors(
+                "let a = 1; let b = a == 1; print(b + 1); print(b < b); print(a == b); \
+                 print(b && a); print(!a);"
+            ),
+            vec![
+                "mismatched types: cannot apply `+` to `bool` and `int`",
+                "cannot apply `<` to `bool` values",
+                "mismatched types: cannot apply `==` to `int` and `bool`",
+                "mismatched types: cannot apply `&&` to `bool` and `int`",
+                "cannot apply `!` to `int` values",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assignment_type_mismatch() {
+        assert_eq!(
+            errors("let mut done = 1 == 2; done = 3; print(done);"),
+            vec!["mismatched types: expected `bool`, found `int`"]
+        );
+    }
+
+    #[test]
+    fn test_well_typed_program() {
+        assert!(
+            errors("let a = 2; let b = a > 1; if b == (a < 3) { print(a * 2); } while a { }")
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_literal_out_of_range() {
+        assert_eq!(
+            errors("let x = 255; let y = 300; print(x + y);"),
+            vec!["literal out of range: `300`"]
+        );
+    }
+
+    #[test]
+    fn test_statements_without_effect() {
+        assert_eq!(
+            warnings("let mut x = 1; x = x; if x == 1 { } print(x);"),
+            vec![
+                "assigning `x` to itself has no effect",
+                "`if` with an empty body has no effect",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reserved_keyword_as_name() {
+        assert_eq!(
+            warnings("let type = 65; let typed = type; print(typed);"),
+            vec!["`type` is a reserved keyword"]
+        );
+    }
+
+    #[test]
+    fn test_loop_condition_never_changes() {
+        assert_eq!(
+            warnings("let n = 3; let mut m = 0; while n > 0 { m = m + 1; print(m); }"),
+            vec!["`while` condition never changes inside the loop"]
+        );
+        // Assigning in a nested statement counts; assigning a shadowing
+        // binding does not.
+        assert!(warnings("let mut n = 3; while n { if n == 2 { n = 0; } n = n - 1; }").is_empty());
+        assert_eq!(
+            warnings("let n = 3; while n { let mut n = 0; n = n + 1; print(n); }"),
+            vec!["`while` condition never changes inside the loop"]
+        );
+        assert!(warnings("while 1 == 0 { }").is_empty());
+        // Reading input gives a new byte each time round.
+        assert!(warnings("while input() != 10 { print(46); }").is_empty());
+        assert!(warnings("let n = 3; while n > 0 { if n == 3 { break; } }").is_empty());
+        assert!(warnings("let n = 3; let mut m = 0; loop { m = m + 1; } while n > m;").is_empty());
+        assert_eq!(
+            warnings("let n = 3; loop { let mut n = 0; n = n + 1; print(n); } while n;"),
+            vec!["`while` condition never changes inside the loop"]
+        );
+    }
+
+    #[test]
+    fn test_break_and_continue() {
+        assert!(
+            warnings("loop { break; } for c in \"ab\" { if c == 97 { continue; } print(c); }")
+                .is_empty()
+        );
+        assert_eq!(
+            errors("break; if 1 { continue; } loop { } while 0 { }"),
+            vec!["`break` outside of a loop", "`continue` outside of a loop"]
+        );
+        assert_eq!(
+            warnings("loop { print(65); break; print(66); }"),
+            vec!["unreachable statement"]
+        );
+    }
+
+    #[test]
+    fn test_match() {
+        assert_eq!(
+            warnings(
+                "let x = 1; match x { 0 => { } 256 => { } _ => { } 2 => { } } \
+                 loop { match x { 1 => { break; } } print(65); }"
+            ),
+            vec!["unreachable pattern", "unreachable pattern"]
+        );
+        assert_eq!(
+            warnings("match 1 { }"),
+            vec!["`match` with no arms has no effect"]
+        );
+        assert_eq!(
+            errors("let s = \"a\"; match s { _ => { break; } }"),
+            vec![
+                "mismatched types: expected a single value, found `str`",
+                "`break` outside of a loop"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_functions() {
+        assert!(
+            warnings(
+                "print(twice(33)); fn twice(n) { let d = n + n; return d; } \
+                 fn loud(c) { print(c); } loud(65);"
+            )
+            .is_empty()
+        );
+        assert_eq!(
+            warnings("let x = 65; fn f(a, _b) { return 1; print(65); } fn g() { } print(x);"),
+            vec![
+                "unreachable statement",
+                "function `f` is never used",
+                "function `g` is never used",
+                "unused variable: `a`",
+            ]
+        );
+        assert_eq!(
+            errors(
+                "let x = 1; fn f(a, a) { return x; } fn f() { } \
+                 if x { fn g() { } return; } loop { fn_h(); } \
+                 fn h() { break; } print(f(1)); prnt(65);"
+            ),
+            vec![
+                "the function `f` is defined more than once",
+                "identifier `a` is bound more than once in this parameter list",
+                "undefined variable `x`",
+                "functions can only be defined at the top level",
+                "`return` outside of a function",
+                "cannot find function `fn_h`",
+                "`break` outside of a loop",
+                "`f` takes 2 arguments but 1 was supplied",
+                "cannot find function `prnt`",
+            ]
+        );
+        // Recursion runs on a call stack.
+        assert!(
+            errors(
+                "fn a(n) { return b(n); } fn b(n) { if n { return a(n - 1); } return 0; } \
+                 fn c() { return c(); } print(a(2) + c());"
+            )
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_strict_rust_conditions() {
+        let source = "let x = 1; if x { } while x == 0 { } loop { } while x;";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        let spans: Vec<_> = Analyzer::new()
+            .with_strict_rust()
+            .analyze(&program)
+            .into_iter()
+            .filter(|d| !d.is_warning())
+            .map(|d| d.span)
+            .collect();
+        assert_eq!(
+            spans,
+            vec![Some(Span::new(11, 15)), Some(Span::new(46, 53))]
+        );
+        assert!(errors(source).is_empty());
+    }
+
+    fn strict_errors(source: &str) -> Vec<String> {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        Analyzer::new()
+            .with_strict_rust()
+            .analyze(&program)
+            .into_iter()
+            .filter(|d| !d.is_warning())
+            .map(|d| d.message)
+            .collect()
+    }
+
+    #[test]
+    fn test_type_annotations() {
+        assert_eq!(
+            errors("let a: u8 = true; print(a);"),
+            vec!["mismatched types: expected `int`, found `bool`"]
+        );
+        assert_eq!(
+            errors("let mut a: bool = false; a = 1; if a { }"),
+            vec!["mismatched types: expected `bool`, found `int`"]
+        );
+        assert_eq!(
+            errors("fn f(b: bool) -> u8 { if b { return 1; } return b; } print(f(2));"),
+            vec![
+                "mismatched types: expected `int`, found `bool`",
+                "mismatched types: expected `bool`, found `int`",
+            ]
+        );
+        assert!(
+            errors("fn f(b: bool) -> bool { return b; } let a: bool = f(true); if a { }")
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_strict_rust_immutable_writes() {
+        assert_eq!(
+            strict_errors("fn f(n: u8) { n = n + 1; print(n); } f(1);"),
+            vec!["cannot assign to immutable argument `n`"]
+        );
+        assert_eq!(
+            strict_errors("let x = 1; x = 2; print(x);"),
+            vec!["cannot assign twice to immutable variable `x`"]
+        );
+        assert!(
+            strict_errors("fn f(n: u8) { let mut n = n; n = n + 1; print(n); } f(1);").is_empty()
+        );
+        assert!(errors("fn f(n) { n = n + 1; print(n); } f(1);").is_empty());
+    }
+
+    #[test]
+    fn test_strict_rust_constant_overflow() {
+        let source = "let a: u8 = 200 + 100; print(a);";
+        assert_eq!(
+            strict_errors(source),
+            vec!["this arithmetic operation will overflow"]
+        );
+        assert!(errors(source).is_empty());
+        assert_eq!(
+            warnings(source),
+            vec!["this arithmetic operation will overflow"]
+        );
+        // Values only known at run time stay a warning.
+        assert!(strict_errors("fn f(x: u8) { print(x * 4); } f(5);").is_empty());
+    }
+
+    #[test]
+    fn test_arithmetic_overflow() {
+        let overflowing = |source| -> Vec<String> {
+            warnings(source)
+                .into_iter()
+                .filter(|w| w.starts_with("this arithmetic"))
+                .collect()
+        };
+        assert_eq!(
+            overflowing("let x = 200; let y = x + 100; let z = 3 - 5; print(y); print(z);"),
+            vec![
+                "this arithmetic operation will overflow",
+                "this arithmetic operation will underflow",
+            ]
+        );
+        let program = Parser::new(Lexer::new("print(250 + 10);").tokenize().unwrap())
+            .parse()
+            .unwrap();
+        let diagnostics = Analyzer::new().analyze(&program);
+        assert_eq!(
+            diagnostics.iter().next().unwrap().notes,
+            vec!["`+` gives 260 here, which wraps around to 4"]
+        );
+
+        // Conditions narrow what a variable can hold.
+        assert!(overflowing("let mut x = 0; if x > 0 { x = x - 1; } print(x);").is_empty());
+        assert!(overflowing("let mut x = 10; while x > 0 { x = x - 1; } print(x);").is_empty());
+        assert_eq!(
+            overflowing("let mut x = 10; while x > 0 { x = x - 1; } print(x - 1);").len(),
+            1
+        );
+        // A value only known at run time may or may not wrap.
+        assert!(overflowing("fn f(x) { print(x * 4); } f(5);").is_empty());
+    }
+
+    #[test]
+    fn test_non_printable_output() {
+        let source = "let x = 5; print(x); print(100 - 100);";
+        assert_eq!(
+            warnings(source),
+            vec![
+                "`print` outputs byte 5, which is not a printable character",
+                "`print` outputs byte 0, which is not a printable character",
+            ]
+        );
+        let program = Parser::new(Lexer::new(source).tokenize().unwrap())
+            .parse()
+            .unwrap();
+        let diagnostics = Analyzer::new().analyze(&program);
+        assert!(diagnostics.iter().next().unwrap().notes[0].contains("`print_num(...)`"));
+        // A byte inside a string is text, not a number.
+        let program = Parser::new(Lexer::new("print(\"\\x05\");").tokenize().unwrap())
+            .parse()
+            .unwrap();
+        let diagnostics = Analyzer::new().analyze(&program);
+        assert!(diagnostics.iter().next().unwrap().notes[0].contains("32 to 126"));
+
+        // Text, newlines and values only known at run time are fine.
+        assert!(warnings("let mut x = 5; x = x + 60; print(x); print(72); print(10);").is_empty());
+        assert!(warnings("let x = 200; let x = x - 100; print(x);").is_empty());
+    }
+
+    #[test]
+    fn test_strings() {
+        assert!(
+            warnings("let s = \"hi\"; for c in s { print(c); } for c in \"!\" { print(c); }")
+                .is_empty()
+        );
+        assert_eq!(
+            errors("let s = \"hi\"; s = \"ho\"; if s == s { } for c in 5 { print(c); }"),
+            vec![
+                "cannot assign to `s`, which holds a string",
+                "cannot apply `==` to `str` values",
+                "`for` loops over a string, found `int`",
+            ]
+        );
+        // Printed whole, a byte at a time.
+        assert!(warnings("let s = \"hi\"; print(s); print(\"there\\n\");").is_empty());
+        assert_eq!(
+            warnings("print(\"beep\\x07\");"),
+            vec!["`print` outputs byte 7, which is not a printable character"]
+        );
+        // `print_num` prints digits, which are always printable.
+        assert!(warnings("print_num(7);").is_empty());
+        assert_eq!(
+            errors("let s = \"hi\"; print_num(s);"),
+            vec!["mismatched types: expected a single value, found `str`"]
+        );
+        assert_eq!(
+            warnings("let s = \"hi\"; for c in s { }"),
+            vec!["unused variable: `c`"]
+        );
+    }
+
+    #[test]
+    fn test_unreachable_statements() {
+        assert_eq!(
+            warnings(
+                "if 1 == 2 { print(65); print(66); } while 3 - 3 { print(67); } if 1 { print(68); }"
+            ),
+            vec!["unreachable statement", "unreachable statement"]
+        );
+        assert_eq!(
+            warnings("if 1 { print(65); } else { print(66); } if 0 { } else { print(67); }"),
+            vec!["unreachable statement"]
+        );
+    }
+}
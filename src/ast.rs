@@ -1,87 +1,437 @@
+use crate::span::Span;
+use crate::symbol_table::Type;
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    string::String,
+    vec,
+    vec::Vec,
+};
+
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum Token {
     // Literals
     Identifier(String),
     Number(i32),
+    Str(String),
 
     // Keywords
     Let,
     Mut,
     Print,
+    Println,
+    PrintNum,
+    Input,
     If,
+    Else,
+    Match,
     While,
+    Loop,
+    Break,
+    Continue,
+    For,
+    In,
+    Fn,
+    Return,
+    True,
+    False,
 
     // Operators
-    Assign,   // =
-    Plus,     // +
-    Minus,    // -
-    Multiply, // *
-    Divide,   // /
-    Equal,    // ==
-    NotEqual, // !=
-    Less,     // <
-    Greater,  // >
+    Assign,       // =
+    FatArrow,     // =>
+    Arrow,        // ->
+    Plus,         // +
+    Minus,        // -
+    Multiply,     // *
+    Divide,       // /
+    Percent,      // %
+    Equal,        // ==
+    NotEqual,     // !=
+    Less,         // <
+    Greater,      // >
+    LessEqual,    // <=
+    GreaterEqual, // >=
+    And,          // &&
+    Or,           // ||
 
     // Delimiters
-    Semicolon,   // ;
-    LeftBrace,   // {
-    RightBrace,  // }
-    LeftParen,   // (
-    RightParen,  // )
-    Exclamation, // !
+    Semicolon,    // ;
+    LeftBrace,    // {
+    RightBrace,   // }
+    LeftParen,    // (
+    RightParen,   // )
+    Comma,        // ,
+    Colon,        // :
+    Exclamation,  // !
+    Hash,         // #
+    LeftBracket,  // [
+    RightBracket, // ]
 
     // Special
     Eof,
 }
 
+impl Token {
+    /// The keyword as written in source, if this is one.
+    pub fn keyword(&self) -> Option<&'static str> {
+        match self {
+            Token::Let => Some("let"),
+            Token::Mut => Some("mut"),
+            Token::Print => Some("print"),
+            Token::Println => Some("println"),
+            Token::PrintNum => Some("print_num"),
+            Token::Input => Some("input"),
+            Token::If => Some("if"),
+            Token::Else => Some("else"),
+            Token::Match => Some("match"),
+            Token::While => Some("while"),
+            Token::Loop => Some("loop"),
+            Token::Break => Some("break"),
+            Token::Continue => Some("continue"),
+            Token::For => Some("for"),
+            Token::In => Some("in"),
+            Token::Fn => Some("fn"),
+            Token::Return => Some("return"),
+            Token::True => Some("true"),
+            Token::False => Some("false"),
+            _ => None,
+        }
+    }
+}
+
+/// A token together with the source range it was read from.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+impl SpannedToken {
+    pub fn new(token: Token, span: Span) -> Self {
+        Self { token, span }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum BinaryOp {
     Add,
     Sub,
     Mul,
     Div,
+    /// Remainder of `Div`.
+    Mod,
     Equal,
     NotEqual,
     Less,
     Greater,
+    LessEqual,
+    GreaterEqual,
+    /// `&&`; the right operand is only evaluated if the left is true.
+    And,
+    /// `||`; the right operand is only evaluated if the left is false.
+    Or,
+}
+
+impl BinaryOp {
+    /// The operator as written in source.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Mod => "%",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::Less => "<",
+            BinaryOp::Greater => ">",
+            BinaryOp::LessEqual => "<=",
+            BinaryOp::GreaterEqual => ">=",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum UnaryOp {
+    /// `!`, negating a `bool`.
+    Not,
+}
+
+impl UnaryOp {
+    /// The operator as written in source.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            UnaryOp::Not => "!",
+        }
+    }
 }
 
 // Rest of the file remains the same...
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum Expr {
     Number(i32),
+    /// `true` or `false`, held in a cell as 1 or 0 like the result of a
+    /// comparison.
+    Bool(bool),
+    /// A string literal; only `let` and `for` accept one.
+    Str(String),
     Variable(String),
+    Unary {
+        operator: UnaryOp,
+        operand: Box<Expr>,
+    },
     Binary {
         left: Box<Expr>,
         operator: BinaryOp,
         right: Box<Expr>,
     },
+    /// `name(args)`, calling a function defined with `fn`.
+    Call {
+        name: String,
+        args: Vec<Expr>,
+    },
+    /// `input()`, the next byte of input, or 0 once all of it has been
+    /// read.
+    Input,
 }
 
+// Statement spans cover the statement itself; for `if`, `match`, `while`,
+// `loop` and `for` that is the header (keyword and condition) rather than the whole block.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
 pub enum Stmt {
     Let {
         name: String,
         mutable: bool,
+        /// The type written after the name, if any.
+        ty: Option<Type>,
         value: Expr,
+        span: Span,
     },
     Assign {
         name: String,
         value: Expr,
+        span: Span,
+    },
+    Print {
+        value: Expr,
+        span: Span,
     },
-    Print(Expr),
+    /// `print_num(value)`, printing the value as decimal digits rather
+    /// than as a byte.
+    PrintNum {
+        value: Expr,
+        span: Span,
+    },
+    /// `if condition { body } else { else_body }`; `else if` is an `If`
+    /// alone in `else_body`, and no `else` at all leaves it empty.
     If {
         condition: Expr,
         body: Vec<Stmt>,
+        else_body: Vec<Stmt>,
+        span: Span,
     },
     While {
         condition: Expr,
         body: Vec<Stmt>,
+        span: Span,
+    },
+    /// `match scrutinee { pattern => { body } ... }`, running the body of
+    /// the first arm whose pattern matches, if any.
+    Match {
+        scrutinee: Expr,
+        arms: Vec<MatchArm>,
+        span: Span,
+    },
+    /// `loop { body }`, which only a `break` ends.
+    Loop {
+        body: Vec<Stmt>,
+        span: Span,
+    },
+    /// `loop { body } while condition;`, which runs `body` once before
+    /// testing `condition` for the first time. Its span is the trailing
+    /// `while condition`.
+    DoWhile {
+        body: Vec<Stmt>,
+        condition: Expr,
+        span: Span,
+    },
+    /// `for name in iterable { body }`, running `body` once per byte of a
+    /// string with `name` bound to it.
+    For {
+        name: String,
+        iterable: Expr,
+        body: Vec<Stmt>,
+        span: Span,
+    },
+    /// Leaves the innermost `while`, `loop` or `for`.
+    Break {
+        span: Span,
+    },
+    /// Skips the rest of the innermost loop's body for this iteration.
+    Continue {
+        span: Span,
+    },
+    /// `fn name(params) { body }`. Its span is the header, up to the
+    /// closing parenthesis.
+    Function(Function),
+    /// `return value;`, leaving the function it is in; a function that
+    /// returns without a value returns 0.
+    Return {
+        value: Option<Expr>,
+        span: Span,
     },
+    /// An expression run for what it does rather than its value, which the
+    /// parser only produces for calls: `name(args);`.
+    Expr {
+        value: Expr,
+        span: Span,
+    },
+}
+
+/// A function definition. Brainfuck has no call stack, so every call either
+/// is inlined or jumps to the one copy of the body and back (see
+/// [`CallStrategy`](crate::codegen::CallStrategy)); a function that calls
+/// itself, directly or not, keeps its variables on a stack on the tape.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<String>,
+    /// The type written for each parameter, if any.
+    pub param_types: Vec<Option<Type>>,
+    /// The type written after `->`, if any.
+    pub return_type: Option<Type>,
+    /// Whether calls copy the body in or jump to it, as an
+    /// `#[inline(...)]` attribute asked.
+    pub inline: Inline,
+    pub body: Vec<Stmt>,
+    pub span: Span,
+}
+
+/// What a function's `#[inline(...)]` attribute asks of its calls.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Inline {
+    /// No attribute, or a bare `#[inline]`: the generator decides.
+    #[default]
+    Auto,
+    /// `#[inline(always)]`: copy the body into every call, unless the
+    /// function has to be jumped to.
+    Always,
+    /// `#[inline(never)]`: keep one copy of the body and jump to it.
+    Never,
+}
+
+/// One `pattern => { body }` arm of a `match`. Its span is the pattern.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub body: Vec<Stmt>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Pattern {
+    /// An integer literal, which wraps to a cell value like any other.
+    Number(i32),
+    /// `_`, matching every value.
+    Wildcard,
 }
 
 pub type Program = Vec<Stmt>;
 
+/// The functions defined at the top level of `program`, by name. Of two
+/// definitions with one name, which the analyzer rejects, the last is kept.
+pub fn functions(program: &[Stmt]) -> BTreeMap<&str, &Function> {
+    program
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Function(function) => Some((function.name.as_str(), function)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Every call, as `(caller, callee)`, that may lead back into the function
+/// making it before it returns, directly or through other functions.
+pub fn recursive_calls<'a>(
+    functions: &BTreeMap<&'a str, &'a Function>,
+) -> BTreeSet<(&'a str, &'a str)> {
+    let callees: BTreeMap<&str, BTreeSet<&str>> = functions
+        .iter()
+        .map(|(&name, function)| {
+            let mut names = BTreeSet::new();
+            for stmt in &function.body {
+                stmt.callees(&mut names);
+            }
+            (name, names)
+        })
+        .collect();
+    let mut recursive = BTreeSet::new();
+    for (&caller, names) in &callees {
+        for &callee in names {
+            let mut pending = vec![callee];
+            let mut seen = BTreeSet::new();
+            while let Some(name) = pending.pop() {
+                if name == caller {
+                    recursive.insert((caller, callee));
+                    break;
+                }
+                if seen.insert(name)
+                    && let Some(next) = callees.get(name)
+                {
+                    pending.extend(next);
+                }
+            }
+        }
+    }
+    recursive
+}
+
+/// Removes the functions that no top-level statement can call, directly or
+/// through other functions, so a program that defines more than it uses
+/// only carries what it runs. Returns their names in definition order.
+pub fn eliminate_dead_functions(program: &mut Program) -> Vec<String> {
+    let functions = functions(program);
+    let mut pending = BTreeSet::new();
+    for stmt in program.iter() {
+        if !matches!(stmt, Stmt::Function(_)) {
+            stmt.callees(&mut pending);
+        }
+    }
+    let mut reachable = BTreeSet::new();
+    while let Some(name) = pending.pop_first() {
+        if reachable.insert(name)
+            && let Some(function) = functions.get(name)
+        {
+            for stmt in &function.body {
+                stmt.callees(&mut pending);
+            }
+        }
+    }
+    let reachable: BTreeSet<String> = reachable.into_iter().map(String::from).collect();
+
+    let mut dead = Vec::new();
+    program.retain(|stmt| match stmt {
+        Stmt::Function(function) if !reachable.contains(&function.name) => {
+            dead.push(function.name.clone());
+            false
+        }
+        _ => true,
+    });
+    dead
+}
+
 // Utility trait for AST traversal
 pub trait Visitor<T> {
     fn visit_program(&mut self, program: &Program) -> T;
@@ -89,16 +439,178 @@ pub trait Visitor<T> {
     fn visit_expr(&mut self, expr: &Expr) -> T;
 }
 
+/// Rewrites a program, taking each node by value. Every method defaults to
+/// rebuilding its node from folded children, so an implementation only
+/// overrides the nodes it changes and calls [`fold_stmt_children`] or
+/// [`fold_expr_children`] to keep descending.
+pub trait Folder {
+    fn fold_program(&mut self, program: Program) -> Program {
+        fold_block(self, program)
+    }
+
+    /// The statements `stmt` becomes: usually one, but a rewrite may expand
+    /// it into several or remove it.
+    fn fold_stmt(&mut self, stmt: Stmt) -> Vec<Stmt> {
+        vec![fold_stmt_children(self, stmt)]
+    }
+
+    fn fold_expr(&mut self, expr: Expr) -> Expr {
+        fold_expr_children(self, expr)
+    }
+}
+
+fn fold_block<F: Folder + ?Sized>(folder: &mut F, stmts: Vec<Stmt>) -> Vec<Stmt> {
+    stmts
+        .into_iter()
+        .flat_map(|stmt| folder.fold_stmt(stmt))
+        .collect()
+}
+
+/// `stmt` with its expressions and nested blocks folded by `folder`.
+pub fn fold_stmt_children<F: Folder + ?Sized>(folder: &mut F, stmt: Stmt) -> Stmt {
+    match stmt {
+        Stmt::Let {
+            name,
+            mutable,
+            ty,
+            value,
+            span,
+        } => Stmt::Let {
+            name,
+            mutable,
+            ty,
+            value: folder.fold_expr(value),
+            span,
+        },
+        Stmt::Assign { name, value, span } => Stmt::Assign {
+            name,
+            value: folder.fold_expr(value),
+            span,
+        },
+        Stmt::Print { value, span } => Stmt::Print {
+            value: folder.fold_expr(value),
+            span,
+        },
+        Stmt::PrintNum { value, span } => Stmt::PrintNum {
+            value: folder.fold_expr(value),
+            span,
+        },
+        Stmt::If {
+            condition,
+            body,
+            else_body,
+            span,
+        } => Stmt::If {
+            condition: folder.fold_expr(condition),
+            body: fold_block(folder, body),
+            else_body: fold_block(folder, else_body),
+            span,
+        },
+        Stmt::While {
+            condition,
+            body,
+            span,
+        } => Stmt::While {
+            condition: folder.fold_expr(condition),
+            body: fold_block(folder, body),
+            span,
+        },
+        Stmt::Match {
+            scrutinee,
+            arms,
+            span,
+        } => Stmt::Match {
+            scrutinee: folder.fold_expr(scrutinee),
+            arms: arms
+                .into_iter()
+                .map(|arm| MatchArm {
+                    body: fold_block(folder, arm.body),
+                    ..arm
+                })
+                .collect(),
+            span,
+        },
+        Stmt::Loop { body, span } => Stmt::Loop {
+            body: fold_block(folder, body),
+            span,
+        },
+        Stmt::DoWhile {
+            body,
+            condition,
+            span,
+        } => Stmt::DoWhile {
+            body: fold_block(folder, body),
+            condition: folder.fold_expr(condition),
+            span,
+        },
+        Stmt::For {
+            name,
+            iterable,
+            body,
+            span,
+        } => Stmt::For {
+            name,
+            iterable: folder.fold_expr(iterable),
+            body: fold_block(folder, body),
+            span,
+        },
+        Stmt::Function(function) => Stmt::Function(Function {
+            body: fold_block(folder, function.body),
+            ..function
+        }),
+        Stmt::Return { value, span } => Stmt::Return {
+            value: value.map(|value| folder.fold_expr(value)),
+            span,
+        },
+        Stmt::Expr { value, span } => Stmt::Expr {
+            value: folder.fold_expr(value),
+            span,
+        },
+        Stmt::Break { .. } | Stmt::Continue { .. } => stmt,
+    }
+}
+
+/// `expr` with its operands and arguments folded by `folder`.
+pub fn fold_expr_children<F: Folder + ?Sized>(folder: &mut F, expr: Expr) -> Expr {
+    match expr {
+        Expr::Unary { operator, operand } => Expr::unary(operator, folder.fold_expr(*operand)),
+        Expr::Binary {
+            left,
+            operator,
+            right,
+        } => {
+            let left = folder.fold_expr(*left);
+            Expr::binary(left, operator, folder.fold_expr(*right))
+        }
+        Expr::Call { name, args } => Expr::Call {
+            name,
+            args: args.into_iter().map(|arg| folder.fold_expr(arg)).collect(),
+        },
+        Expr::Number(_) | Expr::Bool(_) | Expr::Str(_) | Expr::Variable(_) | Expr::Input => expr,
+    }
+}
+
 // Helper functions for AST construction
 impl Expr {
     pub fn number(value: i32) -> Self {
         Expr::Number(value)
     }
 
+    pub fn string(text: impl Into<String>) -> Self {
+        Expr::Str(text.into())
+    }
+
     pub fn variable(name: impl Into<String>) -> Self {
         Expr::Variable(name.into())
     }
 
+    pub fn unary(op: UnaryOp, operand: Expr) -> Self {
+        Expr::Unary {
+            operator: op,
+            operand: Box::new(operand),
+        }
+    }
+
     pub fn binary(left: Expr, op: BinaryOp, right: Expr) -> Self {
         Expr::Binary {
             left: Box::new(left),
@@ -106,6 +618,121 @@ impl Expr {
             right: Box::new(right),
         }
     }
+
+    pub fn call(name: impl Into<String>, args: Vec<Expr>) -> Self {
+        Expr::Call {
+            name: name.into(),
+            args,
+        }
+    }
+
+    /// Whether evaluating the expression calls a function.
+    pub fn calls(&self) -> bool {
+        match self {
+            Expr::Number(_) | Expr::Bool(_) | Expr::Str(_) | Expr::Variable(_) | Expr::Input => {
+                false
+            }
+            Expr::Unary { operand, .. } => operand.calls(),
+            Expr::Binary { left, right, .. } => left.calls() || right.calls(),
+            Expr::Call { .. } => true,
+        }
+    }
+
+    /// Whether evaluating the expression reads input itself, not counting
+    /// the functions it calls.
+    pub fn reads_input(&self) -> bool {
+        match self {
+            Expr::Number(_) | Expr::Bool(_) | Expr::Str(_) | Expr::Variable(_) => false,
+            Expr::Unary { operand, .. } => operand.reads_input(),
+            Expr::Binary { left, right, .. } => left.reads_input() || right.reads_input(),
+            Expr::Call { args, .. } => args.iter().any(Expr::reads_input),
+            Expr::Input => true,
+        }
+    }
+
+    /// Adds the name of every function evaluating the expression may call
+    /// to `names`.
+    pub fn callees<'a>(&'a self, names: &mut BTreeSet<&'a str>) {
+        match self {
+            Expr::Number(_) | Expr::Bool(_) | Expr::Str(_) | Expr::Variable(_) | Expr::Input => {}
+            Expr::Unary { operand, .. } => operand.callees(names),
+            Expr::Binary { left, right, .. } => {
+                left.callees(names);
+                right.callees(names);
+            }
+            Expr::Call { name, args } => {
+                names.insert(name);
+                for arg in args {
+                    arg.callees(names);
+                }
+            }
+        }
+    }
+
+    /// Counts each call written in the expression under the callee's
+    /// name in `counts`.
+    pub fn count_calls<'a>(&'a self, counts: &mut BTreeMap<&'a str, usize>) {
+        match self {
+            Expr::Number(_) | Expr::Bool(_) | Expr::Str(_) | Expr::Variable(_) | Expr::Input => {}
+            Expr::Unary { operand, .. } => operand.count_calls(counts),
+            Expr::Binary { left, right, .. } => {
+                left.count_calls(counts);
+                right.count_calls(counts);
+            }
+            Expr::Call { name, args } => {
+                *counts.entry(name).or_default() += 1;
+                for arg in args {
+                    arg.count_calls(counts);
+                }
+            }
+        }
+    }
+
+    /// The cell value of an expression built from literals alone, or `None`
+    /// if it reads a variable or divides by zero.
+    pub fn constant(&self) -> Option<u8> {
+        self.constant_with(&|_| None)
+    }
+
+    /// Like [`constant`](Self::constant), taking the values of variables
+    /// from `variable` where it knows them.
+    pub fn constant_with(&self, variable: &impl Fn(&str) -> Option<u8>) -> Option<u8> {
+        match self {
+            Expr::Number(value) => Some(value.rem_euclid(256) as u8),
+            Expr::Bool(value) => Some(*value as u8),
+            Expr::Str(_) | Expr::Call { .. } | Expr::Input => None,
+            Expr::Variable(name) => variable(name),
+            Expr::Unary {
+                operator: UnaryOp::Not,
+                operand,
+            } => Some((operand.constant_with(variable)? == 0) as u8),
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                let (left, right) = (
+                    left.constant_with(variable)?,
+                    right.constant_with(variable)?,
+                );
+                Some(match operator {
+                    BinaryOp::Add => left.wrapping_add(right),
+                    BinaryOp::Sub => left.wrapping_sub(right),
+                    BinaryOp::Mul => left.wrapping_mul(right),
+                    BinaryOp::Div => left.checked_div(right)?,
+                    BinaryOp::Mod => left.checked_rem(right)?,
+                    BinaryOp::Equal => (left == right) as u8,
+                    BinaryOp::NotEqual => (left != right) as u8,
+                    BinaryOp::Less => (left < right) as u8,
+                    BinaryOp::Greater => (left > right) as u8,
+                    BinaryOp::LessEqual => (left <= right) as u8,
+                    BinaryOp::GreaterEqual => (left >= right) as u8,
+                    BinaryOp::And => (left != 0 && right != 0) as u8,
+                    BinaryOp::Or => (left != 0 || right != 0) as u8,
+                })
+            }
+        }
+    }
 }
 
 impl Stmt {
@@ -113,7 +740,20 @@ impl Stmt {
         Stmt::Let {
             name: name.into(),
             mutable,
+            ty: None,
             value,
+            span: Span::default(),
+        }
+    }
+
+    /// `let name: ty = value;`.
+    pub fn typed_let(name: impl Into<String>, mutable: bool, ty: Type, value: Expr) -> Self {
+        Stmt::Let {
+            name: name.into(),
+            mutable,
+            ty: Some(ty),
+            value,
+            span: Span::default(),
         }
     }
 
@@ -121,18 +761,314 @@ impl Stmt {
         Stmt::Assign {
             name: name.into(),
             value,
+            span: Span::default(),
         }
     }
 
     pub fn print(expr: Expr) -> Self {
-        Stmt::Print(expr)
+        Stmt::Print {
+            value: expr,
+            span: Span::default(),
+        }
+    }
+
+    pub fn print_num(expr: Expr) -> Self {
+        Stmt::PrintNum {
+            value: expr,
+            span: Span::default(),
+        }
     }
 
     pub fn if_stmt(condition: Expr, body: Vec<Stmt>) -> Self {
-        Stmt::If { condition, body }
+        Self::if_else(condition, body, Vec::new())
+    }
+
+    pub fn if_else(condition: Expr, body: Vec<Stmt>, else_body: Vec<Stmt>) -> Self {
+        Stmt::If {
+            condition,
+            body,
+            else_body,
+            span: Span::default(),
+        }
     }
 
     pub fn while_stmt(condition: Expr, body: Vec<Stmt>) -> Self {
-        Stmt::While { condition, body }
+        Stmt::While {
+            condition,
+            body,
+            span: Span::default(),
+        }
+    }
+
+    pub fn match_stmt(scrutinee: Expr, arms: Vec<MatchArm>) -> Self {
+        Stmt::Match {
+            scrutinee,
+            arms,
+            span: Span::default(),
+        }
+    }
+
+    pub fn loop_stmt(body: Vec<Stmt>) -> Self {
+        Stmt::Loop {
+            body,
+            span: Span::default(),
+        }
+    }
+
+    pub fn do_while(body: Vec<Stmt>, condition: Expr) -> Self {
+        Stmt::DoWhile {
+            body,
+            condition,
+            span: Span::default(),
+        }
+    }
+
+    pub fn for_stmt(name: impl Into<String>, iterable: Expr, body: Vec<Stmt>) -> Self {
+        Stmt::For {
+            name: name.into(),
+            iterable,
+            body,
+            span: Span::default(),
+        }
+    }
+
+    pub fn break_stmt() -> Self {
+        Stmt::Break {
+            span: Span::default(),
+        }
+    }
+
+    pub fn continue_stmt() -> Self {
+        Stmt::Continue {
+            span: Span::default(),
+        }
+    }
+
+    pub fn function(name: impl Into<String>, params: Vec<String>, body: Vec<Stmt>) -> Self {
+        Stmt::Function(Function {
+            name: name.into(),
+            param_types: vec![None; params.len()],
+            params,
+            return_type: None,
+            inline: Inline::Auto,
+            body,
+            span: Span::default(),
+        })
+    }
+
+    pub fn return_stmt(value: Option<Expr>) -> Self {
+        Stmt::Return {
+            value,
+            span: Span::default(),
+        }
+    }
+
+    pub fn expr(value: Expr) -> Self {
+        Stmt::Expr {
+            value,
+            span: Span::default(),
+        }
+    }
+
+    pub fn with_span(mut self, new_span: Span) -> Self {
+        match &mut self {
+            Stmt::Let { span, .. }
+            | Stmt::Assign { span, .. }
+            | Stmt::Print { span, .. }
+            | Stmt::PrintNum { span, .. }
+            | Stmt::If { span, .. }
+            | Stmt::Match { span, .. }
+            | Stmt::While { span, .. }
+            | Stmt::Loop { span, .. }
+            | Stmt::DoWhile { span, .. }
+            | Stmt::For { span, .. }
+            | Stmt::Break { span }
+            | Stmt::Continue { span }
+            | Stmt::Function(Function { span, .. })
+            | Stmt::Return { span, .. }
+            | Stmt::Expr { span, .. } => *span = new_span,
+        }
+        self
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Let { span, .. }
+            | Stmt::Assign { span, .. }
+            | Stmt::Print { span, .. }
+            | Stmt::PrintNum { span, .. }
+            | Stmt::If { span, .. }
+            | Stmt::Match { span, .. }
+            | Stmt::While { span, .. }
+            | Stmt::Loop { span, .. }
+            | Stmt::DoWhile { span, .. }
+            | Stmt::For { span, .. }
+            | Stmt::Break { span }
+            | Stmt::Continue { span }
+            | Stmt::Function(Function { span, .. })
+            | Stmt::Return { span, .. }
+            | Stmt::Expr { span, .. } => *span,
+        }
+    }
+
+    /// Whether running this statement may leave the loop it is in, by
+    /// `break` or `return`. A `break` inside a nested loop belongs to that
+    /// loop instead, but a `return` leaves every loop.
+    pub fn breaks(&self) -> bool {
+        self.may_jump(false)
+    }
+
+    /// Like [`breaks`](Self::breaks), counting `continue` as well.
+    pub fn jumps(&self) -> bool {
+        self.may_jump(true)
+    }
+
+    /// Whether running this statement may `return` from the function it is
+    /// in.
+    pub fn returns(&self) -> bool {
+        match self {
+            Stmt::Return { .. } => true,
+            _ => self.bodies().any(|stmt| stmt.returns()),
+        }
+    }
+
+    /// Whether this statement contains a `return` with a value.
+    pub fn returns_value(&self) -> bool {
+        match self {
+            Stmt::Return { value, .. } => value.is_some(),
+            _ => self.bodies().any(|stmt| stmt.returns_value()),
+        }
+    }
+
+    /// Whether running this statement may assign to a variable called
+    /// `name`, counting assignments to a binding that shadows it.
+    pub fn assigns(&self, name: &str) -> bool {
+        match self {
+            Stmt::Assign { name: target, .. } => target == name,
+            _ => self.bodies().any(|stmt| stmt.assigns(name)),
+        }
+    }
+
+    /// Whether running this statement may read input, counting the body of
+    /// a function it defines.
+    pub fn reads_input(&self) -> bool {
+        match self {
+            Stmt::Function(function) => function.body.iter().any(Stmt::reads_input),
+            _ => {
+                self.own_expr().is_some_and(Expr::reads_input)
+                    || self.bodies().any(Stmt::reads_input)
+            }
+        }
+    }
+
+    /// Whether running this statement may call a function.
+    pub fn calls(&self) -> bool {
+        self.own_expr().is_some_and(Expr::calls) || self.bodies().any(Stmt::calls)
+    }
+
+    /// Adds the name of every function running this statement may call
+    /// to `names`.
+    pub fn callees<'a>(&'a self, names: &mut BTreeSet<&'a str>) {
+        if let Some(expr) = self.own_expr() {
+            expr.callees(names);
+        }
+        for stmt in self.bodies() {
+            stmt.callees(names);
+        }
+    }
+
+    /// Counts each call running this statement makes, as written, under
+    /// the callee's name in `counts`.
+    pub fn count_calls<'a>(&'a self, counts: &mut BTreeMap<&'a str, usize>) {
+        if let Some(expr) = self.own_expr() {
+            expr.count_calls(counts);
+        }
+        for stmt in self.bodies() {
+            stmt.count_calls(counts);
+        }
+    }
+
+    /// The expression this statement evaluates itself, as opposed to those
+    /// of the statements nested in it.
+    fn own_expr(&self) -> Option<&Expr> {
+        match self {
+            Stmt::Let { value, .. }
+            | Stmt::Assign { value, .. }
+            | Stmt::Print { value, .. }
+            | Stmt::PrintNum { value, .. }
+            | Stmt::Expr { value, .. } => Some(value),
+            Stmt::If { condition, .. }
+            | Stmt::While { condition, .. }
+            | Stmt::DoWhile { condition, .. } => Some(condition),
+            Stmt::Match { scrutinee, .. } => Some(scrutinee),
+            Stmt::For { iterable, .. } => Some(iterable),
+            Stmt::Return { value, .. } => value.as_ref(),
+            Stmt::Loop { .. } | Stmt::Break { .. } | Stmt::Continue { .. } | Stmt::Function(_) => {
+                None
+            }
+        }
+    }
+
+    fn may_jump(&self, continues: bool) -> bool {
+        match self {
+            Stmt::Break { .. } | Stmt::Return { .. } => true,
+            Stmt::Continue { .. } => continues,
+            Stmt::If { .. } | Stmt::Match { .. } => {
+                self.bodies().any(|stmt| stmt.may_jump(continues))
+            }
+            _ => self.returns(),
+        }
+    }
+
+    /// The statements nested in this one that run as part of it; a
+    /// function's body only runs when it is called.
+    fn bodies(&self) -> impl Iterator<Item = &Stmt> {
+        let bodies: [&[Stmt]; 2] = match self {
+            Stmt::If {
+                body, else_body, ..
+            } => [body, else_body],
+            Stmt::While { body, .. }
+            | Stmt::Loop { body, .. }
+            | Stmt::DoWhile { body, .. }
+            | Stmt::For { body, .. } => [body, &[]],
+            _ => [&[], &[]],
+        };
+        let arms = match self {
+            Stmt::Match { arms, .. } => arms.as_slice(),
+            _ => &[],
+        };
+        bodies
+            .into_iter()
+            .flatten()
+            .chain(arms.iter().flat_map(|arm| &arm.body))
+    }
+}
+
+impl MatchArm {
+    pub fn new(pattern: Pattern, body: Vec<Stmt>) -> Self {
+        Self {
+            pattern,
+            body,
+            span: Span::default(),
+        }
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = span;
+        self
+    }
+}
+
+impl Pattern {
+    /// The cell value this pattern matches, or `None` for `_`.
+    pub fn value(&self) -> Option<u8> {
+        match self {
+            Pattern::Number(value) => Some(value.rem_euclid(256) as u8),
+            Pattern::Wildcard => None,
+        }
+    }
+
+    pub fn matches(&self, value: u8) -> bool {
+        self.value().is_none_or(|expected| expected == value)
     }
 }
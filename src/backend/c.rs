@@ -0,0 +1,646 @@
+//! A C backend, for compiling programs natively.
+//!
+//! The output is a direct, readable translation of the AST rather than of
+//! the Brainfuck: one `uint8_t` per variable, with arithmetic cast back to
+//! `uint8_t` so it wraps like the Brainfuck cells do. Strings are `char`
+//! arrays.
+
+use super::CodeGenerator;
+use crate::ast::{self, BinaryOp, Expr, Function, Program, Stmt};
+use crate::error::TranspilerResult;
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+/// Identifiers that are valid in mini-rust but reserved in C.
+const RESERVED: &[&str] = &[
+    "auto", "break", "case", "char", "const", "continue", "default", "do", "double", "else",
+    "enum", "extern", "float", "for", "goto", "inline", "int", "long", "main", "putchar",
+    "register", "restrict", "return", "short", "signed", "sizeof", "static", "struct", "switch",
+    "typedef", "uint8_t", "union", "unsigned", "void", "volatile",
+];
+
+/// Written before everything else for programs that read input.
+const INPUT: &str = "static uint8_t input(void) {
+    fflush(stdout);
+    int c = getchar();
+    return c == EOF ? 0 : (uint8_t)c;
+}
+
+";
+
+pub struct CGenerator {
+    output: String,
+    indent: usize,
+    /// C name of each variable in scope, innermost scope last.
+    scopes: Vec<BTreeMap<String, String>>,
+    /// Every C name handed out so far in the function being written.
+    taken: BTreeSet<String>,
+    /// C names of the variables in that function holding strings.
+    strings: BTreeSet<String>,
+    /// C name of each function.
+    functions: BTreeMap<String, String>,
+}
+
+impl Default for CGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CGenerator {
+    pub fn new() -> Self {
+        Self {
+            output: String::new(),
+            indent: 0,
+            scopes: vec![BTreeMap::new()],
+            taken: BTreeSet::new(),
+            strings: BTreeSet::new(),
+            functions: BTreeMap::new(),
+        }
+    }
+
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.indent {
+            self.output.push_str("    ");
+        }
+        self.output.push_str(text);
+        self.output.push('\n');
+    }
+
+    /// Picks the C name for a new binding of `name`. Every redeclaration
+    /// gets a fresh name, since C cannot shadow within a scope and
+    /// `let x = x + 1;` would otherwise read the new, uninitialized `x`.
+    fn declare(&mut self, name: &str) -> String {
+        let base = if RESERVED.contains(&name) {
+            format!("{}_", name)
+        } else {
+            name.to_string()
+        };
+        let mut c_name = base.clone();
+        let mut suffix = 0;
+        while self.taken.contains(&c_name) {
+            suffix += 1;
+            c_name = format!("{}_{}", base, suffix);
+        }
+        self.taken.insert(c_name.clone());
+        c_name
+    }
+
+    fn resolve(&self, name: &str) -> String {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .cloned()
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    fn block(&mut self, body: &[Stmt]) {
+        self.indent += 1;
+        self.scopes.push(BTreeMap::new());
+        for stmt in body {
+            self.stmt(stmt);
+        }
+        self.scopes.pop();
+        self.indent -= 1;
+    }
+
+    /// Writes `function` as a C function taking and returning bytes. Only
+    /// the function names are shared with the rest of the program.
+    fn function(&mut self, function: &Function) {
+        let taken = self.taken.clone();
+        let strings = core::mem::take(&mut self.strings);
+        let scopes = core::mem::replace(&mut self.scopes, vec![BTreeMap::new()]);
+        let mut params = Vec::new();
+        for param in &function.params {
+            let c_name = self.declare(param);
+            params.push(format!("uint8_t {}", c_name));
+            let scope = self.scopes.last_mut().expect("function scope");
+            scope.insert(param.clone(), c_name);
+        }
+        let params = if params.is_empty() {
+            "void".to_string()
+        } else {
+            params.join(", ")
+        };
+        self.line(&format!(
+            "static uint8_t {}({}) {{",
+            self.functions[&function.name], params
+        ));
+        self.block(&function.body);
+        if !matches!(function.body.last(), Some(Stmt::Return { .. })) {
+            self.indent += 1;
+            self.line("return 0;");
+            self.indent -= 1;
+        }
+        self.line("}");
+        self.line("");
+        self.scopes = scopes;
+        self.taken = taken;
+        self.strings = strings;
+    }
+
+    /// Writes an `if`, continuing with `else if` for as long as each
+    /// `else` body is another `if` alone.
+    fn if_chain<'a>(
+        &mut self,
+        mut condition: &'a Expr,
+        mut body: &'a [Stmt],
+        mut else_body: &'a [Stmt],
+    ) {
+        let mut prefix = "";
+        loop {
+            let line = format!("{}if ({}) {{", prefix, self.expr(condition));
+            self.line(&line);
+            self.block(body);
+            match else_body {
+                [] => break,
+                [
+                    Stmt::If {
+                        condition: next,
+                        body: next_body,
+                        else_body: next_else,
+                        ..
+                    },
+                ] => {
+                    (condition, body, else_body) = (next, next_body, next_else);
+                    prefix = "} else ";
+                }
+                _ => {
+                    self.line("} else {");
+                    self.block(else_body);
+                    break;
+                }
+            }
+        }
+        self.line("}");
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Let {
+                name,
+                value: Expr::Str(text),
+                ..
+            } => {
+                let c_name = self.declare(name);
+                self.line(&format!("const char {}[] = {};", c_name, c_string(text)));
+                self.strings.insert(c_name.clone());
+                let scope = self.scopes.last_mut().expect("global scope");
+                scope.insert(name.clone(), c_name);
+            }
+            Stmt::Let {
+                name,
+                mutable,
+                value,
+                ..
+            } => {
+                // The initializer is translated first so that it still
+                // refers to any binding being shadowed.
+                let value = self.expr(value);
+                let c_name = self.declare(name);
+                let qualifier = if *mutable { "" } else { "const " };
+                self.line(&format!("{}uint8_t {} = {};", qualifier, c_name, value));
+                let scope = self.scopes.last_mut().expect("global scope");
+                scope.insert(name.clone(), c_name);
+            }
+            Stmt::Assign { name, value, .. } => {
+                let line = format!("{} = {};", self.resolve(name), self.expr(value));
+                self.line(&line);
+            }
+            // `sizeof` counts the terminating NUL too.
+            Stmt::Print { value, .. } => {
+                let string = match value {
+                    Expr::Str(_) => true,
+                    Expr::Variable(name) => self.strings.contains(&self.resolve(name)),
+                    _ => false,
+                };
+                let value = self.expr(value);
+                let line = if string {
+                    format!("fwrite({v}, 1, sizeof {v} - 1, stdout);", v = value)
+                } else {
+                    format!("putchar({});", value)
+                };
+                self.line(&line);
+            }
+            Stmt::PrintNum { value, .. } => {
+                let line = format!("printf(\"%d\", {});", self.expr(value));
+                self.line(&line);
+            }
+            Stmt::If {
+                condition,
+                body,
+                else_body,
+                ..
+            } => self.if_chain(condition, body, else_body),
+            // Not a `switch`, where `break` would leave the `switch`
+            // instead of the loop around it.
+            Stmt::Match {
+                scrutinee, arms, ..
+            } => {
+                let value = self.expr(scrutinee);
+                let c_name = self.declare("scrutinee");
+                self.line(&format!("const uint8_t {} = {};", c_name, value));
+                let mut prefix = "";
+                for arm in arms {
+                    match arm.pattern.value() {
+                        Some(value) => {
+                            self.line(&format!("{}if ({} == {}) {{", prefix, c_name, value));
+                            self.block(&arm.body);
+                            prefix = "} else ";
+                        }
+                        None => {
+                            self.line(if prefix.is_empty() { "{" } else { "} else {" });
+                            self.block(&arm.body);
+                            prefix = "} else ";
+                            break;
+                        }
+                    }
+                }
+                if !prefix.is_empty() {
+                    self.line("}");
+                }
+            }
+            Stmt::While {
+                condition, body, ..
+            } => {
+                let line = format!("while ({}) {{", self.expr(condition));
+                self.line(&line);
+                self.block(body);
+                self.line("}");
+            }
+            Stmt::Loop { body, .. } => {
+                self.line("for (;;) {");
+                self.block(body);
+                self.line("}");
+            }
+            Stmt::DoWhile {
+                body, condition, ..
+            } => {
+                self.line("do {");
+                self.block(body);
+                let line = format!("}} while ({});", self.expr(condition));
+                self.line(&line);
+            }
+            Stmt::For {
+                name,
+                iterable,
+                body,
+                ..
+            } => {
+                // `sizeof` counts the terminating NUL too.
+                let string = self.expr(iterable);
+                let index = self.declare("i");
+                self.line(&format!(
+                    "for (size_t {i} = 0; {i} < sizeof {s} - 1; {i}++) {{",
+                    i = index,
+                    s = string
+                ));
+                let c_name = self.declare(name);
+                self.indent += 1;
+                self.line(&format!(
+                    "const uint8_t {} = (uint8_t){}[{}];",
+                    c_name, string, index
+                ));
+                self.indent -= 1;
+                self.scopes.push(BTreeMap::from([(name.clone(), c_name)]));
+                self.block(body);
+                self.scopes.pop();
+                self.line("}");
+            }
+            Stmt::Break { .. } => self.line("break;"),
+            Stmt::Continue { .. } => self.line("continue;"),
+            // Written before `main`.
+            Stmt::Function(_) => {}
+            Stmt::Return { value, .. } => {
+                let value = value
+                    .as_ref()
+                    .map_or("0".to_string(), |value| self.expr(value));
+                self.line(&format!("return {};", value));
+            }
+            Stmt::Expr { value, .. } => {
+                let line = format!("{};", self.expr(value));
+                self.line(&line);
+            }
+        }
+    }
+
+    fn expr(&self, expr: &Expr) -> String {
+        match expr {
+            Expr::Number(value) => value.rem_euclid(256).to_string(),
+            Expr::Bool(value) => (*value as u8).to_string(),
+            Expr::Str(text) => c_string(text),
+            Expr::Variable(name) => self.resolve(name),
+            Expr::Unary { operator, operand } => {
+                format!("{}{}", operator.symbol(), self.operand(operand))
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                let (left, right) = (self.operand(left), self.operand(right));
+                match operator {
+                    BinaryOp::Add
+                    | BinaryOp::Sub
+                    | BinaryOp::Mul
+                    | BinaryOp::Div
+                    | BinaryOp::Mod => {
+                        // C promotes to `int`; cast back so results wrap.
+                        format!("(uint8_t)({} {} {})", left, operator.symbol(), right)
+                    }
+                    BinaryOp::Equal
+                    | BinaryOp::NotEqual
+                    | BinaryOp::Less
+                    | BinaryOp::Greater
+                    | BinaryOp::LessEqual
+                    | BinaryOp::GreaterEqual
+                    | BinaryOp::And
+                    | BinaryOp::Or => {
+                        format!("{} {} {}", left, operator.symbol(), right)
+                    }
+                }
+            }
+            Expr::Call { name, args } => {
+                let args: Vec<_> = args.iter().map(|arg| self.expr(arg)).collect();
+                let name = self
+                    .functions
+                    .get(name)
+                    .map_or(name.as_str(), String::as_str);
+                format!("{}({})", name, args.join(", "))
+            }
+            Expr::Input => "input()".to_string(),
+        }
+    }
+
+    /// An operand of a binary operator; comparisons need parentheses there.
+    fn operand(&self, expr: &Expr) -> String {
+        let text = self.expr(expr);
+        match expr {
+            Expr::Binary {
+                operator:
+                    BinaryOp::Equal
+                    | BinaryOp::NotEqual
+                    | BinaryOp::Less
+                    | BinaryOp::Greater
+                    | BinaryOp::LessEqual
+                    | BinaryOp::GreaterEqual
+                    | BinaryOp::And
+                    | BinaryOp::Or,
+                ..
+            } => format!("({})", text),
+            _ => text,
+        }
+    }
+}
+
+/// `text` as a C string literal. Anything but printable ASCII is written as
+/// a three-digit octal escape, which cannot run into the next character.
+fn c_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for byte in text.bytes() {
+        match byte {
+            b'"' | b'\\' => {
+                out.push('\\');
+                out.push(byte as char);
+            }
+            b' '..=b'~' => out.push(byte as char),
+            _ => out.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl CodeGenerator for CGenerator {
+    fn target(&self) -> &'static str {
+        "c"
+    }
+
+    fn extension(&self) -> &'static str {
+        "c"
+    }
+
+    fn comment(&self, text: &str) -> Option<String> {
+        Some(format!("/* {} */\n", text.replace("*/", "* /")))
+    }
+
+    fn generate(&mut self, program: &Program) -> TranspilerResult<String> {
+        *self = Self::new();
+        self.line("#include <stdint.h>");
+        self.line("#include <stdio.h>");
+        self.line("");
+        // C leaves the order of two reads in one expression unspecified.
+        if program.iter().any(Stmt::reads_input) {
+            self.output.push_str(INPUT);
+        }
+        // Declared up front, so each function can call any other.
+        let functions = ast::functions(program);
+        for name in functions.keys() {
+            let c_name = self.declare(name);
+            self.functions.insert(name.to_string(), c_name);
+        }
+        for function in functions.values() {
+            let params = vec!["uint8_t"; function.params.len()];
+            let params = if params.is_empty() {
+                "void".to_string()
+            } else {
+                params.join(", ")
+            };
+            self.line(&format!(
+                "static uint8_t {}({});",
+                self.functions[&function.name], params
+            ));
+        }
+        if !functions.is_empty() {
+            self.line("");
+        }
+        for function in functions.values() {
+            self.function(function);
+        }
+        self.line("int main(void) {");
+        self.block(program);
+        self.indent += 1;
+        self.line("return 0;");
+        self.indent -= 1;
+        self.line("}");
+        Ok(core::mem::take(&mut self.output))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn generate(source: &str) -> String {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        CGenerator::new().generate(&program).unwrap()
+    }
+
+    #[test]
+    fn test_program() {
+        let c = generate("let x = 5; let mut n = 3; while n > 0 { print(x + n); n = n - 1; }");
+        assert_eq!(
+            c,
+            "#include <stdint.h>
+#include <stdio.h>
+
+int main(void) {
+    const uint8_t x = 5;
+    uint8_t n = 3;
+    while (n > 0) {
+        putchar((uint8_t)(x + n));
+        n = (uint8_t)(n - 1);
+    }
+    return 0;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_comparisons_as_operands() {
+        let c = generate("let a = 1; let b = a == 1; if b == (a < 3) { print(a); } print_num(b);");
+        assert!(c.contains("const uint8_t b = a == 1;"));
+        assert!(c.contains("if (b == (a < 3)) {"));
+        assert!(c.contains("printf(\"%d\", b);"));
+    }
+
+    #[test]
+    fn test_else_branches() {
+        let c =
+            generate("let a = 1; if a == 1 { print(a); } else if a == 2 { } else { print(0); }");
+        assert!(c.contains(
+            "    if (a == 1) {
+        putchar(a);
+    } else if (a == 2) {
+    } else {
+        putchar(0);
+    }
+"
+        ));
+    }
+
+    #[test]
+    fn test_loop_break_continue() {
+        let c = generate("loop { if 1 { continue; } break; } loop { print(1); } while 0;");
+        assert!(c.contains(
+            "    for (;;) {
+        if (1) {
+            continue;
+        }
+        break;
+    }
+    do {
+        putchar(1);
+    } while (0);
+"
+        ));
+    }
+
+    #[test]
+    fn test_match() {
+        let c = generate(
+            "let x = 2; match x { 1 => { print(1); } -254 => { } _ => { print(0); } 3 => { } }",
+        );
+        assert!(c.contains(
+            "    const uint8_t scrutinee = x;
+    if (scrutinee == 1) {
+        putchar(1);
+    } else if (scrutinee == 2) {
+    } else {
+        putchar(0);
+    }
+"
+        ));
+        assert!(
+            generate("match 1 { _ => { print(1); } }")
+                .contains("    {\n        putchar(1);\n    }\n")
+        );
+    }
+
+    #[test]
+    fn test_functions() {
+        let c = generate(
+            "fn putchar(main) { print(main); } fn twice(n) { return n + n; } \
+             let n = twice(2); putchar(twice(n) == 8);",
+        );
+        assert!(c.contains(
+            "static uint8_t putchar_(uint8_t);
+static uint8_t twice(uint8_t);
+
+static uint8_t putchar_(uint8_t main_) {
+    putchar(main_);
+    return 0;
+}
+
+static uint8_t twice(uint8_t n) {
+    return (uint8_t)(n + n);
+}
+
+int main(void) {
+    const uint8_t n = twice(2);
+    putchar_(twice(n) == 8);
+"
+        ));
+    }
+
+    #[test]
+    fn test_input() {
+        let c = generate("let c = input(); print(c + input());");
+        assert!(c.contains("static uint8_t input(void) {"));
+        assert!(c.contains("    const uint8_t c = input();\n"));
+        assert!(c.contains("    putchar((uint8_t)(c + input()));\n"));
+        assert!(!generate("print(1);").contains("input"));
+    }
+
+    #[test]
+    fn test_strings() {
+        let c =
+            generate("let s = \"a\\\"\\n\"; for c in s { print(c); } for i in \"!\" { print(i); }");
+        assert!(c.contains("    const char s[] = \"a\\\"\\012\";\n"));
+        assert!(c.contains(
+            "    for (size_t i = 0; i < sizeof s - 1; i++) {
+        const uint8_t c = (uint8_t)s[i];
+        putchar(c);
+    }
+"
+        ));
+        assert!(c.contains(
+            "    for (size_t i_1 = 0; i_1 < sizeof \"!\" - 1; i_1++) {
+        const uint8_t i_2 = (uint8_t)\"!\"[i_1];
+        putchar(i_2);
+"
+        ));
+
+        let c = generate("let s = \"hi\"; print(s); print(\"!\\n\"); let t = 1; print(t);");
+        assert!(c.contains(
+            "    fwrite(s, 1, sizeof s - 1, stdout);
+    fwrite(\"!\\012\", 1, sizeof \"!\\012\" - 1, stdout);
+"
+        ));
+        assert!(c.contains("    putchar(t);\n"));
+    }
+
+    #[test]
+    fn test_shadowing_and_reserved_names() {
+        let c = generate(
+            "let int = 1; let x = int; let x = x + 1; let x_1 = 0;
+             if x == 2 { let x = 7; print(x); } print(x + x_1);",
+        );
+        assert!(c.contains("const uint8_t int_ = 1;"));
+        assert!(c.contains("const uint8_t x_1 = (uint8_t)(x + 1);"));
+        assert!(c.contains("const uint8_t x_1_1 = 0;"));
+        assert!(c.contains("const uint8_t x_2 = 7;"));
+        assert!(c.contains("putchar(x_2);"));
+        assert!(c.contains("    putchar((uint8_t)(x_1 + x_1_1));\n    return 0;\n}\n"));
+    }
+}
@@ -0,0 +1,86 @@
+//! Code generation backends.
+//!
+//! The frontend and semantic analysis produce a checked [`Program`]; a
+//! [`CodeGenerator`] turns it into the text of some target language. Adding a
+//! target means implementing the trait and registering it in [`create`].
+
+pub mod c;
+pub mod ook;
+pub mod rust;
+pub mod wat;
+pub mod whitespace;
+
+use crate::ast::Program;
+use crate::codegen::BrainfuckGenerator;
+use crate::error::{TranspilerError, TranspilerResult};
+use alloc::{boxed::Box, collections::BTreeMap, string::String};
+use core::fmt;
+
+pub trait CodeGenerator: Send {
+    /// Name used to select the backend with `--target`.
+    fn target(&self) -> &'static str;
+
+    /// Extension of the file the output is written to.
+    fn extension(&self) -> &'static str;
+
+    fn generate(&mut self, program: &Program) -> TranspilerResult<String>;
+
+    /// Like [`generate`](Self::generate), passing the code on to `sink`.
+    /// Targets that build their output whole write it all at the end.
+    fn generate_into(
+        &mut self,
+        program: &Program,
+        sink: &mut dyn fmt::Write,
+    ) -> TranspilerResult<()> {
+        let code = self.generate(program)?;
+        sink.write_str(&code).map_err(|_| write_error())
+    }
+
+    /// `text` as a comment to put at the start of the output, or `None` if
+    /// the target has no room for one. Ook! and Whitespace give meaning to
+    /// nearly any text, so they have none.
+    fn comment(&self, _text: &str) -> Option<String> {
+        None
+    }
+
+    /// What each tape cell of the last generated program holds, by the
+    /// name of the variable, for targets that have a tape to describe.
+    fn cell_names(&self) -> BTreeMap<usize, String> {
+        BTreeMap::new()
+    }
+}
+
+/// The error for a sink that would not take the generated code.
+pub(crate) fn write_error() -> TranspilerError {
+    TranspilerError::new("could not write the generated code")
+}
+
+/// Every target accepted by [`create`].
+pub const TARGETS: &[&str] = &["bf", "c", "ook", "rust", "wat", "whitespace"];
+
+/// Instantiates the backend for `target`, if there is one.
+pub fn create(target: &str) -> Option<Box<dyn CodeGenerator>> {
+    match target {
+        "bf" => Some(Box::new(BrainfuckGenerator::new())),
+        "c" => Some(Box::new(c::CGenerator::new())),
+        "ook" => Some(Box::new(ook::OokGenerator::new())),
+        "rust" => Some(Box::new(rust::RustGenerator::new())),
+        "wat" => Some(Box::new(wat::WatGenerator::new())),
+        "whitespace" => Some(Box::new(whitespace::WhitespaceGenerator::new())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_listed_target_exists() {
+        for target in TARGETS {
+            let backend = create(target).unwrap();
+            assert_eq!(backend.target(), *target);
+        }
+        assert!(create("cobol").is_none());
+    }
+}
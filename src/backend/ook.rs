@@ -0,0 +1,87 @@
+//! An Ook! backend.
+//!
+//! Ook! is Brainfuck with each command spelled as a pair of orangutan words,
+//! so this is the optimized Brainfuck with every command replaced.
+
+use super::CodeGenerator;
+use crate::ast::Program;
+use crate::codegen::BrainfuckGenerator;
+use crate::error::TranspilerResult;
+use crate::ir;
+use crate::optimizer::PassManager;
+use alloc::string::String;
+
+/// Commands written on one line of output.
+const COMMANDS_PER_LINE: usize = 8;
+
+#[derive(Default)]
+pub struct OokGenerator;
+
+impl OokGenerator {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl CodeGenerator for OokGenerator {
+    fn target(&self) -> &'static str {
+        "ook"
+    }
+
+    fn extension(&self) -> &'static str {
+        "ook"
+    }
+
+    fn generate(&mut self, program: &Program) -> TranspilerResult<String> {
+        let code = BrainfuckGenerator::new().generate(program)?;
+        let mut ops = ir::parse(&code)?;
+        PassManager::new().run(&mut ops);
+        Ok(translate(&ir::emit(&ops)))
+    }
+}
+
+/// The Ook! spelling of a Brainfuck command.
+fn word(command: char) -> Option<&'static str> {
+    Some(match command {
+        '>' => "Ook. Ook?",
+        '<' => "Ook? Ook.",
+        '+' => "Ook. Ook.",
+        '-' => "Ook! Ook!",
+        '.' => "Ook! Ook.",
+        ',' => "Ook. Ook!",
+        '[' => "Ook! Ook?",
+        ']' => "Ook? Ook!",
+        _ => return None,
+    })
+}
+
+/// Rewrites Brainfuck as Ook!, dropping anything that is not a command.
+pub fn translate(code: &str) -> String {
+    let mut out = String::new();
+    for (i, word) in code.chars().filter_map(word).enumerate() {
+        if i > 0 {
+            out.push(if i % COMMANDS_PER_LINE == 0 {
+                '\n'
+            } else {
+                ' '
+            });
+        }
+        out.push_str(word);
+    }
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translate() {
+        assert_eq!(
+            translate("+[-]> comment .,<"),
+            "Ook. Ook. Ook! Ook? Ook! Ook! Ook? Ook! Ook. Ook? Ook! Ook. Ook. Ook! Ook? Ook.\n"
+        );
+        assert_eq!(translate("+++++++++").lines().count(), 2);
+    }
+}
@@ -0,0 +1,627 @@
+//! A Rust backend.
+//!
+//! Emits an ordinary Rust program equivalent to the input: values are `u8`
+//! with wrapping arithmetic, like the Brainfuck cells, comparisons are
+//! `bool` and strings are byte strings. Reading it back shows what the frontend understood, and building
+//! it gives a native executable to compare the Brainfuck against.
+
+use super::CodeGenerator;
+use crate::ast::{self, BinaryOp, Expr, Function, Pattern, Program, Stmt};
+use crate::error::TranspilerResult;
+use crate::symbol_table::Type;
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+/// Rust keywords that mini-rust accepts as identifiers.
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern", "false",
+    "fn", "for", "impl", "in", "loop", "match", "mod", "move", "pub", "ref", "return", "static",
+    "struct", "trait", "true", "type", "unsafe", "use", "where", "abstract", "become", "box", "do",
+    "final", "gen", "macro", "override", "priv", "try", "typeof", "unsized", "virtual", "yield",
+];
+
+/// Keywords that cannot be written as raw identifiers.
+const NOT_RAW: &[&str] = &["crate", "self", "Self", "super"];
+
+const PRELUDE: &str = "use std::io::Write;
+
+fn print(byte: u8) {
+    std::io::stdout().write_all(&[byte]).unwrap();
+}
+
+fn main() {
+";
+
+/// Written after `main` for programs that read input.
+const INPUT: &str = "
+fn input() -> u8 {
+    std::io::stdout().flush().unwrap();
+    let mut byte = [0];
+    match std::io::Read::read(&mut std::io::stdin(), &mut byte) {
+        Ok(1) => byte[0],
+        _ => 0,
+    }
+}
+";
+
+pub struct RustGenerator {
+    output: String,
+    indent: usize,
+    /// Type of each variable in scope, innermost scope last.
+    scopes: Vec<BTreeMap<String, Type>>,
+}
+
+impl Default for RustGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RustGenerator {
+    pub fn new() -> Self {
+        Self {
+            output: String::new(),
+            indent: 0,
+            scopes: vec![BTreeMap::new()],
+        }
+    }
+
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.indent {
+            self.output.push_str("    ");
+        }
+        self.output.push_str(text);
+        self.output.push('\n');
+    }
+
+    fn block(&mut self, body: &[Stmt]) {
+        self.indent += 1;
+        self.scopes.push(BTreeMap::new());
+        for stmt in body {
+            self.stmt(stmt);
+        }
+        self.scopes.pop();
+        self.indent -= 1;
+    }
+
+    /// Writes an `if`, continuing with `else if` for as long as each
+    /// `else` body is another `if` alone.
+    fn if_chain<'a>(
+        &mut self,
+        mut condition: &'a Expr,
+        mut body: &'a [Stmt],
+        mut else_body: &'a [Stmt],
+    ) {
+        let mut prefix = "";
+        loop {
+            let line = format!("{}if {} {{", prefix, self.condition(condition));
+            self.line(&line);
+            self.block(body);
+            match else_body {
+                [] => break,
+                [
+                    Stmt::If {
+                        condition: next,
+                        body: next_body,
+                        else_body: next_else,
+                        ..
+                    },
+                ] => {
+                    (condition, body, else_body) = (next, next_body, next_else);
+                    prefix = "} else ";
+                }
+                _ => {
+                    self.line("} else {");
+                    self.block(else_body);
+                    break;
+                }
+            }
+        }
+        self.line("}");
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Let {
+                name,
+                mutable,
+                value,
+                ..
+            } => {
+                let ty = self.type_of(value);
+                let line = format!(
+                    "let {}{}: {} = {};",
+                    if *mutable { "mut " } else { "" },
+                    identifier(name),
+                    rust_type(ty),
+                    self.expr(value)
+                );
+                self.line(&line);
+                let scope = self.scopes.last_mut().expect("global scope");
+                scope.insert(name.clone(), ty);
+            }
+            Stmt::Assign { name, value, .. } => {
+                let line = format!("{} = {};", identifier(name), self.expr(value));
+                self.line(&line);
+            }
+            Stmt::Print { value, .. } => {
+                let line = match self.type_of(value) {
+                    Type::Str => format!(
+                        "std::io::stdout().write_all({}).unwrap();",
+                        self.expr(value)
+                    ),
+                    _ => format!("print({});", self.byte(value)),
+                };
+                self.line(&line);
+            }
+            Stmt::PrintNum { value, .. } => {
+                let line = format!("print!(\"{{}}\", {});", self.byte(value));
+                self.line(&line);
+            }
+            Stmt::If {
+                condition,
+                body,
+                else_body,
+                ..
+            } => self.if_chain(condition, body, else_body),
+            // Patterns are written as the cell values they match, and a
+            // `match` without `_` gets an empty one, as Rust wants every
+            // value covered.
+            Stmt::Match {
+                scrutinee, arms, ..
+            } => {
+                let line = format!("match {} {{", self.byte(scrutinee));
+                self.line(&line);
+                self.indent += 1;
+                for arm in arms {
+                    let pattern = match arm.pattern.value() {
+                        Some(value) => value.to_string(),
+                        None => "_".to_string(),
+                    };
+                    self.line(&format!("{} => {{", pattern));
+                    self.block(&arm.body);
+                    self.line("}");
+                }
+                if !arms.iter().any(|arm| arm.pattern == Pattern::Wildcard) {
+                    self.line("_ => {}");
+                }
+                self.indent -= 1;
+                self.line("}");
+            }
+            Stmt::While {
+                condition, body, ..
+            } => {
+                let line = format!("while {} {{", self.condition(condition));
+                self.line(&line);
+                self.block(body);
+                self.line("}");
+            }
+            Stmt::Loop { body, .. } => {
+                self.line("loop {");
+                self.block(body);
+                self.line("}");
+            }
+            // Rust has no do-while, so the condition is tested at the top
+            // of every pass but the first. That way `continue` still tests
+            // it, and it never sees the body's bindings.
+            Stmt::DoWhile {
+                body, condition, ..
+            } => {
+                let mut first = String::from("first_pass");
+                while self.scopes.iter().any(|scope| scope.contains_key(&first)) {
+                    first.push('_');
+                }
+                self.line(&format!("let mut {} = true;", first));
+                let line = format!("while {} || {} {{", first, self.condition(condition));
+                self.line(&line);
+                self.indent += 1;
+                self.line(&format!("{} = false;", first));
+                self.indent -= 1;
+                self.block(body);
+                self.line("}");
+            }
+            Stmt::For {
+                name,
+                iterable,
+                body,
+                ..
+            } => {
+                let line = format!("for &{} in {} {{", identifier(name), self.expr(iterable));
+                self.line(&line);
+                self.scopes
+                    .push(BTreeMap::from([(name.clone(), Type::Int)]));
+                self.block(body);
+                self.scopes.pop();
+                self.line("}");
+            }
+            Stmt::Break { .. } => self.line("break;"),
+            Stmt::Continue { .. } => self.line("continue;"),
+            // Written after `main`.
+            Stmt::Function(_) => {}
+            Stmt::Return { value, .. } => {
+                let value = value
+                    .as_ref()
+                    .map_or("0".to_string(), |value| self.byte(value));
+                self.line(&format!("return {};", value));
+            }
+            Stmt::Expr { value, .. } => {
+                let line = format!("{};", self.expr(value));
+                self.line(&line);
+            }
+        }
+    }
+
+    /// Writes `function` as a Rust function taking and returning `u8`,
+    /// which returns 0 if it ends without a `return`.
+    fn function(&mut self, function: &Function) {
+        let params: Vec<_> = function
+            .params
+            .iter()
+            .map(|param| {
+                let assigned = function.body.iter().any(|stmt| stmt.assigns(param));
+                let mutable = if assigned { "mut " } else { "" };
+                format!("{}{}: u8", mutable, identifier(param))
+            })
+            .collect();
+        self.line("");
+        self.line(&format!(
+            "fn {}({}) -> u8 {{",
+            function_name(&function.name),
+            params.join(", ")
+        ));
+        let scope = function
+            .params
+            .iter()
+            .map(|param| (param.clone(), Type::Int))
+            .collect();
+        let scopes = core::mem::replace(&mut self.scopes, vec![scope]);
+        self.block(&function.body);
+        self.scopes = scopes;
+        if !matches!(function.body.last(), Some(Stmt::Return { .. })) {
+            self.indent += 1;
+            self.line("0");
+            self.indent -= 1;
+        }
+        self.line("}");
+    }
+
+    fn type_of(&self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Number(_) => Type::Int,
+            Expr::Bool(_) => Type::Bool,
+            Expr::Str(_) => Type::Str,
+            Expr::Variable(name) => self
+                .scopes
+                .iter()
+                .rev()
+                .find_map(|scope| scope.get(name))
+                .copied()
+                .unwrap_or(Type::Int),
+            Expr::Unary { .. } => Type::Bool,
+            Expr::Binary { operator, .. } => match operator {
+                BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => {
+                    Type::Int
+                }
+                BinaryOp::Equal
+                | BinaryOp::NotEqual
+                | BinaryOp::Less
+                | BinaryOp::Greater
+                | BinaryOp::LessEqual
+                | BinaryOp::GreaterEqual
+                | BinaryOp::And
+                | BinaryOp::Or => Type::Bool,
+            },
+            Expr::Call { .. } | Expr::Input => Type::Int,
+        }
+    }
+
+    /// `expr` as a `u8`, the way it is stored in a cell.
+    fn byte(&self, expr: &Expr) -> String {
+        match self.type_of(expr) {
+            Type::Int | Type::Str => self.expr(expr),
+            Type::Bool => format!("{} as u8", self.operand(expr)),
+        }
+    }
+
+    /// A loop or `if` condition; any nonzero integer counts as true.
+    fn condition(&self, expr: &Expr) -> String {
+        match self.type_of(expr) {
+            Type::Bool => self.expr(expr),
+            Type::Int | Type::Str => format!("{} != 0", self.expr(expr)),
+        }
+    }
+
+    fn expr(&self, expr: &Expr) -> String {
+        match expr {
+            Expr::Number(value) => value.rem_euclid(256).to_string(),
+            Expr::Bool(value) => value.to_string(),
+            Expr::Str(text) => byte_string(text),
+            Expr::Variable(name) => identifier(name),
+            Expr::Unary { operator, operand } => {
+                format!("{}{}", operator.symbol(), self.operand(operand))
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                let method = match operator {
+                    BinaryOp::Add => "wrapping_add",
+                    BinaryOp::Sub => "wrapping_sub",
+                    BinaryOp::Mul => "wrapping_mul",
+                    BinaryOp::Div => "wrapping_div",
+                    BinaryOp::Mod => "wrapping_rem",
+                    BinaryOp::Equal
+                    | BinaryOp::NotEqual
+                    | BinaryOp::Less
+                    | BinaryOp::Greater
+                    | BinaryOp::LessEqual
+                    | BinaryOp::GreaterEqual
+                    | BinaryOp::And
+                    | BinaryOp::Or => {
+                        return format!(
+                            "{} {} {}",
+                            self.operand(left),
+                            operator.symbol(),
+                            self.operand(right)
+                        );
+                    }
+                };
+                let receiver = match left.as_ref() {
+                    Expr::Number(value) => format!("{}u8", value.rem_euclid(256)),
+                    _ => self.expr(left),
+                };
+                format!("{}.{}({})", receiver, method, self.expr(right))
+            }
+            Expr::Call { name, args } => {
+                let args: Vec<_> = args.iter().map(|arg| self.byte(arg)).collect();
+                format!("{}({})", function_name(name), args.join(", "))
+            }
+            Expr::Input => "input()".to_string(),
+        }
+    }
+
+    /// An operand of a comparison or cast; comparisons need parentheses
+    /// there.
+    fn operand(&self, expr: &Expr) -> String {
+        match self.type_of(expr) {
+            Type::Bool if matches!(expr, Expr::Binary { .. }) => format!("({})", self.expr(expr)),
+            _ => self.expr(expr),
+        }
+    }
+}
+
+fn rust_type(ty: Type) -> &'static str {
+    match ty {
+        Type::Int => "u8",
+        Type::Bool => "bool",
+        Type::Str => "&[u8]",
+    }
+}
+
+/// `text` as a Rust byte string literal.
+fn byte_string(text: &str) -> String {
+    let mut out = String::from("b\"");
+    for byte in text.bytes() {
+        match byte {
+            b'"' | b'\\' => {
+                out.push('\\');
+                out.push(byte as char);
+            }
+            b' '..=b'~' => out.push(byte as char),
+            _ => out.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// `name` as a Rust identifier, escaping keywords.
+fn identifier(name: &str) -> String {
+    if NOT_RAW.contains(&name) {
+        format!("{}_", name)
+    } else if KEYWORDS.contains(&name) {
+        format!("r#{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// `name` as the name of a Rust function, clear of the ones the prelude
+/// defines.
+fn function_name(name: &str) -> String {
+    match name {
+        "main" | "print" => format!("{}_", name),
+        _ => identifier(name),
+    }
+}
+
+impl CodeGenerator for RustGenerator {
+    fn target(&self) -> &'static str {
+        "rust"
+    }
+
+    fn extension(&self) -> &'static str {
+        "rs"
+    }
+
+    fn comment(&self, text: &str) -> Option<String> {
+        Some(format!("// {}\n", text.replace('\n', " ")))
+    }
+
+    fn generate(&mut self, program: &Program) -> TranspilerResult<String> {
+        *self = Self::new();
+        self.output.push_str(PRELUDE);
+        self.block(program);
+        self.line("}");
+        for function in ast::functions(program).into_values() {
+            self.function(function);
+        }
+        if program.iter().any(Stmt::reads_input) {
+            self.output.push_str(INPUT);
+        }
+        Ok(core::mem::take(&mut self.output))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn generate(source: &str) -> String {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        RustGenerator::new().generate(&program).unwrap()
+    }
+
+    #[test]
+    fn test_program() {
+        let rust = generate("let x = 5; let mut n = 3; while n > 0 { print(x + n); n = n - 1; }");
+        assert_eq!(
+            rust.strip_prefix(PRELUDE).unwrap(),
+            "    let x: u8 = 5;
+    let mut n: u8 = 3;
+    while n > 0 {
+        print(x.wrapping_add(n));
+        n = n.wrapping_sub(1);
+    }
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_else_if_chain() {
+        let rust = generate(
+            "let a = 3; if a == 1 { print(1); } else if a == 2 { print(2); }
+             else if a == 3 { print(3); } else { if a == 4 { print(4); } print(5); }",
+        );
+        assert!(rust.contains(
+            "    if a == 1 {
+        print(1);
+    } else if a == 2 {
+        print(2);
+    } else if a == 3 {
+        print(3);
+    } else {
+        if a == 4 {
+            print(4);
+        }
+        print(5);
+    }
+"
+        ));
+    }
+
+    #[test]
+    fn test_do_while() {
+        let rust = generate(
+            "let mut first_pass = 2; loop { let n = 1; first_pass = first_pass - n; } while first_pass;",
+        );
+        assert!(rust.contains(
+            "    let mut first_pass_ = true;
+    while first_pass_ || first_pass != 0 {
+        first_pass_ = false;
+        let n: u8 = 1;
+        first_pass = first_pass.wrapping_sub(n);
+    }
+"
+        ));
+    }
+
+    #[test]
+    fn test_match() {
+        let rust = generate("let x = 2; match x == 2 { 1 => { print(1); } -255 => { } }");
+        assert!(rust.contains(
+            "    match (x == 2) as u8 {
+        1 => {
+            print(1);
+        }
+        1 => {
+        }
+        _ => {}
+    }
+"
+        ));
+        assert!(!generate("match 1 { _ => { } }").contains("_ => {}"));
+    }
+
+    #[test]
+    fn test_functions() {
+        let rust = generate(
+            "fn main(type) { return type == 1; } fn noop() { } \
+             let b = main(1); noop(); print(main(b == 1));",
+        );
+        assert!(rust.ends_with(
+            "    let b: u8 = main_(1);
+    noop();
+    print(main_((b == 1) as u8));
+}
+
+fn main_(r#type: u8) -> u8 {
+    return (r#type == 1) as u8;
+}
+
+fn noop() -> u8 {
+    0
+}
+"
+        ));
+
+        let rust = generate("fn double(n) { n = n * 2; return n; } print(double(3));");
+        assert!(rust.contains("fn double(mut n: u8) -> u8 {"));
+    }
+
+    #[test]
+    fn test_types() {
+        let rust = generate(
+            "let a = 2; let b = a == 1; if b == (a < 3) { print(b); } while a { } print(2 * a);
+             print_num(b);",
+        );
+        assert!(rust.contains("let b: bool = a == 1;"));
+        assert!(rust.contains("if b == (a < 3) {"));
+        assert!(rust.contains("print(b as u8);"));
+        assert!(rust.contains("while a != 0 {"));
+        assert!(rust.contains("print(2u8.wrapping_mul(a));"));
+        assert!(rust.contains("print!(\"{}\", b as u8);"));
+    }
+
+    #[test]
+    fn test_input() {
+        let rust = generate("let c = input(); print(c + input());");
+        assert!(rust.contains("fn input() -> u8 {"));
+        assert!(rust.contains("    let c: u8 = input();\n"));
+        assert!(rust.contains("    print(c.wrapping_add(input()));\n"));
+        assert!(!generate("print(1);").contains("input"));
+    }
+
+    #[test]
+    fn test_strings() {
+        let rust =
+            generate("let s = \"a\\\"\\n\"; for c in s { print(c + 1); } for c in \"!\" { }");
+        assert!(rust.contains("    let s: &[u8] = b\"a\\\"\\x0a\";\n"));
+        assert!(rust.contains("    for &c in s {\n        print(c.wrapping_add(1));\n    }\n"));
+        assert!(rust.contains("    for &c in b\"!\" {\n"));
+
+        let rust = generate("let s = \"hi\"; print(s); print(\"!\");");
+        assert!(rust.contains(
+            "    std::io::stdout().write_all(s).unwrap();
+    std::io::stdout().write_all(b\"!\").unwrap();
+"
+        ));
+    }
+
+    #[test]
+    fn test_keywords() {
+        let rust = generate("let type = 1; let self = type; print(self);");
+        assert!(rust.contains("let r#type: u8 = 1;"));
+        assert!(rust.contains("let self_: u8 = r#type;"));
+    }
+}
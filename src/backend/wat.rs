@@ -0,0 +1,177 @@
+//! A WebAssembly text backend.
+//!
+//! Programs are compiled to Brainfuck and optimized as usual, and the
+//! resulting [`ir`](crate::ir) is translated op by op into a WASI command
+//! module: linear memory holds the tape, and `.`/`,` go through `fd_write`
+//! and `fd_read`. The module runs as is in wasmtime, or in a browser with a
+//! WASI shim.
+
+use super::CodeGenerator;
+use crate::ast::Program;
+use crate::codegen::BrainfuckGenerator;
+use crate::error::TranspilerResult;
+use crate::ir::{self, Op};
+use crate::optimizer::PassManager;
+use crate::verify;
+use alloc::{format, string::String};
+use core::fmt::Write;
+
+/// Cells on the tape, the classic Brainfuck size.
+const TAPE_SIZE: usize = 30_000;
+
+#[derive(Default)]
+pub struct WatGenerator;
+
+impl WatGenerator {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl CodeGenerator for WatGenerator {
+    fn target(&self) -> &'static str {
+        "wat"
+    }
+
+    fn extension(&self) -> &'static str {
+        "wat"
+    }
+
+    fn comment(&self, text: &str) -> Option<String> {
+        Some(format!(";; {}\n", text.replace('\n', " ")))
+    }
+
+    fn generate(&mut self, program: &Program) -> TranspilerResult<String> {
+        let code = BrainfuckGenerator::new().generate(program)?;
+        let mut ops = ir::parse(&code)?;
+        PassManager::new().run(&mut ops);
+        verify::check_tape_size(&ops, TAPE_SIZE).map_err(|error| {
+            error.with_note("help: `--target c` and `--target rust` have no fixed tape")
+        })?;
+        Ok(emit(&ops))
+    }
+}
+
+/// Renders a Brainfuck program as a WASI command module exporting `_start`.
+pub fn emit(ops: &[Op]) -> String {
+    // The iovec and byte count used for I/O sit just past the tape.
+    let iovec = TAPE_SIZE;
+    let count = iovec + 8;
+    let pages = (count + 4).div_ceil(65536);
+
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        r#"(module
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read"
+    (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (memory (export "memory") {pages})
+
+  ;; Writes the cell at $ptr to stdout.
+  (func $output (param $ptr i32)
+    (i32.store (i32.const {iovec}) (local.get $ptr))
+    (i32.store (i32.const {len}) (i32.const 1))
+    (drop (call $fd_write (i32.const 1) (i32.const {iovec}) (i32.const 1) (i32.const {count}))))
+
+  ;; Reads a byte from stdin into the cell at $ptr, or 0 at end of input.
+  (func $input (param $ptr i32)
+    (i32.store8 (local.get $ptr) (i32.const 0))
+    (i32.store (i32.const {iovec}) (local.get $ptr))
+    (i32.store (i32.const {len}) (i32.const 1))
+    (drop (call $fd_read (i32.const 0) (i32.const {iovec}) (i32.const 1) (i32.const {count}))))
+
+  (func (export "_start")
+    (local $ptr i32)
+"#,
+        pages = pages,
+        iovec = iovec,
+        len = iovec + 4,
+        count = count,
+    );
+    let mut labels = 0;
+    emit_ops(ops, 2, &mut labels, &mut out);
+    out.push_str("  )\n)\n");
+    out
+}
+
+fn emit_ops(ops: &[Op], depth: usize, labels: &mut usize, out: &mut String) {
+    let indent = "  ".repeat(depth);
+    for op in ops {
+        match op {
+            Op::Add(n) => {
+                let _ = writeln!(
+                    out,
+                    "{}(i32.store8 (local.get $ptr) (i32.add (i32.load8_u (local.get $ptr)) (i32.const {})))",
+                    indent, n
+                );
+            }
+            Op::Move(n) => {
+                let _ = writeln!(
+                    out,
+                    "{}(local.set $ptr (i32.add (local.get $ptr) (i32.const {})))",
+                    indent, n
+                );
+            }
+            Op::Clear => {
+                let _ = writeln!(out, "{}(i32.store8 (local.get $ptr) (i32.const 0))", indent);
+            }
+            Op::Output => {
+                let _ = writeln!(out, "{}(call $output (local.get $ptr))", indent);
+            }
+            Op::Input => {
+                let _ = writeln!(out, "{}(call $input (local.get $ptr))", indent);
+            }
+            Op::Loop(body) => {
+                let label = *labels;
+                *labels += 1;
+                let _ = writeln!(out, "{}(block $done{}", indent, label);
+                let _ = writeln!(out, "{}  (loop $loop{}", indent, label);
+                let _ = writeln!(
+                    out,
+                    "{}    (br_if $done{} (i32.eqz (i32.load8_u (local.get $ptr))))",
+                    indent, label
+                );
+                emit_ops(body, depth + 2, labels, out);
+                let _ = writeln!(out, "{}    (br $loop{})))", indent, label);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_ops() {
+        let wat = emit(&[Op::Add(3), Op::Loop(vec![Op::Move(-1), Op::Output])]);
+        assert!(wat.contains("(export \"_start\")"));
+        assert!(wat.contains(
+            "    (i32.store8 (local.get $ptr) (i32.add (i32.load8_u (local.get $ptr)) (i32.const 3)))\n"
+        ));
+        assert!(wat.contains(
+            "    (block $done0
+      (loop $loop0
+        (br_if $done0 (i32.eqz (i32.load8_u (local.get $ptr))))
+        (local.set $ptr (i32.add (local.get $ptr) (i32.const -1)))
+        (call $output (local.get $ptr))
+        (br $loop0)))
+"
+        ));
+        assert_eq!(wat.matches('(').count(), wat.matches(')').count());
+    }
+
+    #[test]
+    fn test_program_larger_than_tape() {
+        use crate::ast::{Expr, Stmt};
+
+        let text = "x".repeat(TAPE_SIZE);
+        let program = vec![Stmt::let_stmt("s", false, Expr::string(&text))];
+        let error = WatGenerator::new().generate(&program).unwrap_err();
+        assert!(error.message.starts_with("Program needs 300"));
+        assert!(error.notes[0].contains("--target c"));
+    }
+}
@@ -0,0 +1,277 @@
+//! A Whitespace backend.
+//!
+//! Whitespace has a stack and a heap instead of a tape, so the optimized IR
+//! is lowered to it: heap address 0 holds the tape pointer and cell `i`
+//! lives at address `i + 1`. Every cell is zeroed on startup, since
+//! interpreters disagree on reading an address that was never stored to.
+
+use super::CodeGenerator;
+use crate::ast::Program;
+use crate::codegen::BrainfuckGenerator;
+use crate::error::TranspilerResult;
+use crate::ir::{self, Op};
+use crate::optimizer::PassManager;
+use crate::verify;
+use alloc::{string::String, vec::Vec};
+
+/// Cells on the tape, the classic Brainfuck size.
+const TAPE_SIZE: i64 = 30_000;
+
+/// Heap address of the tape pointer.
+const POINTER: i64 = 0;
+
+/// The Whitespace instructions the backend uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Instr {
+    Push(i64),
+    Dup,
+    Add,
+    Sub,
+    Mod,
+    Store,
+    Retrieve,
+    Mark(usize),
+    Jump(usize),
+    JumpIfZero(usize),
+    OutputChar,
+    ReadChar,
+    End,
+}
+
+#[derive(Default)]
+pub struct WhitespaceGenerator;
+
+impl WhitespaceGenerator {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl CodeGenerator for WhitespaceGenerator {
+    fn target(&self) -> &'static str {
+        "whitespace"
+    }
+
+    fn extension(&self) -> &'static str {
+        "ws"
+    }
+
+    fn generate(&mut self, program: &Program) -> TranspilerResult<String> {
+        let code = BrainfuckGenerator::new().generate(program)?;
+        let mut ops = ir::parse(&code)?;
+        PassManager::new().run(&mut ops);
+        verify::check_tape_size(&ops, TAPE_SIZE as usize).map_err(|error| {
+            error.with_note("help: `--target c` and `--target rust` have no fixed tape")
+        })?;
+        Ok(emit(&ops))
+    }
+}
+
+/// Renders a Brainfuck program as Whitespace.
+pub fn emit(ops: &[Op]) -> String {
+    encode(&lower(ops))
+}
+
+fn lower(ops: &[Op]) -> Vec<Instr> {
+    use Instr::*;
+
+    // Zero cells TAPE_SIZE down to 1, using the pointer as the counter,
+    // then point at cell 0.
+    let mut out = Vec::from([
+        Push(POINTER),
+        Push(TAPE_SIZE),
+        Store,
+        Mark(0),
+        Push(POINTER),
+        Retrieve,
+        Push(0),
+        Store,
+        Push(POINTER),
+        Push(POINTER),
+        Retrieve,
+        Push(1),
+        Sub,
+        Store,
+        Push(POINTER),
+        Retrieve,
+        JumpIfZero(1),
+        Jump(0),
+        Mark(1),
+        Push(POINTER),
+        Push(1),
+        Store,
+    ]);
+    let mut labels = 2;
+    lower_into(ops, &mut labels, &mut out);
+    out.push(End);
+    out
+}
+
+fn lower_into(ops: &[Op], labels: &mut usize, out: &mut Vec<Instr>) {
+    use Instr::*;
+
+    // Pushes the heap address of the current cell.
+    const CELL: [Instr; 2] = [Push(POINTER), Retrieve];
+
+    for op in ops {
+        match op {
+            Op::Add(n) => {
+                out.extend(CELL);
+                out.extend([Dup, Retrieve, Push(*n as i64), Add, Push(256), Mod, Store]);
+            }
+            Op::Move(n) => out.extend([
+                Push(POINTER),
+                Push(POINTER),
+                Retrieve,
+                Push(*n as i64),
+                Add,
+                Store,
+            ]),
+            Op::Clear => {
+                out.extend(CELL);
+                out.extend([Push(0), Store]);
+            }
+            Op::Output => {
+                out.extend(CELL);
+                out.extend([Retrieve, OutputChar]);
+            }
+            Op::Input => {
+                // Clear the cell first so that end of input reads as 0 where
+                // the interpreter leaves the cell alone.
+                out.extend(CELL);
+                out.extend([Dup, Push(0), Store, ReadChar]);
+            }
+            Op::Loop(body) => {
+                let (start, end) = (*labels, *labels + 1);
+                *labels += 2;
+                out.push(Mark(start));
+                out.extend(CELL);
+                out.extend([Retrieve, JumpIfZero(end)]);
+                lower_into(body, labels, out);
+                out.extend([Jump(start), Mark(end)]);
+            }
+        }
+    }
+}
+
+fn encode(instrs: &[Instr]) -> String {
+    let mut out = String::new();
+    for instr in instrs {
+        let (command, parameter) = match *instr {
+            Instr::Push(n) => ("  ", Some(number(n))),
+            Instr::Dup => (" \n ", None),
+            Instr::Add => ("\t   ", None),
+            Instr::Sub => ("\t  \t", None),
+            Instr::Mod => ("\t \t\t", None),
+            Instr::Store => ("\t\t ", None),
+            Instr::Retrieve => ("\t\t\t", None),
+            Instr::Mark(label) => ("\n  ", Some(bits(label as u64))),
+            Instr::Jump(label) => ("\n \n", Some(bits(label as u64))),
+            Instr::JumpIfZero(label) => ("\n\t ", Some(bits(label as u64))),
+            Instr::OutputChar => ("\t\n  ", None),
+            Instr::ReadChar => ("\t\n\t ", None),
+            Instr::End => ("\n\n\n", None),
+        };
+        out.push_str(command);
+        if let Some(parameter) = parameter {
+            out.push_str(&parameter);
+        }
+    }
+    out
+}
+
+/// A signed number: the sign, then its binary digits, then a line feed.
+fn number(n: i64) -> String {
+    let mut out = String::from(if n < 0 { "\t" } else { " " });
+    out.push_str(&bits(n.unsigned_abs()));
+    out
+}
+
+/// Binary digits, most significant first (space for 0, tab for 1), then a
+/// line feed. Also used for labels.
+fn bits(n: u64) -> String {
+    let width = (u64::BITS - n.leading_zeros()).max(1);
+    let mut out: String = (0..width)
+        .rev()
+        .map(|bit| if n >> bit & 1 == 1 { '\t' } else { ' ' })
+        .collect();
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::collections::BTreeMap;
+
+    /// Runs lowered instructions, returning what they print.
+    fn run(instrs: &[Instr], mut input: &[u8]) -> Vec<u8> {
+        let marks: BTreeMap<usize, usize> = instrs
+            .iter()
+            .enumerate()
+            .filter_map(|(pc, instr)| match instr {
+                Instr::Mark(label) => Some((*label, pc)),
+                _ => None,
+            })
+            .collect();
+        let (mut stack, mut heap, mut output) = (Vec::new(), BTreeMap::new(), Vec::new());
+        let mut pc = 0;
+        loop {
+            match instrs[pc] {
+                Instr::Push(n) => stack.push(n),
+                Instr::Dup => stack.push(*stack.last().unwrap()),
+                Instr::Add | Instr::Sub | Instr::Mod => {
+                    let (b, a) = (stack.pop().unwrap(), stack.pop().unwrap());
+                    stack.push(match instrs[pc] {
+                        Instr::Add => a + b,
+                        Instr::Sub => a - b,
+                        _ => a.rem_euclid(b),
+                    });
+                }
+                Instr::Store => {
+                    let (value, address) = (stack.pop().unwrap(), stack.pop().unwrap());
+                    heap.insert(address, value);
+                }
+                Instr::Retrieve => {
+                    let address = stack.pop().unwrap();
+                    stack.push(heap[&address]);
+                }
+                Instr::Mark(_) => {}
+                Instr::Jump(label) => pc = marks[&label],
+                Instr::JumpIfZero(label) => {
+                    if stack.pop().unwrap() == 0 {
+                        pc = marks[&label];
+                    }
+                }
+                Instr::OutputChar => output.push(stack.pop().unwrap() as u8),
+                Instr::ReadChar => {
+                    let address = stack.pop().unwrap();
+                    if let Some((&byte, rest)) = input.split_first() {
+                        heap.insert(address, byte as i64);
+                        input = rest;
+                    }
+                }
+                Instr::End => return output,
+            }
+            pc += 1;
+        }
+    }
+
+    #[test]
+    fn test_lowering_runs_like_brainfuck() {
+        let ops = ir::parse("++++++++[>++++++++<-]>+.+.<-.,.,[.,]").unwrap();
+        assert_eq!(run(&lower(&ops), b"xyz"), b"AB\xffxyz");
+    }
+
+    #[test]
+    fn test_encoding() {
+        assert_eq!(number(5), " \t \t\n");
+        assert_eq!(number(-1), "\t\t\n");
+        assert_eq!(number(0), "  \n");
+        assert_eq!(
+            encode(&[Instr::Push(1), Instr::OutputChar, Instr::End]),
+            "   \t\n\t\n  \n\n\n"
+        );
+        assert!(emit(&[]).chars().all(|c| matches!(c, ' ' | '\t' | '\n')));
+    }
+}
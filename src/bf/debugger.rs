@@ -0,0 +1,267 @@
+use super::interpreter::{Eof, Execution, Overflow, RuntimeError, is_command};
+use alloc::{collections::BTreeSet, vec, vec::Vec};
+
+/// A Brainfuck program being run one command at a time, which pauses
+/// whenever a watched cell changes. It behaves as
+/// [`Interpreter::run`](crate::bf::Interpreter::run) does, only more slowly.
+///
+/// ```
+/// use rusted_brains::bf::{CellWrite, Interpreter, Stop};
+///
+/// let mut debugger = Interpreter::new(">++<+>-").debugger().unwrap();
+/// debugger.watch(1);
+/// let write = |old, new, position| Stop::Write(CellWrite { cell: 1, old, new, position });
+/// assert_eq!(debugger.resume(), Ok(write(0, 1, 1)));
+/// assert_eq!(debugger.resume(), Ok(write(1, 2, 2)));
+/// assert_eq!(debugger.resume(), Ok(write(2, 1, 6)));
+/// assert_eq!(debugger.resume(), Ok(Stop::Finished));
+/// assert_eq!(debugger.into_execution().tape[..2], [1, 1]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Debugger {
+    code: Vec<u8>,
+    /// For every bracket, the offset of its partner.
+    jumps: Vec<usize>,
+    input: Vec<u8>,
+    /// How much of `input` has been read.
+    read: usize,
+    max_steps: Option<u64>,
+    overflow: Overflow,
+    eof: Eof,
+    watched: BTreeSet<usize>,
+    tape: Vec<u8>,
+    pointer: usize,
+    /// Offset of the next command to run, or the length of the code once
+    /// the program has finished.
+    pc: usize,
+    steps: u64,
+    output: Vec<u8>,
+}
+
+/// Why [`Debugger::step`] or [`Debugger::resume`] stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// A watched cell changed.
+    Write(CellWrite),
+    /// The program ran to its end.
+    Finished,
+}
+
+/// A change to a watched cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellWrite {
+    pub cell: usize,
+    pub old: u8,
+    pub new: u8,
+    /// Byte offset of the command that made the change.
+    pub position: usize,
+}
+
+impl Debugger {
+    pub(super) fn new(
+        code: Vec<u8>,
+        jumps: Vec<usize>,
+        input: Vec<u8>,
+        tape_size: usize,
+        max_steps: Option<u64>,
+        overflow: Overflow,
+        eof: Eof,
+    ) -> Self {
+        let mut debugger = Self {
+            code,
+            jumps,
+            input,
+            read: 0,
+            max_steps,
+            overflow,
+            eof,
+            watched: BTreeSet::new(),
+            tape: vec![0; tape_size],
+            pointer: 0,
+            pc: 0,
+            steps: 0,
+            output: Vec::new(),
+        };
+        debugger.skip_comments();
+        debugger
+    }
+
+    /// Pauses whenever `cell` changes. Commands that leave it as it was,
+    /// such as a `,` reading the value it already holds, do not count.
+    pub fn watch(&mut self, cell: usize) {
+        self.watched.insert(cell);
+    }
+
+    /// Runs one command.
+    pub fn step(&mut self) -> Result<Option<Stop>, RuntimeError> {
+        let pc = self.pc;
+        let Some(&command) = self.code.get(pc) else {
+            return Ok(Some(Stop::Finished));
+        };
+
+        if self.max_steps.is_some_and(|limit| self.steps >= limit) {
+            return Err(RuntimeError::StepLimitExceeded {
+                limit: self.max_steps.unwrap_or_default(),
+            });
+        }
+        self.steps += 1;
+
+        let cell = self.pointer;
+        let old = self.tape[cell];
+        match command {
+            b'+' | b'-' => {
+                let (value, wrapped) = match command {
+                    b'+' => old.overflowing_add(1),
+                    _ => old.overflowing_sub(1),
+                };
+                if wrapped && self.overflow == Overflow::Trap {
+                    return Err(RuntimeError::CellOverflow { position: pc });
+                }
+                self.tape[self.pointer] = value;
+            }
+            b'>' => {
+                self.pointer += 1;
+                if self.pointer == self.tape.len() {
+                    return Err(RuntimeError::PointerOverflow {
+                        position: pc,
+                        tape_size: self.tape.len(),
+                    });
+                }
+            }
+            b'<' => {
+                self.pointer = self
+                    .pointer
+                    .checked_sub(1)
+                    .ok_or(RuntimeError::PointerUnderflow { position: pc })?;
+            }
+            b'.' => self.output.push(old),
+            b',' => {
+                self.tape[self.pointer] = self.eof.read(self.input.get(self.read).copied(), old);
+                self.read += 1;
+            }
+            b'[' if old == 0 => self.pc = self.jumps[pc],
+            b']' if old != 0 => self.pc = self.jumps[pc],
+            _ => {}
+        }
+        self.pc += 1;
+        self.skip_comments();
+
+        let new = self.tape[cell];
+        if new != old && self.watched.contains(&cell) {
+            return Ok(Some(Stop::Write(CellWrite {
+                cell,
+                old,
+                new,
+                position: pc,
+            })));
+        }
+        Ok(None)
+    }
+
+    /// Runs until a watched cell changes or the program ends.
+    pub fn resume(&mut self) -> Result<Stop, RuntimeError> {
+        loop {
+            if let Some(stop) = self.step()? {
+                return Ok(stop);
+            }
+        }
+    }
+
+    /// Byte offset of the command that runs next, or the length of the code
+    /// once the program has finished.
+    pub fn position(&self) -> usize {
+        self.pc
+    }
+
+    pub fn tape(&self) -> &[u8] {
+        &self.tape
+    }
+
+    pub fn pointer(&self) -> usize {
+        self.pointer
+    }
+
+    /// What the program has printed so far.
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// Number of commands executed so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// The state the program has reached, as if it ended here.
+    pub fn into_execution(self) -> Execution {
+        Execution {
+            output: self.output,
+            steps: self.steps,
+            tape: self.tape,
+            pointer: self.pointer,
+        }
+    }
+
+    fn skip_comments(&mut self) {
+        while self
+            .code
+            .get(self.pc)
+            .is_some_and(|&byte| !is_command(byte))
+        {
+            self.pc += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bf::{Interpreter, RuntimeError, Stop};
+
+    #[test]
+    fn test_unchanged_cells_do_not_stop() {
+        let mut debugger = Interpreter::new("+-,.").debugger().unwrap();
+        debugger.watch(0);
+        let Ok(Stop::Write(write)) = debugger.resume() else {
+            panic!("expected a write");
+        };
+        assert_eq!((write.old, write.new, write.position), (0, 1, 0));
+        assert!(matches!(debugger.resume(), Ok(Stop::Write(w)) if w.new == 0));
+        // `,` at the end of input stores the 0 the cell already holds.
+        assert_eq!(debugger.resume(), Ok(Stop::Finished));
+        assert_eq!(debugger.output(), [0]);
+    }
+
+    #[test]
+    fn test_stepping() {
+        let mut debugger = Interpreter::new("a+ b[-]").debugger().unwrap();
+        assert_eq!(debugger.position(), 1);
+        assert_eq!(debugger.step(), Ok(None));
+        assert_eq!(debugger.position(), 4);
+        while debugger.step() == Ok(None) {}
+        assert_eq!(debugger.position(), 7);
+        assert_eq!(debugger.steps(), 4);
+        assert_eq!(debugger.step(), Ok(Some(Stop::Finished)));
+        assert_eq!(debugger.steps(), 4);
+    }
+
+    #[test]
+    fn test_runs_like_the_interpreter() {
+        let interpreter = Interpreter::new("++++++++[>++++++++<-]>+.,[.,]").with_input("hi");
+        let mut debugger = interpreter.debugger().unwrap();
+        assert_eq!(debugger.resume(), Ok(Stop::Finished));
+        assert_eq!(debugger.into_execution(), interpreter.run().unwrap());
+    }
+
+    #[test]
+    fn test_errors_stop_the_run() {
+        let mut debugger = Interpreter::new("+[]")
+            .with_max_steps(10)
+            .debugger()
+            .unwrap();
+        debugger.watch(0);
+        assert!(matches!(debugger.resume(), Ok(Stop::Write(_))));
+        assert_eq!(
+            debugger.resume(),
+            Err(RuntimeError::StepLimitExceeded { limit: 10 })
+        );
+    }
+}
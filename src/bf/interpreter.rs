@@ -0,0 +1,413 @@
+use super::debugger::Debugger;
+use crate::charset::Charset;
+use crate::error::TranspilerError;
+use alloc::{
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::fmt;
+
+/// Default number of cells, matching the classic Brainfuck implementation.
+pub const DEFAULT_TAPE_SIZE: usize = 30_000;
+
+/// A configurable Brainfuck interpreter with 8-bit cells, which wrap
+/// unless [`Overflow::Trap`] is chosen.
+///
+/// Characters other than the eight commands are ignored. Reading past the end
+/// of the input stores 0 in the current cell unless another [`Eof`] is
+/// chosen.
+///
+/// ```
+/// use rusted_brains::bf::Interpreter;
+///
+/// let execution = Interpreter::new(",[.,]").with_input("hi").run().unwrap();
+/// assert_eq!(execution.output, b"hi");
+/// assert_eq!(execution.tape[0], 0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Interpreter {
+    code: Vec<u8>,
+    input: Vec<u8>,
+    tape_size: usize,
+    max_steps: Option<u64>,
+    overflow: Overflow,
+    eof: Eof,
+}
+
+/// What `+` on a cell holding 255, or `-` on one holding 0, does.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Overflow {
+    /// Wrap around to 0 or 255, as most interpreters do.
+    #[default]
+    Wrap,
+    /// Stop with [`RuntimeError::CellOverflow`], to catch arithmetic that
+    /// silently wraps. Optimizing may trade `+` for `-` across the wrap, so
+    /// this is meant for code as the generator wrote it.
+    Trap,
+}
+
+/// What `,` does once the input has all been read. Interpreters differ
+/// here, so programs meant to run anywhere should not depend on it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Eof {
+    /// Store 0.
+    #[default]
+    Zero,
+    /// Leave the cell as it was.
+    Unchanged,
+    /// Store 255, i.e. -1.
+    MinusOne,
+}
+
+impl Eof {
+    /// The value `,` leaves in a cell holding `old`, given the next byte
+    /// of input if there is one.
+    pub(super) fn read(self, next: Option<u8>, old: u8) -> u8 {
+        match (next, self) {
+            (Some(byte), _) => byte,
+            (None, Eof::Zero) => 0,
+            (None, Eof::Unchanged) => old,
+            (None, Eof::MinusOne) => 255,
+        }
+    }
+}
+
+/// The observable result of running a program to completion.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Execution {
+    pub output: Vec<u8>,
+    /// Number of commands executed.
+    pub steps: u64,
+    pub tape: Vec<u8>,
+    /// Cell the pointer was on when the program finished.
+    pub pointer: usize,
+}
+
+impl Execution {
+    /// The output decoded as UTF-8, with invalid bytes replaced.
+    pub fn output_string(&self) -> String {
+        String::from_utf8_lossy(&self.output).into_owned()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuntimeError {
+    /// A `[` or `]` at this byte offset has no partner.
+    UnmatchedBracket { position: usize },
+    /// The command at this byte offset moved the pointer left of cell 0.
+    PointerUnderflow { position: usize },
+    /// The command at this byte offset moved the pointer past the last cell.
+    PointerOverflow { position: usize, tape_size: usize },
+    /// The program did not finish within the configured number of steps.
+    StepLimitExceeded { limit: u64 },
+    /// The command at this byte offset wrapped a cell past 0 or 255, with
+    /// [`Overflow::Trap`] chosen.
+    CellOverflow { position: usize },
+}
+
+impl RuntimeError {
+    /// The byte offset of the command that failed, if the error has one.
+    pub fn position(&self) -> Option<usize> {
+        match self {
+            RuntimeError::UnmatchedBracket { position }
+            | RuntimeError::PointerUnderflow { position }
+            | RuntimeError::PointerOverflow { position, .. }
+            | RuntimeError::CellOverflow { position } => Some(*position),
+            RuntimeError::StepLimitExceeded { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::UnmatchedBracket { position } => {
+                write!(f, "Unmatched bracket at position {}", position)
+            }
+            RuntimeError::PointerUnderflow { position } => write!(
+                f,
+                "Tape pointer moved left of cell 0 at position {}",
+                position
+            ),
+            RuntimeError::PointerOverflow {
+                position,
+                tape_size,
+            } => write!(
+                f,
+                "Tape pointer moved past cell {} at position {}",
+                tape_size - 1,
+                position
+            ),
+            RuntimeError::StepLimitExceeded { limit } => {
+                write!(f, "Program did not finish within {} steps", limit)
+            }
+            RuntimeError::CellOverflow { position } => {
+                write!(f, "Cell value wrapped around at position {}", position)
+            }
+        }
+    }
+}
+
+impl core::error::Error for RuntimeError {}
+
+impl From<RuntimeError> for TranspilerError {
+    fn from(error: RuntimeError) -> Self {
+        TranspilerError::new(error.to_string())
+    }
+}
+
+impl Interpreter {
+    pub fn new(code: impl Into<Vec<u8>>) -> Self {
+        Self {
+            code: code.into(),
+            input: Vec::new(),
+            tape_size: DEFAULT_TAPE_SIZE,
+            max_steps: None,
+            overflow: Overflow::Wrap,
+            eof: Eof::Zero,
+        }
+    }
+
+    /// Reads the code as written in `charset` rather than plain Brainfuck.
+    /// Error positions then refer to the decoded program.
+    pub fn with_charset(mut self, charset: &Charset) -> Self {
+        self.code = charset
+            .decode(&String::from_utf8_lossy(&self.code))
+            .into_bytes();
+        self
+    }
+
+    /// Bytes consumed by `,` commands, in order.
+    pub fn with_input(mut self, input: impl Into<Vec<u8>>) -> Self {
+        self.input = input.into();
+        self
+    }
+
+    pub fn with_tape_size(mut self, tape_size: usize) -> Self {
+        self.tape_size = tape_size.max(1);
+        self
+    }
+
+    /// Aborts with [`RuntimeError::StepLimitExceeded`] instead of running
+    /// forever.
+    pub fn with_max_steps(mut self, max_steps: u64) -> Self {
+        self.max_steps = Some(max_steps);
+        self
+    }
+
+    pub fn with_overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    pub fn with_eof(mut self, eof: Eof) -> Self {
+        self.eof = eof;
+        self
+    }
+
+    pub fn run(&self) -> Result<Execution, RuntimeError> {
+        let jumps = self.match_brackets()?;
+        let mut tape = vec![0u8; self.tape_size];
+        let mut pointer = 0usize;
+        let mut pc = 0usize;
+        let mut steps = 0u64;
+        let mut input = self.input.iter().copied();
+        let mut output = Vec::new();
+
+        while pc < self.code.len() {
+            let command = self.code[pc];
+            if !is_command(command) {
+                pc += 1;
+                continue;
+            }
+
+            if self.max_steps.is_some_and(|limit| steps >= limit) {
+                return Err(RuntimeError::StepLimitExceeded {
+                    limit: self.max_steps.unwrap_or_default(),
+                });
+            }
+            steps += 1;
+
+            match command {
+                b'+' | b'-' => {
+                    let (value, wrapped) = match command {
+                        b'+' => tape[pointer].overflowing_add(1),
+                        _ => tape[pointer].overflowing_sub(1),
+                    };
+                    if wrapped && self.overflow == Overflow::Trap {
+                        return Err(RuntimeError::CellOverflow { position: pc });
+                    }
+                    tape[pointer] = value;
+                }
+                b'>' => {
+                    pointer += 1;
+                    if pointer == tape.len() {
+                        return Err(RuntimeError::PointerOverflow {
+                            position: pc,
+                            tape_size: tape.len(),
+                        });
+                    }
+                }
+                b'<' => {
+                    pointer = pointer
+                        .checked_sub(1)
+                        .ok_or(RuntimeError::PointerUnderflow { position: pc })?;
+                }
+                b'.' => output.push(tape[pointer]),
+                b',' => tape[pointer] = self.eof.read(input.next(), tape[pointer]),
+                b'[' if tape[pointer] == 0 => pc = jumps[pc],
+                b']' if tape[pointer] != 0 => pc = jumps[pc],
+                _ => {}
+            }
+            pc += 1;
+        }
+
+        Ok(Execution {
+            output,
+            steps,
+            tape,
+            pointer,
+        })
+    }
+
+    /// Sets the program up to run a command at a time, e.g. to stop when a
+    /// cell changes.
+    pub fn debugger(&self) -> Result<Debugger, RuntimeError> {
+        Ok(Debugger::new(
+            self.code.clone(),
+            self.match_brackets()?,
+            self.input.clone(),
+            self.tape_size,
+            self.max_steps,
+            self.overflow,
+            self.eof,
+        ))
+    }
+
+    /// For every bracket, the offset of its partner.
+    fn match_brackets(&self) -> Result<Vec<usize>, RuntimeError> {
+        let mut jumps = vec![0; self.code.len()];
+        let mut open = Vec::new();
+
+        for (position, &command) in self.code.iter().enumerate() {
+            match command {
+                b'[' => open.push(position),
+                b']' => {
+                    let start = open
+                        .pop()
+                        .ok_or(RuntimeError::UnmatchedBracket { position })?;
+                    jumps[start] = position;
+                    jumps[position] = start;
+                }
+                _ => {}
+            }
+        }
+
+        match open.pop() {
+            Some(position) => Err(RuntimeError::UnmatchedBracket { position }),
+            None => Ok(jumps),
+        }
+    }
+}
+
+pub(super) fn is_command(byte: u8) -> bool {
+    matches!(byte, b'+' | b'-' | b'<' | b'>' | b'.' | b',' | b'[' | b']')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hello() {
+        let code = "++++++++[>++++++++<-]>+.+.";
+        let execution = Interpreter::new(code).run().unwrap();
+
+        assert_eq!(execution.output_string(), "AB");
+        assert_eq!(execution.pointer, 1);
+        assert_eq!(execution.tape[1], 66);
+    }
+
+    #[test]
+    fn test_cells_wrap() {
+        let execution = Interpreter::new("-.+.").run().unwrap();
+        assert_eq!(execution.output, vec![255, 0]);
+    }
+
+    #[test]
+    fn test_trap_on_overflow() {
+        let trap = |code: &str| Interpreter::new(code).with_overflow(Overflow::Trap).run();
+        assert_eq!(trap("+>-"), Err(RuntimeError::CellOverflow { position: 2 }));
+        let full = "+".repeat(256);
+        assert_eq!(
+            trap(&full),
+            Err(RuntimeError::CellOverflow { position: 255 })
+        );
+        assert_eq!(trap("+-[-]+.").unwrap().output, [1]);
+    }
+
+    #[test]
+    fn test_comments_are_not_steps() {
+        let execution = Interpreter::new("a + b + c").run().unwrap();
+        assert_eq!(execution.steps, 2);
+    }
+
+    #[test]
+    fn test_charset() {
+        let charset = Charset::parse("> r\n< l\n+ i\n- d\n. o\n, g\n[ (\n] )").unwrap();
+        let execution = Interpreter::new("iiii(rii ld)ro")
+            .with_charset(&charset)
+            .run()
+            .unwrap();
+        assert_eq!(execution.output, vec![8]);
+    }
+
+    #[test]
+    fn test_eof_reads_zero() {
+        let execution = Interpreter::new("+,").with_input("").run().unwrap();
+        assert_eq!(execution.tape[0], 0);
+    }
+
+    #[test]
+    fn test_eof_setups() {
+        let read = |eof| {
+            let execution = Interpreter::new("+,>+,")
+                .with_input("a")
+                .with_eof(eof)
+                .run()
+                .unwrap();
+            (execution.tape[0], execution.tape[1])
+        };
+        assert_eq!(read(Eof::Zero), (b'a', 0));
+        assert_eq!(read(Eof::Unchanged), (b'a', 1));
+        assert_eq!(read(Eof::MinusOne), (b'a', 255));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            Interpreter::new("+[").run(),
+            Err(RuntimeError::UnmatchedBracket { position: 1 })
+        );
+        assert_eq!(
+            Interpreter::new("]").run(),
+            Err(RuntimeError::UnmatchedBracket { position: 0 })
+        );
+        assert_eq!(
+            Interpreter::new("><<").run(),
+            Err(RuntimeError::PointerUnderflow { position: 2 })
+        );
+        assert_eq!(
+            Interpreter::new(">>").with_tape_size(2).run(),
+            Err(RuntimeError::PointerOverflow {
+                position: 1,
+                tape_size: 2
+            })
+        );
+        assert_eq!(
+            Interpreter::new("+[]").with_max_steps(100).run(),
+            Err(RuntimeError::StepLimitExceeded { limit: 100 })
+        );
+    }
+}
@@ -0,0 +1,19 @@
+//! Running Brainfuck programs.
+//!
+//! The transpiler's output is plain Brainfuck, so anything that needs to know
+//! what a generated program actually does (tests, the CLI, embedding
+//! applications) goes through the [`Interpreter`] here. [`Vm`] runs the
+//! optimized IR or its [`bytecode`](crate::bytecode) form faster, a
+//! [`Transcript`] saves what a run read and wrote so it can be replayed, and
+//! a [`Debugger`] runs a program a command at a time, pausing when a watched
+//! cell changes.
+
+mod debugger;
+mod interpreter;
+mod transcript;
+mod vm;
+
+pub use debugger::{CellWrite, Debugger, Stop};
+pub use interpreter::{Eof, Execution, Interpreter, Overflow, RuntimeError};
+pub use transcript::Transcript;
+pub use vm::Vm;
@@ -0,0 +1,208 @@
+use crate::error::TranspilerError;
+use alloc::{format, string::String, vec::Vec};
+use core::fmt::Write;
+
+/// The bytes a run of a program read and wrote, saved as JSON so an
+/// interactive session can be replayed later as a regression test.
+///
+/// Each byte is stored as the character with the same code point, so text
+/// stays readable and any other byte is written as a `\u00XX` escape.
+///
+/// ```
+/// use rusted_brains::bf::Transcript;
+///
+/// let transcript = Transcript { input: b"ab".to_vec(), output: b"AB\n".to_vec() };
+/// let json = transcript.to_json();
+/// assert_eq!(json, "{\n  \"input\": \"ab\",\n  \"output\": \"AB\\n\"\n}\n");
+/// assert_eq!(Transcript::from_json(&json).unwrap(), transcript);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Transcript {
+    pub input: Vec<u8>,
+    pub output: Vec<u8>,
+}
+
+impl Transcript {
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\n  \"input\": {},\n  \"output\": {}\n}}\n",
+            quote(&self.input),
+            quote(&self.output)
+        )
+    }
+
+    /// Reads what [`to_json`](Self::to_json) writes: an object whose
+    /// `input` and `output` are strings. Other keys are ignored.
+    pub fn from_json(json: &str) -> Result<Self, TranspilerError> {
+        let mut reader = Reader {
+            chars: json.char_indices().peekable(),
+        };
+        let mut transcript = Transcript::default();
+        let (mut input, mut output) = (false, false);
+
+        reader.expect('{')?;
+        if !reader.eat('}') {
+            loop {
+                let key = reader.string()?;
+                reader.expect(':')?;
+                let value = reader.string()?;
+                match key.as_slice() {
+                    b"input" => (transcript.input, input) = (value, true),
+                    b"output" => (transcript.output, output) = (value, true),
+                    _ => {}
+                }
+                if reader.eat('}') {
+                    break;
+                }
+                reader.expect(',')?;
+            }
+        }
+        reader.skip_whitespace();
+        if let Some((position, _)) = reader.chars.next() {
+            return Err(error(position, "unexpected text after the transcript"));
+        }
+        if !(input && output) {
+            return Err(TranspilerError::new(
+                "Transcript needs both an \"input\" and an \"output\" string",
+            ));
+        }
+        Ok(transcript)
+    }
+}
+
+fn quote(bytes: &[u8]) -> String {
+    let mut out = String::from("\"");
+    for &byte in bytes {
+        match byte {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            b' '..=b'~' => out.push(byte as char),
+            _ => {
+                let _ = write!(out, "\\u{:04x}", byte);
+            }
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn error(position: usize, message: &str) -> TranspilerError {
+    TranspilerError::new(format!(
+        "Invalid transcript at byte {}: {}",
+        position, message
+    ))
+}
+
+struct Reader<'a> {
+    chars: core::iter::Peekable<core::str::CharIndices<'a>>,
+}
+
+impl Reader<'_> {
+    fn skip_whitespace(&mut self) {
+        while self
+            .chars
+            .next_if(|(_, c)| c.is_ascii_whitespace())
+            .is_some()
+        {}
+    }
+
+    /// Consumes `expected` if it comes next, ignoring whitespace.
+    fn eat(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+        self.chars.next_if(|&(_, c)| c == expected).is_some()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), TranspilerError> {
+        if self.eat(expected) {
+            return Ok(());
+        }
+        match self.chars.peek() {
+            Some(&(position, found)) => Err(error(
+                position,
+                &format!("expected '{}', found '{}'", expected, found),
+            )),
+            None => Err(TranspilerError::new(format!(
+                "Invalid transcript: expected '{}' before the end",
+                expected
+            ))),
+        }
+    }
+
+    /// A JSON string whose characters are all bytes (up to U+00FF).
+    fn string(&mut self) -> Result<Vec<u8>, TranspilerError> {
+        self.expect('"')?;
+        let mut bytes = Vec::new();
+        loop {
+            let Some((position, c)) = self.chars.next() else {
+                return Err(TranspilerError::new(
+                    "Invalid transcript: unterminated string",
+                ));
+            };
+            let c = match c {
+                '"' => return Ok(bytes),
+                '\\' => match self.chars.next().map(|(_, c)| c) {
+                    Some('"') => '"',
+                    Some('\\') => '\\',
+                    Some('/') => '/',
+                    Some('n') => '\n',
+                    Some('r') => '\r',
+                    Some('t') => '\t',
+                    Some('b') => '\u{8}',
+                    Some('f') => '\u{c}',
+                    Some('u') => {
+                        let hex: String = (0..4)
+                            .filter_map(|_| self.chars.next())
+                            .map(|(_, c)| c)
+                            .collect();
+                        u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| error(position, "invalid \\u escape"))?
+                    }
+                    _ => return Err(error(position, "invalid escape")),
+                },
+                c => c,
+            };
+            let byte = u8::try_from(u32::from(c))
+                .map_err(|_| error(position, "character is not a single byte"))?;
+            bytes.push(byte);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_byte_round_trips() {
+        let transcript = Transcript {
+            input: (0..=255).collect(),
+            output: b"\"quoted\" \\ back".to_vec(),
+        };
+        assert_eq!(
+            Transcript::from_json(&transcript.to_json()).unwrap(),
+            transcript
+        );
+    }
+
+    #[test]
+    fn test_hand_written_transcript() {
+        let json = r#" { "note": "ignored", "output": "hé", "input": "" } "#;
+        let transcript = Transcript::from_json(json).unwrap();
+        assert_eq!(transcript.output, [b'h', 0xe9]);
+        assert!(transcript.input.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_transcripts() {
+        assert!(Transcript::from_json("").is_err());
+        assert!(Transcript::from_json(r#"{"input": "a"}"#).is_err());
+        assert!(Transcript::from_json(r#"{"input": "a", "output": "€"}"#).is_err());
+        assert!(Transcript::from_json(r#"{"input": "a", "output": "b"} x"#).is_err());
+        assert!(Transcript::from_json(r#"{"input": "a" "output": "b"}"#).is_err());
+    }
+}
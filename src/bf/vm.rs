@@ -0,0 +1,187 @@
+use super::{Execution, RuntimeError, interpreter::DEFAULT_TAPE_SIZE};
+use crate::bytecode;
+use crate::error::TranspilerError;
+use crate::ir::Op;
+use alloc::{vec, vec::Vec};
+
+/// A flattened op with loop targets resolved to instruction indices.
+#[derive(Debug, Clone, Copy)]
+enum Instr {
+    Add(u8),
+    Move(isize),
+    Clear,
+    Output,
+    Input,
+    /// Skip to the instruction after the matching `LoopEnd` if the cell is 0.
+    LoopStart(usize),
+    /// Jump back to the instruction after the matching `LoopStart` unless
+    /// the cell is 0.
+    LoopEnd(usize),
+}
+
+/// Runs optimized programs, as [`ir`](crate::ir) or [`bytecode`], with jump
+/// targets computed once up front.
+///
+/// Compared with [`Interpreter`](super::Interpreter), runs and clears take a
+/// single step each, so `steps` counts instructions rather than Brainfuck
+/// commands, and error positions are instruction indices.
+///
+/// ```
+/// use rusted_brains::{bf::Vm, bytecode, ir};
+///
+/// let bytes = bytecode::encode(&ir::parse(",[.,]").unwrap());
+/// let execution = Vm::from_bytecode(&bytes).unwrap().with_input("hi").run().unwrap();
+/// assert_eq!(execution.output, b"hi");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Vm {
+    code: Vec<Instr>,
+    input: Vec<u8>,
+    tape_size: usize,
+    max_steps: Option<u64>,
+}
+
+impl Vm {
+    pub fn new(ops: &[Op]) -> Self {
+        let mut code = Vec::new();
+        flatten(ops, &mut code);
+        Self {
+            code,
+            input: Vec::new(),
+            tape_size: DEFAULT_TAPE_SIZE,
+            max_steps: None,
+        }
+    }
+
+    /// Loads a program serialized with [`bytecode::encode`].
+    pub fn from_bytecode(bytes: &[u8]) -> Result<Self, TranspilerError> {
+        Ok(Self::new(&bytecode::decode(bytes)?))
+    }
+
+    /// Bytes consumed by `,` commands, in order.
+    pub fn with_input(mut self, input: impl Into<Vec<u8>>) -> Self {
+        self.input = input.into();
+        self
+    }
+
+    pub fn with_tape_size(mut self, tape_size: usize) -> Self {
+        self.tape_size = tape_size.max(1);
+        self
+    }
+
+    /// Aborts with [`RuntimeError::StepLimitExceeded`] instead of running
+    /// forever.
+    pub fn with_max_steps(mut self, max_steps: u64) -> Self {
+        self.max_steps = Some(max_steps);
+        self
+    }
+
+    pub fn run(&self) -> Result<Execution, RuntimeError> {
+        let mut tape = vec![0u8; self.tape_size];
+        let mut pointer = 0usize;
+        let mut pc = 0usize;
+        let mut steps = 0u64;
+        let mut input = self.input.iter().copied();
+        let mut output = Vec::new();
+        let limit = self.max_steps.unwrap_or(u64::MAX);
+
+        while let Some(&instr) = self.code.get(pc) {
+            if steps >= limit {
+                return Err(RuntimeError::StepLimitExceeded { limit });
+            }
+            steps += 1;
+
+            match instr {
+                Instr::Add(n) => tape[pointer] = tape[pointer].wrapping_add(n),
+                Instr::Move(n) => {
+                    pointer = pointer
+                        .checked_add_signed(n)
+                        .ok_or(RuntimeError::PointerUnderflow { position: pc })?;
+                    if pointer >= tape.len() {
+                        return Err(RuntimeError::PointerOverflow {
+                            position: pc,
+                            tape_size: tape.len(),
+                        });
+                    }
+                }
+                Instr::Clear => tape[pointer] = 0,
+                Instr::Output => output.push(tape[pointer]),
+                Instr::Input => tape[pointer] = input.next().unwrap_or(0),
+                Instr::LoopStart(end) if tape[pointer] == 0 => pc = end,
+                Instr::LoopEnd(start) if tape[pointer] != 0 => pc = start,
+                Instr::LoopStart(_) | Instr::LoopEnd(_) => {}
+            }
+            pc += 1;
+        }
+
+        Ok(Execution {
+            output,
+            steps,
+            tape,
+            pointer,
+        })
+    }
+}
+
+fn flatten(ops: &[Op], code: &mut Vec<Instr>) {
+    for op in ops {
+        match op {
+            Op::Add(n) => code.push(Instr::Add(*n)),
+            Op::Move(n) => code.push(Instr::Move(*n)),
+            Op::Clear => code.push(Instr::Clear),
+            Op::Output => code.push(Instr::Output),
+            Op::Input => code.push(Instr::Input),
+            Op::Loop(body) => {
+                let start = code.len();
+                code.push(Instr::LoopStart(0));
+                flatten(body, code);
+                let end = code.len();
+                code.push(Instr::LoopEnd(start));
+                code[start] = Instr::LoopStart(end);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bf::Interpreter;
+    use crate::ir;
+    use crate::optimizer::PassManager;
+
+    #[test]
+    fn test_matches_interpreter() {
+        let code = "++++++++[>++++++++<-]>+.+.[-]<,[>+<-]>.";
+        let mut ops = ir::parse(code).unwrap();
+        PassManager::new().run(&mut ops);
+
+        let expected = Interpreter::new(code).with_input("x").run().unwrap();
+        let execution = Vm::new(&ops).with_input("x").run().unwrap();
+        assert_eq!(execution.output, expected.output);
+        assert_eq!(execution.tape, expected.tape);
+        assert!(execution.steps < expected.steps);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            Vm::new(&[Op::Move(1), Op::Move(-2)]).run().unwrap_err(),
+            RuntimeError::PointerUnderflow { position: 1 }
+        );
+        assert_eq!(
+            Vm::new(&[Op::Move(2)]).with_tape_size(2).run().unwrap_err(),
+            RuntimeError::PointerOverflow {
+                position: 0,
+                tape_size: 2
+            }
+        );
+        assert_eq!(
+            Vm::new(&[Op::Add(1), Op::Loop(vec![])])
+                .with_max_steps(100)
+                .run()
+                .unwrap_err(),
+            RuntimeError::StepLimitExceeded { limit: 100 }
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! The compilation database batch mode writes, in the spirit of
+//! `compile_commands.json`: for every input, the command that rebuilds it
+//! on its own and the files it produced, with content hashes so other
+//! tools can tell what is out of date without recompiling.
+
+use crate::report::quote;
+use std::fmt::Write;
+
+/// One input of a batch build.
+pub struct Entry<'a> {
+    pub input: &'a str,
+    /// SHA-256 of the input, or `None` if it could not be read.
+    pub input_sha256: Option<String>,
+    /// `rust2bf` followed by the arguments that build this input alone.
+    pub arguments: Vec<String>,
+    /// What was saved, or `None` if the input did not compile.
+    pub outputs: Option<Vec<Output>>,
+}
+
+/// A file written for one target.
+pub struct Output {
+    pub target: &'static str,
+    pub path: String,
+    pub sha256: String,
+}
+
+/// The database for `entries`, with relative paths resolved against
+/// `directory`.
+pub fn to_json(directory: &str, entries: &[Entry]) -> String {
+    let mut out = format!(
+        "{{\n  \"version\": {},\n  \"directory\": {},\n  \"entries\": [",
+        quote(env!("CARGO_PKG_VERSION")),
+        quote(directory)
+    );
+    for (i, entry) in entries.iter().enumerate() {
+        let arguments: Vec<String> = entry.arguments.iter().map(|arg| quote(arg)).collect();
+        let _ = write!(
+            out,
+            "{}\n    {{\n      \"input\": {},\n      \"input_sha256\": {},\n      \
+             \"arguments\": [{}],\n      \"ok\": {},\n      \"outputs\": [",
+            if i == 0 { "" } else { "," },
+            quote(entry.input),
+            entry
+                .input_sha256
+                .as_deref()
+                .map_or("null".to_string(), quote),
+            arguments.join(", "),
+            entry.outputs.is_some()
+        );
+        let outputs = entry.outputs.as_deref().unwrap_or_default();
+        for (i, output) in outputs.iter().enumerate() {
+            let _ = write!(
+                out,
+                "{}\n        {{\"target\": {}, \"path\": {}, \"sha256\": {}}}",
+                if i == 0 { "" } else { "," },
+                quote(output.target),
+                quote(&output.path),
+                quote(&output.sha256)
+            );
+        }
+        if !outputs.is_empty() {
+            out.push_str("\n      ");
+        }
+        out.push_str("]\n    }");
+    }
+    if !entries.is_empty() {
+        out.push_str("\n  ");
+    }
+    out.push_str("]\n}\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_database() {
+        let entries = [
+            Entry {
+                input: "a.rs",
+                input_sha256: Some("aa".to_string()),
+                arguments: vec!["rust2bf".to_string(), "a.rs".to_string()],
+                outputs: Some(vec![Output {
+                    target: "bf",
+                    path: "a.bf".to_string(),
+                    sha256: "bb".to_string(),
+                }]),
+            },
+            Entry {
+                input: "missing.rs",
+                input_sha256: None,
+                arguments: vec!["rust2bf".to_string(), "missing.rs".to_string()],
+                outputs: None,
+            },
+        ];
+        let json = to_json("/work", &entries);
+        assert!(json.contains("\"directory\": \"/work\""));
+        assert!(json.contains(
+            "\"input\": \"a.rs\",\n      \"input_sha256\": \"aa\",\n      \
+             \"arguments\": [\"rust2bf\", \"a.rs\"],\n      \"ok\": true,\n      \"outputs\": [\n        \
+             {\"target\": \"bf\", \"path\": \"a.bf\", \"sha256\": \"bb\"}\n      ]"
+        ));
+        assert!(json.contains("\"input_sha256\": null"));
+        assert!(json.contains("\"ok\": false,\n      \"outputs\": []"));
+    }
+}
@@ -0,0 +1,155 @@
+//! A compact binary serialization of the optimized [`ir`](crate::ir).
+//!
+//! A file starts with [`MAGIC`], followed by one opcode byte per operation.
+//! `Add` carries its amount as one byte and `Move` its distance as a
+//! zigzag-encoded LEB128 varint; loops are delimited by start and end
+//! opcodes. [`bf::Vm`](crate::bf::Vm) runs it without reparsing text.
+
+use crate::error::TranspilerError;
+use crate::ir::Op;
+use alloc::{format, vec::Vec};
+
+/// File signature: "BFC" and the format version.
+pub const MAGIC: &[u8; 4] = b"BFC\x01";
+
+const ADD: u8 = 0x01;
+const MOVE: u8 = 0x02;
+const CLEAR: u8 = 0x03;
+const OUTPUT: u8 = 0x04;
+const INPUT: u8 = 0x05;
+const LOOP_START: u8 = 0x06;
+const LOOP_END: u8 = 0x07;
+
+/// Serializes a program.
+pub fn encode(ops: &[Op]) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    encode_into(ops, &mut out);
+    out
+}
+
+fn encode_into(ops: &[Op], out: &mut Vec<u8>) {
+    for op in ops {
+        match op {
+            Op::Add(n) => out.extend([ADD, *n]),
+            Op::Move(n) => {
+                out.push(MOVE);
+                let mut zigzag = ((*n << 1) ^ (*n >> (isize::BITS - 1))) as usize;
+                loop {
+                    let byte = (zigzag & 0x7f) as u8;
+                    zigzag >>= 7;
+                    if zigzag == 0 {
+                        out.push(byte);
+                        break;
+                    }
+                    out.push(byte | 0x80);
+                }
+            }
+            Op::Clear => out.push(CLEAR),
+            Op::Output => out.push(OUTPUT),
+            Op::Input => out.push(INPUT),
+            Op::Loop(body) => {
+                out.push(LOOP_START);
+                encode_into(body, out);
+                out.push(LOOP_END);
+            }
+        }
+    }
+}
+
+/// Reads a program serialized by [`encode`].
+pub fn decode(bytes: &[u8]) -> Result<Vec<Op>, TranspilerError> {
+    let code = bytes
+        .strip_prefix(MAGIC)
+        .ok_or_else(|| TranspilerError::new("Not a bytecode file (bad signature)"))?;
+    let invalid = |position: usize| {
+        TranspilerError::new(format!(
+            "Invalid bytecode at byte {}",
+            position + MAGIC.len()
+        ))
+    };
+
+    let mut stack: Vec<(usize, Vec<Op>)> = Vec::new();
+    let mut current = Vec::new();
+    let mut position = 0;
+    while let Some(&opcode) = code.get(position) {
+        let start = position;
+        position += 1;
+        match opcode {
+            ADD => {
+                let n = *code.get(position).ok_or_else(|| invalid(start))?;
+                position += 1;
+                current.push(Op::Add(n));
+            }
+            MOVE => {
+                let mut zigzag = 0usize;
+                let mut shift = 0;
+                loop {
+                    let byte = *code.get(position).ok_or_else(|| invalid(start))?;
+                    position += 1;
+                    if shift >= usize::BITS {
+                        return Err(invalid(start));
+                    }
+                    zigzag |= ((byte & 0x7f) as usize) << shift;
+                    shift += 7;
+                    if byte & 0x80 == 0 {
+                        break;
+                    }
+                }
+                let n = (zigzag >> 1) as isize ^ -((zigzag & 1) as isize);
+                current.push(Op::Move(n));
+            }
+            CLEAR => current.push(Op::Clear),
+            OUTPUT => current.push(Op::Output),
+            INPUT => current.push(Op::Input),
+            LOOP_START => stack.push((start, core::mem::take(&mut current))),
+            LOOP_END => {
+                let (_, outer) = stack.pop().ok_or_else(|| invalid(start))?;
+                let body = core::mem::replace(&mut current, outer);
+                current.push(Op::Loop(body));
+            }
+            _ => return Err(invalid(start)),
+        }
+    }
+
+    match stack.pop() {
+        Some((start, _)) => Err(invalid(start)),
+        None => Ok(current),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let ops = vec![
+            Op::Add(200),
+            Op::Move(-1),
+            Op::Move(1000),
+            Op::Move(isize::MIN),
+            Op::Loop(vec![Op::Clear, Op::Loop(vec![Op::Input, Op::Output])]),
+        ];
+        let bytes = encode(&ops);
+        assert_eq!(&bytes[..7], b"BFC\x01\x01\xc8\x02");
+        assert_eq!(decode(&bytes).unwrap(), ops);
+    }
+
+    #[test]
+    fn test_runs_take_constant_space() {
+        let ops = vec![Op::Add(100), Op::Move(1), Op::Move(-50), Op::Output];
+        assert_eq!(encode(&ops).len(), MAGIC.len() + 2 + 2 + 2 + 1);
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(decode(b"BF\x01").is_err());
+        assert_eq!(
+            decode(b"BFC\x01\x06\x04").unwrap_err().message,
+            "Invalid bytecode at byte 4"
+        );
+        assert!(decode(b"BFC\x01\x07").is_err());
+        assert!(decode(b"BFC\x01\x02\x80").is_err());
+        assert!(decode(b"BFC\x01\xff").is_err());
+    }
+}
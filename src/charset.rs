@@ -0,0 +1,203 @@
+//! Spelling Brainfuck with other symbols.
+//!
+//! Many Brainfuck derivatives differ only in how the eight commands are
+//! written. A [`Charset`] maps each command to a replacement string, so the
+//! generated code can be emitted in such a dialect and read back for the
+//! interpreter.
+
+use crate::error::TranspilerError;
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// The Brainfuck commands, in the order [`Charset`] stores them.
+pub const COMMANDS: [char; 8] = ['>', '<', '+', '-', '.', ',', '[', ']'];
+
+/// A spelling for each of the eight Brainfuck commands.
+///
+/// ```
+/// use rusted_brains::charset::Charset;
+///
+/// let charset = Charset::parse("> r\n< l\n+ i\n- d\n. o\n, g\n[ (\n] )").unwrap();
+/// assert_eq!(charset.encode("+[-]>."), "i(d)ro");
+/// assert_eq!(charset.decode("i(d)ro"), "+[-]>.");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Charset {
+    words: [String; 8],
+}
+
+impl Default for Charset {
+    fn default() -> Self {
+        Self::brainfuck()
+    }
+}
+
+impl Charset {
+    /// Plain Brainfuck, where every command is spelled as itself.
+    pub fn brainfuck() -> Self {
+        Self {
+            words: COMMANDS.map(|command| command.to_string()),
+        }
+    }
+
+    /// Reads a table with one line per command: the command, whitespace,
+    /// then the text that spells it. Blank lines and lines starting with `#`
+    /// are ignored. Every command must be given a distinct, non-empty
+    /// spelling.
+    pub fn parse(table: &str) -> Result<Self, TranspilerError> {
+        let mut words: [Option<String>; 8] = Default::default();
+
+        for (number, line) in table.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut chars = line.chars();
+            let command = chars.next().unwrap_or_default();
+            let word = chars.as_str().trim();
+            let index = COMMANDS.iter().position(|&c| c == command).ok_or_else(|| {
+                TranspilerError::new(format!(
+                    "line {}: `{}` is not a Brainfuck command",
+                    number + 1,
+                    command
+                ))
+            })?;
+            if word.is_empty() {
+                return Err(TranspilerError::new(format!(
+                    "line {}: no spelling given for `{}`",
+                    number + 1,
+                    command
+                )));
+            }
+            if words[index].is_some() {
+                return Err(TranspilerError::new(format!(
+                    "line {}: `{}` is given twice",
+                    number + 1,
+                    command
+                )));
+            }
+            words[index] = Some(word.to_string());
+        }
+
+        let missing: Vec<String> = COMMANDS
+            .iter()
+            .zip(&words)
+            .filter(|(_, word)| word.is_none())
+            .map(|(command, _)| format!("`{}`", command))
+            .collect();
+        if !missing.is_empty() {
+            return Err(TranspilerError::new(format!(
+                "no spelling given for {}",
+                missing.join(", ")
+            )));
+        }
+
+        let words = words.map(Option::unwrap_or_default);
+        for (i, word) in words.iter().enumerate() {
+            if words[..i].contains(word) {
+                return Err(TranspilerError::new(format!(
+                    "`{}` spells more than one command",
+                    word
+                )));
+            }
+        }
+        Ok(Self { words })
+    }
+
+    /// The spelling of `command`, if it is a Brainfuck command.
+    pub fn word(&self, command: char) -> Option<&str> {
+        let index = COMMANDS.iter().position(|&c| c == command)?;
+        Some(&self.words[index])
+    }
+
+    /// Rewrites Brainfuck in this charset, dropping anything that is not a
+    /// command. Multi-character spellings are separated by spaces.
+    pub fn encode(&self, code: &str) -> String {
+        let separator = if self.words.iter().any(|word| word.chars().count() > 1) {
+            " "
+        } else {
+            ""
+        };
+        code.chars()
+            .filter_map(|command| self.word(command))
+            .collect::<Vec<_>>()
+            .join(separator)
+    }
+
+    /// Reads code written in this charset back as Brainfuck. Where spellings
+    /// overlap the longest one wins; text matching none is skipped.
+    pub fn decode(&self, code: &str) -> String {
+        let mut by_length: Vec<(&str, char)> = self
+            .words
+            .iter()
+            .map(String::as_str)
+            .zip(COMMANDS)
+            .collect();
+        by_length.sort_by_key(|(word, _)| core::cmp::Reverse(word.len()));
+
+        let mut out = String::new();
+        let mut rest = code;
+        while let Some(next) = rest.chars().next() {
+            match by_length.iter().find(|(word, _)| rest.starts_with(word)) {
+                Some((word, command)) => {
+                    out.push(*command);
+                    rest = &rest[word.len()..];
+                }
+                None => rest = &rest[next.len_utf8()..],
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OOK: &str = "
+        # Ook!
+        > Ook. Ook?
+        < Ook? Ook.
+        + Ook. Ook.
+        - Ook! Ook!
+        . Ook! Ook.
+        , Ook. Ook!
+        [ Ook! Ook?
+        ] Ook? Ook!
+    ";
+
+    #[test]
+    fn test_round_trip() {
+        let charset = Charset::parse(OOK).unwrap();
+        let encoded = charset.encode("+[->.<]");
+        assert!(encoded.starts_with("Ook. Ook. Ook! Ook? Ook! Ook! Ook. Ook?"));
+        assert_eq!(charset.decode(&encoded), "+[->.<]");
+        assert_eq!(Charset::brainfuck().encode("+x-"), "+-");
+    }
+
+    #[test]
+    fn test_longest_spelling_wins() {
+        let table = "> a\n< aa\n+ b\n- bb\n. c\n, cc\n[ d\n] dd";
+        let charset = Charset::parse(table).unwrap();
+        assert_eq!(charset.decode("aaa bbb"), "<>-+");
+    }
+
+    #[test]
+    fn test_invalid_tables() {
+        let error = |table: &str| Charset::parse(table).unwrap_err().message;
+        assert_eq!(error("x y"), "line 1: `x` is not a Brainfuck command");
+        assert_eq!(error("+ a\n+ b"), "line 2: `+` is given twice");
+        assert_eq!(error("+"), "line 1: no spelling given for `+`");
+        assert_eq!(
+            error("> a\n< b\n+ c\n- d\n. e\n, f\n[ g"),
+            "no spelling given for `]`"
+        );
+        assert_eq!(
+            error("> a\n< b\n+ c\n- d\n. e\n, f\n[ g\n] a"),
+            "`a` spells more than one command"
+        );
+    }
+}
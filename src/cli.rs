@@ -0,0 +1,1003 @@
+use rusted_brains::codegen::CallStrategy;
+use rusted_brains::parser::Dialect;
+
+/// What the command line asks for.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Compile(Box<Options>),
+    /// `example [<name> [<output>]]`: list the built-in examples, or print
+    /// one or write it to a file.
+    Example {
+        name: Option<String>,
+        output: Option<String>,
+    },
+    /// `run <program.bf>`: run compiled Brainfuck, or bytecode saved with
+    /// `--emit bfc`, on standard input, optionally saving the session or
+    /// replaying a saved one.
+    Run {
+        program: String,
+        session: Session,
+        /// `--trap-overflow`: stop when a cell wraps around, which only
+        /// means something in code compiled with `--checked-arithmetic`.
+        trap_overflow: bool,
+    },
+    /// `link <program.bf>... -o <output.bf>`: join compiled programs into
+    /// one that runs them in turn.
+    Link {
+        programs: Vec<String>,
+        output: String,
+    },
+    /// `verify <program>...`: compile and run mini-rust programs, checking
+    /// each prints what its `//! expect:` comments say.
+    Verify {
+        programs: Vec<String>,
+        /// `--trap-overflow`: compile with checked arithmetic and fail at
+        /// the statement whose arithmetic wraps.
+        trap_overflow: bool,
+    },
+    /// `conformance [<program>...]`: run programs, the built-in examples by
+    /// default, on every target and print which ones agree.
+    Conformance {
+        programs: Vec<String>,
+    },
+    /// `grammar [--format ebnf|railroad]`: print the grammar of mini-rust.
+    Grammar(GrammarFormat),
+}
+
+/// How `grammar` writes out the rules.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GrammarFormat {
+    /// W3C EBNF text.
+    Ebnf,
+    /// An HTML page of railroad diagrams.
+    Railroad,
+}
+
+/// Forms other than Brainfuck text the program can be saved in (`--emit`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Emit {
+    /// `bfc`: compact `bytecode`.
+    Bytecode,
+    /// `rust-embed`: a Rust file holding the program as a constant.
+    RustEmbed,
+    /// `rle`: Brainfuck with runs of a command written as `15+`.
+    Rle,
+}
+
+/// What `run` does with a `bf::Transcript` file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Session {
+    /// Just run the program.
+    Live,
+    /// Run on standard input and save what was read and written.
+    Record(String),
+    /// Feed the saved input and check the output still matches.
+    Replay(String),
+}
+
+impl Command {
+    /// Parses the arguments following the program name.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut args = args.into_iter().peekable();
+        match args.peek().map(String::as_str) {
+            Some("example") => {}
+            Some("run") => {
+                args.next();
+                return Self::parse_run(args);
+            }
+            Some("link") => {
+                args.next();
+                return Self::parse_link(args);
+            }
+            Some("verify") => {
+                args.next();
+                return Self::parse_verify(args);
+            }
+            Some("conformance") => {
+                args.next();
+                return Self::parse_conformance(args);
+            }
+            Some("grammar") => {
+                args.next();
+                return Self::parse_grammar(args);
+            }
+            _ => return Options::parse(args).map(|options| Command::Compile(Box::new(options))),
+        }
+        args.next();
+        let name = args.next();
+        let output = args.next();
+        if let Some(extra) = args.next() {
+            return Err(format!("Unexpected argument '{}'\n{}", extra, USAGE));
+        }
+        Ok(Command::Example { name, output })
+    }
+
+    fn parse_run(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut program = None;
+        let mut session = Session::Live;
+        let mut trap_overflow = false;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let next = match arg.as_str() {
+                "--trap-overflow" => {
+                    trap_overflow = true;
+                    continue;
+                }
+                "--record" => Session::Record(value(&arg, args.next())?),
+                "--replay" => Session::Replay(value(&arg, args.next())?),
+                flag if flag.starts_with("--") => {
+                    return Err(format!("Unknown option '{}'\n{}", flag, USAGE));
+                }
+                _ if program.is_none() => {
+                    program = Some(arg);
+                    continue;
+                }
+                _ => return Err(format!("Unexpected argument '{}'\n{}", arg, USAGE)),
+            };
+            if session != Session::Live {
+                return Err("Use only one of --record and --replay".to_string());
+            }
+            session = next;
+        }
+        let program = program.ok_or_else(|| USAGE.to_string())?;
+        Ok(Command::Run {
+            program,
+            session,
+            trap_overflow,
+        })
+    }
+
+    fn parse_link(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut programs = Vec::new();
+        let mut output = None;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-o" | "--output" => output = Some(value(&arg, args.next())?),
+                flag if flag.starts_with('-') => {
+                    return Err(format!("Unknown option '{}'\n{}", flag, USAGE));
+                }
+                _ => programs.push(arg),
+            }
+        }
+        let output = output.ok_or_else(|| format!("link requires -o <output>\n{}", USAGE))?;
+        if programs.is_empty() {
+            return Err(USAGE.to_string());
+        }
+        Ok(Command::Link { programs, output })
+    }
+
+    fn parse_verify(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let (flags, programs): (Vec<String>, Vec<String>) =
+            args.partition(|arg| arg.starts_with('-'));
+        if let Some(flag) = flags.iter().find(|flag| *flag != "--trap-overflow") {
+            return Err(format!("Unknown option '{}'\n{}", flag, USAGE));
+        }
+        if programs.is_empty() {
+            return Err(USAGE.to_string());
+        }
+        Ok(Command::Verify {
+            programs,
+            trap_overflow: !flags.is_empty(),
+        })
+    }
+
+    fn parse_conformance(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let programs: Vec<String> = args.collect();
+        if let Some(flag) = programs.iter().find(|arg| arg.starts_with('-')) {
+            return Err(format!("Unknown option '{}'\n{}", flag, USAGE));
+        }
+        Ok(Command::Conformance { programs })
+    }
+
+    fn parse_grammar(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut format = GrammarFormat::Ebnf;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let value = match arg.split_once('=') {
+                Some(("--format", value)) => value.to_string(),
+                _ if arg == "--format" => value(&arg, args.next())?,
+                _ if arg.starts_with('-') => {
+                    return Err(format!("Unknown option '{}'\n{}", arg, USAGE));
+                }
+                _ => return Err(format!("Unexpected argument '{}'\n{}", arg, USAGE)),
+            };
+            format = match value.as_str() {
+                "ebnf" => GrammarFormat::Ebnf,
+                "railroad" => GrammarFormat::Railroad,
+                other => {
+                    return Err(format!("Unknown grammar format '{}'\n{}", other, USAGE));
+                }
+            };
+        }
+        Ok(Command::Grammar(format))
+    }
+}
+
+/// Command-line options for a single transpiler run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    /// Files to compile; with more than one, the stage-by-stage output is
+    /// skipped and they are compiled in parallel.
+    pub inputs: Vec<String>,
+    /// Worker threads for batch compilation; defaults to the number of CPUs.
+    pub jobs: Option<usize>,
+    /// Source language; see `frontend::FRONTENDS`.
+    pub frontend: String,
+    /// Backends to generate code with, each saved to its own file; see
+    /// `backend::TARGETS`.
+    pub targets: Vec<String>,
+    pub deny_warnings: bool,
+    /// Variation of mini-rust to read the source as.
+    pub dialect: Dialect,
+    pub opt_report: bool,
+    /// Evaluate the programs directly instead of compiling them.
+    pub eval: bool,
+    /// Names of optimization passes to skip.
+    pub disabled_passes: Vec<String>,
+    /// Skip every optimization pass, so the Brainfuck can be written to
+    /// its file while it is generated.
+    pub no_optimize: bool,
+    /// Keep cells from wrapping anywhere but in the program's own
+    /// arithmetic, and skip optimizing, for `run --trap-overflow`.
+    pub checked_arithmetic: bool,
+    /// File with a `charset::Charset` table to spell the Brainfuck with.
+    pub charset: Option<String>,
+    /// Save the optimized program in another form than Brainfuck text.
+    pub emit: Option<Emit>,
+    /// With `--emit rust-embed`, add a `run` function to the Rust file.
+    pub embed_interpreter: bool,
+    /// Print the generated Brainfuck with a narration of each step.
+    pub explain_codegen: bool,
+    /// Leave out the comment naming the transpiler version and options.
+    pub no_metadata: bool,
+    /// Tape cells to leave unused before the first variable.
+    pub guard_cells: usize,
+    /// Cell the pointer is on when the Brainfuck starts, if the program may
+    /// run after other code rather than on a fresh tape.
+    pub entry_cell: Option<usize>,
+    /// Cell to leave the pointer on when the Brainfuck ends.
+    pub exit_cell: Option<usize>,
+    /// Look up division by a constant in loops in generated tables.
+    pub lookup_tables: bool,
+    /// Set literals used often once, in cells of their own.
+    pub constant_pool: bool,
+    /// How the Brainfuck implements function calls.
+    pub calls: CallStrategy,
+    /// Most frames recursive calls may save, for the Brainfuck and
+    /// `--eval`.
+    pub stack_depth: Option<u8>,
+    /// Largest file, in bytes, any target may produce.
+    pub max_size: Option<usize>,
+    /// Most tape cells the Brainfuck may use.
+    pub max_cells: Option<usize>,
+    /// Most Brainfuck commands a run of the program may execute.
+    pub max_est_steps: Option<u64>,
+    /// Print the size of each input and output.
+    pub stats: bool,
+    /// Print how long each stage of compiling took.
+    pub timings: bool,
+    /// File to save the `--stats` and `--timings` reports to as JSON.
+    pub report_json: Option<String>,
+    /// Where batch mode writes its compilation database.
+    pub build_db: String,
+    /// Variables to watch while running the program instead of compiling
+    /// it.
+    pub watch: Vec<String>,
+}
+
+/// Default path of the compilation database written in batch mode.
+pub const BUILD_DB: &str = "rust2bf-build.json";
+
+pub const USAGE: &str = "Usage: rust2bf [options] <filename>...
+       rust2bf example [<name> [<output>]]
+       rust2bf run <program.bf> [--record <file> | --replay <file>]
+                   [--trap-overflow]
+       rust2bf link <program.bf>... -o <output.bf>
+       rust2bf verify [--trap-overflow] <program>...
+       rust2bf conformance [<program>...]
+       rust2bf grammar [--format ebnf|railroad]
+Example: rust2bf example.rs
+
+Options:
+  --frontend <name>       Source language (default: mini-rust)
+  --target <name>[,...]   Output languages (default: bf; repeatable)
+  --deny-warnings         Treat warnings as errors
+  --dialect <name>        mini-rust (default); teaching: also accept print
+                          without parentheses; strict-rust: reject anything
+                          rustc would not, such as bare print(...)
+  --strict-rust           The same as --dialect strict-rust
+  --opt-report            Print what each optimization pass achieved and the
+                          functions dropped because nothing calls them
+  --eval                  Run the program directly instead of compiling it
+  --disable-pass <name>   Skip an optimization pass (repeatable)
+  --no-optimize           Skip every optimization pass, writing the
+                          Brainfuck of a single file as it is generated
+  --checked-arithmetic    Only let cells wrap in the program's own arithmetic,
+                          unoptimized, for run --trap-overflow to check
+  --charset <file>        Spell the Brainfuck commands as given in <file>
+  --emit bfc              Save compact bytecode instead of Brainfuck text
+  --emit rust-embed       Save a .embed.rs file with the program as a const
+  --emit rle              Save run-length-encoded Brainfuck (`15+`) as .rle
+  --embed-interpreter     Add a run() function to the --emit rust-embed file
+  --explain-codegen       Narrate what each part of the Brainfuck does
+  --no-metadata           Omit the version and options comment from output
+  --guard-cells <n>       Leave the first <n> tape cells unused (default: 0)
+  --entry-cell <n>        Start with the pointer on cell <n> of a used tape
+  --exit-cell <n>         End with the pointer on cell <n>
+  --lookup-tables         Divide by constants in loops with lookup tables
+  --constant-pool         Set literals used often once and copy them from there
+  --calls <strategy>      inline: copy each function into every call
+                          (default); dispatch: generate each function once,
+                          unless copying it is shorter; #[inline(...)] on a
+                          function overrides either
+  --stack-depth <n>       Recursive calls waiting at once before the program
+                          stops (default: 16, at most 255)
+  --max-size <bytes>      Fail if any output file is larger than this
+  --max-cells <n>         Fail if the Brainfuck may use more tape cells
+  --max-est-steps <n>     Fail if the Brainfuck runs for more steps
+  --stats                 Print input and output sizes for each file
+  --timings               Print how long each compilation stage took
+  --report-json <file>    Save the --stats and --timings reports as JSON
+  --build-db <file>       Where batch mode describes each build (default:
+                          rust2bf-build.json)
+  --jobs <n>              Files to compile at once in batch mode
+  --watch <name>          Run the program, pausing whenever the variable
+                          <name> changes (repeatable)";
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            inputs: Vec::new(),
+            jobs: None,
+            frontend: "mini-rust".to_string(),
+            targets: Vec::new(),
+            deny_warnings: false,
+            dialect: Dialect::MiniRust,
+            opt_report: false,
+            eval: false,
+            disabled_passes: Vec::new(),
+            no_optimize: false,
+            checked_arithmetic: false,
+            charset: None,
+            emit: None,
+            embed_interpreter: false,
+            explain_codegen: false,
+            no_metadata: false,
+            guard_cells: 0,
+            entry_cell: None,
+            exit_cell: None,
+            lookup_tables: false,
+            constant_pool: false,
+            calls: CallStrategy::Inline,
+            stack_depth: None,
+            max_size: None,
+            max_cells: None,
+            max_est_steps: None,
+            stats: false,
+            timings: false,
+            report_json: None,
+            build_db: BUILD_DB.to_string(),
+            watch: Vec::new(),
+        }
+    }
+}
+
+impl Options {
+    /// Parses the arguments following the program name.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options::default();
+
+        // `--emit=bfc` is the same as `--emit bfc`.
+        let mut args = args.into_iter().flat_map(|arg| match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                vec![flag.to_string(), value.to_string()]
+            }
+            _ => vec![arg],
+        });
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--frontend" => options.frontend = value(&arg, args.next())?,
+                "--target" => {
+                    for target in value(&arg, args.next())?.split(',') {
+                        if !options.targets.iter().any(|t| t == target) {
+                            options.targets.push(target.to_string());
+                        }
+                    }
+                }
+                "--deny-warnings" => options.deny_warnings = true,
+                "--strict-rust" => options.dialect = Dialect::StrictRust,
+                "--dialect" => {
+                    let name = value(&arg, args.next())?;
+                    options.dialect = Dialect::from_name(&name)
+                        .ok_or_else(|| format!("Unknown dialect '{}'\n{}", name, USAGE))?;
+                }
+                "--opt-report" => options.opt_report = true,
+                "--explain-codegen" => options.explain_codegen = true,
+                "--no-metadata" => options.no_metadata = true,
+                "--embed-interpreter" => options.embed_interpreter = true,
+                "--lookup-tables" => options.lookup_tables = true,
+                "--constant-pool" => options.constant_pool = true,
+                "--eval" => options.eval = true,
+                "--stats" => options.stats = true,
+                "--timings" => options.timings = true,
+                "--report-json" => options.report_json = Some(value(&arg, args.next())?),
+                "--build-db" => options.build_db = value(&arg, args.next())?,
+                "--disable-pass" => options.disabled_passes.push(value(&arg, args.next())?),
+                "--no-optimize" => options.no_optimize = true,
+                "--checked-arithmetic" => options.checked_arithmetic = true,
+                "--watch" => options.watch.push(value(&arg, args.next())?),
+                "--charset" => options.charset = Some(value(&arg, args.next())?),
+                "--calls" => match value(&arg, args.next())?.as_str() {
+                    "inline" => options.calls = CallStrategy::Inline,
+                    "dispatch" => options.calls = CallStrategy::Dispatch,
+                    strategy => {
+                        return Err(format!("Unknown call strategy '{}'\n{}", strategy, USAGE));
+                    }
+                },
+                "--emit" => match value(&arg, args.next())?.as_str() {
+                    "bfc" => options.emit = Some(Emit::Bytecode),
+                    "rust-embed" => options.emit = Some(Emit::RustEmbed),
+                    "rle" => options.emit = Some(Emit::Rle),
+                    format => {
+                        return Err(format!("Unknown output format '{}'\n{}", format, USAGE));
+                    }
+                },
+                "--guard-cells" => options.guard_cells = count(&arg, args.next())?,
+                "--entry-cell" => options.entry_cell = Some(count(&arg, args.next())?),
+                "--exit-cell" => options.exit_cell = Some(count(&arg, args.next())?),
+                "--stack-depth" => options.stack_depth = Some(count(&arg, args.next())?),
+                "--max-size" => options.max_size = Some(count(&arg, args.next())?),
+                "--max-cells" => options.max_cells = Some(count(&arg, args.next())?),
+                "--max-est-steps" => options.max_est_steps = Some(count(&arg, args.next())?),
+                "--jobs" => {
+                    let jobs = value(&arg, args.next())?;
+                    match jobs.parse() {
+                        Ok(jobs) if jobs > 0 => options.jobs = Some(jobs),
+                        _ => return Err(format!("Invalid job count '{}'\n{}", jobs, USAGE)),
+                    }
+                }
+                flag if flag.starts_with("--") => {
+                    return Err(format!("Unknown option '{}'\n{}", flag, USAGE));
+                }
+                _ => options.inputs.push(arg),
+            }
+        }
+
+        if options.inputs.is_empty() {
+            return Err(USAGE.to_string());
+        }
+        if options.targets.is_empty() {
+            options.targets.push("bf".to_string());
+        }
+        let bf = options.targets.iter().any(|target| target == "bf");
+        match options.emit {
+            Some(Emit::Bytecode) if !bf || options.charset.is_some() => {
+                return Err("--emit bfc only applies to plain Brainfuck output".to_string());
+            }
+            Some(Emit::RustEmbed) if !bf || options.charset.is_some() => {
+                return Err("--emit rust-embed only applies to plain Brainfuck output".to_string());
+            }
+            Some(Emit::Rle) if !bf || options.charset.is_some() => {
+                return Err("--emit rle only applies to plain Brainfuck output".to_string());
+            }
+            Some(Emit::RustEmbed) => {}
+            _ if options.embed_interpreter => {
+                return Err("--embed-interpreter requires --emit rust-embed".to_string());
+            }
+            _ => {}
+        }
+        if options.guard_cells > 0 && !bf {
+            return Err("--guard-cells only applies to Brainfuck output".to_string());
+        }
+        if (options.entry_cell.is_some() || options.exit_cell.is_some()) && !bf {
+            return Err("--entry-cell and --exit-cell only apply to Brainfuck output".to_string());
+        }
+        if (options.max_cells.is_some() || options.max_est_steps.is_some()) && !bf {
+            return Err(
+                "--max-cells and --max-est-steps only apply to Brainfuck output".to_string(),
+            );
+        }
+        if options.no_optimize && !bf {
+            return Err("--no-optimize only applies to Brainfuck output".to_string());
+        }
+        if options.checked_arithmetic && !bf {
+            return Err("--checked-arithmetic only applies to Brainfuck output".to_string());
+        }
+        if options.checked_arithmetic
+            && (options.charset.is_some()
+                || options.emit.is_some()
+                || options.entry_cell.is_some()
+                || options.no_metadata)
+        {
+            return Err(
+                "--checked-arithmetic needs the comment at the start of the output, \
+                 which --charset, --emit, --entry-cell and --no-metadata leave out"
+                    .to_string(),
+            );
+        }
+        if options.lookup_tables && !bf {
+            return Err("--lookup-tables only applies to Brainfuck output".to_string());
+        }
+        if options.constant_pool && !bf {
+            return Err("--constant-pool only applies to Brainfuck output".to_string());
+        }
+        if options.calls != CallStrategy::Inline && !bf {
+            return Err("--calls only applies to Brainfuck output".to_string());
+        }
+        if options.stack_depth.is_some() && !bf {
+            return Err("--stack-depth only applies to Brainfuck output".to_string());
+        }
+        if options.dialect != Dialect::MiniRust && options.frontend != "mini-rust" {
+            return Err("--dialect only applies to the mini-rust frontend".to_string());
+        }
+        if options.report_json.is_some() && !(options.stats || options.timings) {
+            return Err("--report-json requires --stats or --timings".to_string());
+        }
+        if options.eval && (options.stats || options.timings) {
+            return Err("--stats and --timings do not apply to --eval".to_string());
+        }
+        if options.explain_codegen && !bf {
+            return Err("--explain-codegen only applies to Brainfuck output".to_string());
+        }
+        if !options.watch.is_empty() && (options.eval || options.inputs.len() > 1) {
+            return Err("--watch runs a single program and does not apply to --eval".to_string());
+        }
+        Ok(options)
+    }
+
+    /// The arguments that compile `input` alone to the same outputs, leaving
+    /// out options that only change what is printed.
+    pub fn arguments(&self, input: &str) -> Vec<String> {
+        let mut args = vec![
+            "--frontend".to_string(),
+            self.frontend.clone(),
+            "--target".to_string(),
+            self.targets.join(","),
+        ];
+        let mut flag = |flag: &str, on: bool| {
+            if on {
+                args.push(flag.to_string());
+            }
+        };
+        flag("--deny-warnings", self.deny_warnings);
+        flag("--no-metadata", self.no_metadata);
+        flag("--embed-interpreter", self.embed_interpreter);
+        flag("--lookup-tables", self.lookup_tables);
+        flag("--constant-pool", self.constant_pool);
+        flag("--no-optimize", self.no_optimize);
+        flag("--checked-arithmetic", self.checked_arithmetic);
+        if self.dialect != Dialect::MiniRust {
+            args.extend(["--dialect".to_string(), self.dialect.name().to_string()]);
+        }
+        if self.calls == CallStrategy::Dispatch {
+            args.extend(["--calls".to_string(), "dispatch".to_string()]);
+        }
+        if let Some(depth) = self.stack_depth {
+            args.extend(["--stack-depth".to_string(), depth.to_string()]);
+        }
+        for pass in &self.disabled_passes {
+            args.extend(["--disable-pass".to_string(), pass.clone()]);
+        }
+        if let Some(charset) = &self.charset {
+            args.extend(["--charset".to_string(), charset.clone()]);
+        }
+        match self.emit {
+            Some(Emit::Bytecode) => args.extend(["--emit".to_string(), "bfc".to_string()]),
+            Some(Emit::RustEmbed) => {
+                args.extend(["--emit".to_string(), "rust-embed".to_string()]);
+            }
+            Some(Emit::Rle) => args.extend(["--emit".to_string(), "rle".to_string()]),
+            None => {}
+        }
+        if self.guard_cells > 0 {
+            args.extend(["--guard-cells".to_string(), self.guard_cells.to_string()]);
+        }
+        let cells = [
+            ("--entry-cell", self.entry_cell),
+            ("--exit-cell", self.exit_cell),
+        ];
+        for (flag, cell) in cells {
+            if let Some(cell) = cell {
+                args.extend([flag.to_string(), cell.to_string()]);
+            }
+        }
+        let budgets = [
+            ("--max-size", self.max_size.map(|max| max as u64)),
+            ("--max-cells", self.max_cells.map(|max| max as u64)),
+            ("--max-est-steps", self.max_est_steps),
+        ];
+        for (flag, budget) in budgets {
+            if let Some(max) = budget {
+                args.extend([flag.to_string(), max.to_string()]);
+            }
+        }
+        args.push(input.to_string());
+        args
+    }
+}
+
+fn value(flag: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("Option '{}' requires a value\n{}", flag, USAGE))
+}
+
+/// A non-negative number given to `flag`.
+fn count<T: core::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = self::value(flag, value)?;
+    value
+        .parse()
+        .map_err(|_| format!("Invalid number '{}' for '{}'\n{}", value, flag, USAGE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn test_parse_flags() {
+        let options = parse(&["--deny-warnings", "main.rs"]).unwrap();
+        assert_eq!(options.inputs, vec!["main.rs"]);
+        assert_eq!(options.targets, vec!["bf"]);
+        assert_eq!(options.frontend, "mini-rust");
+        assert!(options.deny_warnings);
+        assert!(!options.eval);
+        assert!(!options.no_metadata);
+        assert!(parse(&["--no-metadata", "main.rs"]).unwrap().no_metadata);
+        assert!(parse(&["--eval", "main.rs"]).unwrap().eval);
+    }
+
+    #[test]
+    fn test_parse_flag_values() {
+        let options = parse(&["--disable-pass", "fold-runs", "a.rs", "--opt-report"]).unwrap();
+        assert_eq!(options.disabled_passes, vec!["fold-runs"]);
+        assert!(options.opt_report);
+        assert!(parse(&["a.rs", "--disable-pass"]).is_err());
+        assert!(parse(&["--no-optimize", "a.rs"]).unwrap().no_optimize);
+        assert!(parse(&["--no-optimize", "--target", "c", "a.rs"]).is_err());
+        assert!(
+            parse(&["--checked-arithmetic", "a.rs"])
+                .unwrap()
+                .checked_arithmetic
+        );
+        assert!(parse(&["--checked-arithmetic", "--target", "c", "a.rs"]).is_err());
+        assert!(parse(&["--checked-arithmetic", "--no-metadata", "a.rs"]).is_err());
+        assert!(parse(&["--checked-arithmetic", "--emit", "bfc", "a.rs"]).is_err());
+
+        let options = parse(&["--charset", "ook.txt", "a.rs"]).unwrap();
+        assert_eq!(options.charset.as_deref(), Some("ook.txt"));
+    }
+
+    #[test]
+    fn test_guard_cells() {
+        assert_eq!(parse(&["a.rs"]).unwrap().guard_cells, 0);
+        assert_eq!(
+            parse(&["--guard-cells", "4", "a.rs"]).unwrap().guard_cells,
+            4
+        );
+        assert!(parse(&["--guard-cells", "-1", "a.rs"]).is_err());
+        assert!(parse(&["--guard-cells", "4", "--target", "c", "a.rs"]).is_err());
+    }
+
+    #[test]
+    fn test_pointer_convention() {
+        let options = parse(&["a.rs"]).unwrap();
+        assert_eq!((options.entry_cell, options.exit_cell), (None, None));
+        let options = parse(&["--entry-cell", "0", "--exit-cell=3", "a.rs"]).unwrap();
+        assert_eq!((options.entry_cell, options.exit_cell), (Some(0), Some(3)));
+        assert!(parse(&["--exit-cell", "0", "--target", "c", "a.rs"]).is_err());
+    }
+
+    #[test]
+    fn test_lookup_tables() {
+        let options = parse(&["--lookup-tables", "a.rs"]).unwrap();
+        assert!(options.lookup_tables);
+        assert!(
+            options
+                .arguments("a.rs")
+                .contains(&"--lookup-tables".to_string())
+        );
+        assert!(parse(&["--lookup-tables", "--target", "c", "a.rs"]).is_err());
+    }
+
+    #[test]
+    fn test_constant_pool() {
+        let options = parse(&["--constant-pool", "a.rs"]).unwrap();
+        assert!(options.constant_pool);
+        assert!(
+            options
+                .arguments("a.rs")
+                .contains(&"--constant-pool".to_string())
+        );
+        assert!(parse(&["--constant-pool", "--target", "c", "a.rs"]).is_err());
+    }
+
+    #[test]
+    fn test_call_strategy() {
+        assert_eq!(parse(&["a.rs"]).unwrap().calls, CallStrategy::Inline);
+        let options = parse(&["--calls=dispatch", "a.rs"]).unwrap();
+        assert_eq!(options.calls, CallStrategy::Dispatch);
+        let arguments = options.arguments("a.rs");
+        assert!(
+            arguments
+                .windows(2)
+                .any(|pair| pair == ["--calls", "dispatch"])
+        );
+        assert!(parse(&["--calls", "stack", "a.rs"]).is_err());
+        assert!(parse(&["--calls", "dispatch", "--target", "c", "a.rs"]).is_err());
+    }
+
+    #[test]
+    fn test_stack_depth() {
+        assert_eq!(parse(&["a.rs"]).unwrap().stack_depth, None);
+        let options = parse(&["--stack-depth", "40", "a.rs"]).unwrap();
+        assert_eq!(options.stack_depth, Some(40));
+        let arguments = options.arguments("a.rs");
+        assert!(
+            arguments
+                .windows(2)
+                .any(|pair| pair == ["--stack-depth", "40"])
+        );
+        assert!(parse(&["--stack-depth", "256", "a.rs"]).is_err());
+        assert!(parse(&["--stack-depth", "8", "--target", "c", "a.rs"]).is_err());
+    }
+
+    #[test]
+    fn test_dialect() {
+        assert_eq!(parse(&["a.rs"]).unwrap().dialect, Dialect::MiniRust);
+        let options = parse(&["--dialect=teaching", "a.rs"]).unwrap();
+        assert_eq!(options.dialect, Dialect::Teaching);
+        assert!(
+            options
+                .arguments("a.rs")
+                .windows(2)
+                .any(|pair| pair == ["--dialect", "teaching"])
+        );
+        let options = parse(&["--strict-rust", "a.rs"]).unwrap();
+        assert_eq!(options.dialect, Dialect::StrictRust);
+        assert!(parse(&["--dialect", "pirate", "a.rs"]).is_err());
+        assert!(parse(&["--strict-rust", "--frontend", "rust", "a.rs"]).is_err());
+    }
+
+    #[test]
+    fn test_budgets() {
+        let options = parse(&["--max-size", "100", "--max-est-steps", "5000", "a.rs"]).unwrap();
+        assert_eq!(options.max_size, Some(100));
+        assert_eq!(options.max_cells, None);
+        assert_eq!(options.max_est_steps, Some(5000));
+        assert!(parse(&["--max-size", "1k", "a.rs"]).is_err());
+        assert!(parse(&["--max-size", "100", "--target", "c", "a.rs"]).is_ok());
+        assert!(parse(&["--max-cells", "100", "--target", "c", "a.rs"]).is_err());
+    }
+
+    #[test]
+    fn test_reports() {
+        let options = parse(&["--stats", "--report-json=r.json", "a.rs"]).unwrap();
+        assert!(options.stats);
+        assert!(!options.timings);
+        assert_eq!(options.report_json.as_deref(), Some("r.json"));
+        assert!(parse(&["--report-json", "r.json", "a.rs"]).is_err());
+        assert!(parse(&["--timings", "--eval", "a.rs"]).is_err());
+    }
+
+    #[test]
+    fn test_arguments_round_trip() {
+        let options = parse(&[
+            "--target=bf,c",
+            "--disable-pass",
+            "fold-runs",
+            "--no-optimize",
+            "--checked-arithmetic",
+            "--guard-cells",
+            "2",
+            "--exit-cell",
+            "0",
+            "--max-cells",
+            "300",
+            "--stats",
+            "--jobs",
+            "2",
+            "a.rs",
+            "b.rs",
+        ])
+        .unwrap();
+        let args = options.arguments("b.rs");
+        assert_eq!(args[..4], ["--frontend", "mini-rust", "--target", "bf,c"]);
+        let single = Options::parse(args).unwrap();
+        assert_eq!(
+            single,
+            Options {
+                inputs: vec!["b.rs".to_string()],
+                jobs: None,
+                stats: false,
+                ..options
+            }
+        );
+    }
+
+    #[test]
+    fn test_emit_bytecode() {
+        let emit = |args: &[&str]| parse(args).map(|options| options.emit);
+        assert_eq!(emit(&["--emit", "bfc", "a.rs"]), Ok(Some(Emit::Bytecode)));
+        assert!(parse(&["--emit", "exe", "a.rs"]).is_err());
+        assert!(parse(&["--emit", "bfc", "--target", "c", "a.rs"]).is_err());
+    }
+
+    #[test]
+    fn test_emit_rust_embed() {
+        let options = parse(&["--emit=rust-embed", "--embed-interpreter", "a.rs"]).unwrap();
+        assert_eq!(options.emit, Some(Emit::RustEmbed));
+        assert!(options.embed_interpreter);
+        assert!(parse(&["--embed-interpreter", "a.rs"]).is_err());
+        assert!(parse(&["--emit", "rust-embed", "--charset", "ook.txt", "a.rs"]).is_err());
+    }
+
+    #[test]
+    fn test_emit_rle() {
+        let options = parse(&["--emit", "rle", "a.rs"]).unwrap();
+        assert_eq!(options.emit, Some(Emit::Rle));
+        assert_eq!(options.arguments("a.rs")[4..6], ["--emit", "rle"]);
+        assert!(parse(&["--emit", "rle", "--target", "c", "a.rs"]).is_err());
+        assert!(parse(&["--emit", "rle", "--charset", "ook.txt", "a.rs"]).is_err());
+    }
+
+    #[test]
+    fn test_multiple_targets() {
+        let options = parse(&[
+            "--target", "bf,c", "--target", "wat", "--target", "c", "a.rs",
+        ]);
+        assert_eq!(options.unwrap().targets, vec!["bf", "c", "wat"]);
+        assert!(parse(&["--emit", "bfc", "--target", "c,bf", "a.rs"]).is_ok());
+    }
+
+    #[test]
+    fn test_explain_codegen() {
+        assert!(
+            parse(&["--explain-codegen", "a.rs"])
+                .unwrap()
+                .explain_codegen
+        );
+        assert!(parse(&["--explain-codegen", "--target", "wat", "a.rs"]).is_err());
+    }
+
+    #[test]
+    fn test_watch() {
+        let options = parse(&["--watch", "x", "a.rs", "--watch=y"]).unwrap();
+        assert_eq!(options.watch, vec!["x", "y"]);
+        assert!(parse(&["--watch", "x", "--eval", "a.rs"]).is_err());
+        assert!(parse(&["--watch", "x", "a.rs", "b.rs"]).is_err());
+        assert!(parse(&["a.rs", "--watch"]).is_err());
+    }
+
+    #[test]
+    fn test_batch_inputs() {
+        let options = parse(&["a.rs", "--jobs", "4", "b.rs"]).unwrap();
+        assert_eq!(options.inputs, vec!["a.rs", "b.rs"]);
+        assert_eq!(options.jobs, Some(4));
+        assert!(parse(&["a.rs", "--jobs", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_run_command() {
+        let command = |args: &[&str]| Command::parse(args.iter().map(|s| s.to_string()));
+        assert_eq!(
+            command(&["run", "a.bf", "--record", "s.json"]).unwrap(),
+            Command::Run {
+                program: "a.bf".to_string(),
+                session: Session::Record("s.json".to_string()),
+                trap_overflow: false,
+            }
+        );
+        assert_eq!(
+            command(&["run", "--replay", "s.json", "a.bf", "--trap-overflow"]).unwrap(),
+            Command::Run {
+                program: "a.bf".to_string(),
+                session: Session::Replay("s.json".to_string()),
+                trap_overflow: true,
+            }
+        );
+        assert!(command(&["run"]).is_err());
+        assert!(command(&["run", "a.bf", "b.bf"]).is_err());
+        assert!(command(&["run", "a.bf", "--record", "a", "--replay", "b"]).is_err());
+    }
+
+    #[test]
+    fn test_link_command() {
+        let command = |args: &[&str]| Command::parse(args.iter().map(|s| s.to_string()));
+        assert_eq!(
+            command(&["link", "a.bf", "b.bf", "-o", "ab.bf"]).unwrap(),
+            Command::Link {
+                programs: vec!["a.bf".to_string(), "b.bf".to_string()],
+                output: "ab.bf".to_string()
+            }
+        );
+        assert!(command(&["link", "a.bf", "b.bf"]).is_err());
+        assert!(command(&["link", "--output=ab.bf"]).is_err());
+        assert!(command(&["link", "a.bf", "-x", "-o", "ab.bf"]).is_err());
+    }
+
+    #[test]
+    fn test_verify_command() {
+        let command = |args: &[&str]| Command::parse(args.iter().map(|s| s.to_string()));
+        assert_eq!(
+            command(&["verify", "a.rs", "b.rs"]).unwrap(),
+            Command::Verify {
+                programs: vec!["a.rs".to_string(), "b.rs".to_string()],
+                trap_overflow: false,
+            }
+        );
+        assert_eq!(
+            command(&["verify", "--trap-overflow", "a.rs"]).unwrap(),
+            Command::Verify {
+                programs: vec!["a.rs".to_string()],
+                trap_overflow: true,
+            }
+        );
+        assert!(command(&["verify"]).is_err());
+        assert!(command(&["verify", "a.rs", "--bogus"]).is_err());
+    }
+
+    #[test]
+    fn test_conformance_command() {
+        let command = |args: &[&str]| Command::parse(args.iter().map(|s| s.to_string()));
+        assert_eq!(
+            command(&["conformance"]).unwrap(),
+            Command::Conformance {
+                programs: Vec::new()
+            }
+        );
+        assert_eq!(
+            command(&["conformance", "a.rs"]).unwrap(),
+            Command::Conformance {
+                programs: vec!["a.rs".to_string()]
+            }
+        );
+        assert!(command(&["conformance", "--bogus"]).is_err());
+    }
+
+    #[test]
+    fn test_grammar_command() {
+        let command = |args: &[&str]| Command::parse(args.iter().map(|s| s.to_string()));
+        assert_eq!(
+            command(&["grammar"]),
+            Ok(Command::Grammar(GrammarFormat::Ebnf))
+        );
+        assert_eq!(
+            command(&["grammar", "--format", "railroad"]),
+            Ok(Command::Grammar(GrammarFormat::Railroad))
+        );
+        assert_eq!(
+            command(&["grammar", "--format=ebnf"]),
+            Ok(Command::Grammar(GrammarFormat::Ebnf))
+        );
+        assert!(command(&["grammar", "--format", "yacc"]).is_err());
+        assert!(command(&["grammar", "--format"]).is_err());
+        assert!(command(&["grammar", "a.rs"]).is_err());
+    }
+
+    #[test]
+    fn test_example_command() {
+        let command = |args: &[&str]| Command::parse(args.iter().map(|s| s.to_string()));
+        assert_eq!(
+            command(&["example", "fizzbuzz"]).unwrap(),
+            Command::Example {
+                name: Some("fizzbuzz".to_string()),
+                output: None
+            }
+        );
+        assert!(command(&["example", "a", "b", "c"]).is_err());
+        assert!(matches!(
+            command(&["main.rs"]).unwrap(),
+            Command::Compile(options) if options.inputs == ["main.rs"]
+        ));
+    }
+
+    #[test]
+    fn test_missing_input() {
+        assert!(parse(&["--deny-warnings"]).is_err());
+        assert!(parse(&["a.rs", "--bogus"]).is_err());
+    }
+}
@@ -1,22 +1,299 @@
-use crate::ast::{BinaryOp, Expr, Program, Stmt, Visitor};
-use crate::error::TranspilerResult;
-use std::collections::HashMap;
+use crate::ast::{
+    self, BinaryOp, Expr, Function, Inline, MatchArm, Pattern, Program, Stmt, UnaryOp, Visitor,
+};
+use crate::backend::{self, CodeGenerator};
+use crate::error::{TranspilerError, TranspilerResult};
+use crate::ranges::{self, Interval, Ranges};
+use crate::span::Span;
+use crate::symbol_table::{Symbol, SymbolTable, Type};
+use crate::trace;
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::fmt;
+use core::ops::Range;
+
+mod dispatch;
+
+/// Where temporaries start while the layout pass works out how many cells
+/// the variables need.
+const TEMP_OFFSET: usize = 100;
+
+/// How many uses just outside a loop one use inside it counts as when
+/// placing variables, taking a loop to run about this many times.
+const LOOP_WEIGHT: usize = 8;
+
+/// Roughly how many bytes of code a call through the dispatch loop takes,
+/// with the blocks it adds, whatever the function.
+const CALL_SIZE: usize = 480;
+
+/// The cells a loop with a `break` or `continue` in it keeps track of
+/// where it is going with.
+#[derive(Debug, Clone, Copy)]
+struct LoopCells {
+    /// Set while the loop should keep going; `break` clears it.
+    guard: usize,
+    /// Set at the start of each iteration; `break` and `continue` clear it
+    /// so the rest of the body is skipped.
+    alive: usize,
+}
+
+/// A call being expanded in place. Brainfuck has no call stack, so each
+/// call gets a copy of the function's code, with its parameters bound to
+/// cells holding the arguments and a cell for the result.
+#[derive(Debug, Clone)]
+struct Frame {
+    /// Where `return` leaves the value.
+    result: usize,
+    /// Cleared by `return` so the rest of the body is skipped, if anything
+    /// can follow one.
+    alive: Option<usize>,
+    /// How many entries of `loops` belong to the caller.
+    loops: usize,
+}
+
+/// How many frames recursive calls may save unless
+/// [`BrainfuckGenerator::with_stack_depth`] says otherwise.
+pub const DEFAULT_STACK_DEPTH: u8 = 16;
+
+/// How [`BrainfuckGenerator`] turns function calls into Brainfuck, which
+/// has no way to jump to code and back.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CallStrategy {
+    /// Expands a copy of the function's body at every call. The output grows
+    /// with every call, but runs as fast as if the body had been written out.
+    /// A recursive function would never finish expanding, so it is
+    /// generated as with [`Dispatch`](Self::Dispatch), along with every
+    /// function that calls it and every one marked `#[inline(never)]`.
+    #[default]
+    Inline,
+    /// Generates each function's body once, unless expanding it at every
+    /// call comes out shorter or it is marked `#[inline(always)]`. The
+    /// program is cut into blocks at every call, and a loop around them all
+    /// runs one block after another, each leaving the number of the next in
+    /// a cell. The output stays small however often a function is called,
+    /// but every jump between blocks costs steps, and more so once there
+    /// are over 255 and their numbers take two cells.
+    ///
+    /// A call that may lead back into the function making it first saves
+    /// that function's cells on a stack at the end of the variables, and
+    /// restores them once it returns. The stack has room for
+    /// [`with_stack_depth`](BrainfuckGenerator::with_stack_depth) frames; a
+    /// call that finds it full ends the program there, keeping what it has
+    /// printed.
+    Dispatch,
+}
 
 pub struct BrainfuckGenerator {
-    variables: HashMap<String, usize>,
+    symbols: SymbolTable,
+    next_var_addr: usize,
     memory_ptr: usize,
     output: String,
+    /// How much code has been passed on to the sink ahead of `output`.
+    flushed: usize,
+    /// Cells from here on hold temporaries, which nothing reads after the
+    /// statement that computed them.
+    first_temp_addr: usize,
     next_temp_addr: usize,
+    checked: bool,
+    /// Cell to leave the pointer on once the program is done.
+    exit_cell: Option<usize>,
+    lookup_tables: bool,
+    constant_pool: bool,
+    /// Where each literal is set in a cell of its own, recorded by the
+    /// layout pass.
+    literals: BTreeMap<u8, LiteralUses>,
+    /// The cell holding each pooled literal, set once before the program
+    /// runs.
+    pool: BTreeMap<u8, usize>,
+    calls: CallStrategy,
+    stack_depth: u8,
+    /// The cells each function saves when a call may lead back into it, as
+    /// indices into `variables`; found by the layout pass.
+    frame_cells: BTreeMap<String, Vec<usize>>,
+    /// How many blocks the dispatch loop runs, found by the layout pass.
+    dispatch_blocks: usize,
+    /// How many `while` and `loop` loops the code being generated is
+    /// inside.
+    loop_depth: usize,
+    /// Cells of each enclosing loop that has a `break` or `continue`,
+    /// innermost last.
+    loops: Vec<LoopCells>,
+    functions: BTreeMap<String, Function>,
+    /// The functions called through the dispatch loop rather than
+    /// expanded; see [`dispatched_functions`](Self::dispatched_functions).
+    dispatched: BTreeSet<String>,
+    /// The calls being expanded, innermost last.
+    frames: Vec<Frame>,
+    /// Cell of each single-cell variable, in the order they are declared;
+    /// empty until the layout pass has chosen them.
+    placement: Vec<usize>,
+    /// Cells of the single-cell variables declared so far.
+    variables: Vec<usize>,
+    /// How many of `variables` are still in use. An expanded call hands
+    /// its parameters' and locals' cells back when it returns, and the
+    /// next variables declared take the same ones again.
+    live_variables: usize,
+    /// How often the code reads or writes each variable cell, a use inside
+    /// a loop counting as [`LOOP_WEIGHT`] outside it.
+    uses: BTreeMap<usize, usize>,
+    /// The values each variable can hold at the code being generated, so
+    /// checked code can leave out guards against wrapping that never fire.
+    ranges: Ranges,
+    /// Code generated for each statement, recorded as each one finishes.
+    source_map: SourceMap,
+    // Expressions carry no spans of their own, so internal errors are
+    // reported at the statement containing them.
+    current_span: Span,
+    /// The first construct that could not be generated. The analyzer
+    /// rejects every program that leads to one, so this only catches ASTs
+    /// built by hand and shapes codegen does not know about yet.
+    error: Option<TranspilerError>,
+}
+
+impl Default for BrainfuckGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl BrainfuckGenerator {
     pub fn new() -> Self {
         Self {
-            variables: HashMap::new(),
+            symbols: SymbolTable::new(),
+            next_var_addr: 0,
             memory_ptr: 0,
             output: String::new(),
-            next_temp_addr: 100, // Start temp variables at cell 100
+            flushed: 0,
+            first_temp_addr: TEMP_OFFSET,
+            next_temp_addr: TEMP_OFFSET,
+            checked: false,
+            exit_cell: None,
+            lookup_tables: false,
+            constant_pool: false,
+            literals: BTreeMap::new(),
+            pool: BTreeMap::new(),
+            calls: CallStrategy::Inline,
+            stack_depth: DEFAULT_STACK_DEPTH,
+            frame_cells: BTreeMap::new(),
+            dispatch_blocks: 0,
+            loop_depth: 0,
+            loops: Vec::new(),
+            functions: BTreeMap::new(),
+            dispatched: BTreeSet::new(),
+            frames: Vec::new(),
+            placement: Vec::new(),
+            variables: Vec::new(),
+            live_variables: 0,
+            uses: BTreeMap::new(),
+            ranges: Ranges::new(),
+            source_map: SourceMap::default(),
+            current_span: Span::default(),
+            error: None,
+        }
+    }
+
+    /// Leaves the first `cells` cells of the tape unused, so a routine that
+    /// steps left of a variable still lands on the tape.
+    pub fn with_guard(mut self, cells: usize) -> Self {
+        self.next_var_addr = cells;
+        self
+    }
+
+    /// Generates code for a pointer that starts on `cell` rather than cell
+    /// 0, as when the program runs after other Brainfuck. Cells are still
+    /// numbered from the start of the tape.
+    pub fn with_entry_cell(mut self, cell: usize) -> Self {
+        self.memory_ptr = cell;
+        self
+    }
+
+    /// Ends the program with the pointer on `cell`, so more Brainfuck can
+    /// follow it knowing where it is.
+    pub fn with_exit_cell(mut self, cell: usize) -> Self {
+        self.exit_cell = Some(cell);
+        self
+    }
+
+    /// Keeps cells from wrapping anywhere but in the program's own
+    /// arithmetic, so running the output with
+    /// [`Overflow::Trap`](crate::bf::Overflow::Trap) stops exactly where the
+    /// source overflows. Comparisons become much slower, unless the values
+    /// compared are known not to need it, so this is meant for testing.
+    pub fn with_checked_arithmetic(mut self) -> Self {
+        self.checked = true;
+        self
+    }
+
+    /// Divides by a constant inside a `while` loop by looking the answer up
+    /// in a table of all 256 results rather than counting it out. A lookup
+    /// takes steps in proportion to the dividend, where counting takes its
+    /// square, at the cost of a few kilobytes of Brainfuck for each one.
+    pub fn with_lookup_tables(mut self) -> Self {
+        self.lookup_tables = true;
+        self
+    }
+
+    /// Keeps a literal the program sets many times, such as the 10 ending
+    /// every `println`, in a cell of its own that is set once at the start
+    /// and read wherever the literal is used. Only literals for which that
+    /// makes the output shorter are pooled.
+    pub fn with_constant_pool(mut self) -> Self {
+        self.constant_pool = true;
+        self
+    }
+
+    /// Chooses how function calls are generated; see [`CallStrategy`].
+    pub fn with_call_strategy(mut self, calls: CallStrategy) -> Self {
+        self.calls = calls;
+        self
+    }
+
+    /// Makes room for `depth` recursive calls to be waiting at once, each
+    /// saving a frame of its caller's cells; see
+    /// [`CallStrategy::Dispatch`]. The default is [`DEFAULT_STACK_DEPTH`].
+    pub fn with_stack_depth(mut self, depth: u8) -> Self {
+        self.stack_depth = depth;
+        self
+    }
+
+    /// Which statement each part of the last generated program came from.
+    pub fn source_map(&self) -> &SourceMap {
+        &self.source_map
+    }
+
+    /// The variable held in each cell of the last generated program; a
+    /// string's cells are named `byte 0 of s` and so on. A cell that two
+    /// variables share, as a `for` variable shares the string's, keeps the
+    /// name of the one declared first.
+    pub fn cell_names(&self) -> BTreeMap<usize, String> {
+        let mut names = BTreeMap::new();
+        for (_, symbol) in self.symbols.iter() {
+            for (i, addr) in symbol.cells.clone().enumerate() {
+                names.entry(addr).or_insert_with(|| match symbol.ty {
+                    Type::Str => format!("byte {} of {}", i, symbol.name),
+                    _ => symbol.name.clone(),
+                });
+            }
         }
+        for (value, &addr) in &self.pool {
+            names.insert(addr, format!("constant {}", value));
+        }
+        names
+    }
+
+    /// The cells of every variable called `name` in the last generated
+    /// program, including parameters bound to another variable's cell.
+    pub fn cells_of(&self, name: &str) -> BTreeSet<usize> {
+        self.symbols
+            .iter()
+            .filter(|(_, symbol)| symbol.name == name)
+            .flat_map(|(_, symbol)| symbol.cells.clone())
+            .collect()
     }
 
     fn mul_values(&mut self, result_addr: usize, left_addr: usize, right_addr: usize) {
@@ -51,56 +328,687 @@ impl BrainfuckGenerator {
         self.output.push_str("-]");
     }
 
-    fn div_values(&mut self, result_addr: usize, left_addr: usize, right_addr: usize) {
-        // Integer division: result = left / right
-        // Clear result
-        self.move_to(result_addr);
+    /// Integer division and remainder at once, by counting how many whole
+    /// copies of `right` can be taken from `left` and what is left of the
+    /// last, partial one. Dividing by zero gives 0 for both, since Brainfuck
+    /// has no way to fail; the evaluator reports it instead.
+    fn divmod(
+        &mut self,
+        quotient_addr: usize,
+        remainder_addr: usize,
+        left_addr: usize,
+        right_addr: usize,
+    ) {
+        self.move_to(quotient_addr);
+        self.clear_cell();
+        self.move_to(remainder_addr);
         self.clear_cell();
 
-        let temp_left = self.get_temp_addr();
-        let temp_right = self.get_temp_addr();
-
-        self.copy_value(left_addr, temp_left);
-        self.copy_value(right_addr, temp_right);
-
-        // Simplified division: subtract right from left until left < right
-        // Each subtraction increments the result
-        self.move_to(temp_left);
-        self.output.push('['); // While temp_left > 0
+        let remaining = self.get_temp_addr();
+        let divisor = self.get_temp_addr();
+        let by_zero = self.get_temp_addr();
+        self.copy_value(left_addr, remaining);
 
-        // Subtract temp_right from temp_left
-        let temp_sub = self.get_temp_addr();
-        self.copy_value(temp_right, temp_sub);
-        self.move_to(temp_sub);
+        // A zero divisor would never use anything up, so divide nothing.
+        self.copy_value(right_addr, divisor);
+        self.set_value(by_zero, 1);
+        self.move_to(divisor);
         self.output.push('[');
-        self.move_to(temp_left);
+        self.move_to(by_zero);
         self.output.push('-');
-        self.move_to(temp_sub);
+        self.move_to(divisor);
+        self.output.push_str("[-]]");
+        self.move_to(by_zero);
+        self.output.push('[');
+        self.move_to(remaining);
+        self.clear_cell();
+        self.move_to(by_zero);
         self.output.push_str("-]");
 
-        // Increment result
-        self.move_to(result_addr);
+        // Take `right` from what remains one unit at a time, so a last
+        // partial copy leaves 0 rather than wrapping; only whole copies
+        // count, and only a partial one leaves a remainder.
+        let whole = self.get_temp_addr();
+        let counter = self.get_temp_addr();
+        self.move_to(remaining);
+        self.output.push('[');
+        self.set_value(whole, 1);
+        self.copy_value(right_addr, counter);
+        self.move_to(remainder_addr);
+        self.clear_cell();
+        self.count_down_unwrapped(remaining, counter, whole, Some(remainder_addr));
+        self.move_to(whole);
+        self.output.push_str("[-");
+        self.move_to(quotient_addr);
         self.output.push('+');
-
-        self.move_to(temp_left);
+        self.move_to(remainder_addr);
+        self.clear_cell();
+        self.move_to(whole);
+        self.output.push(']');
+        self.move_to(remaining);
         self.output.push(']');
     }
 
     pub fn generate(&mut self, program: &Program) -> TranspilerResult<String> {
-        self.visit_program(program);
-        Ok(self.output.clone())
+        let mut code = String::new();
+        self.generate_into(program, &mut code)?;
+        Ok(code)
+    }
+
+    /// Like [`generate`](Self::generate), passing the code on to `sink`
+    /// after each top-level statement rather than holding all of it. A
+    /// program whose calls are dispatched is still built whole, since its
+    /// blocks are only written out once they are all numbered.
+    pub fn generate_into(
+        &mut self,
+        program: &Program,
+        sink: &mut dyn fmt::Write,
+    ) -> TranspilerResult<()> {
+        let _span = trace::span!("codegen");
+        self.functions = ast::functions(program)
+            .into_iter()
+            .map(|(name, function)| (name.to_string(), function.clone()))
+            .collect();
+        self.dispatched = self.dispatched_functions(program);
+        // Temporaries go after every variable, plus the scratch cell
+        // `set_value` uses after the last one. Strings and unrolled `for`
+        // bodies make that hard to count up front, so lay the variables out
+        // once to find out.
+        let mut layout = self.layout(program, 0);
+        // Numbering more blocks than a cell can takes cells of its own, so
+        // lay the program out again knowing how many there are.
+        if layout.dispatch_blocks > dispatch::NARROW_BLOCKS {
+            layout = self.layout(program, layout.dispatch_blocks);
+        }
+        self.place_variables(&layout);
+        self.frame_cells = core::mem::take(&mut layout.frame_cells);
+        self.dispatch_blocks = layout.dispatch_blocks;
+        // Pooled literals go between the scratch cell after the variables
+        // and the temporaries they are copied into, each set with the next
+        // as its scratch cell.
+        let mut scratch = layout.next_var_addr;
+        if self.constant_pool {
+            // Each pooled cell makes every trip between a variable and the
+            // temporaries one cell longer, and copying a variable takes
+            // about four.
+            let cost = 4 * layout.uses.values().sum::<usize>();
+            for (&value, uses) in &layout.literals {
+                if uses.savings(value) > cost {
+                    scratch += 1;
+                    self.pool.insert(value, scratch);
+                }
+            }
+        }
+        self.first_temp_addr = scratch + 1;
+        self.next_temp_addr = self.first_temp_addr;
+        for (value, addr) in self.pool.clone() {
+            self.set_value(addr, value.into());
+        }
+        self.emit_program(program, sink)
+            .map_err(|_| backend::write_error())?;
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        if let Some(cell) = self.exit_cell {
+            self.move_to(cell);
+        }
+        self.flush(sink).map_err(|_| backend::write_error())
+    }
+
+    /// Generates `program` as [`visit_program`](Visitor::visit_program)
+    /// does, flushing the code to `sink` after each top-level statement.
+    fn emit_program(&mut self, program: &Program, sink: &mut dyn fmt::Write) -> fmt::Result {
+        if self.dispatches(program) {
+            self.generate_dispatch(program);
+            return self.flush(sink);
+        }
+        for stmt in program {
+            self.generate_stmt(stmt);
+            self.flush(sink)?;
+        }
+        Ok(())
+    }
+
+    /// Passes the code generated so far on to `sink`.
+    fn flush(&mut self, sink: &mut dyn fmt::Write) -> fmt::Result {
+        self.flushed += self.output.len();
+        sink.write_str(&self.output)?;
+        self.output.clear();
+        Ok(())
+    }
+
+    /// How far into the whole program's code the next byte goes.
+    fn position(&self) -> usize {
+        self.flushed + self.output.len()
+    }
+
+    /// Whether `program` calls a function through the dispatch loop.
+    fn dispatches(&self, program: &Program) -> bool {
+        program.iter().any(|stmt| self.calls_dispatched(stmt))
+    }
+
+    /// Whether running `stmt` may call a function through the dispatch
+    /// loop.
+    fn calls_dispatched(&self, stmt: &Stmt) -> bool {
+        let mut names = BTreeSet::new();
+        stmt.callees(&mut names);
+        names.into_iter().any(|name| self.dispatched.contains(name))
+    }
+
+    /// The functions whose calls jump to one copy of the body: those that
+    /// may call themselves, which would never finish expanding, those
+    /// marked `#[inline(never)]`, and with [`CallStrategy::Dispatch`] the
+    /// unmarked ones whose copies would come to more than the calls cost.
+    /// An expanded body cannot jump, so a function that calls one of these
+    /// is dispatched too, even if marked `#[inline(always)]`.
+    fn dispatched_functions(&self, program: &Program) -> BTreeSet<String> {
+        let functions = ast::functions(program);
+        let callees: BTreeMap<&str, BTreeSet<&str>> = functions
+            .iter()
+            .map(|(&name, function)| {
+                let mut names = BTreeSet::new();
+                for stmt in &function.body {
+                    stmt.callees(&mut names);
+                }
+                (name, names)
+            })
+            .collect();
+        let spread = |dispatched: &mut BTreeSet<String>| loop {
+            let callers: Vec<String> = callees
+                .iter()
+                .filter(|(name, names)| {
+                    !dispatched.contains(**name)
+                        && names.iter().any(|callee| dispatched.contains(*callee))
+                })
+                .map(|(name, _)| name.to_string())
+                .collect();
+            if callers.is_empty() {
+                break;
+            }
+            dispatched.extend(callers);
+        };
+
+        let mut dispatched: BTreeSet<String> = ast::recursive_calls(&functions)
+            .into_iter()
+            .map(|(caller, _)| caller.to_string())
+            .chain(
+                functions
+                    .values()
+                    .filter(|function| function.inline == Inline::Never)
+                    .map(|function| function.name.clone()),
+            )
+            .collect();
+        spread(&mut dispatched);
+        if self.calls == CallStrategy::Dispatch {
+            let mut counts = BTreeMap::new();
+            for stmt in program
+                .iter()
+                .chain(functions.values().flat_map(|f| &f.body))
+            {
+                stmt.count_calls(&mut counts);
+            }
+            let smaller: Vec<String> = functions
+                .values()
+                .filter(|function| {
+                    function.inline == Inline::Auto && !dispatched.contains(&function.name)
+                })
+                .filter(|function| {
+                    // One copy is always smaller than a call and a body.
+                    let calls = counts.get(function.name.as_str()).copied().unwrap_or(0);
+                    calls > 1 && self.copy_size(function) * (calls - 1) > CALL_SIZE * calls
+                })
+                .map(|function| function.name.clone())
+                .collect();
+            dispatched.extend(smaller);
+            spread(&mut dispatched);
+        }
+        dispatched
+    }
+
+    /// How much longer each call to `function` expanded in place makes
+    /// the code, with arguments read from input so nothing is known about
+    /// them.
+    fn copy_size(&self, function: &Function) -> usize {
+        let size = |calls: usize| {
+            let args: Vec<Expr> = function.params.iter().map(|_| Expr::Input).collect();
+            let call = Stmt::expr(Expr::call(&function.name, args));
+            let program: Program = self
+                .functions
+                .values()
+                .cloned()
+                .map(Stmt::Function)
+                .chain(vec![call; calls])
+                .collect();
+            let mut generator = BrainfuckGenerator::new();
+            generator.checked = self.checked;
+            generator
+                .generate(&program)
+                .map_or(usize::MAX, |code| code.len())
+        };
+        size(2).saturating_sub(size(1))
+    }
+
+    /// Generates `program` only to see which cells it uses, given how many
+    /// blocks an earlier layout found the dispatch loop to have.
+    fn layout(&self, program: &Program, dispatch_blocks: usize) -> BrainfuckGenerator {
+        let mut layout = BrainfuckGenerator::new()
+            .with_guard(self.next_var_addr)
+            .with_call_strategy(self.calls)
+            .with_stack_depth(self.stack_depth);
+        layout.functions = self.functions.clone();
+        layout.dispatched = self.dispatched.clone();
+        layout.dispatch_blocks = dispatch_blocks;
+        // Only the cells matter, so the code is dropped as it goes.
+        let _ = layout.emit_program(program, &mut Discard);
+        layout
+    }
+
+    /// Most of the code is moves between variables and the temporaries
+    /// right after them, so the variables the layout pass saw used most,
+    /// counting uses in loops as many, go closest to the temporaries.
+    /// Temporaries themselves are handed out in the order they are needed.
+    /// Strings go after all of them, each followed by its scratch cell.
+    fn place_variables(&mut self, layout: &BrainfuckGenerator) {
+        let uses = |i: &usize| layout.uses.get(&layout.variables[*i]).copied().unwrap_or(0);
+        let mut order: Vec<usize> = (0..layout.variables.len()).collect();
+        order.sort_by_key(uses);
+        self.placement = vec![0; order.len()];
+        for (offset, i) in order.into_iter().enumerate() {
+            self.placement[i] = self.next_var_addr + offset;
+        }
+        self.next_var_addr += self.placement.len();
+    }
+
+    fn allocate_variable(&mut self, name: &str, mutable: bool, span: Span) -> usize {
+        let addr = self.allocate_cell();
+        self.symbols
+            .declare(Symbol::new(name, mutable, span).with_cells(addr..addr + 1));
+        trace::debug!(name, cell = addr, "allocated variable");
+        addr
     }
 
-    fn allocate_variable(&mut self, name: &str) -> usize {
-        let addr = self.memory_ptr;
-        self.variables.insert(name.to_string(), addr);
-        self.memory_ptr += 1;
+    /// A single cell that keeps its value from one statement to the next,
+    /// laid out like a variable's.
+    fn allocate_cell(&mut self) -> usize {
+        let index = self.live_variables;
+        self.live_variables += 1;
+        let addr = match self.variables.get(index) {
+            Some(&addr) => addr,
+            None => {
+                let addr = match self.placement.get(index) {
+                    Some(&addr) => addr,
+                    None => {
+                        self.next_var_addr += 1;
+                        self.next_var_addr - 1
+                    }
+                };
+                self.variables.push(addr);
+                addr
+            }
+        };
+        self.count_use(addr);
         addr
     }
 
+    /// Puts `text` in cells of its own, one byte per cell, and one more
+    /// for the last byte's scratch cell. Dispatched functions store their
+    /// strings in whatever order they are called, so that cell cannot be
+    /// the next string's first byte.
+    fn store_string(&mut self, text: &str) -> Range<usize> {
+        let start = self.next_var_addr;
+        self.next_var_addr += text.len() + 1;
+        // In order, since each byte's scratch cell is the next byte's.
+        for (addr, byte) in (start..).zip(text.bytes()) {
+            self.set_value(addr, byte.into());
+        }
+        start..start + text.len()
+    }
+
+    /// The cells of the string a `for` walks over.
+    fn string_cells(&mut self, iterable: &Expr) -> Range<usize> {
+        match iterable {
+            Expr::Str(text) => self.store_string(text),
+            Expr::Variable(string) => match self.symbols.lookup(string) {
+                Some(symbol) if symbol.ty == Type::Str => symbol.cells.clone(),
+                Some(_) => {
+                    self.internal_error(format!("`for` over `{}`, not a string", string));
+                    0..0
+                }
+                None => {
+                    self.internal_error(format!("undefined variable `{}`", string));
+                    0..0
+                }
+            },
+            _ => {
+                self.internal_error("`for` over an expression, not a string");
+                0..0
+            }
+        }
+    }
+
+    /// The cell of the variable `name`, counting the use for the layout.
+    fn variable_addr(&mut self, name: &str) -> Option<usize> {
+        let Some(addr) = self.symbols.lookup(name).map(Symbol::addr) else {
+            self.internal_error(format!("undefined variable `{}`", name));
+            return None;
+        };
+        self.count_use(addr);
+        Some(addr)
+    }
+
+    /// Counts a use of the variable in `addr` for the layout, weighted by
+    /// how many loops it is inside.
+    fn count_use(&mut self, addr: usize) {
+        let weight = LOOP_WEIGHT.saturating_pow(self.loop_depth.try_into().unwrap_or(u32::MAX));
+        let uses = self.uses.entry(addr).or_default();
+        *uses = uses.saturating_add(weight);
+    }
+
+    fn generate_block(&mut self, body: &[Stmt]) {
+        self.symbols.enter_scope();
+        self.ranges.enter_scope();
+        self.generate_stmts(body);
+        self.ranges.exit_scope();
+        self.symbols.exit_scope();
+    }
+
+    /// Generates `body` in order. Brainfuck cannot jump, so once a
+    /// statement may have run a `break`, `continue` or `return`, the rest
+    /// of the body only runs if the innermost `alive` cell is still set.
+    fn generate_stmts(&mut self, body: &[Stmt]) {
+        for (i, stmt) in body.iter().enumerate() {
+            self.generate_stmt(stmt);
+            let rest = &body[i + 1..];
+            if rest.is_empty() || !stmt.jumps() {
+                continue;
+            }
+            // Nothing after an unconditional jump ever runs.
+            if matches!(
+                stmt,
+                Stmt::Break { .. } | Stmt::Continue { .. } | Stmt::Return { .. }
+            ) {
+                return;
+            }
+            if let Some(alive) = self.alive() {
+                let flag = self.get_temp_addr();
+                self.copy_value(alive, flag);
+                self.move_to(flag);
+                self.output.push('[');
+                self.generate_stmts(rest);
+                self.move_to(flag);
+                self.clear_cell();
+                self.output.push(']');
+                return;
+            }
+        }
+    }
+
+    /// The loops with a `break` or `continue` in the function being
+    /// expanded, or in the whole program outside any call.
+    fn frame_loops(&self) -> &[LoopCells] {
+        let start = self.frames.last().map_or(0, |frame| frame.loops);
+        &self.loops[start..]
+    }
+
+    /// The cell a jump clears to skip the rest of the code it is in: the
+    /// innermost loop's, or the function's if no loop in it is running.
+    fn alive(&self) -> Option<usize> {
+        match self.frame_loops().last() {
+            Some(cells) => Some(cells.alive),
+            None => self.frames.last().and_then(|frame| frame.alive),
+        }
+    }
+
+    /// Expands a call to `name`, returning the cell holding its result.
+    /// The arguments are evaluated in the caller's scope. A variable is
+    /// passed by binding the parameter to its cell, unless the function
+    /// assigns to the parameter, and anything else is stored in a cell of
+    /// its own.
+    ///
+    /// Nothing but the result outlives the call, so every cell it took
+    /// after that one, variable or temporary, is free again once it
+    /// returns, and a function called from many places needs no more
+    /// cells than one call does.
+    fn generate_call(&mut self, name: &str, args: &[Expr]) -> usize {
+        let result = self.get_temp_addr();
+        let live_variables = self.live_variables;
+        self.set_value(result, 0);
+        let Some(function) = self.functions.get(name).cloned() else {
+            self.internal_error(format!("call to undefined function `{}`", name));
+            return result;
+        };
+        if args.len() != function.params.len() {
+            self.internal_error(format!(
+                "`{}` called with {} arguments instead of {}",
+                name,
+                args.len(),
+                function.params.len()
+            ));
+            return result;
+        }
+        let values: Vec<(usize, bool)> = args
+            .iter()
+            .map(|arg| {
+                let addr = self.evaluate_expression(arg);
+                (addr, matches!(arg, Expr::Variable(_)))
+            })
+            .collect();
+
+        self.symbols.enter_function();
+        let mut ranges = Ranges::new();
+        for (param, arg) in function.params.iter().zip(args) {
+            ranges.declare(param, self.ranges.of(arg));
+        }
+        let ranges = core::mem::replace(&mut self.ranges, ranges);
+        for (param, (addr, variable)) in function.params.iter().zip(values) {
+            if variable && !function.body.iter().any(|stmt| stmt.assigns(param)) {
+                self.symbols
+                    .declare(Symbol::new(param, false, function.span).with_cells(addr..addr + 1));
+            } else {
+                let cell = self.allocate_variable(param, false, function.span);
+                self.store_value(addr, cell);
+            }
+        }
+        // Only needed if code can follow a `return`.
+        let alive = function
+            .body
+            .iter()
+            .enumerate()
+            .any(|(i, stmt)| {
+                stmt.returns()
+                    && !(i + 1 == function.body.len() && matches!(stmt, Stmt::Return { .. }))
+            })
+            .then(|| {
+                let alive = self.get_temp_addr();
+                self.set_value(alive, 1);
+                alive
+            });
+        self.frames.push(Frame {
+            result,
+            alive,
+            loops: self.loops.len(),
+        });
+        self.generate_stmts(&function.body);
+        self.frames.pop();
+        self.ranges = ranges;
+        self.symbols.exit_function();
+        self.live_variables = live_variables;
+        self.next_temp_addr = result + 1;
+        result
+    }
+
+    /// Generates a loop that runs while `guard` is nonzero, setting it to
+    /// `condition` again after each iteration if there is one.
+    ///
+    /// Inside, only what holds on every pass is known, narrowed by
+    /// `condition` if it was `tested` before the first one. After, the
+    /// condition has failed, unless a `break` can end the loop instead.
+    fn generate_loop(
+        &mut self,
+        guard: usize,
+        condition: Option<&Expr>,
+        tested: bool,
+        body: &[Stmt],
+    ) {
+        self.ranges.widen(body);
+        let before = self.ranges.clone();
+        if let Some(condition) = condition.filter(|_| tested) {
+            self.ranges.assume(condition, true);
+        }
+        let alive = body.iter().any(Stmt::jumps).then(|| {
+            let alive = self.get_temp_addr();
+            self.loops.push(LoopCells { guard, alive });
+            alive
+        });
+        self.move_to(guard);
+        self.output.push('[');
+        self.loop_depth += 1;
+        if let Some(alive) = alive {
+            self.set_value(alive, 1);
+        }
+
+        self.generate_block(body);
+
+        match (condition, alive) {
+            (None, _) => {}
+            (Some(condition), None) => {
+                let new_condition_addr = self.evaluate_condition(condition);
+                self.store_value(new_condition_addr, guard);
+            }
+            // A `break` cleared the guard, which testing the condition
+            // again would undo.
+            (Some(condition), Some(_)) => {
+                let flag = self.get_temp_addr();
+                self.copy_value(guard, flag);
+                self.move_to(flag);
+                self.output.push('[');
+                let new_condition_addr = self.evaluate_condition(condition);
+                self.store_value(new_condition_addr, guard);
+                self.move_to(flag);
+                self.clear_cell();
+                self.output.push(']');
+            }
+        }
+        self.loop_depth -= 1;
+        if alive.is_some() {
+            self.loops.pop();
+        }
+        self.move_to(guard);
+        self.output.push(']');
+        self.ranges = before;
+        if let Some(condition) = condition
+            && !body.iter().any(Stmt::breaks)
+        {
+            self.ranges.assume(condition, false);
+        }
+    }
+
+    /// Generates a `match` as a cascade of equality tests on one copy of
+    /// the scrutinee. `pending` stays set until an arm runs, so at most one
+    /// does; that also makes it safe to test a variable in place, as an arm
+    /// assigning to it has already run. Arms the analyzer reports as
+    /// unreachable are left out.
+    fn generate_match(&mut self, scrutinee: &Expr, arms: &[MatchArm]) {
+        let mut seen = BTreeSet::new();
+        let mut reachable = Vec::new();
+        for arm in arms {
+            match arm.pattern.value() {
+                Some(value) => {
+                    if seen.insert(value) {
+                        reachable.push(arm);
+                    }
+                }
+                None => {
+                    reachable.push(arm);
+                    break;
+                }
+            }
+        }
+        if let Some(value) = scrutinee.constant() {
+            if let Some(arm) = reachable.iter().find(|arm| arm.pattern.matches(value)) {
+                self.generate_block(&arm.body);
+            }
+            return;
+        }
+
+        let scrutinee_range = self.ranges.of(scrutinee);
+        let scrutinee_addr = self.evaluate_expression(scrutinee);
+        let pending = self.get_temp_addr();
+        self.set_value(pending, 1);
+        let before = self.ranges.clone();
+        for arm in reachable {
+            let mark = self.next_temp_addr;
+            let matched = match arm.pattern {
+                Pattern::Number(value) => {
+                    let value_addr = self.get_temp_addr();
+                    self.set_value(value_addr, value);
+                    let matched = self.get_temp_addr();
+                    let value = Interval::exact(value.rem_euclid(256) as u8);
+                    self.compare_equal(
+                        matched,
+                        (scrutinee_addr, scrutinee_range),
+                        (value_addr, value),
+                    );
+                    Some(matched)
+                }
+                Pattern::Wildcard => None,
+            };
+            if let Some(matched) = matched {
+                self.move_to(matched);
+                self.output.push('[');
+                self.clear_cell();
+            }
+            self.move_to(pending);
+            self.output.push('[');
+            self.clear_cell();
+            self.generate_block(&arm.body);
+            let after_arm = core::mem::replace(&mut self.ranges, before.clone());
+            self.ranges.join(&after_arm);
+            self.move_to(pending);
+            self.output.push(']');
+            if let Some(matched) = matched {
+                self.move_to(matched);
+                self.output.push(']');
+            }
+            self.next_temp_addr = mark;
+        }
+    }
+
+    /// Generates `stmt`, then hands its temporaries back so the next
+    /// statement reuses the same cells. Statements nested in a body take
+    /// theirs after the enclosing condition's, which stays live throughout.
+    /// Every routine clears a temporary before relying on its value, so
+    /// whatever a previous statement left there does no harm.
+    fn generate_stmt(&mut self, stmt: &Stmt) {
+        let mark = self.next_temp_addr;
+        let start = self.position();
+        let enclosing_span = core::mem::replace(&mut self.current_span, stmt.span());
+        self.visit_stmt(stmt);
+        self.current_span = enclosing_span;
+        self.source_map
+            .statements
+            .push((start..self.position(), stmt.span()));
+        trace::debug!(
+            released = self.next_temp_addr - mark,
+            "released temporaries"
+        );
+        self.next_temp_addr = mark;
+    }
+
+    /// Reports that the current statement could not be generated. Code
+    /// generation carries on, so the layout stays consistent, but only the
+    /// first error is returned.
+    fn internal_error(&mut self, message: impl Into<String>) {
+        if self.error.is_none() {
+            let message = format!("internal error: {}", message.into());
+            self.error = Some(TranspilerError::with_span(message, self.current_span));
+        }
+    }
+
     fn get_temp_addr(&mut self) -> usize {
         let addr = self.next_temp_addr;
         self.next_temp_addr += 1;
+        trace::debug!(cell = addr, "allocated temporary");
         addr
     }
 
@@ -117,17 +1025,102 @@ impl BrainfuckGenerator {
         self.output.push_str("[-]");
     }
 
+    /// Prints `text` from a single cell, which goes from each byte to the
+    /// next by counting up or down, or is set afresh when that is longer.
+    fn print_text(&mut self, text: &str) {
+        let cell = self.get_temp_addr();
+        let mut current = None;
+        for byte in text.bytes() {
+            let up = byte.wrapping_sub(current.unwrap_or(0));
+            let down = current.unwrap_or(0).wrapping_sub(byte);
+            match current {
+                Some(_) if usize::from(up.min(down)) <= set_value_len(byte) => {
+                    self.move_to(cell);
+                    if up <= down {
+                        self.output.push_str(&"+".repeat(up.into()));
+                    } else {
+                        self.output.push_str(&"-".repeat(down.into()));
+                    }
+                }
+                _ => self.set_value(cell, byte.into()),
+            }
+            self.move_to(cell);
+            self.output.push('.');
+            current = Some(byte);
+        }
+    }
+
+    /// Prints the value in `addr` as decimal digits, splitting them off by
+    /// dividing by 10. A leading digit is printed only if it or one before
+    /// it is nonzero, and one the value can never reach is not computed.
+    fn print_decimal(&mut self, addr: usize, range: Interval) {
+        let units = self.get_temp_addr();
+        if range.max < 10 {
+            self.copy_value(addr, units);
+            self.print_digit(units);
+            return;
+        }
+        let ten = self.get_temp_addr();
+        // Everything above the units, nonzero whenever a tens digit is
+        // printed.
+        let rest = self.get_temp_addr();
+        self.set_value(ten, 10);
+        self.divmod(rest, units, addr, ten);
+        let tens = if range.max < 100 {
+            rest
+        } else {
+            let hundreds = self.get_temp_addr();
+            let tens = self.get_temp_addr();
+            self.divmod(hundreds, tens, rest, ten);
+            self.move_to(hundreds);
+            self.output.push('[');
+            self.print_digit(hundreds);
+            self.clear_cell();
+            self.output.push(']');
+            tens
+        };
+        self.move_to(rest);
+        self.output.push('[');
+        self.print_digit(tens);
+        self.move_to(rest);
+        self.clear_cell();
+        self.output.push(']');
+        self.print_digit(units);
+    }
+
+    /// Turns the digit in `addr` into its ASCII character and prints it.
+    fn print_digit(&mut self, addr: usize) {
+        // `0` is 6 * 8.
+        let counter = self.get_temp_addr();
+        self.set_value(counter, 6);
+        self.output.push('[');
+        self.move_to(addr);
+        self.output.push_str("++++++++");
+        self.move_to(counter);
+        self.output.push_str("-]");
+        self.move_to(addr);
+        self.output.push('.');
+    }
+
     fn set_value(&mut self, addr: usize, value: i32) {
         self.move_to(addr);
         self.clear_cell();
 
+        // Cells wrap at 256, so only the residue matters; without this a
+        // large or negative literal would expand to millions of `+`/`-`.
+        let value = value.rem_euclid(256);
+
         if value > 0 {
             // Optimize for larger values using loops
             if value >= 10 {
-                let loop_count = (value as f64).sqrt() as i32;
+                let loop_count = value.isqrt();
                 let remainder = value - (loop_count * loop_count);
 
-                // Set up loop counter
+                // Set up loop counter. The scratch cell may still hold a
+                // value from an earlier iteration of an enclosing loop.
+                self.move_to(addr + 1);
+                self.clear_cell();
+                self.move_to(addr);
                 self.output.push_str(&"+".repeat(loop_count as usize));
                 self.output.push('[');
                 self.move_to(addr + 1);
@@ -151,12 +1144,36 @@ impl BrainfuckGenerator {
             } else {
                 self.output.push_str(&"+".repeat(value as usize));
             }
-        } else if value < 0 {
-            self.output.push_str(&"-".repeat((-value) as usize));
         }
     }
 
+    /// A cell holding `n`: its pooled cell, or else a new temporary set to
+    /// it. `printed` says the cell is only going to be printed.
+    fn literal(&mut self, n: i32, printed: bool) -> usize {
+        let value = n.rem_euclid(256) as u8;
+        // Reading a pooled cell means going back to it from the
+        // temporaries, and copying it unless it is only printed.
+        let trip = 2 * (self.next_temp_addr - self.first_temp_addr + 1);
+        let uses = self.literals.entry(value).or_default();
+        uses.count += 1;
+        uses.reads += if printed { trip } else { COPY_LEN + 2 * trip };
+        if let Some(&addr) = self.pool.get(&value) {
+            return addr;
+        }
+        let addr = self.get_temp_addr();
+        self.set_value(addr, n);
+        addr
+    }
+
+    fn is_temp(&self, addr: usize) -> bool {
+        addr >= self.first_temp_addr
+    }
+
     fn copy_value(&mut self, from: usize, to: usize) {
+        // Clearing the destination would clear the source too.
+        if from == to {
+            return;
+        }
         let temp = self.get_temp_addr();
 
         // Clear destination and temp
@@ -184,6 +1201,67 @@ impl BrainfuckGenerator {
         self.output.push(']');
     }
 
+    /// Sets `to` to the value in `from`, emptying `from` when it is a
+    /// temporary instead of copying it back.
+    fn store_value(&mut self, from: usize, to: usize) {
+        if from == to || !self.is_temp(from) {
+            self.copy_value(from, to);
+            return;
+        }
+        self.move_to(to);
+        self.clear_cell();
+        self.transfer(from, to, '+');
+    }
+
+    /// Empties `from` into `to`, applying `op` (`+` or `-`) once per unit.
+    fn transfer(&mut self, from: usize, to: usize, op: char) {
+        self.move_to(from);
+        self.output.push_str("[-");
+        self.move_to(to);
+        self.output.push(op);
+        self.move_to(from);
+        self.output.push(']');
+    }
+
+    /// Generates `name = name + value` (or `-`) by changing the variable's
+    /// cell directly rather than computing the sum elsewhere and copying it
+    /// back. Returns false, generating nothing, for any other assignment.
+    fn update_in_place(&mut self, addr: usize, name: &str, value: &Expr) -> bool {
+        let Expr::Binary {
+            left,
+            operator,
+            right,
+        } = value
+        else {
+            return false;
+        };
+        let is_target = |expr: &Expr| matches!(expr, Expr::Variable(source) if source == name);
+        let (op, operand) = match operator {
+            BinaryOp::Add if is_target(left) => ('+', right),
+            BinaryOp::Add if is_target(right) => ('+', left),
+            BinaryOp::Sub if is_target(left) => ('-', right),
+            _ => return false,
+        };
+
+        // Written out in full so the cell wraps exactly when the arithmetic
+        // does; the optimizer picks the shorter direction afterwards.
+        if let Some(n) = operand.constant() {
+            self.move_to(addr);
+            self.output.extend(core::iter::repeat_n(op, n.into()));
+            return true;
+        }
+        let mut operand_addr = self.evaluate_expression(operand);
+        // Applying the operand must not consume it when it is a variable,
+        // least of all when it is the target itself, as in `x = x + x`.
+        if !self.is_temp(operand_addr) {
+            let temp = self.get_temp_addr();
+            self.copy_value(operand_addr, temp);
+            operand_addr = temp;
+        }
+        self.transfer(operand_addr, addr, op);
+        true
+    }
+
     fn add_values(&mut self, result_addr: usize, left_addr: usize, right_addr: usize) {
         // Copy left to result
         self.copy_value(left_addr, result_addr);
@@ -214,7 +1292,23 @@ impl BrainfuckGenerator {
         self.output.push(']');
     }
 
-    fn compare_equal(&mut self, result_addr: usize, left_addr: usize, right_addr: usize) {
+    /// Sets `result_addr` to whether the two cells are equal, given the
+    /// values each can hold. Checked code only subtracts one from the other
+    /// where that is known not to go below zero, and otherwise counts both
+    /// down together.
+    fn compare_equal(
+        &mut self,
+        result_addr: usize,
+        (left_addr, left): (usize, Interval),
+        (right_addr, right): (usize, Interval),
+    ) {
+        let (left_addr, right_addr, guarded) = if !self.checked || left.min >= right.max {
+            (left_addr, right_addr, false)
+        } else if right.min >= left.max {
+            (right_addr, left_addr, false)
+        } else {
+            (left_addr, right_addr, true)
+        };
         let temp1 = self.get_temp_addr();
         let temp2 = self.get_temp_addr();
 
@@ -227,13 +1321,17 @@ impl BrainfuckGenerator {
         self.clear_cell();
         self.output.push('+'); // Assume equal initially
 
-        // Subtract temp2 from temp1
-        self.move_to(temp2);
-        self.output.push_str("[-");
-        self.move_to(temp1);
-        self.output.push('-');
-        self.move_to(temp2);
-        self.output.push(']');
+        if guarded {
+            self.count_down_unwrapped(temp1, temp2, result_addr, None);
+        } else {
+            // Subtract temp2 from temp1
+            self.move_to(temp2);
+            self.output.push_str("[-");
+            self.move_to(temp1);
+            self.output.push('-');
+            self.move_to(temp2);
+            self.output.push(']');
+        }
 
         // If temp1 is not zero, values were not equal
         self.move_to(temp1);
@@ -244,164 +1342,677 @@ impl BrainfuckGenerator {
         self.output.push_str("[-]]"); // Clear temp1
     }
 
-    fn evaluate_condition(&mut self, condition: &Expr) -> usize {
-        match condition {
-            Expr::Binary {
-                left,
-                operator: BinaryOp::Equal,
-                right,
-            } => {
-                let left_addr = self.evaluate_expression(left);
-                let right_addr = self.evaluate_expression(right);
-                let result_addr = self.get_temp_addr();
-                self.compare_equal(result_addr, left_addr, right_addr);
-                result_addr
-            }
-            Expr::Binary {
-                left,
-                operator: BinaryOp::Greater,
-                right: _,
-            } => {
-                // Simplified: just evaluate left side
-                self.evaluate_expression(left)
+    /// Sets `result_addr` to `table(index)` with one case per possible
+    /// index, nested so that case `k` is reached after `k` decrements:
+    ///
+    /// ```text
+    /// index[- index[- ... flag[- result = table(255) ] ... index] flag[- result = table(1) ] index]
+    /// flag[- result = table(0) ]
+    /// ```
+    ///
+    /// Only the innermost case entered still finds `flag` set. `result_addr`
+    /// must be the last temporary allocated, so that the scratch cell
+    /// `set_value` uses is `index`, which is 0 by then.
+    fn lookup(&mut self, result_addr: usize, index_addr: usize, table: impl Fn(u8) -> u8) {
+        let index = self.get_temp_addr();
+        let flag = self.get_temp_addr();
+        self.copy_value(index_addr, index);
+        self.set_value(flag, 1);
+        self.move_to(result_addr);
+        self.clear_cell();
+
+        for _ in 0..u8::MAX {
+            self.move_to(index);
+            self.output.push_str("[-");
+        }
+        for k in (0..=u8::MAX).rev() {
+            self.move_to(flag);
+            self.output.push_str("[-");
+            self.set_value(result_addr, table(k).into());
+            self.move_to(flag);
+            self.output.push(']');
+            if k > 0 {
+                self.move_to(index);
+                self.output.push(']');
             }
-            _ => self.evaluate_expression(condition),
         }
     }
 
-    fn evaluate_expression(&mut self, expr: &Expr) -> usize {
-        match expr {
-            Expr::Number(n) => {
-                let addr = self.get_temp_addr();
-                self.set_value(addr, *n);
-                addr
-            }
-            Expr::Variable(name) => {
-                if let Some(&addr) = self.variables.get(name) {
-                    addr
-                } else {
-                    // Error: undefined variable - create a zero cell
+    /// The divisor to look `/` or `%` up by, if tables are on and this one
+    /// is worth it.
+    fn table_divisor(&self, right: &Expr) -> Option<u8> {
+        if !self.lookup_tables || self.loop_depth == 0 {
+            return None;
+        }
+        right.constant().filter(|&divisor| divisor > 0)
+    }
+
+    /// `left && right` or `left || right`. The result starts as `left`, and
+    /// `right` is generated inside a branch taken only when that does not
+    /// already settle it, so it is not evaluated otherwise.
+    fn logical(&mut self, left: &Expr, operator: &BinaryOp, right: &Expr) -> usize {
+        let left_addr = self.evaluate_expression(left);
+        let result_addr = self.get_temp_addr();
+        let undecided = self.get_temp_addr();
+        self.copy_value(left_addr, result_addr);
+        self.copy_value(result_addr, undecided);
+        if matches!(operator, BinaryOp::Or) {
+            self.negate(undecided);
+        }
+
+        self.move_to(undecided);
+        self.output.push('[');
+        let right_addr = self.evaluate_expression(right);
+        self.copy_value(right_addr, result_addr);
+        self.move_to(undecided);
+        self.clear_cell();
+        self.output.push(']');
+        result_addr
+    }
+
+    /// Flips `addr` between 0 and 1.
+    fn negate(&mut self, addr: usize) {
+        let temp = self.get_temp_addr();
+        self.set_value(temp, 1);
+        self.move_to(addr);
+        self.output.push_str("[-");
+        self.move_to(temp);
+        self.output.push('-');
+        self.move_to(addr);
+        self.output.push(']');
+        self.transfer(temp, addr, '+');
+    }
+
+    /// Unsigned `left < right`: take `right` from a copy of `left` one unit
+    /// at a time, and `left` is smaller exactly when it runs out first.
+    fn compare_less(&mut self, result_addr: usize, left_addr: usize, right_addr: usize) {
+        let remaining = self.get_temp_addr();
+        let counter = self.get_temp_addr();
+        let enough = self.get_temp_addr();
+        self.copy_value(left_addr, remaining);
+        self.copy_value(right_addr, counter);
+        self.set_value(enough, 1);
+        self.count_down_unwrapped(remaining, counter, enough, None);
+
+        self.set_value(result_addr, 1);
+        self.move_to(enough);
+        self.output.push('[');
+        self.move_to(result_addr);
+        self.output.push('-');
+        self.move_to(enough);
+        self.output.push_str("-]");
+        self.move_to(remaining);
+        self.clear_cell();
+    }
+
+    /// Empties `counter`, taking one from `value` each time unless it is
+    /// already 0, in which case `result` is cleared instead. Unlike a plain
+    /// subtraction this never wraps, but testing `value` means moving it out
+    /// and back on every pass. Each unit taken is added to `taken`, if given.
+    fn count_down_unwrapped(
+        &mut self,
+        value: usize,
+        counter: usize,
+        result: usize,
+        taken: Option<usize>,
+    ) {
+        let spare = self.get_temp_addr();
+        let nonzero = self.get_temp_addr();
+        let zero = self.get_temp_addr();
+        self.move_to(spare);
+        self.clear_cell();
+        self.move_to(nonzero);
+        self.clear_cell();
+
+        self.move_to(counter);
+        self.output.push('[');
+        // nonzero = value != 0, moving value through spare
+        self.move_to(value);
+        self.output.push_str("[-");
+        self.move_to(spare);
+        self.output.push('+');
+        self.move_to(nonzero);
+        self.clear_cell();
+        self.output.push('+');
+        self.move_to(value);
+        self.output.push(']');
+        self.transfer(spare, value, '+');
+
+        self.move_to(zero);
+        self.clear_cell();
+        self.output.push('+');
+        self.move_to(nonzero);
+        self.output.push_str("[-");
+        self.move_to(value);
+        self.output.push('-');
+        if let Some(taken) = taken {
+            self.move_to(taken);
+            self.output.push('+');
+        }
+        self.move_to(zero);
+        self.output.push('-');
+        self.move_to(nonzero);
+        self.output.push(']');
+        self.move_to(zero);
+        self.output.push_str("[-");
+        self.move_to(result);
+        self.clear_cell();
+        self.move_to(zero);
+        self.output.push(']');
+
+        self.move_to(counter);
+        self.output.push_str("-]");
+    }
+
+    fn evaluate_condition(&mut self, condition: &Expr) -> usize {
+        match condition {
+            Expr::Binary {
+                left,
+                operator: BinaryOp::Equal,
+                right,
+            } => {
+                let ranges = (self.ranges.of(left), self.ranges.of(right));
+                let left_addr = self.evaluate_expression(left);
+                let right_addr = self.evaluate_expression(right);
+                let result_addr = self.get_temp_addr();
+                self.compare_equal(result_addr, (left_addr, ranges.0), (right_addr, ranges.1));
+                result_addr
+            }
+            _ => self.evaluate_expression(condition),
+        }
+    }
+
+    fn evaluate_expression(&mut self, expr: &Expr) -> usize {
+        match expr {
+            Expr::Number(n) => self.literal(*n, false),
+            Expr::Bool(value) => {
+                let addr = self.get_temp_addr();
+                self.set_value(addr, (*value).into());
+                addr
+            }
+            // Strings are only accepted by `let`, `for` and `print`, which
+            // handle them without this; the analyzer reports any other use.
+            Expr::Str(_) => {
+                self.internal_error("a string used as a single value");
+                let addr = self.get_temp_addr();
+                self.set_value(addr, 0);
+                addr
+            }
+            Expr::Variable(name) => {
+                if let Some(addr) = self.variable_addr(name) {
+                    addr
+                } else {
                     let addr = self.get_temp_addr();
                     self.set_value(addr, 0);
                     addr
                 }
             }
+            Expr::Unary {
+                operator: UnaryOp::Not,
+                operand,
+            } => {
+                let operand_addr = self.evaluate_expression(operand);
+                let result_addr = self.get_temp_addr();
+                self.copy_value(operand_addr, result_addr);
+                self.negate(result_addr);
+                result_addr
+            }
+            Expr::Binary {
+                left,
+                operator: operator @ (BinaryOp::And | BinaryOp::Or),
+                right,
+            } => self.logical(left, operator, right),
             Expr::Binary {
                 left,
                 operator,
                 right,
             } => {
+                let ranges = (self.ranges.of(left), self.ranges.of(right));
                 let left_addr = self.evaluate_expression(left);
                 let right_addr = self.evaluate_expression(right);
                 let result_addr = self.get_temp_addr();
+                let operands = ((left_addr, ranges.0), (right_addr, ranges.1));
 
                 match operator {
                     BinaryOp::Add => self.add_values(result_addr, left_addr, right_addr),
                     BinaryOp::Sub => self.sub_values(result_addr, left_addr, right_addr),
                     BinaryOp::Mul => self.mul_values(result_addr, left_addr, right_addr),
-                    BinaryOp::Div => self.div_values(result_addr, left_addr, right_addr),
-                    BinaryOp::Equal => self.compare_equal(result_addr, left_addr, right_addr),
+                    BinaryOp::Div if let Some(divisor) = self.table_divisor(right) => {
+                        self.lookup(result_addr, left_addr, |x| x / divisor);
+                    }
+                    BinaryOp::Mod if let Some(divisor) = self.table_divisor(right) => {
+                        self.lookup(result_addr, left_addr, |x| x % divisor);
+                    }
+                    BinaryOp::Div => {
+                        let remainder = self.get_temp_addr();
+                        self.divmod(result_addr, remainder, left_addr, right_addr);
+                    }
+                    BinaryOp::Mod => {
+                        let quotient = self.get_temp_addr();
+                        self.divmod(quotient, result_addr, left_addr, right_addr);
+                    }
+                    BinaryOp::Equal => self.compare_equal(result_addr, operands.0, operands.1),
                     BinaryOp::NotEqual => {
-                        self.compare_equal(result_addr, left_addr, right_addr);
-                        // Flip the result (same as before)
-                        let temp = self.get_temp_addr();
-                        self.move_to(temp);
-                        self.output.push('+');
-                        self.move_to(result_addr);
-                        self.output.push_str("[-");
-                        self.move_to(temp);
-                        self.output.push('-');
-                        self.move_to(result_addr);
-                        self.output.push(']');
-                        self.move_to(temp);
-                        self.output.push_str("[-");
-                        self.move_to(result_addr);
-                        self.output.push('+');
-                        self.move_to(temp);
-                        self.output.push(']');
+                        self.compare_equal(result_addr, operands.0, operands.1);
+                        self.negate(result_addr);
                     }
-                    BinaryOp::Less => {
-                        // Simplified: copy left value
-                        self.copy_value(left_addr, result_addr);
+                    // `a <= b` is `!(b < a)`, and `a >= b` is `!(a < b)`.
+                    BinaryOp::LessEqual => {
+                        self.compare_less(result_addr, right_addr, left_addr);
+                        self.negate(result_addr);
                     }
-                    BinaryOp::Greater => {
-                        // Simplified: copy left value
-                        self.copy_value(left_addr, result_addr);
+                    BinaryOp::GreaterEqual => {
+                        self.compare_less(result_addr, left_addr, right_addr);
+                        self.negate(result_addr);
                     }
+                    // Short-circuiting operators went to `logical` above.
+                    BinaryOp::And | BinaryOp::Or => {
+                        self.internal_error(format!("unsupported operator `{}`", operator.symbol()))
+                    }
+                    BinaryOp::Less => self.compare_less(result_addr, left_addr, right_addr),
+                    BinaryOp::Greater => self.compare_less(result_addr, right_addr, left_addr),
                 }
 
                 result_addr
             }
+            Expr::Call { name, args } => self.generate_call(name, args),
+            // `,` overwrites the cell, except at the end of input on
+            // interpreters that leave it unchanged, where it should read 0.
+            Expr::Input => {
+                let addr = self.get_temp_addr();
+                self.move_to(addr);
+                self.clear_cell();
+                self.output.push(',');
+                addr
+            }
         }
     }
 }
 
+/// Byte ranges of generated Brainfuck and the statements they implement,
+/// for pointing a runtime error back at the source.
+///
+/// ```
+/// use rusted_brains::{ast::*, codegen::BrainfuckGenerator, span::Span};
+///
+/// let print = Stmt::print(Expr::number(7)).with_span(Span::new(0, 9));
+/// let mut generator = BrainfuckGenerator::new();
+/// let code = generator.generate(&vec![print]).unwrap();
+/// assert_eq!(generator.source_map().span_at(code.len() - 1), Some(Span::new(0, 9)));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    statements: Vec<(Range<usize>, Span)>,
+}
+
+impl SourceMap {
+    /// The span of the innermost statement whose code includes `position`.
+    pub fn span_at(&self, position: usize) -> Option<Span> {
+        // Statements are recorded as they finish, so a nested statement
+        // comes before the one enclosing it.
+        self.statements
+            .iter()
+            .find(|(code, _)| code.contains(&position))
+            .map(|&(_, span)| span)
+    }
+
+    /// The code of each statement and its span, innermost first.
+    pub fn statements(&self) -> impl Iterator<Item = (Range<usize>, Span)> + '_ {
+        self.statements.iter().cloned()
+    }
+}
+
+impl CodeGenerator for BrainfuckGenerator {
+    fn target(&self) -> &'static str {
+        "bf"
+    }
+
+    fn extension(&self) -> &'static str {
+        "bf"
+    }
+
+    /// A loop at the very start of the program never runs, since every
+    /// cell starts at zero, so it can hold any text with balanced brackets.
+    fn comment(&self, text: &str) -> Option<String> {
+        Some(format!("[{}]\n", text.replace('[', "(").replace(']', ")")))
+    }
+
+    fn generate(&mut self, program: &Program) -> TranspilerResult<String> {
+        BrainfuckGenerator::generate(self, program)
+    }
+
+    fn generate_into(
+        &mut self,
+        program: &Program,
+        sink: &mut dyn fmt::Write,
+    ) -> TranspilerResult<()> {
+        BrainfuckGenerator::generate_into(self, program, sink)
+    }
+
+    fn cell_names(&self) -> BTreeMap<usize, String> {
+        BrainfuckGenerator::cell_names(self)
+    }
+}
+
 impl Visitor<()> for BrainfuckGenerator {
-    fn visit_program(&mut self, program: &Program) -> () {
+    fn visit_program(&mut self, program: &Program) {
+        if self.dispatches(program) {
+            self.generate_dispatch(program);
+            return;
+        }
         for stmt in program {
-            self.visit_stmt(stmt);
+            self.generate_stmt(stmt);
         }
     }
 
-    fn visit_stmt(&mut self, stmt: &Stmt) -> () {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
         match stmt {
-            Stmt::Let { name, value, .. } => {
-                let addr = self.allocate_variable(name);
+            Stmt::Let {
+                name,
+                mutable,
+                value: Expr::Str(text),
+                span,
+                ..
+            } => {
+                let cells = self.store_string(text);
+                self.ranges.declare(name, Interval::FULL);
+                self.symbols.declare(
+                    Symbol::new(name, *mutable, *span)
+                        .with_type(Type::Str)
+                        .with_cells(cells),
+                );
+            }
+            Stmt::Let {
+                name,
+                mutable,
+                value,
+                span,
+                ..
+            } => {
+                // The initializer may refer to a binding this one shadows.
                 let value_addr = self.evaluate_expression(value);
-                self.copy_value(value_addr, addr);
+                let addr = self.allocate_variable(name, *mutable, *span);
+                self.store_value(value_addr, addr);
+                self.ranges.declare(name, self.ranges.of(value));
             }
-            Stmt::Assign { name, value } => {
-                if let Some(&addr) = self.variables.get(name) {
+            Stmt::Assign { name, value, .. } => {
+                if let Some(addr) = self.variable_addr(name)
+                    && !self.update_in_place(addr, name, value)
+                {
                     let value_addr = self.evaluate_expression(value);
-                    self.copy_value(value_addr, addr);
+                    self.store_value(value_addr, addr);
                 }
+                self.ranges.assign(name, self.ranges.of(value));
             }
-            Stmt::Print(expr) => {
+            Stmt::Print {
+                value: Expr::Str(text),
+                ..
+            } => self.print_text(text),
+            Stmt::Print {
+                value: iterable @ Expr::Variable(name),
+                ..
+            } if self
+                .symbols
+                .lookup(name)
+                .is_some_and(|symbol| symbol.ty == Type::Str) =>
+            {
+                for addr in self.string_cells(iterable) {
+                    self.move_to(addr);
+                    self.output.push('.');
+                }
+            }
+            Stmt::Print {
+                value: Expr::Number(n),
+                ..
+            } => {
+                let addr = self.literal(*n, true);
+                self.move_to(addr);
+                self.output.push('.');
+            }
+            Stmt::Print { value: expr, .. } => {
                 let addr = self.evaluate_expression(expr);
                 self.move_to(addr);
                 self.output.push('.');
             }
-            Stmt::If { condition, body } => {
-                let condition_addr = self.evaluate_condition(condition);
+            // A value known in advance is printed as text.
+            Stmt::PrintNum { value, .. } => match self.ranges.of(value) {
+                range if range.min == range.max && !value.calls() => {
+                    self.print_text(&range.min.to_string());
+                }
+                range => {
+                    let addr = self.evaluate_expression(value);
+                    self.print_decimal(addr, range);
+                }
+            },
+            // Bodies that can never run (reported by the analyzer) are left
+            // out entirely.
+            Stmt::If {
+                condition,
+                else_body,
+                ..
+            } if condition.constant() == Some(0) => self.generate_block(else_body),
+            Stmt::While { condition, .. } if condition.constant() == Some(0) => {}
+            Stmt::If {
+                condition,
+                body,
+                else_body,
+                ..
+            } => {
+                let mut condition_addr = self.evaluate_condition(condition);
+                // The cell is cleared to leave the block, so a variable used
+                // as the condition is tested through a copy.
+                if !self.is_temp(condition_addr) {
+                    let temp = self.get_temp_addr();
+                    self.copy_value(condition_addr, temp);
+                    condition_addr = temp;
+                }
+                // Set until the first branch runs, so the `else` runs only
+                // if it does not.
+                let else_flag =
+                    (!else_body.is_empty() && condition.constant().is_none()).then(|| {
+                        let flag = self.get_temp_addr();
+                        self.set_value(flag, 1);
+                        flag
+                    });
                 self.move_to(condition_addr);
                 self.output.push('[');
-
-                for stmt in body {
-                    self.visit_stmt(stmt);
+                if let Some(flag) = else_flag {
+                    self.move_to(flag);
+                    self.clear_cell();
                 }
 
+                let before = self.ranges.clone();
+                self.ranges.assume(condition, true);
+                self.generate_block(body);
+                let after_body = core::mem::replace(&mut self.ranges, before);
+                self.ranges.assume(condition, false);
+
                 // Clear condition and end if
                 self.move_to(condition_addr);
                 self.clear_cell();
                 self.output.push(']');
-            }
-            Stmt::While { condition, body } => {
-                let condition_addr = self.evaluate_condition(condition);
-                self.move_to(condition_addr);
-                self.output.push('[');
 
-                for stmt in body {
-                    self.visit_stmt(stmt);
+                if let Some(flag) = else_flag {
+                    self.move_to(flag);
+                    self.output.push('[');
+                    self.generate_block(else_body);
+                    self.move_to(flag);
+                    self.clear_cell();
+                    self.output.push(']');
                 }
-
-                // Re-evaluate condition
-                let new_condition_addr = self.evaluate_condition(condition);
-                self.copy_value(new_condition_addr, condition_addr);
-                self.move_to(condition_addr);
-                self.output.push(']');
+                self.ranges.join(&after_body);
+            }
+            Stmt::Match {
+                scrutinee, arms, ..
+            } => self.generate_match(scrutinee, arms),
+            Stmt::While {
+                condition, body, ..
+            } => {
+                let mut condition_addr = self.evaluate_condition(condition);
+                // `break` clears the condition's cell, which must not be a
+                // variable's.
+                if !self.is_temp(condition_addr) && body.iter().any(Stmt::breaks) {
+                    let temp = self.get_temp_addr();
+                    self.copy_value(condition_addr, temp);
+                    condition_addr = temp;
+                }
+                self.generate_loop(condition_addr, Some(condition), true, body);
+            }
+            Stmt::Loop { body, .. } => {
+                let guard = self.get_temp_addr();
+                self.set_value(guard, 1);
+                self.generate_loop(guard, None, false, body);
+            }
+            // The first pass runs unconditionally, then the condition is
+            // tested after each one as `while` does.
+            Stmt::DoWhile {
+                body, condition, ..
+            } => {
+                let guard = self.get_temp_addr();
+                self.set_value(guard, 1);
+                self.generate_loop(guard, Some(condition), false, body);
+            }
+            // The length is known, so the walk is unrolled: the body is
+            // generated once per byte with `name` bound to that byte's cell.
+            Stmt::For {
+                name,
+                iterable,
+                body,
+                span,
+            } => {
+                let cells = self.string_cells(iterable);
+                // Each byte after a `break` is skipped.
+                let jumps = body.iter().any(Stmt::jumps).then(|| {
+                    let guard = self.get_temp_addr();
+                    let alive = self.get_temp_addr();
+                    self.set_value(guard, 1);
+                    self.loops.push(LoopCells { guard, alive });
+                    (guard, alive, self.get_temp_addr())
+                });
+                self.ranges.widen(body);
+                let before = self.ranges.clone();
+                for addr in cells {
+                    self.ranges = before.clone();
+                    self.ranges.enter_scope();
+                    self.ranges.declare(name, ranges::bytes(iterable));
+                    self.symbols.enter_scope();
+                    self.symbols
+                        .declare(Symbol::new(name, false, *span).with_cells(addr..addr + 1));
+                    match jumps {
+                        None => self.generate_stmts(body),
+                        Some((guard, alive, flag)) => {
+                            self.copy_value(guard, flag);
+                            self.move_to(flag);
+                            self.output.push('[');
+                            self.set_value(alive, 1);
+                            self.generate_stmts(body);
+                            self.move_to(flag);
+                            self.clear_cell();
+                            self.output.push(']');
+                        }
+                    }
+                    self.symbols.exit_scope();
+                }
+                self.ranges = before;
+                if jumps.is_some() {
+                    self.loops.pop();
+                }
+            }
+            Stmt::Break { .. } => match self.frame_loops().last().copied() {
+                Some(cells) => {
+                    self.set_value(cells.alive, 0);
+                    self.set_value(cells.guard, 0);
+                }
+                None => self.internal_error("`break` outside of a loop"),
+            },
+            Stmt::Continue { .. } => match self.frame_loops().last().copied() {
+                Some(cells) => self.set_value(cells.alive, 0),
+                None => self.internal_error("`continue` outside of a loop"),
+            },
+            // Each call expands the body where it is made, or jumps to the
+            // one copy the dispatch loop generates.
+            Stmt::Function(_) => {}
+            // Leaves every loop in the function as `break` would, and the
+            // rest of the body.
+            Stmt::Return { value, .. } => {
+                let Some(frame) = self.frames.last().cloned() else {
+                    self.internal_error("`return` outside of a function");
+                    return;
+                };
+                match value {
+                    Some(value) => {
+                        let addr = self.evaluate_expression(value);
+                        self.store_value(addr, frame.result);
+                    }
+                    None => self.set_value(frame.result, 0),
+                }
+                for cells in self.frame_loops().to_vec() {
+                    self.set_value(cells.alive, 0);
+                    self.set_value(cells.guard, 0);
+                }
+                if let Some(alive) = frame.alive {
+                    self.set_value(alive, 0);
+                }
+            }
+            Stmt::Expr { value, .. } => {
+                self.evaluate_expression(value);
             }
         }
     }
 
-    fn visit_expr(&mut self, expr: &Expr) -> () {
+    fn visit_expr(&mut self, expr: &Expr) {
         self.evaluate_expression(expr);
     }
 }
 
+/// A sink for code nobody reads, as in the layout pass.
+struct Discard;
+
+impl fmt::Write for Discard {
+    fn write_str(&mut self, _: &str) -> fmt::Result {
+        Ok(())
+    }
+}
+
+/// How many instructions `set_value` takes to set `value` once the
+/// pointer is on the cell.
+fn set_value_len(value: u8) -> usize {
+    let value = usize::from(value);
+    if value < 10 {
+        return 3 + value;
+    }
+    let loop_count = value.isqrt();
+    21 + 2 * loop_count + (value - loop_count * loop_count)
+}
+
+/// How many instructions `copy_value` takes between neighbouring cells
+/// once the pointer is on the destination.
+const COPY_LEN: usize = 28;
+
+/// Where the code sets one literal in a cell of its own.
+#[derive(Debug, Clone, Copy, Default)]
+struct LiteralUses {
+    count: usize,
+    /// About how many instructions reading it from a pooled cell would
+    /// take instead, all told.
+    reads: usize,
+}
+
+impl LiteralUses {
+    /// How many fewer instructions it would take to set `value` once and
+    /// read it from its cell at every use.
+    fn savings(&self, value: u8) -> usize {
+        let set = set_value_len(value);
+        (self.count * set).saturating_sub(set + self.reads)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::ast::*;
+    #[cfg(feature = "interpreter")]
+    use crate::bf::Interpreter;
 
     #[test]
     fn test_generate_let_statement() {
@@ -412,6 +2023,135 @@ mod tests {
         assert!(!result.is_empty());
     }
 
+    #[test]
+    fn test_internal_errors() {
+        let error = |program: Program| {
+            let error = BrainfuckGenerator::new().generate(&program).unwrap_err();
+            (error.message, error.span)
+        };
+        // Programs the analyzer would reject are reported at the statement
+        // that could not be generated rather than compiled wrongly.
+        assert_eq!(
+            error(vec![
+                Stmt::let_stmt("x", false, Expr::number(1)),
+                Stmt::while_stmt(
+                    Expr::variable("x"),
+                    vec![Stmt::print(Expr::variable("y")).with_span(Span::new(12, 20))],
+                )
+                .with_span(Span::new(0, 7)),
+            ]),
+            (
+                "internal error: undefined variable `y`".into(),
+                Some(Span::new(12, 20))
+            )
+        );
+        assert_eq!(
+            error(vec![
+                Stmt::print(Expr::binary(
+                    Expr::string("s"),
+                    BinaryOp::Add,
+                    Expr::number(1)
+                )),
+                Stmt::break_stmt()
+            ])
+            .0,
+            "internal error: a string used as a single value"
+        );
+        assert_eq!(
+            error(vec![Stmt::continue_stmt().with_span(Span::new(4, 13))]),
+            (
+                "internal error: `continue` outside of a loop".into(),
+                Some(Span::new(4, 13))
+            )
+        );
+        assert_eq!(
+            error(vec![Stmt::for_stmt("c", Expr::number(5), vec![])]).0,
+            "internal error: `for` over an expression, not a string"
+        );
+    }
+
+    #[test]
+    fn test_guard_cells_stay_unused() {
+        let program = vec![
+            Stmt::let_stmt("x", false, Expr::number(12)),
+            Stmt::print(Expr::variable("x")),
+        ];
+        let plain = BrainfuckGenerator::new().generate(&program).unwrap();
+        let guarded = BrainfuckGenerator::new()
+            .with_guard(3)
+            .generate(&program)
+            .unwrap();
+        // Every cell shifts right, so only the first move grows.
+        assert_eq!(guarded, format!(">>>{}", plain));
+    }
+
+    #[test]
+    fn test_cell_names() {
+        let program = vec![
+            Stmt::let_stmt("x", false, Expr::number(1)),
+            Stmt::let_stmt("s", false, Expr::string("ab")),
+            Stmt::for_stmt(
+                "c",
+                Expr::variable("s"),
+                vec![Stmt::print(Expr::variable("c"))],
+            ),
+        ];
+        let mut generator = BrainfuckGenerator::new().with_guard(1);
+        generator.generate(&program).unwrap();
+        let names: Vec<(usize, String)> = generator.cell_names().into_iter().collect();
+        assert_eq!(
+            names,
+            [
+                (1, "x".into()),
+                (2, "byte 0 of s".into()),
+                (3, "byte 1 of s".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_busy_variables_sit_next_to_temporaries() {
+        let program = vec![
+            Stmt::let_stmt("s", false, Expr::string("a")),
+            Stmt::let_stmt("x", false, Expr::number(1)),
+            Stmt::let_stmt("y", false, Expr::number(2)),
+            Stmt::let_stmt("z", false, Expr::number(3)),
+            Stmt::print(Expr::variable("y")),
+            Stmt::print(Expr::variable("y")),
+            Stmt::print(Expr::variable("x")),
+        ];
+        let mut generator = BrainfuckGenerator::new();
+        generator.generate(&program).unwrap();
+        let names: Vec<String> = generator.cell_names().into_values().collect();
+        assert_eq!(names, ["z", "x", "y", "byte 0 of s"]);
+        // The string's scratch cell, then the one after the variables.
+        assert_eq!(generator.first_temp_addr, 6);
+    }
+
+    #[test]
+    fn test_uses_in_loops_weigh_more() {
+        let x = || Expr::variable("x");
+        let program = vec![
+            Stmt::let_stmt("x", true, Expr::number(3)),
+            Stmt::let_stmt("y", false, Expr::number(65)),
+            Stmt::print(Expr::variable("y")),
+            Stmt::print(Expr::variable("y")),
+            Stmt::print(Expr::variable("y")),
+            Stmt::print(Expr::variable("y")),
+            Stmt::while_stmt(
+                Expr::binary(x(), BinaryOp::Greater, Expr::number(0)),
+                vec![Stmt::assign(
+                    "x",
+                    Expr::binary(x(), BinaryOp::Sub, Expr::number(1)),
+                )],
+            ),
+        ];
+        let mut generator = BrainfuckGenerator::new();
+        generator.generate(&program).unwrap();
+        let names: Vec<String> = generator.cell_names().into_values().collect();
+        assert_eq!(names, ["y", "x"]);
+    }
+
     #[test]
     fn test_generate_print_statement() {
         let mut generator = BrainfuckGenerator::new();
@@ -423,4 +2163,945 @@ mod tests {
         let result = generator.generate(&program).unwrap();
         assert!(result.contains('.'));
     }
+
+    #[cfg(feature = "interpreter")]
+    fn run(program: &Program) -> Vec<u8> {
+        let code = BrainfuckGenerator::new().generate(program).unwrap();
+        Interpreter::new(code).run().unwrap().output
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_generated_program_output() {
+        let program = vec![
+            Stmt::let_stmt("x", false, Expr::number(10)),
+            Stmt::let_stmt("y", true, Expr::number(5)),
+            Stmt::print(Expr::variable("x")),
+            Stmt::assign(
+                "y",
+                Expr::binary(Expr::variable("x"), BinaryOp::Add, Expr::variable("y")),
+            ),
+            Stmt::print(Expr::variable("y")),
+        ];
+
+        assert_eq!(run(&program), vec![10, 15]);
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_programs_concatenate() {
+        let n = || Expr::variable("n");
+        let first = vec![
+            Stmt::let_stmt("x", false, Expr::number(60)),
+            Stmt::print(Expr::binary(
+                Expr::variable("x"),
+                BinaryOp::Add,
+                Expr::number(5),
+            )),
+        ];
+        // Starts where the first left off, on cells it left dirty.
+        let second = vec![
+            Stmt::let_stmt("n", true, Expr::number(2)),
+            Stmt::while_stmt(
+                n(),
+                vec![
+                    Stmt::print(Expr::binary(n(), BinaryOp::Add, Expr::number(64))),
+                    Stmt::assign("n", Expr::binary(n(), BinaryOp::Sub, Expr::number(1))),
+                ],
+            ),
+        ];
+        let first = BrainfuckGenerator::new()
+            .with_exit_cell(3)
+            .generate(&first)
+            .unwrap();
+        let second = BrainfuckGenerator::new()
+            .with_entry_cell(3)
+            .with_exit_cell(1)
+            .generate(&second)
+            .unwrap();
+
+        let run = Interpreter::new(first + &second).run().unwrap();
+        assert_eq!(run.output, b"ABA");
+        assert_eq!(run.pointer, 1);
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_lookup_tables() {
+        let x = || Expr::variable("x");
+        let program = vec![
+            Stmt::let_stmt("x", true, Expr::number(120)),
+            Stmt::while_stmt(
+                x(),
+                vec![
+                    Stmt::print(Expr::binary(x(), BinaryOp::Div, Expr::number(7))),
+                    Stmt::print(Expr::binary(x(), BinaryOp::Mod, Expr::number(7))),
+                    Stmt::assign("x", Expr::binary(x(), BinaryOp::Sub, Expr::number(3))),
+                ],
+            ),
+        ];
+        let expected: Vec<u8> = (1..=40u8)
+            .rev()
+            .flat_map(|x| [x * 3 / 7, x * 3 % 7])
+            .collect();
+
+        let counted = BrainfuckGenerator::new().generate(&program).unwrap();
+        let counted = Interpreter::new(counted).run().unwrap();
+        let looked_up = BrainfuckGenerator::new()
+            .with_lookup_tables()
+            .generate(&program)
+            .unwrap();
+        let looked_up = Interpreter::new(looked_up).run().unwrap();
+        assert_eq!(counted.output, expected);
+        assert_eq!(looked_up.output, expected);
+        assert!(looked_up.steps * 5 < counted.steps);
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_comparisons() {
+        let n = Expr::number;
+        let mut program = Vec::new();
+        let operators = [
+            BinaryOp::Less,
+            BinaryOp::Greater,
+            BinaryOp::LessEqual,
+            BinaryOp::GreaterEqual,
+        ];
+        for (left, right) in [(3, 5), (5, 3), (4, 4), (0, 0), (0, 255), (255, 0)] {
+            for operator in &operators {
+                program.push(Stmt::print(Expr::binary(
+                    n(left),
+                    operator.clone(),
+                    n(right),
+                )));
+            }
+        }
+        assert_eq!(
+            run(&program),
+            vec![
+                1, 0, 1, 0, // 3 vs 5
+                0, 1, 0, 1, // 5 vs 3
+                0, 0, 1, 1, // 4 vs 4
+                0, 0, 1, 1, // 0 vs 0
+                1, 0, 1, 0, // 0 vs 255
+                0, 1, 0, 1, // 255 vs 0
+            ]
+        );
+
+        let i = || Expr::variable("i");
+        let counting = vec![
+            Stmt::let_stmt("i", true, n(0)),
+            Stmt::while_stmt(
+                Expr::binary(i(), BinaryOp::Less, n(10)),
+                vec![
+                    Stmt::print(i()),
+                    Stmt::assign("i", Expr::binary(i(), BinaryOp::Add, n(1))),
+                ],
+            ),
+            Stmt::if_stmt(
+                Expr::binary(i(), BinaryOp::Greater, n(9)),
+                vec![Stmt::print(n(65))],
+            ),
+        ];
+        assert_eq!(run(&counting), vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 65]);
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_logical_operators() {
+        let truth = |value: bool| {
+            Expr::binary(Expr::number(value as i32), BinaryOp::Equal, Expr::number(1))
+        };
+        let mut program = Vec::new();
+        for operator in [BinaryOp::And, BinaryOp::Or] {
+            for (left, right) in [(false, false), (false, true), (true, false), (true, true)] {
+                program.push(Stmt::print(Expr::binary(
+                    truth(left),
+                    operator.clone(),
+                    truth(right),
+                )));
+            }
+        }
+        assert_eq!(run(&program), vec![0, 0, 0, 1, 0, 1, 1, 1]);
+
+        let not = |operand| Expr::unary(UnaryOp::Not, operand);
+        let program = vec![
+            Stmt::let_stmt("t", false, truth(true)),
+            Stmt::print(not(Expr::variable("t"))),
+            Stmt::print(not(truth(false))),
+            Stmt::print(not(not(Expr::variable("t")))),
+            // The operand is only copied, not changed.
+            Stmt::print(Expr::variable("t")),
+        ];
+        assert_eq!(run(&program), vec![0, 1, 1, 1]);
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_else_branches() {
+        let x = || Expr::variable("x");
+        let print = |value| vec![Stmt::print(Expr::number(value))];
+        let below = |n| Expr::binary(x(), BinaryOp::Less, Expr::number(n));
+        let mut program = vec![Stmt::let_stmt("x", true, Expr::number(0))];
+        for _ in 0..3 {
+            program.push(Stmt::if_else(
+                below(1),
+                print(1),
+                vec![Stmt::if_else(below(2), print(2), print(3))],
+            ));
+            program.push(Stmt::assign(
+                "x",
+                Expr::binary(x(), BinaryOp::Add, Expr::number(1)),
+            ));
+        }
+        // A branch that changes the condition does not run the other one.
+        program.push(Stmt::if_else(
+            below(9),
+            vec![Stmt::assign("x", Expr::number(9))],
+            print(4),
+        ));
+        // A constant condition leaves out the branch that cannot run.
+        program.push(Stmt::if_else(Expr::number(0), print(5), print(6)));
+        program.push(Stmt::if_else(Expr::number(1), print(7), print(8)));
+        assert_eq!(run(&program), vec![1, 2, 3, 6, 7]);
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_break_and_continue() {
+        let n = || Expr::variable("n");
+        let is = |value| Expr::binary(n(), BinaryOp::Equal, Expr::number(value));
+        let increment = || Stmt::assign("n", Expr::binary(n(), BinaryOp::Add, Expr::number(1)));
+        let mut program = vec![
+            Stmt::let_stmt("n", true, Expr::number(0)),
+            // loop { n += 1; if n == 2 { continue; } if n == 4 { break; } print(n); }
+            Stmt::loop_stmt(vec![
+                increment(),
+                Stmt::if_stmt(is(2), vec![Stmt::continue_stmt()]),
+                Stmt::if_stmt(is(4), vec![Stmt::break_stmt()]),
+                Stmt::print(n()),
+            ]),
+            // A `break` in the inner loop leaves only that one.
+            // while n { while 1 { break; } print(n); if n == 6 { break; } n += 1; }
+            Stmt::while_stmt(
+                n(),
+                vec![
+                    Stmt::while_stmt(Expr::number(1), vec![Stmt::break_stmt()]),
+                    Stmt::print(n()),
+                    Stmt::if_stmt(is(6), vec![Stmt::break_stmt()]),
+                    increment(),
+                ],
+            ),
+            Stmt::print(n()),
+            // for c in "abcd" { if c == 98 { continue; } if c == 99 { break; } print(c); }
+            Stmt::for_stmt(
+                "c",
+                Expr::string("abcd"),
+                vec![
+                    Stmt::if_stmt(
+                        Expr::binary(Expr::variable("c"), BinaryOp::Equal, Expr::number(98)),
+                        vec![Stmt::continue_stmt()],
+                    ),
+                    Stmt::if_stmt(
+                        Expr::binary(Expr::variable("c"), BinaryOp::Equal, Expr::number(99)),
+                        vec![Stmt::break_stmt()],
+                    ),
+                    Stmt::print(Expr::variable("c")),
+                ],
+            ),
+        ];
+        // loop { n -= 1; if n == 5 { continue; } print(n); } while n > 4;
+        // loop { print(n); } while 0;
+        program.push(Stmt::do_while(
+            vec![
+                Stmt::assign("n", Expr::binary(n(), BinaryOp::Sub, Expr::number(1))),
+                Stmt::if_stmt(is(5), vec![Stmt::continue_stmt()]),
+                Stmt::print(n()),
+            ],
+            Expr::binary(n(), BinaryOp::Greater, Expr::number(4)),
+        ));
+        program.push(Stmt::do_while(vec![Stmt::print(n())], Expr::number(0)));
+        assert_eq!(run(&program), vec![1, 3, 4, 5, 6, 6, b'a', 4, 4]);
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_functions() {
+        let var = Expr::variable;
+        let ret = |value| Stmt::return_stmt(Some(value));
+        let call = |name, args| Expr::call(name, args);
+        let program = vec![
+            // fn max(a, b) { if a > b { return a; } return b; }
+            Stmt::function(
+                "max",
+                vec!["a".to_string(), "b".to_string()],
+                vec![
+                    Stmt::if_stmt(
+                        Expr::binary(var("a"), BinaryOp::Greater, var("b")),
+                        vec![ret(var("a"))],
+                    ),
+                    ret(var("b")),
+                ],
+            ),
+            // fn first_over(n) {
+            //     let mut i = 0;
+            //     while i < 9 { if i * 3 > n { return i; } i += 1; }
+            //     print(0);
+            // }
+            Stmt::function(
+                "first_over",
+                vec!["n".to_string()],
+                vec![
+                    Stmt::let_stmt("i", true, Expr::number(0)),
+                    Stmt::while_stmt(
+                        Expr::binary(var("i"), BinaryOp::Less, Expr::number(9)),
+                        vec![
+                            Stmt::if_stmt(
+                                Expr::binary(
+                                    Expr::binary(var("i"), BinaryOp::Mul, Expr::number(3)),
+                                    BinaryOp::Greater,
+                                    var("n"),
+                                ),
+                                vec![ret(var("i"))],
+                            ),
+                            Stmt::assign(
+                                "i",
+                                Expr::binary(var("i"), BinaryOp::Add, Expr::number(1)),
+                            ),
+                        ],
+                    ),
+                    Stmt::print(Expr::number(0)),
+                ],
+            ),
+            Stmt::let_stmt("x", false, Expr::number(4)),
+            // print(max(x, 2)); print(max(1, x + 3)); print(first_over(max(x, 7)));
+            Stmt::print(call("max", vec![var("x"), Expr::number(2)])),
+            Stmt::print(call(
+                "max",
+                vec![
+                    Expr::number(1),
+                    Expr::binary(var("x"), BinaryOp::Add, Expr::number(3)),
+                ],
+            )),
+            Stmt::print(call(
+                "first_over",
+                vec![call("max", vec![var("x"), Expr::number(7)])],
+            )),
+            // Running off the end returns 0.
+            Stmt::print(call("first_over", vec![Expr::number(200)])),
+            Stmt::expr(call("first_over", vec![Expr::number(0)])),
+            Stmt::print(var("x")),
+        ];
+        assert_eq!(run(&program), vec![4, 7, 3, 0, 0, 4]);
+
+        // Assigning to a parameter leaves the caller's variable alone.
+        let double = vec![
+            Stmt::function(
+                "double",
+                vec!["n".to_string()],
+                vec![
+                    Stmt::assign("n", Expr::binary(var("n"), BinaryOp::Mul, Expr::number(2))),
+                    ret(var("n")),
+                ],
+            ),
+            Stmt::let_stmt("x", false, Expr::number(3)),
+            Stmt::print(call("double", vec![var("x")])),
+            Stmt::print(var("x")),
+        ];
+        assert_eq!(run(&double), vec![6, 3]);
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_match() {
+        let x = || Expr::variable("x");
+        let arm = |value, body| MatchArm::new(Pattern::Number(value), body);
+        let program = vec![
+            Stmt::let_stmt("x", true, Expr::number(0)),
+            // while x < 5 {
+            //     match x { 1 => { x = 3; print(1); } 3 => { print(3); } 4 => { break; }
+            //               1 => { print(9); } _ => { print(0); } }
+            //     x += 1;
+            // }
+            Stmt::while_stmt(
+                Expr::binary(x(), BinaryOp::Less, Expr::number(5)),
+                vec![
+                    Stmt::match_stmt(
+                        x(),
+                        vec![
+                            arm(
+                                1,
+                                vec![
+                                    Stmt::assign("x", Expr::number(3)),
+                                    Stmt::print(Expr::number(1)),
+                                ],
+                            ),
+                            arm(3, vec![Stmt::print(Expr::number(3))]),
+                            arm(4, vec![Stmt::break_stmt()]),
+                            arm(1, vec![Stmt::print(Expr::number(9))]),
+                            MatchArm::new(Pattern::Wildcard, vec![Stmt::print(Expr::number(0))]),
+                        ],
+                    ),
+                    Stmt::assign("x", Expr::binary(x(), BinaryOp::Add, Expr::number(1))),
+                ],
+            ),
+            // match 7 { 7 => { print(7); } _ => { print(0); } }
+            Stmt::match_stmt(
+                Expr::number(7),
+                vec![
+                    arm(7, vec![Stmt::print(Expr::number(7))]),
+                    MatchArm::new(Pattern::Wildcard, vec![Stmt::print(Expr::number(0))]),
+                ],
+            ),
+            // match x + 1 { 9 => { print(9); } -251 => { print(5); } }
+            Stmt::match_stmt(
+                Expr::binary(x(), BinaryOp::Add, Expr::number(1)),
+                vec![
+                    arm(9, vec![Stmt::print(Expr::number(9))]),
+                    arm(-251, vec![Stmt::print(Expr::number(5))]),
+                ],
+            ),
+        ];
+        assert_eq!(run(&program), vec![0, 1, 7, 5]);
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_else_if_chain() {
+        // if x == 0 { print(0); } else if x == 1 { print(1); } ... else { print(99); }
+        let arms = 20;
+        let chain = (0..arms)
+            .rev()
+            .fold(vec![Stmt::print(Expr::number(99))], |else_body, n| {
+                vec![Stmt::if_else(
+                    Expr::binary(Expr::variable("x"), BinaryOp::Equal, Expr::number(n)),
+                    vec![Stmt::print(Expr::number(n))],
+                    else_body,
+                )]
+            });
+        let mut program = Vec::new();
+        for x in [0, 7, 19, 20, 200] {
+            program.push(Stmt::let_stmt("x", false, Expr::number(x)));
+            program.extend(chain.clone());
+        }
+        assert_eq!(run(&program), vec![0, 7, 19, 99, 99]);
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_multiply_and_divide() {
+        let x = || Expr::variable("x");
+        let program = vec![
+            Stmt::let_stmt(
+                "x",
+                false,
+                Expr::binary(Expr::number(3), BinaryOp::Mul, Expr::number(4)),
+            ),
+            Stmt::print(x()),
+            Stmt::print(Expr::binary(x(), BinaryOp::Mul, Expr::number(30))),
+            Stmt::print(Expr::binary(x(), BinaryOp::Div, Expr::number(5))),
+            Stmt::print(Expr::binary(x(), BinaryOp::Div, Expr::number(12))),
+            Stmt::print(Expr::binary(x(), BinaryOp::Div, Expr::number(13))),
+            Stmt::print(Expr::binary(x(), BinaryOp::Div, Expr::number(0))),
+        ];
+        // 360 wraps to 104; dividing by zero gives 0.
+        assert_eq!(run(&program), vec![12, 104, 2, 1, 0, 0]);
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_remainder() {
+        let x = || Expr::variable("x");
+        let program = vec![
+            Stmt::let_stmt("x", false, Expr::number(17)),
+            Stmt::print(Expr::binary(x(), BinaryOp::Mod, Expr::number(5))),
+            Stmt::print(Expr::binary(x(), BinaryOp::Mod, Expr::number(17))),
+            Stmt::print(Expr::binary(x(), BinaryOp::Mod, Expr::number(20))),
+            Stmt::print(Expr::binary(x(), BinaryOp::Mod, Expr::number(1))),
+            Stmt::print(Expr::binary(x(), BinaryOp::Mod, Expr::number(0))),
+            Stmt::print(Expr::binary(
+                Expr::number(0),
+                BinaryOp::Mod,
+                Expr::number(3),
+            )),
+        ];
+        // Like division, a zero divisor gives 0.
+        assert_eq!(run(&program), vec![2, 0, 17, 0, 0, 0]);
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_out_of_range_literals_wrap() {
+        let program = vec![
+            Stmt::print(Expr::number(-1)),
+            Stmt::print(Expr::number(i32::MIN + 65)),
+        ];
+        let code = BrainfuckGenerator::new().generate(&program).unwrap();
+
+        assert!(code.len() < 1000);
+        assert_eq!(run(&program), vec![255, 65]);
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_literals_in_loops() {
+        // A literal of 10 or more is built with a loop in the cell after it,
+        // which an earlier iteration may have left holding a value.
+        let i = || Expr::variable("i");
+        let program = vec![
+            Stmt::let_stmt("i", true, Expr::number(0)),
+            Stmt::while_stmt(
+                Expr::binary(i(), BinaryOp::Less, Expr::number(3)),
+                vec![
+                    Stmt::print(Expr::binary(i(), BinaryOp::Add, Expr::number(48))),
+                    Stmt::print(Expr::number(10)),
+                    Stmt::assign("i", Expr::binary(i(), BinaryOp::Add, Expr::number(1))),
+                ],
+            ),
+        ];
+        assert_eq!(run(&program), b"0\n1\n2\n");
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_aliased_assignments() {
+        let x = || Expr::variable("x");
+        let program = vec![
+            Stmt::let_stmt("x", true, Expr::number(20)),
+            Stmt::assign("x", x()),
+            Stmt::print(x()),
+            Stmt::assign("x", Expr::binary(x(), BinaryOp::Add, x())),
+            Stmt::print(x()),
+            Stmt::assign("x", Expr::binary(Expr::number(1), BinaryOp::Add, x())),
+            Stmt::print(x()),
+            Stmt::assign("x", Expr::binary(x(), BinaryOp::Sub, Expr::number(200))),
+            Stmt::print(x()),
+            Stmt::if_stmt(x(), vec![]),
+            Stmt::print(x()),
+        ];
+        assert_eq!(run(&program), [20, 40, 41, 97, 97]);
+    }
+
+    #[test]
+    fn test_statements_share_temporaries() {
+        let cells = |statements: usize| {
+            let sum = Expr::binary(Expr::number(30), BinaryOp::Add, Expr::number(35));
+            let program = vec![Stmt::print(sum); statements];
+            let code = BrainfuckGenerator::new().generate(&program).unwrap();
+            crate::verify::tape_cells(&crate::ir::parse(&code).unwrap())
+        };
+        assert_eq!(cells(1), cells(50));
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_temporaries_reused_inside_loops() {
+        let n = || Expr::variable("n");
+        let program = vec![
+            Stmt::let_stmt("n", true, Expr::number(3)),
+            Stmt::while_stmt(
+                n(),
+                vec![
+                    Stmt::print(Expr::binary(n(), BinaryOp::Add, Expr::number(64))),
+                    Stmt::if_stmt(
+                        Expr::binary(n(), BinaryOp::NotEqual, Expr::number(2)),
+                        vec![Stmt::print(Expr::number(33))],
+                    ),
+                    Stmt::assign("n", Expr::binary(n(), BinaryOp::Sub, Expr::number(1))),
+                ],
+            ),
+        ];
+        assert_eq!(run(&program), b"C!BA!");
+    }
+
+    #[test]
+    fn test_calls_hand_back_their_cells() {
+        let var = Expr::variable;
+        // fn scale(n) { let mut m = n * 3; m = m + n; return m; }
+        let scale = Stmt::function(
+            "scale",
+            vec!["n".to_string()],
+            vec![
+                Stmt::let_stmt(
+                    "m",
+                    true,
+                    Expr::binary(var("n"), BinaryOp::Mul, Expr::number(3)),
+                ),
+                Stmt::assign("m", Expr::binary(var("m"), BinaryOp::Add, var("n"))),
+                Stmt::return_stmt(Some(var("m"))),
+            ],
+        );
+        let cells = |calls: usize| {
+            let call = || Expr::call("scale", vec![Expr::number(5)]);
+            let mut program = vec![scale.clone()];
+            for _ in 0..calls {
+                program.push(Stmt::print(Expr::binary(call(), BinaryOp::Add, call())));
+                program.push(Stmt::expr(call()));
+            }
+            let code = BrainfuckGenerator::new().generate(&program).unwrap();
+            crate::verify::tape_cells(&crate::ir::parse(&code).unwrap())
+        };
+        assert_eq!(cells(1), cells(20));
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_checked_arithmetic_traps_on_overflow() {
+        use crate::bf::{Overflow, RuntimeError};
+
+        let x = || Expr::variable("x");
+        let overflow = Stmt::assign("x", Expr::binary(x(), BinaryOp::Add, Expr::number(10)))
+            .with_span(Span::new(40, 51));
+        let program = vec![
+            Stmt::let_stmt("x", true, Expr::number(250)),
+            Stmt::if_stmt(
+                Expr::binary(Expr::number(3), BinaryOp::NotEqual, x()),
+                vec![Stmt::print(x()), overflow],
+            ),
+        ];
+        let mut generator = BrainfuckGenerator::new().with_checked_arithmetic();
+        let code = generator.generate(&program).unwrap();
+        let error = Interpreter::new(code)
+            .with_overflow(Overflow::Trap)
+            .run()
+            .unwrap_err();
+        let RuntimeError::CellOverflow { position } = error else {
+            panic!("{}", error);
+        };
+        assert_eq!(
+            generator.source_map().span_at(position),
+            Some(Span::new(40, 51))
+        );
+
+        // The comparison alone wraps when not checked.
+        let mut unchecked = program.clone();
+        unchecked[1] = Stmt::if_stmt(
+            Expr::binary(Expr::number(3), BinaryOp::NotEqual, x()),
+            vec![],
+        );
+        let code = BrainfuckGenerator::new().generate(&unchecked).unwrap();
+        assert!(
+            Interpreter::new(code)
+                .with_overflow(Overflow::Trap)
+                .run()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_checked_comparisons_skip_proven_guards() {
+        let x = || Expr::variable("x");
+        let countdown = |condition| {
+            vec![
+                Stmt::let_stmt("x", true, Expr::number(10)),
+                Stmt::let_stmt("y", true, Expr::number(0)),
+                Stmt::while_stmt(
+                    condition,
+                    vec![
+                        Stmt::assign("x", Expr::binary(x(), BinaryOp::Sub, Expr::number(1))),
+                        Stmt::assign("y", x()),
+                    ],
+                ),
+            ]
+        };
+        let checked = |program: &Program| {
+            BrainfuckGenerator::new()
+                .with_checked_arithmetic()
+                .generate(program)
+                .unwrap()
+        };
+        let unchecked = |program: &Program| BrainfuckGenerator::new().generate(program).unwrap();
+
+        // Nothing is below zero, so `x != 0` never wraps, whichever side
+        // `x` is on.
+        let program = countdown(Expr::binary(x(), BinaryOp::NotEqual, Expr::number(0)));
+        assert_eq!(checked(&program), unchecked(&program));
+        let swapped = countdown(Expr::binary(Expr::number(0), BinaryOp::NotEqual, x()));
+        assert_eq!(checked(&swapped), unchecked(&program));
+
+        // Either of two variables may be the larger.
+        let program = countdown(Expr::binary(x(), BinaryOp::NotEqual, Expr::variable("y")));
+        assert!(checked(&program).len() > unchecked(&program).len());
+    }
+
+    #[test]
+    fn test_in_place_update_skips_copies() {
+        let mut generator = BrainfuckGenerator::new();
+        let program = vec![
+            Stmt::let_stmt("x", true, Expr::number(1)),
+            Stmt::assign(
+                "x",
+                Expr::binary(Expr::variable("x"), BinaryOp::Add, Expr::number(2)),
+            ),
+        ];
+        let code = generator.generate(&program).unwrap();
+        assert!(code.ends_with("<++"), "{}", code);
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_generated_equality() {
+        let program = vec![
+            Stmt::let_stmt("a", false, Expr::number(30)),
+            Stmt::if_stmt(
+                Expr::binary(Expr::variable("a"), BinaryOp::Equal, Expr::number(30)),
+                vec![Stmt::print(Expr::number(1))],
+            ),
+            Stmt::if_stmt(
+                Expr::binary(Expr::variable("a"), BinaryOp::NotEqual, Expr::number(30)),
+                vec![Stmt::print(Expr::number(2))],
+            ),
+        ];
+
+        assert_eq!(run(&program), vec![1]);
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_print_text() {
+        let text = "Hello, World!\n\u{7f}\0";
+        let program = vec![
+            Stmt::print(Expr::string(text)),
+            Stmt::let_stmt("s", false, Expr::string("ok")),
+            Stmt::print(Expr::variable("s")),
+        ];
+        assert_eq!(run(&program), format!("{}ok", text).as_bytes());
+
+        // One cell for the text, and the scratch cell after it.
+        let code = BrainfuckGenerator::new()
+            .generate(&vec![Stmt::print(Expr::string(text))])
+            .unwrap();
+        assert_eq!(
+            crate::verify::tape_cells(&crate::ir::parse(&code).unwrap()),
+            Some(3)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_input() {
+        // Upper-cases a line, then adds up the bytes after it.
+        use crate::frontend::{Frontend, MiniRust};
+        let program = MiniRust
+            .parse(
+                "let mut c = input();
+                 while c != 10 { print(c - 32); c = input(); }
+                 let sum = input() + input();
+                 print_num(sum + input());",
+            )
+            .unwrap();
+        let code = BrainfuckGenerator::new().generate(&program).unwrap();
+        let run = |input: &str| {
+            Interpreter::new(code.clone())
+                .with_input(input)
+                .run()
+                .unwrap()
+        };
+        assert_eq!(run("hi\n\x01\x02\x03").output, b"HI6");
+        // Reading past the end gives 0.
+        assert_eq!(run("ok\n\x09").output, b"OK9");
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_print_num() {
+        // A parameter could hold anything, so every digit is worked out.
+        let mut program = vec![Stmt::function(
+            "show",
+            vec!["x".to_string()],
+            vec![
+                Stmt::print_num(Expr::variable("x")),
+                Stmt::print(Expr::number(32)),
+            ],
+        )];
+        for value in [0, 7, 10, 42, 99, 100, 105, 255] {
+            program.push(Stmt::expr(Expr::call("show", vec![Expr::number(value)])));
+        }
+        assert_eq!(run(&program), b"0 7 10 42 99 100 105 255 ");
+
+        let program = vec![
+            Stmt::let_stmt("i", true, Expr::number(0)),
+            Stmt::while_stmt(
+                Expr::binary(Expr::variable("i"), BinaryOp::Less, Expr::number(12)),
+                vec![
+                    Stmt::print_num(Expr::variable("i")),
+                    Stmt::assign(
+                        "i",
+                        Expr::binary(Expr::variable("i"), BinaryOp::Add, Expr::number(1)),
+                    ),
+                ],
+            ),
+        ];
+        assert_eq!(run(&program), b"01234567891011");
+
+        // A value known in advance is simply printed as text.
+        let generate = |stmt| BrainfuckGenerator::new().generate(&vec![stmt]).unwrap();
+        assert_eq!(
+            generate(Stmt::print_num(Expr::number(142))),
+            generate(Stmt::print(Expr::string("142")))
+        );
+    }
+
+    #[test]
+    fn test_set_value_len() {
+        for value in 0..=u8::MAX {
+            let mut generator = BrainfuckGenerator::new();
+            generator.set_value(0, value.into());
+            assert_eq!(generator.output.len(), set_value_len(value), "{}", value);
+        }
+    }
+
+    #[test]
+    fn test_copy_len() {
+        let mut generator = BrainfuckGenerator::new();
+        generator.memory_ptr = 1;
+        generator.next_temp_addr = 2;
+        generator.copy_value(0, 1);
+        assert_eq!(generator.output.len(), COPY_LEN);
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_constant_pool() {
+        let generate = |program: &Program, pool: bool| {
+            let mut generator = BrainfuckGenerator::new();
+            if pool {
+                generator = generator.with_constant_pool();
+            }
+            let code = generator.generate(program).unwrap();
+            (code, generator.cell_names())
+        };
+
+        // Spaces and newlines are printed straight from their cells.
+        let mut program = vec![Stmt::let_stmt("x", true, Expr::number(65))];
+        for _ in 0..4 {
+            program.extend([
+                Stmt::print(Expr::variable("x")),
+                Stmt::print(Expr::number(32)),
+                Stmt::print(Expr::number(10)),
+                Stmt::assign(
+                    "x",
+                    Expr::binary(Expr::variable("x"), BinaryOp::Add, Expr::number(1)),
+                ),
+            ]);
+        }
+        let (plain, _) = generate(&program, false);
+        let (pooled, names) = generate(&program, true);
+        assert!(pooled.len() < plain.len());
+        assert!(names.values().any(|name| name == "constant 32"));
+        assert!(names.values().any(|name| name == "constant 10"));
+        assert!(!names.values().any(|name| name == "constant 1"));
+        let output = Interpreter::new(pooled).run().unwrap().output;
+        assert_eq!(output, Interpreter::new(plain).run().unwrap().output);
+
+        // A literal used once, or one no longer to set than to go back and
+        // read, is set where it is used as before.
+        let program = vec![
+            Stmt::print(Expr::number(100)),
+            Stmt::print(Expr::number(1)),
+            Stmt::print(Expr::number(1)),
+        ];
+        assert_eq!(generate(&program, true), generate(&program, false));
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_booleans_are_zero_or_one() {
+        let compare = |left, op, right| Expr::binary(Expr::number(left), op, Expr::number(right));
+        let program = vec![
+            Stmt::let_stmt("a", false, Expr::number(200)),
+            Stmt::let_stmt("t", false, Expr::Bool(true)),
+            Stmt::print(Expr::variable("t")),
+            Stmt::print(Expr::Bool(false)),
+            Stmt::print(Expr::binary(
+                Expr::variable("a"),
+                BinaryOp::Greater,
+                Expr::number(3),
+            )),
+            Stmt::print(compare(7, BinaryOp::NotEqual, 9)),
+            Stmt::print(compare(7, BinaryOp::LessEqual, 9)),
+            Stmt::print(Expr::binary(
+                compare(9, BinaryOp::Less, 7),
+                BinaryOp::Or,
+                Expr::variable("t"),
+            )),
+            Stmt::print(Expr::unary(UnaryOp::Not, Expr::variable("t"))),
+            Stmt::print(Expr::binary(
+                compare(200, BinaryOp::GreaterEqual, 3),
+                BinaryOp::Equal,
+                Expr::Bool(true),
+            )),
+        ];
+
+        assert_eq!(run(&program), vec![1, 0, 1, 1, 1, 1, 0, 1]);
+    }
+
+    #[test]
+    fn test_unreachable_bodies_are_skipped() {
+        let program = vec![
+            Stmt::if_stmt(
+                Expr::binary(Expr::number(1), BinaryOp::Equal, Expr::number(2)),
+                vec![Stmt::print(Expr::number(1))],
+            ),
+            Stmt::while_stmt(Expr::number(0), vec![Stmt::print(Expr::number(2))]),
+        ];
+        let code = BrainfuckGenerator::new().generate(&program).unwrap();
+
+        assert_eq!(code, "");
+    }
+
+    #[test]
+    fn test_generate_into_flushes_each_statement() {
+        /// Keeps each piece of code it is passed separately.
+        struct Pieces(Vec<String>);
+
+        impl fmt::Write for Pieces {
+            fn write_str(&mut self, code: &str) -> fmt::Result {
+                self.0.push(code.to_string());
+                Ok(())
+            }
+        }
+
+        let program = vec![
+            Stmt::let_stmt("x", true, Expr::number(3)).with_span(Span::new(0, 14)),
+            Stmt::while_stmt(
+                Expr::variable("x"),
+                vec![Stmt::assign(
+                    "x",
+                    Expr::binary(Expr::variable("x"), BinaryOp::Sub, Expr::number(1)),
+                )],
+            )
+            .with_span(Span::new(15, 40)),
+            Stmt::print(Expr::variable("x")).with_span(Span::new(41, 50)),
+        ];
+        let whole = BrainfuckGenerator::new().generate(&program).unwrap();
+        let mut generator = BrainfuckGenerator::new();
+        let mut pieces = Pieces(Vec::new());
+        generator.generate_into(&program, &mut pieces).unwrap();
+
+        // Each top-level statement is passed on as soon as it is done.
+        let pieces = pieces.0;
+        assert!(pieces.len() >= program.len(), "{:?}", pieces);
+        assert_eq!(pieces.concat(), whole);
+        // Positions still count from the start of the whole program.
+        assert_eq!(
+            generator.source_map().span_at(whole.len() - 1),
+            Some(Span::new(41, 50))
+        );
+
+        /// Takes no code at all.
+        struct Full;
+
+        impl fmt::Write for Full {
+            fn write_str(&mut self, _: &str) -> fmt::Result {
+                Err(fmt::Error)
+            }
+        }
+
+        let error = BrainfuckGenerator::new()
+            .generate_into(&program, &mut Full)
+            .unwrap_err();
+        assert_eq!(error.message, "could not write the generated code");
+    }
 }
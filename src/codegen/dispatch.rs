@@ -0,0 +1,1196 @@
+//! Function calls through a dispatch loop, for [`CallStrategy::Dispatch`].
+//!
+//! The program is cut into numbered blocks wherever control has to move
+//! somewhere other than the next statement: at a call to a function that
+//! is not expanded in place, at the return from one, and around any `if`,
+//! loop or `match` that contains such a call or a `return`. Everything
+//! else is generated as usual, inside the block it falls in. A `state`
+//! cell holds the number of the block to run next, and the program becomes
+//! a single loop that runs that block until one sets it to 0:
+//!
+//! ```text
+//! state = 1
+//! state[ index = state  flag = 1
+//!        index[- index[- ... flag[- block n ] ... index] flag[- block 1 ] index]
+//! ]
+//! ```
+//!
+//! As in [`lookup`](BrainfuckGenerator::lookup), only the innermost case
+//! entered still finds `flag` set. A program of more than 255 blocks
+//! numbers them with two cells, each from 1 to 255: a group of 255 blocks
+//! and the block within it. The loop then picks the group the same way and
+//! within that the block, and `state` holds the second number, which is
+//! still only 0 once the program ends. A call stores its arguments in the
+//! function's parameter cells and the number of the block after it in the
+//! function's `return_to` cell, then jumps to the function's first block.
+//! Values that have to outlive a block, such as a call's result, are kept
+//! in cells of their own, laid out like variables.
+//!
+//! Every function has one set of cells, so a call that may lead back into
+//! the function making it would overwrite the caller's. Such a call copies
+//! the caller's cells, its frame, onto a stack first, and copies them back
+//! once it returns. The stack is a run of cells after the variables with
+//! the latest frame at its start: pushing moves everything on it along by
+//! the frame's size, which keeps the pointer's moves fixed, as Brainfuck
+//! needs, at the cost of steps in proportion to the stack's size. A
+//! `depth` cell counts the frames, and a call that finds the stack full
+//! sets `state` to 0, ending the program.
+//!
+//! [`CallStrategy::Dispatch`]: super::CallStrategy::Dispatch
+
+use super::BrainfuckGenerator;
+use crate::ast::{self, BinaryOp, Expr, MatchArm, Program, Stmt, UnaryOp};
+use crate::error::TranspilerError;
+use crate::ranges::Ranges;
+use crate::span::Span;
+use crate::symbol_table::Symbol;
+use alloc::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    format,
+    string::String,
+    vec,
+    vec::Vec,
+};
+use core::ops::Range;
+
+/// How many blocks one cell can number.
+pub(super) const NARROW_BLOCKS: usize = u8::MAX as usize;
+
+/// The cells a block's number is kept in.
+#[derive(Debug, Clone, Copy)]
+struct BlockCells {
+    /// The block within its group, or with no groups the block itself; 0
+    /// ends the program.
+    low: usize,
+    /// The group, with more blocks than `low` can number alone.
+    high: Option<usize>,
+}
+
+/// The code of one block, with its source map relative to its start.
+#[derive(Debug, Default)]
+struct Block {
+    code: String,
+    statements: Vec<(Range<usize>, Span)>,
+}
+
+/// The cells a function's one copy is called through.
+#[derive(Debug, Clone)]
+struct Routine {
+    name: String,
+    /// Block the body starts in.
+    entry: usize,
+    params: Vec<usize>,
+    /// Where `return` leaves the value.
+    result: usize,
+    /// Block to go on with once the function returns.
+    return_to: BlockCells,
+    /// The parameters and `return_to`, as indices into `variables`, which
+    /// are part of the function's frame.
+    frame: Vec<usize>,
+}
+
+/// Where recursive calls save their callers' frames.
+#[derive(Debug, Clone)]
+struct Stack {
+    /// How many frames are on the stack.
+    depth: usize,
+    /// The latest frame first.
+    cells: Range<usize>,
+}
+
+/// Where `break` and `continue` go in a loop cut into blocks.
+#[derive(Debug, Clone, Copy)]
+struct Targets {
+    exit: usize,
+    next: usize,
+}
+
+#[derive(Debug)]
+pub(super) struct Dispatch {
+    state: BlockCells,
+    index: usize,
+    flag: usize,
+    /// Block `n` is `blocks[n - 1]`; state 0 ends the program.
+    blocks: Vec<Block>,
+    /// The block being generated, or `None` after a jump until the next one
+    /// starts.
+    current: Option<usize>,
+    routines: BTreeMap<String, Routine>,
+    /// Functions called so far whose bodies are still to be generated.
+    pending: VecDeque<String>,
+    /// The function being generated.
+    routine: Option<Routine>,
+    /// Targets of each enclosing loop cut into blocks, innermost last.
+    loops: Vec<Targets>,
+    /// Statements cut into blocks that are still being generated, with
+    /// where each started in the current block.
+    open: Vec<(Span, usize)>,
+    /// How many cells have been set aside for values that outlive a block.
+    held: usize,
+    /// Calls, as `(caller, callee)`, that save the caller's frame.
+    recursive: BTreeSet<(String, String)>,
+    /// Set if any call does.
+    stack: Option<Stack>,
+    /// Indices into `variables` of the cells [`routine`] sets aside, which
+    /// belong to no frame but the called function's.
+    ///
+    /// [`routine`]: BrainfuckGenerator::routine
+    routine_cells: BTreeSet<usize>,
+    /// The frame of each function that recursive calls save, as indices
+    /// into `variables`.
+    frames: BTreeMap<String, Vec<usize>>,
+}
+
+impl Dispatch {
+    fn new_block(&mut self) -> usize {
+        self.blocks.push(Block::default());
+        self.blocks.len()
+    }
+}
+
+impl BrainfuckGenerator {
+    pub(super) fn generate_dispatch(&mut self, program: &Program) {
+        // Blocks run in whatever order the calls make, so nothing is known
+        // about what the variables hold.
+        self.ranges = Ranges::unknown();
+        let entry_ptr = self.memory_ptr;
+        let index = self.get_temp_addr();
+        let flag = self.get_temp_addr();
+        let recursive = ast::recursive_calls(&ast::functions(program))
+            .into_iter()
+            .map(|(caller, callee)| (caller.into(), callee.into()))
+            .collect();
+        let state = self.allocate_variable("dispatch state", true, Span::default());
+        let group = self
+            .wide_dispatch()
+            .then(|| self.allocate_variable("dispatch group", true, Span::default()));
+        let mut d = Dispatch {
+            state: BlockCells {
+                low: state,
+                high: group,
+            },
+            index,
+            flag,
+            blocks: Vec::new(),
+            current: None,
+            routines: BTreeMap::new(),
+            pending: VecDeque::new(),
+            routine: None,
+            loops: Vec::new(),
+            open: Vec::new(),
+            held: 0,
+            recursive,
+            stack: None,
+            routine_cells: BTreeSet::new(),
+            frames: BTreeMap::new(),
+        };
+        if !d.recursive.is_empty() {
+            let depth = self.allocate_variable("stack depth", true, Span::default());
+            let size = self.stack_size();
+            d.stack = Some(Stack {
+                depth,
+                cells: self.next_var_addr..self.next_var_addr + size,
+            });
+            self.next_var_addr += size;
+        }
+        let prefix = core::mem::take(&mut self.output);
+        let prefix_statements = core::mem::take(&mut self.source_map.statements);
+
+        let main = d.new_block();
+        self.start_block(&mut d, main);
+        self.lower_stmts(&mut d, program);
+        if d.current.is_some() {
+            self.move_to(d.state.low);
+            self.clear_cell();
+            self.end_block(&mut d);
+        }
+        while let Some(name) = d.pending.pop_front() {
+            self.generate_routine(&mut d, &name);
+        }
+        self.frame_cells = core::mem::take(&mut d.frames);
+        // The layout pass only knows how large the frames are now that it
+        // has generated every function.
+        if let Some(stack) = &d.stack {
+            self.next_var_addr += self.stack_size().saturating_sub(stack.cells.len());
+        }
+
+        self.output = prefix;
+        self.source_map.statements = prefix_statements;
+        self.memory_ptr = entry_ptr;
+        self.dispatch_blocks = d.blocks.len();
+        if d.blocks.len() > NARROW_BLOCKS * NARROW_BLOCKS {
+            if self.error.is_none() {
+                let message = format!(
+                    "dispatching calls takes {} blocks, more than two cells can number",
+                    d.blocks.len()
+                );
+                let help = if d.recursive.is_empty() {
+                    "help: leave out `--calls dispatch` and `#[inline(never)]` to copy each \
+                     function into its calls instead"
+                } else {
+                    "help: recursive functions are always dispatched; make fewer calls, \
+                     or fewer branches and loops around them"
+                };
+                self.error = Some(
+                    TranspilerError::new(message)
+                        .with_note(format!(
+                            "the dispatch loop keeps the number of the next block in two \
+                             cells, so it has room for {}; every call and return starts a \
+                             new one",
+                            NARROW_BLOCKS * NARROW_BLOCKS
+                        ))
+                        .with_note(help),
+                );
+            }
+            return;
+        }
+        // The stack counts on starting out empty.
+        if let Some(stack) = &d.stack {
+            for cell in core::iter::once(stack.depth).chain(stack.cells.clone()) {
+                self.move_to(cell);
+                self.clear_cell();
+            }
+        }
+        self.assign_block(d.state, 1);
+        self.move_to(d.state.low);
+        self.output.push('[');
+        let mut blocks = core::mem::take(&mut d.blocks);
+        match d.state.high {
+            None => self.switch(&d, d.state.low, blocks.len(), &mut |generator, i| {
+                generator.emit_block(core::mem::take(&mut blocks[i]));
+            }),
+            Some(group) => {
+                let groups = blocks.len().div_ceil(NARROW_BLOCKS);
+                self.switch(&d, group, groups, &mut |generator, g| {
+                    let first = g * NARROW_BLOCKS;
+                    let count = NARROW_BLOCKS.min(blocks.len() - first);
+                    generator.switch(&d, d.state.low, count, &mut |generator, i| {
+                        generator.emit_block(core::mem::take(&mut blocks[first + i]));
+                    });
+                });
+            }
+        }
+        self.move_to(d.state.low);
+        self.output.push(']');
+    }
+
+    /// Whether block numbers take two cells, as the layout pass found.
+    fn wide_dispatch(&self) -> bool {
+        self.dispatch_blocks > NARROW_BLOCKS
+    }
+
+    /// Runs `case(i)` for the value `i + 1` of `selector`, out of `count`
+    /// cases, each generated starting and ending on `flag`.
+    fn switch(
+        &mut self,
+        d: &Dispatch,
+        selector: usize,
+        count: usize,
+        case: &mut dyn FnMut(&mut Self, usize),
+    ) {
+        self.copy_value(selector, d.index);
+        self.set_value(d.flag, 1);
+        for _ in 0..count {
+            self.move_to(d.index);
+            self.output.push_str("[-");
+        }
+        for i in (0..count).rev() {
+            self.move_to(d.flag);
+            self.output.push_str("[-");
+            case(self, i);
+            self.move_to(d.flag);
+            self.output.push(']');
+            self.move_to(d.index);
+            self.output.push(']');
+        }
+    }
+
+    /// Adds a block's code, which starts and ends on `flag`, here.
+    fn emit_block(&mut self, block: Block) {
+        let offset = self.output.len();
+        self.output.push_str(&block.code);
+        self.source_map.statements.extend(
+            block
+                .statements
+                .into_iter()
+                .map(|(code, span)| (code.start + offset..code.end + offset, span)),
+        );
+    }
+
+    /// Sets `cells` to the number of `block`, or to end the program for 0.
+    fn assign_block(&mut self, cells: BlockCells, block: usize) {
+        match cells.high {
+            Some(group) if block > 0 => {
+                self.assign_constant(cells.low, (block - 1) % NARROW_BLOCKS + 1);
+                self.assign_constant(group, (block - 1) / NARROW_BLOCKS + 1);
+            }
+            _ => self.assign_constant(cells.low, block),
+        }
+    }
+
+    fn copy_block(&mut self, from: BlockCells, to: BlockCells) {
+        self.copy_value(from.low, to.low);
+        if let (Some(from), Some(to)) = (from.high, to.high) {
+            self.copy_value(from, to);
+        }
+    }
+
+    /// Generates the one copy of `name`'s body, returning 0 if it runs off
+    /// the end.
+    fn generate_routine(&mut self, d: &mut Dispatch, name: &str) {
+        let (Some(function), Some(routine)) = (
+            self.functions.get(name).cloned(),
+            d.routines.get(name).cloned(),
+        ) else {
+            return;
+        };
+        self.start_block(d, routine.entry);
+        self.symbols.enter_function();
+        for (param, &cell) in function.params.iter().zip(&routine.params) {
+            self.symbols
+                .declare(Symbol::new(param, false, function.span).with_cells(cell..cell + 1));
+        }
+        d.routine = Some(routine.clone());
+        let first = self.live_variables;
+        self.lower_stmts(d, &function.body);
+        if d.current.is_some() {
+            self.move_to(routine.result);
+            self.clear_cell();
+            self.copy_block(routine.return_to, d.state);
+            self.end_block(d);
+        }
+        if d.recursive.iter().any(|(caller, _)| caller == name) {
+            let locals = (first..self.live_variables).filter(|i| !d.routine_cells.contains(i));
+            let frame = routine.frame.iter().copied().chain(locals).collect();
+            d.frames.insert(name.into(), frame);
+        }
+        d.routine = None;
+        self.symbols.exit_function();
+    }
+
+    fn start_block(&mut self, d: &mut Dispatch, block: usize) {
+        d.current = Some(block);
+        self.memory_ptr = d.flag;
+        for (_, start) in &mut d.open {
+            *start = 0;
+        }
+    }
+
+    /// Finishes the current block, which must already have set `state`.
+    fn end_block(&mut self, d: &mut Dispatch) {
+        let Some(block) = d.current.take() else {
+            return;
+        };
+        self.move_to(d.flag);
+        let end = self.output.len();
+        for &(span, start) in d.open.iter().rev() {
+            self.source_map.statements.push((start..end, span));
+        }
+        d.blocks[block - 1] = Block {
+            code: core::mem::take(&mut self.output),
+            statements: core::mem::take(&mut self.source_map.statements),
+        };
+    }
+
+    /// Ends the current block, if a jump has not already, going on with
+    /// `block`.
+    fn jump(&mut self, d: &mut Dispatch, block: usize) {
+        if d.current.is_none() {
+            return;
+        }
+        let mark = self.next_temp_addr;
+        self.assign_block(d.state, block);
+        self.next_temp_addr = mark;
+        self.end_block(d);
+    }
+
+    /// Ends the current block, going on with `yes` if `condition` holds and
+    /// `no` otherwise.
+    fn branch(&mut self, d: &mut Dispatch, condition: &Expr, yes: usize, no: usize) {
+        let mark = self.next_temp_addr;
+        let mut condition_addr = self.evaluate_condition(condition);
+        if !self.is_temp(condition_addr) {
+            let temp = self.get_temp_addr();
+            self.copy_value(condition_addr, temp);
+            condition_addr = temp;
+        }
+        self.branch_on(d, condition_addr, yes, no);
+        self.next_temp_addr = mark;
+    }
+
+    /// Like [`branch`](Self::branch) on whether the temporary `flag` is
+    /// nonzero, clearing it.
+    fn branch_on(&mut self, d: &mut Dispatch, flag: usize, yes: usize, no: usize) {
+        self.assign_block(d.state, no);
+        self.move_to(flag);
+        self.output.push('[');
+        self.assign_block(d.state, yes);
+        self.move_to(flag);
+        self.clear_cell();
+        self.output.push(']');
+        self.end_block(d);
+    }
+
+    /// Sets a cell that is not a temporary, whose neighbour `set_value`
+    /// must not use as scratch.
+    fn assign_constant(&mut self, addr: usize, value: usize) {
+        let temp = self.get_temp_addr();
+        self.set_value(temp, value as i32);
+        self.store_value(temp, addr);
+    }
+
+    /// Whether `stmt` has to be cut into blocks rather than generated as
+    /// usual.
+    fn cuts(&self, d: &Dispatch, stmt: &Stmt) -> bool {
+        self.calls_dispatched(stmt) || stmt.returns() || (!d.loops.is_empty() && stmt.jumps())
+    }
+
+    fn lower_block(&mut self, d: &mut Dispatch, body: &[Stmt]) {
+        self.symbols.enter_scope();
+        self.lower_stmts(d, body);
+        self.symbols.exit_scope();
+    }
+
+    /// Generates `body` in order, up to a jump that leaves it.
+    fn lower_stmts(&mut self, d: &mut Dispatch, body: &[Stmt]) {
+        for stmt in body {
+            if d.current.is_none() {
+                return;
+            }
+            if self.cuts(d, stmt) {
+                self.lower_stmt(d, stmt);
+            } else {
+                self.generate_stmt(stmt);
+            }
+        }
+    }
+
+    fn lower_stmt(&mut self, d: &mut Dispatch, stmt: &Stmt) {
+        let mark = self.next_temp_addr;
+        let enclosing_span = core::mem::replace(&mut self.current_span, stmt.span());
+        d.open.push((stmt.span(), self.output.len()));
+        match stmt {
+            // Only the value calls anything, so once the calls have been
+            // made the rest is an ordinary statement.
+            Stmt::Let { value, .. }
+            | Stmt::Assign { value, .. }
+            | Stmt::Print { value, .. }
+            | Stmt::PrintNum { value, .. }
+            | Stmt::Expr { value, .. } => {
+                let value = self.lower_calls(d, value);
+                let mut stmt = stmt.clone();
+                if let Stmt::Let { value: old, .. }
+                | Stmt::Assign { value: old, .. }
+                | Stmt::Print { value: old, .. }
+                | Stmt::PrintNum { value: old, .. }
+                | Stmt::Expr { value: old, .. } = &mut stmt
+                {
+                    *old = value;
+                }
+                self.generate_stmt(&stmt);
+            }
+            Stmt::If {
+                condition,
+                body,
+                else_body,
+                span,
+            } => {
+                let condition = self.lower_calls(d, condition);
+                if body.iter().chain(else_body).any(|stmt| self.cuts(d, stmt)) {
+                    self.lower_if(d, &condition, body, else_body);
+                } else {
+                    self.generate_stmt(
+                        &Stmt::if_else(condition, body.clone(), else_body.clone()).with_span(*span),
+                    );
+                }
+            }
+            Stmt::Match {
+                scrutinee,
+                arms,
+                span,
+            } => {
+                let scrutinee = self.lower_calls(d, scrutinee);
+                let cut = arms
+                    .iter()
+                    .flat_map(|arm| &arm.body)
+                    .any(|stmt| self.cuts(d, stmt));
+                if cut {
+                    self.lower_match(d, &scrutinee, arms);
+                } else {
+                    self.generate_stmt(&Stmt::match_stmt(scrutinee, arms.clone()).with_span(*span));
+                }
+            }
+            Stmt::While {
+                condition, body, ..
+            } => {
+                let (test, next, exit) = (d.new_block(), d.new_block(), d.new_block());
+                self.jump(d, test);
+                self.start_block(d, test);
+                let condition = self.lower_calls(d, condition);
+                self.branch(d, &condition, next, exit);
+                self.start_block(d, next);
+                self.lower_loop_body(d, Targets { exit, next: test }, body);
+                self.start_block(d, exit);
+            }
+            Stmt::Loop { body, .. } => {
+                let (next, exit) = (d.new_block(), d.new_block());
+                self.jump(d, next);
+                self.start_block(d, next);
+                self.lower_loop_body(d, Targets { exit, next }, body);
+                self.start_block(d, exit);
+            }
+            Stmt::DoWhile {
+                body, condition, ..
+            } => {
+                let (next, test, exit) = (d.new_block(), d.new_block(), d.new_block());
+                self.jump(d, next);
+                self.start_block(d, next);
+                self.lower_loop_body(d, Targets { exit, next: test }, body);
+                self.start_block(d, test);
+                let condition = self.lower_calls(d, condition);
+                self.branch(d, &condition, next, exit);
+                self.start_block(d, exit);
+            }
+            // Unrolled as usual, with each byte's pass a block of its own
+            // for `continue` to go to.
+            Stmt::For {
+                name,
+                iterable,
+                body,
+                span,
+            } => {
+                let cells = self.string_cells(iterable);
+                let passes: Vec<usize> = cells.clone().map(|_| d.new_block()).collect();
+                let exit = d.new_block();
+                let mut next = passes.first().copied().unwrap_or(exit);
+                for (i, addr) in cells.enumerate() {
+                    self.jump(d, next);
+                    self.start_block(d, next);
+                    next = passes.get(i + 1).copied().unwrap_or(exit);
+                    self.symbols.enter_scope();
+                    self.symbols
+                        .declare(Symbol::new(name, false, *span).with_cells(addr..addr + 1));
+                    d.loops.push(Targets { exit, next });
+                    self.loop_depth += 1;
+                    self.lower_stmts(d, body);
+                    self.loop_depth -= 1;
+                    d.loops.pop();
+                    self.symbols.exit_scope();
+                }
+                self.jump(d, exit);
+                self.start_block(d, exit);
+            }
+            Stmt::Break { .. } => match d.loops.last().copied() {
+                Some(targets) => self.jump(d, targets.exit),
+                None => self.internal_error("`break` outside of a loop"),
+            },
+            Stmt::Continue { .. } => match d.loops.last().copied() {
+                Some(targets) => self.jump(d, targets.next),
+                None => self.internal_error("`continue` outside of a loop"),
+            },
+            Stmt::Function(_) => {}
+            Stmt::Return { value, .. } => match d.routine.clone() {
+                Some(routine) => {
+                    match value {
+                        Some(value) => {
+                            let value = self.lower_calls(d, value);
+                            let addr = self.evaluate_expression(&value);
+                            self.store_value(addr, routine.result);
+                        }
+                        None => {
+                            self.move_to(routine.result);
+                            self.clear_cell();
+                        }
+                    }
+                    self.copy_block(routine.return_to, d.state);
+                    self.end_block(d);
+                }
+                None => self.internal_error("`return` outside of a function"),
+            },
+        }
+        if let Some((span, start)) = d.open.pop()
+            && d.current.is_some()
+        {
+            self.source_map
+                .statements
+                .push((start..self.output.len(), span));
+        }
+        self.current_span = enclosing_span;
+        self.next_temp_addr = mark;
+    }
+
+    fn lower_if(&mut self, d: &mut Dispatch, condition: &Expr, body: &[Stmt], else_body: &[Stmt]) {
+        let yes = d.new_block();
+        let no = (!else_body.is_empty()).then(|| d.new_block());
+        let after = d.new_block();
+        self.branch(d, condition, yes, no.unwrap_or(after));
+        self.start_block(d, yes);
+        self.lower_block(d, body);
+        self.jump(d, after);
+        if let Some(no) = no {
+            self.start_block(d, no);
+            self.lower_block(d, else_body);
+            self.jump(d, after);
+        }
+        self.start_block(d, after);
+    }
+
+    /// Keeps the scrutinee in a cell of its own, then tests it as a chain of
+    /// `if`s, leaving out the arms [`generate_match`] would.
+    ///
+    /// [`generate_match`]: BrainfuckGenerator::generate_match
+    fn lower_match(&mut self, d: &mut Dispatch, scrutinee: &Expr, arms: &[MatchArm]) {
+        let held = self.hold(d, "match", scrutinee);
+        let mut seen = BTreeSet::new();
+        let mut chain = Vec::new();
+        for arm in arms {
+            match arm.pattern.value() {
+                Some(value) if seen.insert(value) => chain.push(arm),
+                Some(_) => {}
+                None => {
+                    chain.push(arm);
+                    break;
+                }
+            }
+        }
+        let mut rest = Vec::new();
+        for arm in chain.into_iter().rev() {
+            rest = match arm.pattern.value() {
+                Some(value) => {
+                    let test = Expr::binary(
+                        Expr::variable(&held),
+                        BinaryOp::Equal,
+                        Expr::number(value.into()),
+                    );
+                    vec![Stmt::if_else(test, arm.body.clone(), rest).with_span(arm.span)]
+                }
+                None => arm.body.clone(),
+            };
+        }
+        self.lower_block(d, &rest);
+    }
+
+    fn lower_loop_body(&mut self, d: &mut Dispatch, targets: Targets, body: &[Stmt]) {
+        d.loops.push(targets);
+        self.loop_depth += 1;
+        self.lower_block(d, body);
+        self.loop_depth -= 1;
+        d.loops.pop();
+        self.jump(d, targets.next);
+    }
+
+    /// Makes every call in `expr`, in the order evaluating it would, and
+    /// returns what is left to evaluate once they are done. The right side
+    /// of `&&` or `||` is only called when it would be evaluated. Input is
+    /// read in place as well, so it comes in the same order relative to
+    /// the input the calls read.
+    fn lower_calls(&mut self, d: &mut Dispatch, expr: &Expr) -> Expr {
+        if !expr.calls() && !expr.reads_input() {
+            return expr.clone();
+        }
+        match expr {
+            Expr::Unary { operator, operand } => {
+                Expr::unary(operator.clone(), self.lower_calls(d, operand))
+            }
+            Expr::Binary {
+                left,
+                operator: operator @ (BinaryOp::And | BinaryOp::Or),
+                right,
+            } if right.calls() || right.reads_input() => {
+                let left = self.lower_calls(d, left);
+                let held = self.hold(d, operator.symbol(), &left);
+                let undecided = match operator {
+                    BinaryOp::Or => Expr::unary(UnaryOp::Not, Expr::variable(&held)),
+                    _ => Expr::variable(&held),
+                };
+                let (right_block, after) = (d.new_block(), d.new_block());
+                self.branch(d, &undecided, right_block, after);
+                self.start_block(d, right_block);
+                let right = self.lower_calls(d, right);
+                let mark = self.next_temp_addr;
+                let addr = self.evaluate_expression(&right);
+                if let Some(held_addr) = self.variable_addr(&held) {
+                    self.store_value(addr, held_addr);
+                }
+                self.next_temp_addr = mark;
+                self.jump(d, after);
+                self.start_block(d, after);
+                Expr::Variable(held)
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                let left = self.lower_calls(d, left);
+                Expr::binary(left, operator.clone(), self.lower_calls(d, right))
+            }
+            Expr::Call { name, args } if self.dispatched.contains(name) => {
+                Expr::Variable(self.lower_call(d, name, args))
+            }
+            // Expanded in place, but made now all the same, so it runs
+            // before any call to its right.
+            Expr::Call { name, args } => {
+                let args = args.iter().map(|arg| self.lower_calls(d, arg)).collect();
+                Expr::Variable(self.hold(d, name, &Expr::call(name, args)))
+            }
+            Expr::Input => Expr::Variable(self.hold(d, "input", expr)),
+            Expr::Number(_) | Expr::Bool(_) | Expr::Str(_) | Expr::Variable(_) => expr.clone(),
+        }
+    }
+
+    /// Calls `name`, returning the variable its result is kept in.
+    fn lower_call(&mut self, d: &mut Dispatch, name: &str, args: &[Expr]) -> String {
+        let args: Vec<Expr> = args.iter().map(|arg| self.lower_calls(d, arg)).collect();
+        let Some(routine) = self.routine(d, name) else {
+            self.internal_error(format!("call to undefined function `{}`", name));
+            return self.hold(d, name, &Expr::number(0));
+        };
+        if args.len() != routine.params.len() {
+            self.internal_error(format!(
+                "`{}` called with {} arguments instead of {}",
+                name,
+                args.len(),
+                routine.params.len()
+            ));
+            return self.hold(d, name, &Expr::number(0));
+        }
+
+        let saved = self.save_frame(d, name);
+        let mark = self.next_temp_addr;
+        for (arg, &param) in args.iter().zip(&routine.params) {
+            let addr = self.evaluate_expression(arg);
+            self.store_value(addr, param);
+        }
+        let back = d.new_block();
+        self.assign_block(routine.return_to, back);
+        self.next_temp_addr = mark;
+        self.jump(d, routine.entry);
+        self.start_block(d, back);
+        if let Some((stack, frame)) = saved {
+            self.pop_frame(&stack, &frame);
+        }
+
+        let (held, addr) = self.held_variable(d, name);
+        self.move_to(addr);
+        self.clear_cell();
+        self.transfer(routine.result, addr, '+');
+        held
+    }
+
+    /// Before a call to `callee` that may lead back into the function
+    /// making it, checks there is room on the stack, ending the program if
+    /// not, and pushes the caller's frame. Returns the stack and the frame's
+    /// cells, for [`pop_frame`](Self::pop_frame) once the call returns.
+    fn save_frame(&mut self, d: &mut Dispatch, callee: &str) -> Option<(Stack, Vec<usize>)> {
+        let caller = d.routine.as_ref()?.name.clone();
+        if !d.recursive.contains(&(caller.clone(), callee.into())) {
+            return None;
+        }
+        let stack = d.stack.clone()?;
+        // Empty in the layout pass, which is still finding the frames.
+        let frame: Vec<usize> = self
+            .frame_cells
+            .get(&caller)
+            .map(|indices| indices.iter().map(|&i| self.placement[i]).collect())
+            .unwrap_or_default();
+
+        let mark = self.next_temp_addr;
+        let room = self.get_temp_addr();
+        self.copy_value(stack.depth, room);
+        self.move_to(room);
+        self.output.push_str(&"-".repeat(self.stack_depth.into()));
+        let push = d.new_block();
+        self.branch_on(d, room, push, 0);
+        self.next_temp_addr = mark;
+        self.start_block(d, push);
+
+        self.move_to(stack.depth);
+        self.output.push('+');
+        let size = frame.len();
+        for cell in (stack.cells.start..stack.cells.end.saturating_sub(size)).rev() {
+            self.transfer(cell, cell + size, '+');
+        }
+        for (i, &cell) in frame.iter().enumerate() {
+            self.copy_value(cell, stack.cells.start + i);
+        }
+        self.next_temp_addr = mark;
+        Some((stack, frame))
+    }
+
+    /// Moves the latest frame on the stack back into `frame`.
+    fn pop_frame(&mut self, stack: &Stack, frame: &[usize]) {
+        for (i, &cell) in frame.iter().enumerate() {
+            self.move_to(cell);
+            self.clear_cell();
+            self.transfer(stack.cells.start + i, cell, '+');
+        }
+        let size = frame.len();
+        for cell in stack.cells.start + size..stack.cells.end {
+            self.transfer(cell, cell - size, '+');
+        }
+        self.move_to(stack.depth);
+        self.output.push('-');
+    }
+
+    /// Cells for the stack to hold the deepest recursion allowed of the
+    /// largest frame.
+    fn stack_size(&self) -> usize {
+        let frame = self.frame_cells.values().map(Vec::len).max();
+        usize::from(self.stack_depth) * frame.unwrap_or(0)
+    }
+
+    /// The cells `name` is called through, setting them aside the first
+    /// time it is called.
+    fn routine(&mut self, d: &mut Dispatch, name: &str) -> Option<Routine> {
+        if let Some(routine) = d.routines.get(name) {
+            return Some(routine.clone());
+        }
+        let params = self.functions.get(name)?.params.len();
+        // An expanded call hands its cells back, so the next ones may come
+        // from before the end of `variables`.
+        let first = self.live_variables;
+        let routine = Routine {
+            name: name.into(),
+            entry: d.new_block(),
+            params: (0..params).map(|_| self.allocate_cell()).collect(),
+            result: self.allocate_cell(),
+            return_to: BlockCells {
+                low: self.allocate_cell(),
+                high: self.wide_dispatch().then(|| self.allocate_cell()),
+            },
+            // Everything but the result, which the caller reads once it
+            // has returned.
+            frame: (first..first + params)
+                .chain(first + params + 1..self.live_variables)
+                .collect(),
+        };
+        d.routine_cells.extend(first..self.live_variables);
+        d.routines.insert(name.into(), routine.clone());
+        d.pending.push_back(name.into());
+        Some(routine)
+    }
+
+    /// Stores `value` in a variable of its own, so that it outlives the
+    /// block, and returns the variable's name.
+    fn hold(&mut self, d: &mut Dispatch, label: &str, value: &Expr) -> String {
+        let mark = self.next_temp_addr;
+        let addr = self.evaluate_expression(value);
+        let (name, cell) = self.held_variable(d, label);
+        self.store_value(addr, cell);
+        self.next_temp_addr = mark;
+        name
+    }
+
+    fn held_variable(&mut self, d: &mut Dispatch, label: &str) -> (String, usize) {
+        d.held += 1;
+        let name = format!("{} #{}", label, d.held);
+        let span = self.current_span;
+        let cell = self.allocate_variable(&name, true, span);
+        (name, cell)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "interpreter")]
+    use crate::bf::Interpreter;
+    use crate::codegen::CallStrategy;
+    #[cfg(feature = "interpreter")]
+    use crate::eval::{EvalError, Evaluator};
+    use crate::frontend::{Frontend, MiniRust};
+
+    fn generate(source: &str, calls: CallStrategy) -> (String, BrainfuckGenerator) {
+        let program = MiniRust.parse(source).unwrap();
+        let mut generator = BrainfuckGenerator::new().with_call_strategy(calls);
+        (generator.generate(&program).unwrap(), generator)
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_dispatch_matches_inlining() {
+        let sources = [
+            "fn max(a, b) { if a > b { return a; } return b; } print(max(3, 7) + max(9, 2));",
+            "fn sq(n) { return n * n; } print(sq(sq(2)) + sq(3));",
+            "fn shout(c) { print(c); print(c); } shout(65); shout(66);",
+            "fn none() { print(1); } let x = none(); print(x);",
+            // Returns from inside loops, and loops around calls.
+            "fn sq(n) { return n * n; }
+             fn root(n) { let mut i = 0; while i < 16 { if sq(i) >= n { return i; } i = i + 1; } return 99; }
+             let mut k = 0;
+             while root(k) < 5 { k = k + 3; if k == 9 { continue; } print(k); }
+             let mut j = 0;
+             loop { j = j + 1; if sq(j) > 30 { break; } }
+             loop { j = j + sq(1); } while root(j) < 4;
+             print(j);",
+            // Calls on the right of `&&` and `||` only run when needed.
+            "fn say(c) { print(c); return c; }
+             let x = 1;
+             print(x == 0 && say(65) > 0);
+             print(x == 1 && say(66) > 0);
+             print(x == 1 || say(67) > 0);
+             print(x == 0 || say(68) > 0);",
+            "fn sq(n) { return n * n; }
+             match sq(2) { 4 => { print(sq(5)); } _ => { print(48); } }
+             let x = 3;
+             match x { 1 => { print(1); } 3 => { match sq(x) { 9 => { print(2); } _ => {} } } _ => { print(3); } }",
+            "fn twice(n) { n = n * 2; return n; }
+             let s = \"abc\";
+             for c in s { if c == 99 { break; } if c == 97 { continue; } print(twice(c)); }
+             let x = 5;
+             print(twice(x) + x);",
+        ];
+        for source in sources {
+            let mut program = MiniRust.parse(source).unwrap();
+            let expected = Evaluator::new().run(&program).unwrap().output;
+            for calls in [CallStrategy::Inline, CallStrategy::Dispatch] {
+                let (code, _) = generate(source, calls);
+                let output = Interpreter::new(code).run().unwrap().output;
+                assert_eq!(output, expected, "{:?} of {}", calls, source);
+            }
+            // Small functions are copied in even with `Dispatch`, so
+            // dispatch every one as well.
+            for stmt in &mut program {
+                if let Stmt::Function(function) = stmt {
+                    function.inline = ast::Inline::Never;
+                }
+            }
+            let code = BrainfuckGenerator::new().generate(&program).unwrap();
+            let output = Interpreter::new(code).run().unwrap().output;
+            assert_eq!(output, expected, "never inlined, {}", source);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_input() {
+        // Reads happen left to right, and only when `&&` gets that far.
+        let calls = "print(next() * 10 + input());
+             print(input() == 0 && next() > 0);
+             print(next() + next());";
+        for function in [
+            "fn next() { return input() - 48; }",
+            "#[inline(never)] fn next() { return input() - 48; }",
+        ] {
+            let source = format!("{} {}", function, calls);
+            let program = MiniRust.parse(&source).unwrap();
+            let expected = Evaluator::new().with_input("4A.52").run(&program).unwrap();
+            assert_eq!(expected.output, vec![105, 0, 7]);
+            let (code, _) = generate(&source, CallStrategy::Dispatch);
+            let output = Interpreter::new(code)
+                .with_input("4A.52")
+                .run()
+                .unwrap()
+                .output;
+            assert_eq!(output, expected.output, "{}", function);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_recursion() {
+        let sources = [
+            "fn fib(n) { if n < 2 { return n; } return fib(n - 1) + fib(n - 2); } print(fib(11));",
+            "fn even(n) { if n == 0 { return 1; } return odd(n - 1); }
+             fn odd(n) { if n == 0 { return 0; } return even(n - 1); }
+             print(even(9)); print(odd(9));",
+            // Locals, loops and held results all outlive the calls inside.
+            "fn sum(n) {
+                 let mut total = 0;
+                 let mut i = 0;
+                 while i < n { total = total + sum(i) + 1; i = i + 1; }
+                 match n { 3 => { print(total); } _ => {} }
+                 return total;
+             }
+             print(sum(4));",
+            "fn ack(m, n) {
+                 if m == 0 { return n + 1; }
+                 if n == 0 { return ack(m - 1, 1); }
+                 return ack(m - 1, ack(m, n - 1));
+             }
+             print(ack(2, 3));",
+            "fn count(n) { if n > 0 { print(n); count(n - 1); print(n); } } count(4);",
+        ];
+        for source in sources {
+            let program = MiniRust.parse(source).unwrap();
+            let expected = Evaluator::new().run(&program).unwrap().output;
+            // Recursive programs are dispatched whatever the strategy.
+            let (inline, _) = generate(source, CallStrategy::Inline);
+            let (code, _) = generate(source, CallStrategy::Dispatch);
+            assert_eq!(inline, code);
+            let output = Interpreter::new(code).run().unwrap().output;
+            assert_eq!(output, expected, "{}", source);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_call_in_for_body() {
+        // `f` is generated first, so its string is stored before `g`'s,
+        // and storing it must leave the byte `g` is walking over alone.
+        let source = "fn f(p) {
+                 if p < 100 { let mut i = 0; loop { i = i + 1; if i > 1 { break; } } }
+                 if p < 100 { for c in \"aba\" { } }
+             }
+             fn g() { for c in \"b\" { f(c); print(c); } }
+             f(1); g();";
+        let program = MiniRust.parse(source).unwrap();
+        let expected = Evaluator::new().run(&program).unwrap().output;
+        assert_eq!(expected, b"b");
+        let (code, generator) = generate(source, CallStrategy::Dispatch);
+        assert!(generator.dispatched.contains("f"));
+        let output = Interpreter::new(code).run().unwrap().output;
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_recursion_after_an_expanded_call() {
+        // The expanded call hands its cells back, and the recursive
+        // function's take them again; its frame must still cover them.
+        let sources = [
+            "fn down(n) { if n > 0 { down(n - 1); } } fn id(a) { print(a); } id(66); down(3); print(65);",
+            "fn fact(n) { if n < 2 { return 1; } return n * fact(n - 1); }
+             fn add(a, b) { return a + b; }
+             print_num(add(2, 3)); print_num(fact(5));",
+        ];
+        for source in sources {
+            let program = MiniRust.parse(source).unwrap();
+            let expected = Evaluator::new().run(&program).unwrap().output;
+            for calls in [CallStrategy::Inline, CallStrategy::Dispatch] {
+                let (code, _) = generate(source, calls);
+                let output = Interpreter::new(code).run().unwrap().output;
+                assert_eq!(output, expected, "{:?} of {}", calls, source);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_full_stack_ends_the_program() {
+        let source = "fn down(n) { print(n); if n > 0 { down(n - 1); } print(n); } down(6);";
+        let program = MiniRust.parse(source).unwrap();
+        let run = |depth| {
+            let mut generator = BrainfuckGenerator::new().with_stack_depth(depth);
+            let code = generator.generate(&program).unwrap();
+            Interpreter::new(code).run().unwrap().output
+        };
+        assert_eq!(run(6), [6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(run(4), [6, 5, 4, 3, 2]);
+        assert!(matches!(
+            Evaluator::new().with_stack_depth(4).run(&program),
+            Err(EvalError::StackOverflow { depth: 4, .. })
+        ));
+    }
+
+    #[test]
+    fn test_functions_are_generated_once() {
+        let function =
+            "fn show(n) { print(48 + n / 100); print(48 + n / 10 % 10); print(48 + n % 10); } ";
+        let source = format!(
+            "{}{}",
+            function,
+            "show(7); show(42); show(255); show(100);".repeat(3)
+        );
+        let (inline, _) = generate(&source, CallStrategy::Inline);
+        let (dispatch, _) = generate(&source, CallStrategy::Dispatch);
+        assert!(dispatch.len() * 3 < inline.len());
+
+        // Without calls there is nothing to dispatch.
+        let source = "let x = 3; print(x * 2);";
+        assert_eq!(
+            generate(source, CallStrategy::Inline).0,
+            generate(source, CallStrategy::Dispatch).0
+        );
+    }
+
+    #[test]
+    fn test_inline_attributes() {
+        let show =
+            "fn show(n) { print(48 + n / 100); print(48 + n / 10 % 10); print(48 + n % 10); }";
+        let calls = "show(7); show(42); show(255); show(100);";
+        let dispatched = |source: &str, calls| generate(source, calls).1.dispatch_blocks > 0;
+        // Left to itself, `Dispatch` copies a small function into its
+        // calls and jumps to a large one.
+        assert!(!dispatched(
+            &format!("fn f(c) {{ print(c); }} {}", "f(1); f(2); f(3);"),
+            CallStrategy::Dispatch
+        ));
+        assert!(dispatched(
+            &format!("{} {}", show, calls),
+            CallStrategy::Dispatch
+        ));
+        // The attributes override either strategy.
+        let always = format!("#[inline(always)] {} {}", show, calls);
+        assert!(!dispatched(&always, CallStrategy::Dispatch));
+        let never = format!("#[inline(never)] {} {}", show, calls);
+        assert!(dispatched(&never, CallStrategy::Inline));
+        // Except that a function calling itself, or calling one that is
+        // dispatched, cannot be copied in.
+        let source = "#[inline(always)] fn down(n) { if n > 0 { down(n - 1); } } down(3);";
+        assert!(dispatched(source, CallStrategy::Inline));
+        let source = "#[inline(never)] fn f() { print(1); } #[inline(always)] fn g() { f(); } g();";
+        let (_, generator) = generate(source, CallStrategy::Inline);
+        assert!(generator.dispatched.contains("g"));
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_recursion_inlines_the_rest() {
+        // Only the recursive function goes through the dispatch loop; its
+        // helpers are copied into it.
+        let source = "fn digit(n) { print(48 + n % 10); return n / 10; }
+             fn twice(n) { return n + n; }
+             fn down(n) { let rest = digit(n); if rest > 0 { down(rest); } print(twice(n) % 10 + 48); }
+             down(123);";
+        let program = MiniRust.parse(source).unwrap();
+        let expected = Evaluator::new().run(&program).unwrap().output;
+        let (code, generator) = generate(source, CallStrategy::Inline);
+        assert_eq!(generator.dispatched.iter().collect::<Vec<_>>(), ["down"]);
+        let output = Interpreter::new(code).run().unwrap().output;
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_source_map() {
+        let source = "#[inline(never)] fn show(c) { print(c); } let x = 65; show(x); print(x + 1);";
+        let (code, generator) = generate(source, CallStrategy::Dispatch);
+        let prints: Vec<&str> = code
+            .match_indices('.')
+            .map(|(position, _)| {
+                let span = generator.source_map().span_at(position).unwrap();
+                &source[span.start..span.end]
+            })
+            .collect();
+        // Blocks are laid out last first, and the function's comes last.
+        assert_eq!(prints, ["print(x + 1);", "print(c);"]);
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_many_blocks() {
+        // Past 255 blocks, their numbers take two cells.
+        let sources = [
+            format!(
+                "#[inline(never)] fn f() {{ print(1); }} {}",
+                "f();".repeat(300)
+            ),
+            format!(
+                "fn f(n) {{ if n > 0 {{ {} }} print(n); }} f(1);",
+                "f(n - 1);".repeat(130)
+            ),
+        ];
+        for source in &sources {
+            let program = MiniRust.parse(source).unwrap();
+            let expected = Evaluator::new().run(&program).unwrap().output;
+            let mut generator = BrainfuckGenerator::new()
+                .with_call_strategy(CallStrategy::Dispatch)
+                .with_stack_depth(2);
+            let code = generator.generate(&program).unwrap();
+            assert!(generator.dispatch_blocks > NARROW_BLOCKS);
+            let output = Interpreter::new(code).run().unwrap().output;
+            assert_eq!(output, expected);
+        }
+    }
+}
@@ -0,0 +1,492 @@
+//! `rust2bf conformance`: runs programs on every target and interpreter
+//! setup that can be run here, and prints a matrix of which ones print
+//! what the program should, so targets drifting apart are caught.
+//!
+//! Every setup reads what the program's `//! input:` comments hold, and
+//! what it should print is what its `//! expect:` comments say, or else
+//! what evaluating it prints. The C, Rust and WebAssembly targets are
+//! built and run with `cc`, `rustc` and `wasmtime`, and skipped where those
+//! are not installed. Whitespace has nothing to run it with, so it is left
+//! out. Besides the usual 0, the interpreter is also run leaving the cell
+//! unchanged and storing -1 at the end of input, as other interpreters do;
+//! a program that reads past its input may fail there. The interpreter
+//! only has 8-bit cells, so there are no cell-size setups to vary.
+
+use crate::examples;
+use rusted_brains::analysis::Analyzer;
+use rusted_brains::ast::Program;
+use rusted_brains::backend;
+use rusted_brains::bf::{Eof, Interpreter, Vm};
+use rusted_brains::bytecode;
+use rusted_brains::charset::Charset;
+use rusted_brains::codegen::{BrainfuckGenerator, CallStrategy};
+use rusted_brains::error::TranspilerError;
+use rusted_brains::eval::Evaluator;
+use rusted_brains::expect;
+use rusted_brains::frontend::{Frontend, MiniRust};
+use rusted_brains::ir;
+use rusted_brains::optimizer::PassManager;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Most commands or statements any one run may take.
+const MAX_STEPS: u64 = 100_000_000;
+
+/// The Ook! spellings the `ook` target writes.
+const OOK: &str = "
+> Ook. Ook?
+< Ook? Ook.
++ Ook. Ook.
+- Ook! Ook!
+. Ook! Ook.
+, Ook. Ook!
+[ Ook! Ook?
+] Ook? Ook!
+";
+
+/// How one program fared on one setup.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Pass,
+    /// It printed something else, or could not be built or run.
+    Fail(String),
+    /// The tools to run it are not installed.
+    Skip(String),
+}
+
+/// Runs a program on its input, returning what it printed. Files it needs
+/// go under the directory it is given, named after the program.
+type Run = fn(&Program, &[u8], &Path) -> Result<Vec<u8>, Outcome>;
+
+/// A way of running a program: its column in the matrix, and how to run
+/// it.
+pub struct Setup {
+    pub name: &'static str,
+    run: Run,
+}
+
+/// Every setup, in the order of the matrix's columns.
+pub const SETUPS: &[Setup] = &[
+    Setup {
+        name: "eval",
+        run: evaluate,
+    },
+    Setup {
+        name: "bf",
+        run: |program, input, _| {
+            interpret(
+                &optimized(BrainfuckGenerator::new(), program)?,
+                input,
+                Eof::Zero,
+            )
+        },
+    },
+    Setup {
+        name: "bf-O0",
+        run: |program, input, _| {
+            interpret(
+                &generate(BrainfuckGenerator::new(), program)?,
+                input,
+                Eof::Zero,
+            )
+        },
+    },
+    Setup {
+        name: "eof-keep",
+        run: |program, input, _| {
+            interpret(
+                &optimized(BrainfuckGenerator::new(), program)?,
+                input,
+                Eof::Unchanged,
+            )
+        },
+    },
+    Setup {
+        name: "eof-255",
+        run: |program, input, _| {
+            interpret(
+                &optimized(BrainfuckGenerator::new(), program)?,
+                input,
+                Eof::MinusOne,
+            )
+        },
+    },
+    Setup {
+        name: "bfc",
+        run: bytecode_vm,
+    },
+    Setup {
+        name: "ook",
+        run: ook,
+    },
+    Setup {
+        name: "dispatch",
+        run: |program, input, _| {
+            let generator = BrainfuckGenerator::new().with_call_strategy(CallStrategy::Dispatch);
+            interpret(&optimized(generator, program)?, input, Eof::Zero)
+        },
+    },
+    Setup {
+        name: "checked",
+        run: |program, input, _| {
+            let generator = BrainfuckGenerator::new().with_checked_arithmetic();
+            interpret(&generate(generator, program)?, input, Eof::Zero)
+        },
+    },
+    Setup { name: "c", run: c },
+    Setup {
+        name: "rust",
+        run: rust,
+    },
+    Setup {
+        name: "wat",
+        run: wat,
+    },
+];
+
+/// Runs each program on every setup, printing the matrix followed by what
+/// went wrong where. Returns whether nothing failed.
+pub fn conformance(programs: &[String]) -> bool {
+    let corpus: Vec<(String, String)> = if programs.is_empty() {
+        examples::EXAMPLES
+            .iter()
+            .map(|(name, source)| (name.to_string(), source.to_string()))
+            .collect()
+    } else {
+        let mut corpus = Vec::new();
+        for filename in programs {
+            match fs::read_to_string(filename) {
+                Ok(source) => corpus.push((filename.clone(), source)),
+                Err(e) => {
+                    eprintln!("Error: Could not read file '{}': {}", filename, e);
+                    return false;
+                }
+            }
+        }
+        corpus
+    };
+    let dir = std::env::temp_dir().join(format!("rust2bf-conformance-{}", std::process::id()));
+    if let Err(e) = fs::create_dir_all(&dir) {
+        eprintln!("Error: Could not create '{}': {}", dir.display(), e);
+        return false;
+    }
+
+    let rows: Vec<Vec<Outcome>> = corpus
+        .iter()
+        .enumerate()
+        .map(|(i, (_, source))| check(source, &dir.join(format!("program{}", i))))
+        .collect();
+    let _ = fs::remove_dir_all(&dir);
+
+    let names: Vec<&str> = corpus.iter().map(|(name, _)| name.as_str()).collect();
+    print!("{}", matrix(&names, &rows));
+
+    let mut skipped = Vec::new();
+    for (name, row) in names.iter().zip(&rows) {
+        for (setup, outcome) in SETUPS.iter().zip(row) {
+            match outcome {
+                Outcome::Fail(reason) => println!("FAILED {} on {}: {}", name, setup.name, reason),
+                Outcome::Skip(reason) if !skipped.contains(&setup.name) => {
+                    skipped.push(setup.name);
+                    println!("skipped {}: {}", setup.name, reason);
+                }
+                _ => {}
+            }
+        }
+    }
+    let count = |f: fn(&Outcome) -> bool| rows.iter().flatten().filter(|o| f(o)).count();
+    let failed = count(|o| matches!(o, Outcome::Fail(_)));
+    println!(
+        "{} passed, {} failed, {} skipped",
+        count(|o| *o == Outcome::Pass),
+        failed,
+        count(|o| matches!(o, Outcome::Skip(_)))
+    );
+    failed == 0
+}
+
+/// Runs `source` on every setup, in the order of [`SETUPS`]. A program
+/// that does not compile, or whose input or expected output is unknown,
+/// fails on all of them.
+pub fn check(source: &str, path: &Path) -> Vec<Outcome> {
+    let fail_all = |reason: String| vec![Outcome::Fail(reason); SETUPS.len()];
+    let program = match MiniRust.parse(source) {
+        Ok(program) => program,
+        Err(errors) => return fail_all(first_error(errors.iter())),
+    };
+    let diagnostics = Analyzer::new().analyze(&program);
+    if diagnostics.has_errors() {
+        return fail_all(first_error(diagnostics.iter().filter(|d| !d.is_warning())));
+    }
+    let input = match expect::input(source) {
+        Ok(input) => input,
+        Err(e) => return fail_all(e.message),
+    };
+    let expected = match expect::expected_output(source) {
+        Ok(Some(expected)) => expected,
+        Ok(None) => match evaluate(&program, &input, path) {
+            Ok(output) => output,
+            Err(Outcome::Fail(reason)) => return fail_all(reason),
+            Err(other) => return vec![other; SETUPS.len()],
+        },
+        Err(e) => return fail_all(e.message),
+    };
+    SETUPS
+        .iter()
+        .map(|setup| match (setup.run)(&program, &input, path) {
+            Ok(output) if output == expected => Outcome::Pass,
+            Ok(output) => Outcome::Fail(format!(
+                "expected \"{}\", got \"{}\"",
+                expect::escape(&expected),
+                expect::escape(&output)
+            )),
+            Err(outcome) => outcome,
+        })
+        .collect()
+}
+
+/// The message of the first of `errors`.
+fn first_error<'a>(mut errors: impl Iterator<Item = &'a TranspilerError>) -> String {
+    errors
+        .next()
+        .map_or_else(|| "does not compile".to_string(), |e| e.message.clone())
+}
+
+/// The matrix, a row per program and a column per setup.
+fn matrix(names: &[&str], rows: &[Vec<Outcome>]) -> String {
+    let width = names.iter().map(|name| name.len()).max().unwrap_or(0);
+    let mut out = format!("{:width$}", "");
+    for setup in SETUPS {
+        out.push_str(&format!("  {:4}", setup.name));
+    }
+    out.truncate(out.trim_end().len());
+    out.push('\n');
+    for (name, row) in names.iter().zip(rows) {
+        out.push_str(&format!("{:width$}", name));
+        for (setup, outcome) in SETUPS.iter().zip(row) {
+            let cell = match outcome {
+                Outcome::Pass => "ok",
+                Outcome::Fail(_) => "FAIL",
+                Outcome::Skip(_) => "skip",
+            };
+            out.push_str(&format!("  {:1$}", cell, setup.name.len().max(4)));
+        }
+        out.truncate(out.trim_end().len());
+        out.push('\n');
+    }
+    out
+}
+
+fn evaluate(program: &Program, input: &[u8], _: &Path) -> Result<Vec<u8>, Outcome> {
+    match Evaluator::new()
+        .with_input(input)
+        .with_max_steps(MAX_STEPS)
+        .run(program)
+    {
+        Ok(evaluation) => Ok(evaluation.output),
+        Err(e) => Err(Outcome::Fail(e.to_string())),
+    }
+}
+
+fn generate(mut generator: BrainfuckGenerator, program: &Program) -> Result<String, Outcome> {
+    generator
+        .generate(program)
+        .map_err(|e| Outcome::Fail(e.message))
+}
+
+/// The generator's code run through the default optimization passes.
+fn optimized(generator: BrainfuckGenerator, program: &Program) -> Result<String, Outcome> {
+    let mut ops =
+        ir::parse(&generate(generator, program)?).map_err(|e| Outcome::Fail(e.message))?;
+    PassManager::new().run(&mut ops);
+    Ok(ir::emit(&ops))
+}
+
+fn interpret(code: &str, input: &[u8], eof: Eof) -> Result<Vec<u8>, Outcome> {
+    Interpreter::new(code)
+        .with_input(input)
+        .with_eof(eof)
+        .with_max_steps(MAX_STEPS)
+        .run()
+        .map(|execution| execution.output)
+        .map_err(|e| Outcome::Fail(e.to_string()))
+}
+
+fn bytecode_vm(program: &Program, input: &[u8], _: &Path) -> Result<Vec<u8>, Outcome> {
+    let code = optimized(BrainfuckGenerator::new(), program)?;
+    let ops = ir::parse(&code).map_err(|e| Outcome::Fail(e.message))?;
+    Vm::from_bytecode(&bytecode::encode(&ops))
+        .map_err(|e| Outcome::Fail(e.message))?
+        .with_input(input)
+        .with_max_steps(MAX_STEPS)
+        .run()
+        .map(|execution| execution.output)
+        .map_err(|e| Outcome::Fail(e.to_string()))
+}
+
+fn ook(program: &Program, input: &[u8], _: &Path) -> Result<Vec<u8>, Outcome> {
+    let code = target("ook", program)?;
+    let charset = Charset::parse(OOK).map_err(|e| Outcome::Fail(e.message))?;
+    interpret(&charset.decode(&code), input, Eof::Zero)
+}
+
+/// The program as the backend for `name` writes it.
+fn target(name: &str, program: &Program) -> Result<String, Outcome> {
+    let Some(mut backend) = backend::create(name) else {
+        return Err(Outcome::Skip(format!("no `{}` target", name)));
+    };
+    backend
+        .generate(program)
+        .map_err(|e| Outcome::Fail(e.message))
+}
+
+fn c(program: &Program, input: &[u8], path: &Path) -> Result<Vec<u8>, Outcome> {
+    let source = path.with_extension("c");
+    let binary = path.with_extension("c.exe");
+    write(&source, &target("c", program)?)?;
+    tool(Command::new("cc").arg("-o").arg(&binary).arg(&source), &[])?;
+    tool(&mut Command::new(&binary), input)
+}
+
+fn rust(program: &Program, input: &[u8], path: &Path) -> Result<Vec<u8>, Outcome> {
+    let source = path.with_extension("rs");
+    let binary = path.with_extension("rs.exe");
+    write(&source, &target("rust", program)?)?;
+    tool(
+        Command::new("rustc")
+            .args(["--edition", "2021", "--crate-name", "conformance", "-o"])
+            .arg(&binary)
+            .arg(&source),
+        &[],
+    )?;
+    tool(&mut Command::new(&binary), input)
+}
+
+fn wat(program: &Program, input: &[u8], path: &Path) -> Result<Vec<u8>, Outcome> {
+    let source = path.with_extension("wat");
+    write(&source, &target("wat", program)?)?;
+    tool(Command::new("wasmtime").arg("run").arg(&source), input)
+}
+
+fn write(path: &Path, contents: &str) -> Result<(), Outcome> {
+    fs::write(path, contents)
+        .map_err(|e| Outcome::Fail(format!("could not write '{}': {}", path.display(), e)))
+}
+
+/// Runs `command` with `input` on its stdin, returning what it printed. A
+/// tool that is not installed skips the setup.
+fn tool(command: &mut Command, input: &[u8]) -> Result<Vec<u8>, Outcome> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            // A program that stops reading early closes the pipe, which is
+            // not a failure of its own.
+            let _ = child.stdin.take().map(|mut stdin| stdin.write_all(input));
+            child.wait_with_output()
+        });
+    match output {
+        Ok(output) if output.status.success() => Ok(output.stdout),
+        Ok(output) => Err(Outcome::Fail(format!(
+            "`{}` failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            Err(Outcome::Skip(format!("`{}` not found", program)))
+        }
+        Err(e) => Err(Outcome::Fail(format!("could not run `{}`: {}", program, e))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory of its own for the files `test` builds.
+    fn scratch(test: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "rust2bf-conformance-{}-{}",
+            test,
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_examples_conform() {
+        let dir = scratch("examples");
+        for (name, source) in examples::EXAMPLES {
+            for (setup, outcome) in SETUPS.iter().zip(check(source, &dir.join(name))) {
+                assert!(
+                    !matches!(outcome, Outcome::Fail(_)),
+                    "{} on {}: {:?}",
+                    name,
+                    setup.name,
+                    outcome
+                );
+            }
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_wrong_output_fails_everywhere() {
+        let dir = scratch("wrong");
+        let outcomes = check("print(65);\n//! expect: B\n", &dir.join("wrong"));
+        assert!(
+            matches!(&outcomes[0], Outcome::Fail(reason) if reason == "expected \"B\", got \"A\"")
+        );
+        assert!(!outcomes.contains(&Outcome::Pass));
+        let outcomes = check("print(x);", &dir.join("invalid"));
+        assert!(outcomes.iter().all(|o| matches!(o, Outcome::Fail(_))));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_reading_past_the_input() {
+        let dir = scratch("eof");
+        let outcome = |source: &str, setup: &str| {
+            let column = SETUPS.iter().position(|s| s.name == setup).unwrap();
+            check(source, &dir.join("eof")).swap_remove(column)
+        };
+        let source = "//! input: a\n//! expect: a\\0\nprint(input()); print(input());";
+        assert_eq!(outcome(source, "bf"), Outcome::Pass);
+        assert_eq!(outcome(source, "eof-keep"), Outcome::Pass);
+        assert_eq!(
+            outcome(source, "eof-255"),
+            Outcome::Fail("expected \"a\\x00\", got \"a\\xff\"".to_string())
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_matrix() {
+        let rows = vec![
+            SETUPS
+                .iter()
+                .map(|setup| match setup.name {
+                    "c" => Outcome::Fail(String::new()),
+                    "wat" => Outcome::Skip(String::new()),
+                    _ => Outcome::Pass,
+                })
+                .collect(),
+        ];
+        let matrix = matrix(&["hello"], &rows);
+        let lines: Vec<&str> = matrix.lines().collect();
+        assert_eq!(
+            lines[0],
+            "       eval  bf    bf-O0  eof-keep  eof-255  bfc   ook   dispatch  checked  c     rust  wat"
+        );
+        assert_eq!(
+            lines[1],
+            "hello  ok    ok    ok     ok        ok       ok    ok    ok        ok       FAIL  ok    skip"
+        );
+    }
+}
@@ -0,0 +1,73 @@
+//! Rust source embedding a generated program (`--emit rust-embed`).
+//!
+//! The file declares `pub const PROGRAM: &str` holding the Brainfuck, so a
+//! Rust project can `include!` it or add it as a module without a build
+//! script. With the interpreter bundled it also has a dependency-free
+//! `pub fn run(input: &[u8]) -> Vec<u8>`.
+
+use alloc::{string::String, vec::Vec};
+
+/// Characters of Brainfuck per line of the string literal.
+const LINE_WIDTH: usize = 76;
+
+/// The interpreter, kept in its own file so the tests below can build it.
+const RUN: &str = include_str!("run.rs");
+
+/// A Rust module with `code` as `PROGRAM`, followed by the interpreter if
+/// `interpreter` is set.
+///
+/// ```
+/// use rusted_brains::embed::rust_source;
+///
+/// let source = rust_source("+[-].", false);
+/// assert!(source.contains("pub const PROGRAM: &str = \"+[-].\";"));
+/// ```
+pub fn rust_source(code: &str, interpreter: bool) -> String {
+    let mut out = String::from("/// Brainfuck generated by rust2bf.\npub const PROGRAM: &str = \"");
+    // Brainfuck needs no escaping; break it over lines with `\` so the
+    // newlines and indentation are not part of the string.
+    let commands: String = code.chars().filter(|c| "+-<>[].,".contains(*c)).collect();
+    let lines: Vec<&str> = commands
+        .as_bytes()
+        .chunks(LINE_WIDTH)
+        .map(|line| core::str::from_utf8(line).unwrap_or_default())
+        .collect();
+    out.push_str(&lines.join("\\\n    "));
+    out.push_str("\";\n");
+    if interpreter {
+        out.push_str(RUN);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_long_programs_wrap() {
+        let code = "+".repeat(LINE_WIDTH * 2 + 1);
+        let source = rust_source(&code, false);
+        assert_eq!(source.lines().count(), 4);
+        assert!(source.lines().all(|line| line.len() <= LINE_WIDTH + 30));
+        assert!(!source.contains("fn run"));
+    }
+
+    #[test]
+    fn test_comments_are_left_out() {
+        let source = rust_source("[Generated by \"rust2bf\"]\n+.", false);
+        assert!(source.contains("PROGRAM: &str = \"[]+.\";"));
+    }
+
+    mod bundled {
+        const PROGRAM: &str = ",[>+++<-]>.[-]++++++\
+            ++++.";
+        include!("run.rs");
+
+        #[test]
+        fn test_bundled_interpreter() {
+            assert_eq!(run(&[5]), [15, 10]);
+            assert_eq!(run(&[]), [0, 10]);
+        }
+    }
+}
@@ -0,0 +1,39 @@
+
+/// Runs [`PROGRAM`] on `input` and returns what it prints. The tape holds
+/// 30000 wrapping byte cells and reading past the end of `input` gives 0.
+pub fn run(input: &[u8]) -> Vec<u8> {
+    let code = PROGRAM.as_bytes();
+    let mut jumps = vec![0; code.len()];
+    let mut open = Vec::new();
+    for (pc, &command) in code.iter().enumerate() {
+        match command {
+            b'[' => open.push(pc),
+            b']' => {
+                let start = open.pop().expect("unbalanced brackets");
+                jumps[start] = pc;
+                jumps[pc] = start;
+            }
+            _ => {}
+        }
+    }
+
+    let mut tape = vec![0u8; 30000];
+    let (mut pointer, mut pc) = (0, 0);
+    let mut input = input.iter().copied();
+    let mut output = Vec::new();
+    while pc < code.len() {
+        match code[pc] {
+            b'+' => tape[pointer] = tape[pointer].wrapping_add(1),
+            b'-' => tape[pointer] = tape[pointer].wrapping_sub(1),
+            b'>' => pointer += 1,
+            b'<' => pointer -= 1,
+            b'.' => output.push(tape[pointer]),
+            b',' => tape[pointer] = input.next().unwrap_or(0),
+            b'[' if tape[pointer] == 0 => pc = jumps[pc],
+            b']' if tape[pointer] != 0 => pc = jumps[pc],
+            _ => {}
+        }
+        pc += 1;
+    }
+    output
+}
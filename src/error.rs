@@ -1,39 +1,82 @@
-use std::fmt;
+use crate::span::Span;
+use alloc::{string::String, vec, vec::Vec};
+use core::fmt;
 
 pub type TranspilerResult<T> = Result<T, TranspilerError>;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
 #[derive(Debug, Clone)]
 pub struct TranspilerError {
     pub message: String,
-    pub position: Option<usize>,
+    pub span: Option<Span>,
+    pub severity: Severity,
+    /// Extra lines shown under the excerpt, e.g. `help: did you mean ...`.
+    pub notes: Vec<String>,
+    /// Secondary locations, each shown with its own excerpt.
+    pub related: Vec<(String, Span)>,
 }
 
 impl TranspilerError {
     pub fn new(message: impl Into<String>) -> Self {
         Self {
             message: message.into(),
-            position: None,
+            span: None,
+            severity: Severity::Error,
+            notes: Vec::new(),
+            related: Vec::new(),
+        }
+    }
+
+    pub fn with_span(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            message: message.into(),
+            span: Some(span),
+            severity: Severity::Error,
+            notes: Vec::new(),
+            related: Vec::new(),
         }
     }
 
-    pub fn with_position(message: impl Into<String>, position: usize) -> Self {
+    pub fn warning(message: impl Into<String>, span: Span) -> Self {
         Self {
             message: message.into(),
-            position: Some(position),
+            span: Some(span),
+            severity: Severity::Warning,
+            notes: Vec::new(),
+            related: Vec::new(),
         }
     }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_related(mut self, message: impl Into<String>, span: Span) -> Self {
+        self.related.push((message.into(), span));
+        self
+    }
+
+    pub fn is_warning(&self) -> bool {
+        self.severity == Severity::Warning
+    }
 }
 
 impl fmt::Display for TranspilerError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self.position {
-            Some(pos) => write!(f, "{} at position {}", self.message, pos),
+        match self.span {
+            Some(span) => write!(f, "{} at position {}", self.message, span.start),
             None => write!(f, "{}", self.message),
         }
     }
 }
 
-impl std::error::Error for TranspilerError {}
+impl core::error::Error for TranspilerError {}
 
 impl From<String> for TranspilerError {
     fn from(message: String) -> Self {
@@ -46,3 +89,111 @@ impl From<&str> for TranspilerError {
         Self::new(message)
     }
 }
+
+/// All errors and warnings collected while compiling a single input.
+///
+/// Phases push into this instead of returning on the first problem, so one
+/// run reports every error that could be detected.
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    errors: Vec<TranspilerError>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, error: impl Into<TranspilerError>) {
+        self.errors.push(error.into());
+    }
+
+    pub fn extend(&mut self, other: Diagnostics) {
+        self.errors.extend(other.errors);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+
+    pub fn iter(&self) -> core::slice::Iter<'_, TranspilerError> {
+        self.errors.iter()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.errors.iter().any(|e| !e.is_warning())
+    }
+
+    /// Turns every warning into an error, for `--deny-warnings`.
+    pub fn deny_warnings(&mut self) {
+        for error in &mut self.errors {
+            error.severity = Severity::Error;
+        }
+    }
+
+    /// Returns `value` if no errors were reported, otherwise all collected
+    /// diagnostics. Warnings alone do not fail the result.
+    pub fn into_result<T>(self, value: T) -> Result<T, Diagnostics> {
+        if !self.has_errors() {
+            Ok(value)
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, error) in self.errors.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl core::error::Error for Diagnostics {}
+
+impl From<TranspilerError> for Diagnostics {
+    fn from(error: TranspilerError) -> Self {
+        Self {
+            errors: vec![error],
+        }
+    }
+}
+
+impl From<String> for Diagnostics {
+    fn from(message: String) -> Self {
+        TranspilerError::new(message).into()
+    }
+}
+
+impl From<&str> for Diagnostics {
+    fn from(message: &str) -> Self {
+        TranspilerError::new(message).into()
+    }
+}
+
+impl IntoIterator for Diagnostics {
+    type Item = TranspilerError;
+    type IntoIter = alloc::vec::IntoIter<TranspilerError>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.errors.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Diagnostics {
+    type Item = &'a TranspilerError;
+    type IntoIter = core::slice::Iter<'a, TranspilerError>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.errors.iter()
+    }
+}
@@ -0,0 +1,662 @@
+//! Running mini-rust programs directly from the AST.
+//!
+//! [`Evaluator`] defines what a program means: the generated Brainfuck is
+//! correct when it prints what the evaluator prints. It is also a quick way
+//! to try a program without compiling and interpreting the Brainfuck.
+//!
+//! Values behave like the Brainfuck cells they compile to: 8-bit and
+//! wrapping, with comparisons producing 0 or 1 and any nonzero condition
+//! counting as true. Strings are byte sequences that only `for` can read.
+
+use crate::ast::{self, BinaryOp, Expr, Function, Program, Stmt, UnaryOp};
+use crate::codegen::DEFAULT_STACK_DEPTH;
+use crate::error::TranspilerError;
+use crate::span::Span;
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::fmt;
+
+/// A tree-walking interpreter for the mini-rust AST.
+///
+/// ```
+/// use rusted_brains::ast::{Expr, Stmt};
+/// use rusted_brains::eval::Evaluator;
+///
+/// let program = vec![
+///     Stmt::let_stmt("x", false, Expr::number(72)),
+///     Stmt::print(Expr::variable("x")),
+/// ];
+/// assert_eq!(Evaluator::new().run(&program).unwrap().output, b"H");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Evaluator {
+    max_steps: Option<u64>,
+    stack_depth: u8,
+    input: Vec<u8>,
+}
+
+impl Default for Evaluator {
+    fn default() -> Self {
+        Self {
+            max_steps: None,
+            stack_depth: DEFAULT_STACK_DEPTH,
+            input: Vec::new(),
+        }
+    }
+}
+
+/// The observable result of evaluating a program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation {
+    pub output: Vec<u8>,
+    /// Number of statements executed plus loop iterations.
+    pub steps: u64,
+}
+
+impl Evaluation {
+    /// The output decoded as UTF-8, with invalid bytes replaced.
+    pub fn output_string(&self) -> String {
+        String::from_utf8_lossy(&self.output).into_owned()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalError {
+    /// The statement at `span` used a variable that is not in scope.
+    UndefinedVariable { name: String, span: Span },
+    /// The statement at `span` divided by zero.
+    DivisionByZero { span: Span },
+    /// The statement at `span` used a string as a single value, walked
+    /// over something other than a string, or called a function with the
+    /// wrong number of arguments.
+    MismatchedTypes { span: Span },
+    /// The statement at `span` called a function that is not defined.
+    UndefinedFunction { name: String, span: Span },
+    /// The statement at `span` made a recursive call with the call stack
+    /// already holding `depth` frames, where the compiled program stops.
+    StackOverflow { depth: u8, span: Span },
+    /// The program did not finish within the configured number of steps.
+    StepLimitExceeded { limit: u64 },
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::UndefinedVariable { name, .. } => {
+                write!(f, "Undefined variable '{}'", name)
+            }
+            EvalError::UndefinedFunction { name, .. } => {
+                write!(f, "Undefined function '{}'", name)
+            }
+            EvalError::StackOverflow { depth, .. } => {
+                write!(f, "Recursion deeper than the call stack's {} frames", depth)
+            }
+            EvalError::DivisionByZero { .. } => write!(f, "Division by zero"),
+            EvalError::MismatchedTypes { .. } => write!(f, "Mismatched types"),
+            EvalError::StepLimitExceeded { limit } => {
+                write!(f, "Program did not finish within {} steps", limit)
+            }
+        }
+    }
+}
+
+impl core::error::Error for EvalError {}
+
+impl From<EvalError> for TranspilerError {
+    fn from(error: EvalError) -> Self {
+        let span = match &error {
+            EvalError::UndefinedVariable { span, .. }
+            | EvalError::UndefinedFunction { span, .. }
+            | EvalError::StackOverflow { span, .. }
+            | EvalError::DivisionByZero { span }
+            | EvalError::MismatchedTypes { span } => Some(*span),
+            EvalError::StepLimitExceeded { .. } => None,
+        };
+        let message = error.to_string();
+        match span {
+            Some(span) => TranspilerError::with_span(message, span),
+            None => TranspilerError::new(message),
+        }
+    }
+}
+
+impl Evaluator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Aborts with [`EvalError::StepLimitExceeded`] instead of running
+    /// forever.
+    pub fn with_max_steps(mut self, max_steps: u64) -> Self {
+        self.max_steps = Some(max_steps);
+        self
+    }
+
+    /// How many frames recursive calls may save, as
+    /// [`BrainfuckGenerator::with_stack_depth`] does; one more is
+    /// [`EvalError::StackOverflow`].
+    pub fn with_stack_depth(mut self, depth: u8) -> Self {
+        self.stack_depth = depth;
+        self
+    }
+
+    /// The bytes `input()` reads, in order; once they run out it gives 0,
+    /// as `,` does in the Brainfuck interpreter.
+    pub fn with_input(mut self, input: impl Into<Vec<u8>>) -> Self {
+        self.input = input.into();
+        self
+    }
+
+    pub fn run(&self, program: &Program) -> Result<Evaluation, EvalError> {
+        let functions = ast::functions(program);
+        let mut state = State {
+            max_steps: self.max_steps,
+            scopes: vec![BTreeMap::new()],
+            recursive: ast::recursive_calls(&functions),
+            functions,
+            calls: Vec::new(),
+            stack_depth: self.stack_depth,
+            saved: 0,
+            input: self.input.iter(),
+            output: Vec::new(),
+            steps: 0,
+        };
+        state.block(program)?;
+
+        Ok(Evaluation {
+            output: state.output,
+            steps: state.steps,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Byte(u8),
+    Bytes(Vec<u8>),
+}
+
+/// How a statement finished, telling the loop or function around it
+/// whether to go on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Flow {
+    Next,
+    Break,
+    Continue,
+    Return(u8),
+}
+
+struct State<'a> {
+    max_steps: Option<u64>,
+    /// Innermost scope last. A call swaps in scopes of its own, as a
+    /// function cannot see its caller's variables.
+    scopes: Vec<BTreeMap<String, Value>>,
+    functions: BTreeMap<&'a str, &'a Function>,
+    /// The functions running, innermost last.
+    calls: Vec<&'a str>,
+    /// Calls that may lead back into their caller, which save its frame.
+    recursive: BTreeSet<(&'a str, &'a str)>,
+    stack_depth: u8,
+    /// How many frames the calls running have saved.
+    saved: u8,
+    input: core::slice::Iter<'a, u8>,
+    output: Vec<u8>,
+    steps: u64,
+}
+
+impl<'a> State<'a> {
+    /// Runs `body` in a new scope.
+    fn scoped(&mut self, body: &[Stmt]) -> Result<Flow, EvalError> {
+        self.scopes.push(BTreeMap::new());
+        let result = self.block(body);
+        self.scopes.pop();
+        result
+    }
+
+    /// Runs `body` up to the first `break` or `continue`, if any.
+    fn block(&mut self, body: &[Stmt]) -> Result<Flow, EvalError> {
+        for stmt in body {
+            let flow = self.stmt(stmt)?;
+            if flow != Flow::Next {
+                return Ok(flow);
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// Counts a step: a statement, or one more iteration of a loop.
+    fn step(&mut self) -> Result<(), EvalError> {
+        if let Some(limit) = self.max_steps
+            && self.steps >= limit
+        {
+            return Err(EvalError::StepLimitExceeded { limit });
+        }
+        self.steps += 1;
+        Ok(())
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<Flow, EvalError> {
+        self.step()?;
+        match stmt {
+            Stmt::Let {
+                name, value, span, ..
+            } => {
+                let value = match value {
+                    Expr::Str(text) => Value::Bytes(text.as_bytes().to_vec()),
+                    _ => Value::Byte(self.expr(value, *span)?),
+                };
+                let scope = self.scopes.last_mut().expect("global scope");
+                scope.insert(name.clone(), value);
+            }
+            Stmt::Assign { name, value, span } => {
+                let value = self.expr(value, *span)?;
+                let cell = self
+                    .scopes
+                    .iter_mut()
+                    .rev()
+                    .find_map(|scope| scope.get_mut(name))
+                    .ok_or_else(|| undefined(name, *span))?;
+                *cell = Value::Byte(value);
+            }
+            Stmt::Print { value, span } => match self.bytes(value, *span)? {
+                Some(bytes) => self.output.extend(bytes),
+                None => {
+                    let value = self.expr(value, *span)?;
+                    self.output.push(value);
+                }
+            },
+            Stmt::PrintNum { value, span } => {
+                let value = self.expr(value, *span)?;
+                self.output.extend(value.to_string().bytes());
+            }
+            Stmt::If {
+                condition,
+                body,
+                else_body,
+                span,
+            } => {
+                return if self.expr(condition, *span)? != 0 {
+                    self.scoped(body)
+                } else {
+                    self.scoped(else_body)
+                };
+            }
+            Stmt::Match {
+                scrutinee,
+                arms,
+                span,
+            } => {
+                let value = self.expr(scrutinee, *span)?;
+                if let Some(arm) = arms.iter().find(|arm| arm.pattern.matches(value)) {
+                    return self.scoped(&arm.body);
+                }
+            }
+            Stmt::While {
+                condition,
+                body,
+                span,
+            } => {
+                while self.expr(condition, *span)? != 0 {
+                    match self.scoped(body)? {
+                        Flow::Next | Flow::Continue => {}
+                        Flow::Break => break,
+                        flow @ Flow::Return(_) => return Ok(flow),
+                    }
+                    self.step()?;
+                }
+            }
+            Stmt::Loop { body, .. } => loop {
+                match self.scoped(body)? {
+                    Flow::Next | Flow::Continue => {}
+                    Flow::Break => break,
+                    flow @ Flow::Return(_) => return Ok(flow),
+                }
+                self.step()?;
+            },
+            Stmt::DoWhile {
+                body,
+                condition,
+                span,
+            } => loop {
+                match self.scoped(body)? {
+                    Flow::Next | Flow::Continue => {}
+                    Flow::Break => break,
+                    flow @ Flow::Return(_) => return Ok(flow),
+                }
+                if self.expr(condition, *span)? == 0 {
+                    break;
+                }
+                self.step()?;
+            },
+            Stmt::For {
+                name,
+                iterable,
+                body,
+                span,
+            } => {
+                let bytes = self
+                    .bytes(iterable, *span)?
+                    .ok_or(EvalError::MismatchedTypes { span: *span })?;
+                for byte in bytes {
+                    self.scopes
+                        .push(BTreeMap::from([(name.clone(), Value::Byte(byte))]));
+                    let result = self.block(body);
+                    self.scopes.pop();
+                    match result? {
+                        Flow::Next | Flow::Continue => {}
+                        Flow::Break => break,
+                        flow @ Flow::Return(_) => return Ok(flow),
+                    }
+                    self.step()?;
+                }
+            }
+            Stmt::Break { .. } => return Ok(Flow::Break),
+            Stmt::Continue { .. } => return Ok(Flow::Continue),
+            // Functions are looked up by name when called.
+            Stmt::Function(_) => {}
+            Stmt::Return { value, span } => {
+                let value = match value {
+                    Some(value) => self.expr(value, *span)?,
+                    None => 0,
+                };
+                return Ok(Flow::Return(value));
+            }
+            Stmt::Expr { value, span } => {
+                self.expr(value, *span)?;
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// Evaluates `expr`, reporting errors at `span`, the statement it is
+    /// part of.
+    fn expr(&mut self, expr: &Expr, span: Span) -> Result<u8, EvalError> {
+        match expr {
+            Expr::Number(value) => Ok(value.rem_euclid(256) as u8),
+            Expr::Bool(value) => Ok(*value as u8),
+            Expr::Str(_) => Err(EvalError::MismatchedTypes { span }),
+            Expr::Variable(name) => match self.lookup(name, span)? {
+                Value::Byte(value) => Ok(*value),
+                Value::Bytes(_) => Err(EvalError::MismatchedTypes { span }),
+            },
+            Expr::Unary {
+                operator: UnaryOp::Not,
+                operand,
+            } => Ok((self.expr(operand, span)? == 0) as u8),
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                let left = self.expr(left, span)?;
+                match operator {
+                    BinaryOp::And if left == 0 => return Ok(0),
+                    BinaryOp::Or if left != 0 => return Ok(1),
+                    _ => {}
+                }
+                let right = self.expr(right, span)?;
+                Ok(match operator {
+                    BinaryOp::Add => left.wrapping_add(right),
+                    BinaryOp::Sub => left.wrapping_sub(right),
+                    BinaryOp::Mul => left.wrapping_mul(right),
+                    BinaryOp::Div => left
+                        .checked_div(right)
+                        .ok_or(EvalError::DivisionByZero { span })?,
+                    BinaryOp::Mod => left
+                        .checked_rem(right)
+                        .ok_or(EvalError::DivisionByZero { span })?,
+                    BinaryOp::Equal => (left == right) as u8,
+                    BinaryOp::NotEqual => (left != right) as u8,
+                    BinaryOp::Less => (left < right) as u8,
+                    BinaryOp::Greater => (left > right) as u8,
+                    BinaryOp::LessEqual => (left <= right) as u8,
+                    BinaryOp::GreaterEqual => (left >= right) as u8,
+                    BinaryOp::And | BinaryOp::Or => (right != 0) as u8,
+                })
+            }
+            Expr::Call { name, args } => self.call(name, args, span),
+            Expr::Input => Ok(self.input.next().copied().unwrap_or(0)),
+        }
+    }
+
+    /// Runs the function `name` with `args` bound to its parameters,
+    /// returning what it returns, or 0 if it ends without a `return`.
+    fn call(&mut self, name: &str, args: &[Expr], span: Span) -> Result<u8, EvalError> {
+        let Some(&function) = self.functions.get(name) else {
+            return Err(EvalError::UndefinedFunction {
+                name: name.to_string(),
+                span,
+            });
+        };
+        if args.len() != function.params.len() {
+            return Err(EvalError::MismatchedTypes { span });
+        }
+        let mut frame = BTreeMap::new();
+        for (param, arg) in function.params.iter().zip(args) {
+            frame.insert(param.clone(), Value::Byte(self.expr(arg, span)?));
+        }
+        let saves = self
+            .calls
+            .last()
+            .is_some_and(|&caller| self.recursive.contains(&(caller, name)));
+        if saves {
+            if self.saved == self.stack_depth {
+                return Err(EvalError::StackOverflow {
+                    depth: self.stack_depth,
+                    span,
+                });
+            }
+            self.saved += 1;
+        }
+
+        let caller = core::mem::replace(&mut self.scopes, vec![frame]);
+        self.calls.push(&function.name);
+        let flow = self.block(&function.body);
+        self.calls.pop();
+        self.scopes = caller;
+        if saves {
+            self.saved -= 1;
+        }
+        match flow? {
+            Flow::Return(value) => Ok(value),
+            _ => Ok(0),
+        }
+    }
+}
+
+impl State<'_> {
+    /// The bytes of `expr` if it is a string, or `None` for a single value.
+    fn bytes(&self, expr: &Expr, span: Span) -> Result<Option<Vec<u8>>, EvalError> {
+        Ok(match expr {
+            Expr::Str(text) => Some(text.as_bytes().to_vec()),
+            Expr::Variable(name) => match self.lookup(name, span)? {
+                Value::Bytes(bytes) => Some(bytes.clone()),
+                Value::Byte(_) => None,
+            },
+            _ => None,
+        })
+    }
+
+    fn lookup(&self, name: &str, span: Span) -> Result<&Value, EvalError> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .ok_or_else(|| undefined(name, span))
+    }
+}
+
+fn undefined(name: &str, span: Span) -> EvalError {
+    EvalError::UndefinedVariable {
+        name: name.to_string(),
+        span,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn eval(source: &str) -> Result<Evaluation, EvalError> {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        Evaluator::new().with_max_steps(10_000).run(&program)
+    }
+
+    #[test]
+    fn test_arithmetic_wraps() {
+        let evaluation = eval("let x = 250; print(x + 10); print(3 - 5); print(20 * 13);");
+        assert_eq!(evaluation.unwrap().output, vec![4, 254, 4]);
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let source = "let x = 5; let y = 3;
+            if x > y { print(1); }
+            if x < y { print(2); }
+            let mut n = 3;
+            while n > 0 { print(n); n = n - 1; }";
+        assert_eq!(eval(source).unwrap().output, vec![1, 3, 2, 1]);
+        let source = "let x = 5; if !(x > 9) { print(1); } if !(x > 1) { print(2); }";
+        assert_eq!(eval(source).unwrap().output, vec![1]);
+        let source =
+            "let x = 5; if x < 3 { print(1); } else if x < 6 { print(2); } else { print(3); }";
+        assert_eq!(eval(source).unwrap().output, vec![2]);
+    }
+
+    #[test]
+    fn test_booleans() {
+        let source = "let mut done = false; let mut n = 0;
+            while done == false { n = n + 1; if n == 3 { done = true; } }
+            print(n, done, !done, true && n > 2);";
+        assert_eq!(eval(source).unwrap().output, vec![3, 32, 1, 32, 0, 32, 1]);
+    }
+
+    #[test]
+    fn test_print_num() {
+        let source = "let x = 250; print_num(x + 10); print(32); print_num(x); print_num(3 > 2);";
+        assert_eq!(eval(source).unwrap().output, b"4 2501");
+    }
+
+    #[test]
+    fn test_input() {
+        let tokens = Lexer::new("while input() != 0 { } print(input(), input() + 1);")
+            .tokenize()
+            .unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        let evaluation = Evaluator::new().with_input("ab\0c").run(&program).unwrap();
+        // Once the input runs out every read gives 0.
+        assert_eq!(evaluation.output, vec![b'c', 32, 1]);
+    }
+
+    #[test]
+    fn test_break_and_continue() {
+        let source = "let mut n = 0;
+            loop { n = n + 1; if n % 2 == 0 { continue; } if n > 6 { break; } print(n); }
+            while 1 { break; }
+            for c in \"abc\" { if c == 98 { continue; } print(c); }
+            for c in \"abc\" { print(c); break; }
+            loop { n = n - 1; if n == 5 { continue; } print(n); } while n > 4;";
+        assert_eq!(
+            eval(source).unwrap().output,
+            vec![1, 3, 5, b'a', b'c', b'a', 6, 4]
+        );
+    }
+
+    #[test]
+    fn test_match() {
+        let source = "let mut x = 0;
+            while x < 5 {
+                match x { 1 => { x = 3; print(1); } 3 => { print(3); } 4 => { break; } _ => { print(0); } }
+                x = x + 1;
+            }
+            match x + 1 { 9 => { print(9); } -251 => { let x = 6; print(x); } }
+            match x { }";
+        assert_eq!(eval(source).unwrap().output, vec![0, 1, 6]);
+    }
+
+    #[test]
+    fn test_functions() {
+        let source = "fn max(a, b) { if a > b { return a; } return b; }
+            fn first_square_over(n) { let mut i = 0; loop { if i * i > n { return i; } i = i + 1; } }
+            fn shout(c) { print(c - 32); }
+            let x = 3;
+            print(max(x, 9));
+            print(first_square_over(max(20, x)));
+            shout(97);
+            print(shout(98));";
+        assert_eq!(eval(source).unwrap().output, vec![9, 5, b'A', b'B', 0]);
+        // A function sees only its parameters.
+        assert!(matches!(
+            eval("let x = 1; fn f() { return x; } print(f());"),
+            Err(EvalError::UndefinedVariable { name, .. }) if name == "x"
+        ));
+        assert!(matches!(
+            eval("print(f(1));"),
+            Err(EvalError::UndefinedFunction { name, .. }) if name == "f"
+        ));
+    }
+
+    #[test]
+    fn test_recursion() {
+        let source = "fn fib(n) { if n < 2 { return n; } return fib(n - 1) + fib(n - 2); }
+            fn even(n) { if n == 0 { return 1; } return odd(n - 1); }
+            fn odd(n) { if n == 0 { return 0; } return even(n - 1); }
+            print(fib(10)); print(even(7)); print(odd(7));";
+        assert_eq!(eval(source).unwrap().output, vec![55, 0, 1]);
+        // Every call but the first saves a frame.
+        let source = "fn down(n) { print(n); if n > 0 { down(n - 1); } } down(20);";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        let run = |depth| Evaluator::new().with_stack_depth(depth).run(&program);
+        assert_eq!(run(20).unwrap().output.len(), 21);
+        assert!(matches!(
+            run(19),
+            Err(EvalError::StackOverflow { depth: 19, .. })
+        ));
+    }
+
+    #[test]
+    fn test_block_scope() {
+        let source = "let mut x = 1; if x == 1 { let x = 7; print(x); x = 8; } print(x);";
+        assert_eq!(eval(source).unwrap().output, vec![7, 1]);
+    }
+
+    #[test]
+    fn test_strings() {
+        let source = "let s = \"abc\"; for c in s { print(c - 32); } for c in \"\" { print(c); }";
+        assert_eq!(eval(source).unwrap().output, b"ABC");
+        let source = "let s = \"hi\"; print(s); println(\", there\");";
+        assert_eq!(eval(source).unwrap().output, b"hi, there\n");
+        assert!(matches!(
+            eval("let s = \"a\"; print(s + 1);"),
+            Err(EvalError::MismatchedTypes { .. })
+        ));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(matches!(
+            eval("print(y);"),
+            Err(EvalError::UndefinedVariable { name, .. }) if name == "y"
+        ));
+        assert!(matches!(
+            eval("let x = 0; print(4 / x);"),
+            Err(EvalError::DivisionByZero { .. })
+        ));
+        // The right operand of `&&` and `||` is only evaluated if needed.
+        let source = "let x = 0; if x != 0 && 4 / x == 1 { print(1); }
+            if x == 0 || 4 / x == 1 { print(2); }";
+        assert_eq!(eval(source).unwrap().output, vec![2]);
+        assert_eq!(
+            eval("let x = 1; while x == 1 { }"),
+            Err(EvalError::StepLimitExceeded { limit: 10_000 })
+        );
+    }
+}
@@ -0,0 +1,64 @@
+//! Example programs built into the binary, for `rust2bf example`.
+//!
+//! Each one is a file under `examples/`, so the snapshot tests compile and
+//! run them along with the rest.
+
+/// Name and source of each example, in the order they are listed.
+pub const EXAMPLES: &[(&str, &str)] = &[
+    ("hello_world", include_str!("../examples/hello_world.txt")),
+    ("fizzbuzz", include_str!("../examples/fizzbuzz.txt")),
+    ("counter", include_str!("../examples/counter.txt")),
+    ("echo", include_str!("../examples/echo.txt")),
+];
+
+/// The source of the example called `name`.
+pub fn find(name: &str) -> Option<&'static str> {
+    EXAMPLES
+        .iter()
+        .find(|(example, _)| *example == name)
+        .map(|(_, source)| *source)
+}
+
+/// The example names, comma-separated, for messages.
+pub fn names() -> String {
+    EXAMPLES
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusted_brains::bf::Interpreter;
+    use rusted_brains::pipeline;
+
+    #[test]
+    fn test_hello_world() {
+        let compiled = pipeline::compile(find("hello_world").unwrap()).unwrap();
+        assert!(compiled.warnings.is_empty());
+
+        let execution = Interpreter::new(compiled.code).run().unwrap();
+        assert_eq!(execution.output, b"Hello, World!\n");
+        assert!(find("hello").is_none());
+    }
+
+    #[test]
+    fn test_echo() {
+        let compiled = pipeline::compile(find("echo").unwrap()).unwrap();
+        assert!(compiled.warnings.is_empty());
+
+        let execution = Interpreter::new(compiled.code.clone())
+            .with_input("Hi there\nand more")
+            .run()
+            .unwrap();
+        assert_eq!(execution.output, b"Hi there\n");
+        // Input that runs out before a newline still ends the line.
+        let execution = Interpreter::new(compiled.code)
+            .with_input("Hi")
+            .run()
+            .unwrap();
+        assert_eq!(execution.output, b"Hi\n");
+    }
+}
@@ -0,0 +1,175 @@
+//! What a program should print, written into its own source.
+//!
+//! A line `//! expect: text` holds output the program must produce. The
+//! text uses the escapes of string literals, except that `\x` goes up to
+//! `\xff` since output is bytes, not text. Lines are joined in order with
+//! nothing in between, so each line break in the output is written out as
+//! `\n`. `rust2bf verify` and the snapshot tests check these comments.
+//!
+//! A line `//! input: text`, written the same way, holds what the program
+//! reads while they are checked.
+
+use crate::error::TranspilerError;
+use crate::span::Span;
+use alloc::{format, string::String, vec::Vec};
+
+/// Starts a line of expected output.
+pub const MARKER: &str = "//! expect:";
+
+/// Starts a line of the input the expected output is for.
+pub const INPUT_MARKER: &str = "//! input:";
+
+/// The output `source` expects, or `None` if it has no [`MARKER`] lines.
+///
+/// ```
+/// use rusted_brains::expect;
+///
+/// let source = "//! expect: Hi\\n\n//! expect: \\x01\nprint(72);";
+/// assert_eq!(expect::expected_output(source).unwrap().unwrap(), b"Hi\n\x01");
+/// ```
+pub fn expected_output(source: &str) -> Result<Option<Vec<u8>>, TranspilerError> {
+    marked(source, MARKER, "expected output")
+}
+
+/// The input `source` reads while its output is checked, empty if it has no
+/// [`INPUT_MARKER`] lines.
+///
+/// ```
+/// use rusted_brains::expect;
+///
+/// let source = "//! input: ab\n//! expect: a\nprint(input());";
+/// assert_eq!(expect::input(source).unwrap(), b"ab");
+/// ```
+pub fn input(source: &str) -> Result<Vec<u8>, TranspilerError> {
+    Ok(marked(source, INPUT_MARKER, "input")?.unwrap_or_default())
+}
+
+/// The bytes spelled by the lines of `source` starting with `marker`, or
+/// `None` if there are none. `what` names them in errors.
+fn marked(source: &str, marker: &str, what: &str) -> Result<Option<Vec<u8>>, TranspilerError> {
+    let mut bytes = None;
+    let mut start = 0;
+    for line in source.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if let Some(rest) = trimmed.strip_prefix(marker) {
+            let rest = rest.trim_end_matches(['\n', '\r']);
+            let text = rest.strip_prefix(' ').unwrap_or(rest);
+            let offset =
+                start + (line.len() - trimmed.len()) + marker.len() + (rest.len() - text.len());
+            unescape(text, offset, what, bytes.get_or_insert_with(Vec::new))?;
+        }
+        start += line.len();
+    }
+    Ok(bytes)
+}
+
+/// Writes `bytes` the way [`MARKER`] lines spell them.
+pub fn escape(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .flat_map(|&byte| core::ascii::escape_default(byte))
+        .map(char::from)
+        .collect()
+}
+
+/// Appends the bytes `text` spells to `out`; `offset` is where `text`
+/// starts in the source.
+fn unescape(
+    text: &str,
+    offset: usize,
+    what: &str,
+    out: &mut Vec<u8>,
+) -> Result<(), TranspilerError> {
+    let mut chars = text.char_indices();
+    while let Some((i, ch)) = chars.next() {
+        if ch != '\\' {
+            let mut buffer = [0; 4];
+            out.extend_from_slice(ch.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+        let byte = match chars.next().map(|(_, ch)| ch) {
+            Some('n') => b'\n',
+            Some('r') => b'\r',
+            Some('t') => b'\t',
+            Some('0') => b'\0',
+            Some('\\') => b'\\',
+            Some('"') => b'"',
+            Some('\'') => b'\'',
+            Some('x') => {
+                let digits = chars.as_str().get(..2).unwrap_or_default();
+                let value = u8::from_str_radix(digits, 16)
+                    .ok()
+                    .filter(|_| digits.bytes().all(|digit| digit.is_ascii_hexdigit()));
+                match value {
+                    Some(value) => {
+                        chars.nth(1);
+                        value
+                    }
+                    None => {
+                        return Err(TranspilerError::with_span(
+                            format!("Invalid hex escape in {}", what),
+                            Span::new(offset + i, offset + i + 2),
+                        )
+                        .with_note("help: write two hex digits, as in `\\xff`"));
+                    }
+                }
+            }
+            other => {
+                let end = offset + i + 1 + other.map_or(0, char::len_utf8);
+                return Err(TranspilerError::with_span(
+                    format!("Unknown escape in {}", what),
+                    Span::new(offset + i, end),
+                ));
+            }
+        };
+        out.push(byte);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expected_output() {
+        assert_eq!(expected_output("print(1);").unwrap(), None);
+        assert_eq!(expected_output("//! expect:").unwrap(), Some(Vec::new()));
+        let source = "  //! expect:  a\\tb\r\nprint(1);\n//! expect: \\xf6\\\\\\\"";
+        assert_eq!(
+            expected_output(source).unwrap(),
+            Some(b" a\tb\xf6\\\"".to_vec())
+        );
+        // Other comments, and the marker anywhere but at the start of a
+        // line, are not expectations.
+        assert_eq!(
+            expected_output("// expect: a\nprint(1); //! expect: b").unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_invalid_escapes() {
+        let source = "print(1);\n//! expect: ok\\q";
+        let error = expected_output(source).unwrap_err();
+        assert_eq!(error.message, "Unknown escape in expected output");
+        assert_eq!(error.span, Some(Span::new(24, 26)));
+        let error = expected_output("//! expect: \\x4").unwrap_err();
+        assert_eq!(error.message, "Invalid hex escape in expected output");
+        assert_eq!(error.span, Some(Span::new(12, 14)));
+    }
+
+    #[test]
+    fn test_input() {
+        let source = "//! input: a\\n\n//! expect: a\n//! input: \\x00b\nprint(input());";
+        assert_eq!(input(source).unwrap(), b"a\n\0b");
+        assert_eq!(input("print(1);").unwrap(), b"");
+        let error = input("//! input: \\q").unwrap_err();
+        assert_eq!(error.message, "Unknown escape in input");
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape(b"Hi\n\x01\xff\"\\"), "Hi\\n\\x01\\xff\\\"\\\\");
+    }
+}
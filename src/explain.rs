@@ -0,0 +1,278 @@
+//! Plain-English narration of generated Brainfuck, for learning how the
+//! generator works.
+//!
+//! [`explain`] walks the optimized [`ir`](crate::ir) and pairs each piece
+//! of code with what it does, tracking which cell the pointer is on. The
+//! narration never uses Brainfuck command characters, so the listing is
+//! itself a runnable program equivalent to the original.
+
+use crate::ir::{self, Op};
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// Width of the code column before the narration starts.
+const CODE_WIDTH: usize = 24;
+
+/// One line per operation, the code followed by its narration, with loop
+/// bodies indented.
+///
+/// ```
+/// use rusted_brains::{explain::explain, ir};
+///
+/// let listing = explain(&ir::parse(">+++[->++<]").unwrap());
+/// assert!(listing.contains("double cell 1 into cell 2"));
+/// ```
+pub fn explain(ops: &[Op]) -> String {
+    explain_with_names(ops, &BTreeMap::new())
+}
+
+/// Like [`explain`], but cells in `names` are called by the variable they
+/// hold, as in "add 5 to x (cell 0)", using the map from
+/// [`CodeGenerator::cell_names`](crate::backend::CodeGenerator::cell_names).
+///
+/// ```
+/// use rusted_brains::{explain::explain_with_names, ir};
+/// use std::collections::BTreeMap;
+///
+/// let names = BTreeMap::from([(0, "x".to_string())]);
+/// let listing = explain_with_names(&ir::parse("+++++").unwrap(), &names);
+/// assert!(listing.contains("add 5 to x (cell 0)"));
+/// ```
+pub fn explain_with_names(ops: &[Op], names: &BTreeMap<usize, String>) -> String {
+    let mut out = String::new();
+    Narrator {
+        out: &mut out,
+        names,
+    }
+    .block(ops, Some(0), 0);
+    out
+}
+
+struct Narrator<'a> {
+    out: &'a mut String,
+    names: &'a BTreeMap<usize, String>,
+}
+
+impl Narrator<'_> {
+    fn line(&mut self, depth: usize, code: &str, narration: &str) {
+        let code = format!("{}{}", "  ".repeat(depth), code);
+        self.out.push_str(&format!(
+            "{:<width$} {}\n",
+            code,
+            narration,
+            width = CODE_WIDTH - 1
+        ));
+    }
+
+    /// Narrates `ops` starting with the pointer at `pointer` (`None` when
+    /// it cannot be known statically) and returns where it ends up.
+    fn block(&mut self, ops: &[Op], mut pointer: Option<isize>, depth: usize) -> Option<isize> {
+        for op in &runs(ops) {
+            let code = ir::emit(core::slice::from_ref(op));
+            let here = cell(pointer, self.names);
+            match op {
+                Op::Move(n) => {
+                    pointer = pointer.map(|p| p + n);
+                    let narration = match pointer {
+                        Some(p) => format!("move to {}", named(p, self.names)),
+                        None if *n > 0 => format!("move {} right", plural(n.unsigned_abs())),
+                        None => format!("move {} left", plural(n.unsigned_abs())),
+                    };
+                    self.line(depth, &code, &narration);
+                }
+                Op::Add(n) if *n <= 128 => {
+                    self.line(depth, &code, &format!("add {} to {}", n, here))
+                }
+                Op::Add(n) => {
+                    let narration = format!("subtract {} from {}", 256 - *n as usize, here);
+                    self.line(depth, &code, &narration);
+                }
+                Op::Clear => self.line(depth, &code, &format!("set {} to 0", here)),
+                Op::Output => self.line(depth, &code, &format!("print {}", here)),
+                Op::Input => self.line(depth, &code, &format!("read a byte into {}", here)),
+                Op::Loop(body) => {
+                    if let Some(narration) = transfer(body, pointer, self.names) {
+                        self.line(depth, &code, &narration);
+                        continue;
+                    }
+                    self.line(depth, "[", &format!("repeat while {} is not 0:", here));
+                    let end = self.block(body, pointer, depth + 1);
+                    // The loop exits on the cell it tests, wherever that
+                    // is; only a balanced body keeps it fixed.
+                    if end != pointer {
+                        pointer = None;
+                    }
+                    let here = cell(pointer, self.names);
+                    self.line(depth, "]", &format!("end of loop over {}", here));
+                }
+            }
+        }
+        pointer
+    }
+}
+
+/// `ops` with adjacent additions and moves merged, so `+++` is narrated
+/// once rather than three times.
+fn runs(ops: &[Op]) -> Vec<Op> {
+    let mut merged: Vec<Op> = Vec::new();
+    for op in ops {
+        match (merged.last_mut(), op) {
+            (Some(Op::Add(total)), Op::Add(n)) => *total = total.wrapping_add(*n),
+            (Some(Op::Move(total)), Op::Move(n)) => *total += n,
+            _ => merged.push(op.clone()),
+        }
+    }
+    merged.retain(|op| !matches!(op, Op::Add(0) | Op::Move(0)));
+    merged
+}
+
+/// Describes a loop that empties the current cell into others, such as the
+/// `[->+>+<<]` used to copy a value, or `None` if `body` is not one.
+fn transfer(
+    body: &[Op],
+    pointer: Option<isize>,
+    names: &BTreeMap<usize, String>,
+) -> Option<String> {
+    let mut offset = 0isize;
+    let mut deltas: BTreeMap<isize, u8> = BTreeMap::new();
+    for op in body {
+        match op {
+            Op::Move(n) => offset += n,
+            Op::Add(n) => {
+                let delta = deltas.entry(offset).or_insert(0);
+                *delta = delta.wrapping_add(*n);
+            }
+            _ => return None,
+        }
+    }
+    if offset != 0 || deltas.remove(&0) != Some(255) {
+        return None;
+    }
+
+    let source = cell(pointer, names);
+    let target = |offset: isize| match pointer {
+        Some(p) => named(p + offset, names),
+        None => relative(offset),
+    };
+    if deltas.is_empty() {
+        return Some(format!("set {} to 0", source));
+    }
+    if deltas.values().all(|&delta| delta == 1) {
+        let targets: Vec<String> = deltas.keys().map(|&offset| target(offset)).collect();
+        return Some(format!("move {} into {}", source, and_list(&targets)));
+    }
+    if let [(&offset, &delta)] = deltas.iter().collect::<Vec<_>>()[..] {
+        return Some(match delta {
+            2 => format!("double {} into {}", source, target(offset)),
+            255 => format!("subtract {} from {}", source, target(offset)),
+            _ if delta < 128 => format!("add {} times {} to {}", source, delta, target(offset)),
+            _ => format!(
+                "subtract {} times {} from {}",
+                source,
+                256 - delta as usize,
+                target(offset)
+            ),
+        });
+    }
+    let targets: Vec<String> = deltas
+        .iter()
+        .map(|(&offset, &delta)| format!("{} times {}", target(offset), delta))
+        .collect();
+    Some(format!("add {} to each of {}", source, and_list(&targets)))
+}
+
+fn cell(pointer: Option<isize>, names: &BTreeMap<usize, String>) -> String {
+    match pointer {
+        Some(p) => named(p, names),
+        None => "the current cell".to_string(),
+    }
+}
+
+/// Cell `p`, by the name of its variable if it has one.
+fn named(p: isize, names: &BTreeMap<usize, String>) -> String {
+    match usize::try_from(p).ok().and_then(|p| names.get(&p)) {
+        Some(name) => format!("{} (cell {})", name, p),
+        None => format!("cell {}", p),
+    }
+}
+
+/// The cell `offset` away from the current one.
+fn relative(offset: isize) -> String {
+    match offset {
+        1 => "the next cell".to_string(),
+        -1 => "the previous cell".to_string(),
+        _ if offset > 0 => format!("the cell {} to the right", plural(offset.unsigned_abs())),
+        _ => format!("the cell {} to the left", plural(offset.unsigned_abs())),
+    }
+}
+
+fn plural(cells: usize) -> String {
+    if cells == 1 {
+        "1 cell".to_string()
+    } else {
+        format!("{} cells", cells)
+    }
+}
+
+fn and_list(items: &[String]) -> String {
+    match items {
+        [] => String::new(),
+        [only] => only.clone(),
+        [init @ .., last] => format!("{} and {}", init.join(" and "), last),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn explain_code(code: &str) -> String {
+        explain(&ir::parse(code).unwrap())
+    }
+
+    #[test]
+    fn test_narration() {
+        assert_eq!(
+            explain_code(">>+++[-]<<[->+>+<<]."),
+            "\
+>>                      move to cell 2
++++                     add 3 to cell 2
+[-]                     set cell 2 to 0
+<<                      move to cell 0
+[->+>+<<]               move cell 0 into cell 1 and cell 2
+.                       print cell 0
+"
+        );
+    }
+
+    #[test]
+    fn test_unbalanced_loop_loses_track_of_pointer() {
+        let listing = explain_code("+[>+]-[->+<]");
+        assert!(listing.contains("[                       repeat while cell 0 is not 0:"));
+        assert!(listing.contains("  >                     move to cell 1"));
+        assert!(listing.contains("]                       end of loop over the current cell"));
+        assert!(listing.contains("subtract 1 from the current cell"));
+        assert!(listing.contains("move the current cell into the next cell"));
+    }
+
+    #[test]
+    fn test_named_cells() {
+        let names = BTreeMap::from([(0, "x".to_string()), (2, "byte 0 of s".to_string())]);
+        let listing = explain_with_names(&ir::parse("+++[->>+<<]>>.>").unwrap(), &names);
+        assert!(listing.contains("add 3 to x (cell 0)"));
+        assert!(listing.contains("move x (cell 0) into byte 0 of s (cell 2)"));
+        assert!(listing.contains("print byte 0 of s (cell 2)"));
+        assert!(listing.contains("move to cell 3"));
+    }
+
+    #[test]
+    fn test_listing_runs_like_the_original() {
+        let code = "++++++++[>++++++++<-]>+.,[-<+>]<.";
+        let listing = explain_code(code);
+        assert_eq!(ir::parse(&listing).unwrap(), ir::parse(code).unwrap());
+    }
+}
@@ -0,0 +1,180 @@
+//! C bindings.
+//!
+//! [`rust2bf_compile`] runs [`pipeline::compile`] and hands back a
+//! heap-allocated [`Rust2BfOutput`] that the caller releases with
+//! [`rust2bf_output_free`]. The matching declarations are in
+//! `include/rusted_brains.h`; build the library for C with
+//! `cargo rustc --release --lib --features ffi --crate-type cdylib` (or
+//! `staticlib`).
+
+use crate::error::{Severity, TranspilerError};
+use crate::pipeline;
+use alloc::boxed::Box;
+use alloc::ffi::CString;
+use alloc::vec::Vec;
+use core::ffi::{CStr, c_char};
+use core::ptr;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rust2BfSeverity {
+    Error = 0,
+    Warning = 1,
+}
+
+#[repr(C)]
+pub struct Rust2BfDiagnostic {
+    pub message: *mut c_char,
+    pub severity: Rust2BfSeverity,
+    /// Whether `start`, `end`, `line` and `column` are meaningful.
+    pub has_span: bool,
+    /// Byte offsets into the source.
+    pub start: usize,
+    pub end: usize,
+    /// 1-based line and column of `start`.
+    pub line: usize,
+    pub column: usize,
+}
+
+#[repr(C)]
+pub struct Rust2BfOutput {
+    /// The generated Brainfuck, or null if compilation failed.
+    pub code: *mut c_char,
+    /// Errors (on failure) or warnings (on success).
+    pub diagnostics: *mut Rust2BfDiagnostic,
+    pub diagnostic_count: usize,
+}
+
+fn c_string(text: &str) -> *mut c_char {
+    // Interior NULs cannot be represented; diagnostics never need them.
+    CString::new(text.replace('\0', ""))
+        .unwrap_or_default()
+        .into_raw()
+}
+
+fn diagnostic(source: &str, error: &TranspilerError) -> Rust2BfDiagnostic {
+    let span = error.span.unwrap_or_default();
+    let location = span.location(source);
+    Rust2BfDiagnostic {
+        message: c_string(&error.message),
+        severity: match error.severity {
+            Severity::Error => Rust2BfSeverity::Error,
+            Severity::Warning => Rust2BfSeverity::Warning,
+        },
+        has_span: error.span.is_some(),
+        start: span.start,
+        end: span.end,
+        line: location.line,
+        column: location.column,
+    }
+}
+
+/// Compiles the NUL-terminated mini-rust `source` to Brainfuck.
+///
+/// Returns null only if `source` is null or not valid UTF-8.
+///
+/// # Safety
+///
+/// `source` must be null or point to a NUL-terminated string. The result
+/// must be released with [`rust2bf_output_free`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rust2bf_compile(source: *const c_char) -> *mut Rust2BfOutput {
+    if source.is_null() {
+        return ptr::null_mut();
+    }
+    // SAFETY: the caller guarantees a NUL-terminated string.
+    let Ok(source) = unsafe { CStr::from_ptr(source) }.to_str() else {
+        return ptr::null_mut();
+    };
+
+    let (code, diagnostics) = match pipeline::compile(source) {
+        Ok(compiled) => (c_string(&compiled.code), compiled.warnings),
+        Err(errors) => (ptr::null_mut(), errors),
+    };
+    let diagnostics: Box<[Rust2BfDiagnostic]> = diagnostics
+        .iter()
+        .map(|error| diagnostic(source, error))
+        .collect::<Vec<_>>()
+        .into();
+    let diagnostic_count = diagnostics.len();
+
+    Box::into_raw(Box::new(Rust2BfOutput {
+        code,
+        diagnostics: Box::into_raw(diagnostics) as *mut Rust2BfDiagnostic,
+        diagnostic_count,
+    }))
+}
+
+/// Releases an output returned by [`rust2bf_compile`]. Null is ignored.
+///
+/// # Safety
+///
+/// `output` must be null or a pointer returned by [`rust2bf_compile`] that
+/// has not already been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rust2bf_output_free(output: *mut Rust2BfOutput) {
+    if output.is_null() {
+        return;
+    }
+    // SAFETY: every pointer below was created by `rust2bf_compile` with the
+    // matching `into_raw`.
+    unsafe {
+        let output = Box::from_raw(output);
+        if !output.code.is_null() {
+            drop(CString::from_raw(output.code));
+        }
+        let diagnostics = Box::from_raw(ptr::slice_from_raw_parts_mut(
+            output.diagnostics,
+            output.diagnostic_count,
+        ));
+        for diagnostic in diagnostics.iter() {
+            drop(CString::from_raw(diagnostic.message));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile(source: &str) -> *mut Rust2BfOutput {
+        let source = CString::new(source).unwrap();
+        unsafe { rust2bf_compile(source.as_ptr()) }
+    }
+
+    #[test]
+    fn test_compile_success() {
+        let output = compile("let x = 65; print(x);");
+        unsafe {
+            let code = CStr::from_ptr((*output).code).to_str().unwrap();
+            assert!(code.ends_with('.'));
+            assert_eq!((*output).diagnostic_count, 0);
+            rust2bf_output_free(output);
+        }
+    }
+
+    #[test]
+    fn test_compile_failure_reports_diagnostics() {
+        let output = compile("print(y);");
+        unsafe {
+            assert!((*output).code.is_null());
+            assert_eq!((*output).diagnostic_count, 1);
+            let error = &*(*output).diagnostics;
+            assert_eq!(
+                CStr::from_ptr(error.message).to_str().unwrap(),
+                "undefined variable `y`"
+            );
+            assert_eq!(error.severity, Rust2BfSeverity::Error);
+            assert!(error.has_span);
+            assert_eq!((error.start, error.end), (0, 9));
+            assert_eq!((error.line, error.column), (1, 1));
+            rust2bf_output_free(output);
+        }
+    }
+
+    #[test]
+    fn test_null_source() {
+        assert!(unsafe { rust2bf_compile(ptr::null()) }.is_null());
+        unsafe { rust2bf_output_free(ptr::null_mut()) };
+    }
+}
@@ -0,0 +1,126 @@
+//! Source languages.
+//!
+//! A [`Frontend`] turns source text into the common [`Program`] AST, after
+//! which semantic analysis, optimization and code generation are shared. The
+//! built-in frontend is the Rust-like language handled by [`Lexer`] and
+//! [`Parser`]; others can be added by implementing the trait and registering
+//! them in [`create`].
+
+use crate::ast::Program;
+use crate::error::Diagnostics;
+use crate::lexer::Lexer;
+use crate::parser::{Dialect, Parser};
+use crate::tokens::Tokens;
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+
+#[cfg(feature = "rust-frontend")]
+pub mod rust;
+
+pub trait Frontend: Send + Sync {
+    /// Name used to select the frontend with `--frontend`.
+    fn name(&self) -> &'static str;
+
+    /// Parses `source`, reporting every problem that could be detected.
+    fn parse(&self, source: &str) -> Result<Program, Diagnostics>;
+
+    /// The token stream, formatted for the CLI's stage-by-stage output.
+    /// Frontends without a separate lexing phase return `None`.
+    fn tokens(&self, _source: &str) -> Option<String> {
+        None
+    }
+}
+
+/// The default frontend: the Rust-like language described in the README.
+pub struct MiniRust;
+
+impl Frontend for MiniRust {
+    fn name(&self) -> &'static str {
+        "mini-rust"
+    }
+
+    fn parse(&self, source: &str) -> Result<Program, Diagnostics> {
+        Parser::from_tokens(Tokens::lex(source)?).parse()
+    }
+
+    fn tokens(&self, source: &str) -> Option<String> {
+        let tokens = Lexer::new(source).tokenize().ok()?;
+        Some(format!(
+            "{:?}",
+            tokens.iter().map(|t| &t.token).collect::<Vec<_>>()
+        ))
+    }
+}
+
+/// [`MiniRust`] in another [`Dialect`], as chosen with `--dialect`.
+pub struct MiniRustDialect(pub Dialect);
+
+impl Frontend for MiniRustDialect {
+    fn name(&self) -> &'static str {
+        "mini-rust"
+    }
+
+    fn parse(&self, source: &str) -> Result<Program, Diagnostics> {
+        Parser::from_tokens(Tokens::lex(source)?)
+            .with_dialect(self.0)
+            .parse()
+    }
+
+    fn tokens(&self, source: &str) -> Option<String> {
+        MiniRust.tokens(source)
+    }
+}
+
+/// Every frontend accepted by [`create`].
+pub const FRONTENDS: &[&str] = &[
+    "mini-rust",
+    #[cfg(feature = "rust-frontend")]
+    "rust",
+];
+
+/// Instantiates the frontend called `name`, if there is one.
+pub fn create(name: &str) -> Option<Box<dyn Frontend>> {
+    match name {
+        "mini-rust" => Some(Box::new(MiniRust)),
+        #[cfg(feature = "rust-frontend")]
+        "rust" => Some(Box::new(rust::Rust)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{Expr, Stmt};
+
+    #[test]
+    fn test_every_listed_frontend_exists() {
+        for name in FRONTENDS {
+            assert_eq!(create(name).unwrap().name(), *name);
+        }
+        assert!(create("cobol").is_none());
+    }
+
+    #[test]
+    fn test_mini_rust_frontend() {
+        let program = MiniRust.parse("print(1);").unwrap();
+        assert!(matches!(
+            program.as_slice(),
+            [Stmt::Print {
+                value: Expr::Number(1),
+                ..
+            }]
+        ));
+        assert!(MiniRust.parse("print(1").is_err());
+    }
+
+    #[test]
+    fn test_dialects() {
+        assert!(MiniRust.parse("print 1;").is_err());
+        assert!(MiniRustDialect(Dialect::Teaching).parse("print 1;").is_ok());
+        assert!(
+            MiniRustDialect(Dialect::StrictRust)
+                .parse("print(1);")
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,810 @@
+//! A frontend for genuine Rust, parsed with `syn`.
+//!
+//! The input must be a file rustc would accept containing `fn main()` and
+//! any number of `const fn` helpers. Its body may use:
+//!
+//! - `let` (optionally `mut`, optionally with a type annotation) with an
+//!   initializer
+//! - assignment, including `+=`, `-=`, `*=` and `/=`
+//! - `if` / `else if` / `else`, `while` and `loop`, with unlabeled
+//!   `break` and `continue`
+//! - `match` on integer or `bool` literal patterns and `_`, without guards
+//! - integer and `bool` literals, `+ - * / %`, `== != < > <= >=`, `&&`, `||`
+//!   and `!`
+//! - `print!("{}", e)` and `println!("{}", e)` / `println!()`, and
+//!   `print!("text")` / `println!("text")` without placeholders
+//! - calls to the `const fn` helpers with constant arguments
+//!
+//! A `const fn` body must be a single expression of its parameters and
+//! other `const fn` calls. Each call is evaluated while compiling and
+//! replaced by its result, so lookup tables written as functions cost
+//! nothing at run time; there is no way to call code at run time.
+//!
+//! `{}` prints a value in decimal, as rustc's `Display` for `u8` does, and
+//! `println!` follows it with a newline. Everything else is reported as
+//! unsupported rather than silently dropped.
+
+use super::Frontend;
+use crate::ast::{BinaryOp, Expr, MatchArm, Pattern, Program, Stmt, UnaryOp};
+use crate::error::{Diagnostics, TranspilerError};
+use crate::span::Span;
+use std::collections::BTreeMap;
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+
+pub struct Rust;
+
+impl Frontend for Rust {
+    fn name(&self) -> &'static str {
+        "rust"
+    }
+
+    fn parse(&self, source: &str) -> Result<Program, Diagnostics> {
+        // Blank out a byte order mark rather than removing it, so spans
+        // still index into `source`.
+        let source = match source.strip_prefix('\u{feff}') {
+            Some(rest) => format!("   {}", rest),
+            None => source.to_string(),
+        };
+        let file: syn::File = syn::parse_str(&source).map_err(|error| {
+            let mut diagnostics = Diagnostics::new();
+            for error in error {
+                diagnostics.push(TranspilerError::with_span(
+                    error.to_string(),
+                    span_of(error.span()),
+                ));
+            }
+            diagnostics
+        })?;
+
+        let mut lowering = Lowering::default();
+        let program = lowering.file(&file);
+        lowering.diagnostics.into_result(program)
+    }
+}
+
+/// Converts a `proc_macro2` span to byte offsets into the parsed source.
+fn span_of(span: proc_macro2::Span) -> Span {
+    let range = span.byte_range();
+    Span::new(range.start, range.end)
+}
+
+#[derive(Default)]
+struct Lowering {
+    diagnostics: Diagnostics,
+    const_fns: BTreeMap<String, ConstFn>,
+    // The `const fn` calls being evaluated, innermost last, with the values
+    // of their parameters.
+    calls: Vec<(String, BTreeMap<String, u8>)>,
+}
+
+/// A `const fn` helper, reduced to what evaluating a call needs.
+struct ConstFn {
+    params: Vec<String>,
+    body: syn::Expr,
+    returns_bool: bool,
+}
+
+impl Lowering {
+    fn unsupported(&mut self, what: &str, node: &impl Spanned) {
+        self.diagnostics.push(TranspilerError::with_span(
+            format!("{} is not supported", what),
+            span_of(node.span()),
+        ));
+    }
+
+    fn file(&mut self, file: &syn::File) -> Program {
+        let mut program = Vec::new();
+        let mut found_main = false;
+
+        // Helpers first, since `main` may call ones defined after it.
+        for item in &file.items {
+            if let syn::Item::Fn(function) = item
+                && function.sig.constness.is_some()
+            {
+                self.const_fn(function);
+            }
+        }
+        for item in &file.items {
+            match item {
+                syn::Item::Fn(function)
+                    if function.sig.ident == "main" && function.sig.inputs.is_empty() =>
+                {
+                    found_main = true;
+                    program = self.block(&function.block);
+                }
+                syn::Item::Fn(function) if function.sig.constness.is_some() => {}
+                _ => self.unsupported("an item other than `fn main()` or a `const fn`", item),
+            }
+        }
+
+        if !found_main {
+            self.diagnostics
+                .push(TranspilerError::new("expected a `fn main()`"));
+        }
+        program
+    }
+
+    fn const_fn(&mut self, function: &syn::ItemFn) {
+        let sig = &function.sig;
+        if !sig.generics.params.is_empty() {
+            return self.unsupported("a generic `const fn`", &sig.generics);
+        }
+        let mut params = Vec::new();
+        for input in &sig.inputs {
+            let syn::FnArg::Typed(typed) = input else {
+                return self.unsupported("`self`", input);
+            };
+            match &*typed.pat {
+                syn::Pat::Ident(binding) if binding.by_ref.is_none() => {
+                    params.push(binding.ident.to_string())
+                }
+                pat => return self.unsupported("this pattern", pat),
+            }
+        }
+        let body = match function.block.stmts.as_slice() {
+            [syn::Stmt::Expr(body, None)] => body.clone(),
+            _ => {
+                return self.unsupported(
+                    "a `const fn` body other than a single expression",
+                    &function.block,
+                );
+            }
+        };
+        let returns_bool = match &sig.output {
+            syn::ReturnType::Type(_, ty) => {
+                matches!(&**ty, syn::Type::Path(path) if path.path.is_ident("bool"))
+            }
+            syn::ReturnType::Default => false,
+        };
+        self.const_fns.insert(
+            sig.ident.to_string(),
+            ConstFn {
+                params,
+                body,
+                returns_bool,
+            },
+        );
+    }
+
+    /// Evaluates a call to a `const fn`, giving the literal it is replaced by.
+    fn call(&mut self, call: &syn::ExprCall) -> Option<Expr> {
+        let name = match &*call.func {
+            syn::Expr::Path(path) if path.qself.is_none() => path.path.get_ident(),
+            _ => None,
+        };
+        let Some(name) = name.map(|ident| ident.to_string()) else {
+            self.unsupported("a call to anything but a `const fn`", call);
+            return None;
+        };
+        let span = span_of(call.span());
+        if self.calls.iter().any(|(caller, _)| *caller == name) {
+            self.diagnostics.push(TranspilerError::with_span(
+                format!("`{}` calls itself, so it cannot be evaluated", name),
+                span,
+            ));
+            return None;
+        }
+        let Some(function) = self.const_fns.get(&name) else {
+            self.unsupported("a call to anything but a `const fn`", call);
+            return None;
+        };
+        let (params, body, returns_bool) = (
+            function.params.clone(),
+            function.body.clone(),
+            function.returns_bool,
+        );
+        if call.args.len() != params.len() {
+            self.diagnostics.push(TranspilerError::with_span(
+                format!(
+                    "`{}` takes {} arguments but {} were given",
+                    name,
+                    params.len(),
+                    call.args.len()
+                ),
+                span,
+            ));
+            return None;
+        }
+
+        let mut values = BTreeMap::new();
+        for (param, arg) in params.into_iter().zip(&call.args) {
+            let value = self.expr(arg)?;
+            let Some(value) = value.constant() else {
+                self.diagnostics.push(
+                    TranspilerError::with_span(
+                        format!("the arguments of `{}` must be constants", name),
+                        span_of(arg.span()),
+                    )
+                    .with_note("calls to a `const fn` are evaluated while compiling"),
+                );
+                return None;
+            };
+            values.insert(param, value);
+        }
+
+        self.calls.push((name.clone(), values));
+        let result = self.expr(&body);
+        self.calls.pop();
+        match result?.constant() {
+            Some(value) if returns_bool => Some(Expr::Bool(value != 0)),
+            Some(value) => Some(Expr::number(value as i32)),
+            None => {
+                self.diagnostics.push(TranspilerError::with_span(
+                    format!("evaluating this call to `{}` divides by zero", name),
+                    span,
+                ));
+                None
+            }
+        }
+    }
+
+    fn block(&mut self, block: &syn::Block) -> Vec<Stmt> {
+        let mut body = Vec::new();
+        for stmt in &block.stmts {
+            self.stmt(stmt, &mut body);
+        }
+        body
+    }
+
+    fn stmt(&mut self, stmt: &syn::Stmt, out: &mut Vec<Stmt>) {
+        let span = span_of(stmt.span());
+        match stmt {
+            syn::Stmt::Local(local) => self.local(local, span, out),
+            syn::Stmt::Macro(mac) => self.print_macro(&mac.mac, span, out),
+            syn::Stmt::Expr(expr, _) => self.expr_stmt(expr, span, out),
+            syn::Stmt::Item(item) => self.unsupported("a nested item", item),
+        }
+    }
+
+    fn local(&mut self, local: &syn::Local, span: Span, out: &mut Vec<Stmt>) {
+        let pat = match &local.pat {
+            syn::Pat::Type(typed) => &*typed.pat,
+            pat => pat,
+        };
+        let syn::Pat::Ident(binding) = pat else {
+            return self.unsupported("this pattern", &local.pat);
+        };
+        if binding.by_ref.is_some() || binding.subpat.is_some() {
+            return self.unsupported("this pattern", &local.pat);
+        }
+        let Some(init) = &local.init else {
+            return self.unsupported("a `let` without an initializer", local);
+        };
+        if let Some((_, diverge)) = &init.diverge {
+            return self.unsupported("`let ... else`", diverge);
+        }
+
+        if let Some(value) = self.expr(&init.expr) {
+            out.push(
+                Stmt::let_stmt(
+                    binding.ident.to_string(),
+                    binding.mutability.is_some(),
+                    value,
+                )
+                .with_span(span),
+            );
+        }
+    }
+
+    fn expr_stmt(&mut self, expr: &syn::Expr, span: Span, out: &mut Vec<Stmt>) {
+        match expr {
+            syn::Expr::If(expr_if) => self.if_stmt(expr_if, out),
+            syn::Expr::While(expr_while) => {
+                if let Some(label) = &expr_while.label {
+                    return self.unsupported("a loop label", label);
+                }
+                let header =
+                    span_of(expr_while.while_token.span).to(span_of(expr_while.cond.span()));
+                let body = self.block(&expr_while.body);
+                if let Some(condition) = self.expr(&expr_while.cond) {
+                    out.push(Stmt::while_stmt(condition, body).with_span(header));
+                }
+            }
+            syn::Expr::Match(expr_match) => self.match_stmt(expr_match, out),
+            syn::Expr::Loop(expr_loop) => {
+                if let Some(label) = &expr_loop.label {
+                    return self.unsupported("a loop label", label);
+                }
+                let body = self.block(&expr_loop.body);
+                out.push(Stmt::loop_stmt(body).with_span(span_of(expr_loop.loop_token.span)));
+            }
+            syn::Expr::Break(expr_break) => {
+                if let Some(label) = &expr_break.label {
+                    return self.unsupported("a loop label", label);
+                }
+                if let Some(value) = &expr_break.expr {
+                    return self.unsupported("a `break` with a value", value);
+                }
+                out.push(Stmt::break_stmt().with_span(span));
+            }
+            syn::Expr::Continue(expr_continue) => {
+                if let Some(label) = &expr_continue.label {
+                    return self.unsupported("a loop label", label);
+                }
+                out.push(Stmt::continue_stmt().with_span(span));
+            }
+            syn::Expr::Assign(assign) => {
+                let name = self.target(&assign.left);
+                let value = self.expr(&assign.right);
+                if let (Some(name), Some(value)) = (name, value) {
+                    out.push(Stmt::assign(name, value).with_span(span));
+                }
+            }
+            syn::Expr::Binary(binary) => {
+                let operator = match binary.op {
+                    syn::BinOp::AddAssign(_) => BinaryOp::Add,
+                    syn::BinOp::SubAssign(_) => BinaryOp::Sub,
+                    syn::BinOp::MulAssign(_) => BinaryOp::Mul,
+                    syn::BinOp::DivAssign(_) => BinaryOp::Div,
+                    syn::BinOp::RemAssign(_) => BinaryOp::Mod,
+                    _ => return self.unsupported("an expression statement", expr),
+                };
+                let name = self.target(&binary.left);
+                let value = self.expr(&binary.right);
+                if let (Some(name), Some(value)) = (name, value) {
+                    let value = Expr::binary(Expr::variable(name.clone()), operator, value);
+                    out.push(Stmt::assign(name, value).with_span(span));
+                }
+            }
+            syn::Expr::Macro(mac) => self.print_macro(&mac.mac, span, out),
+            _ => self.unsupported("an expression statement", expr),
+        }
+    }
+
+    /// Lowers `if`, with `else if` as an `if` alone in the `else` body.
+    fn if_stmt(&mut self, expr_if: &syn::ExprIf, out: &mut Vec<Stmt>) {
+        let header = span_of(expr_if.if_token.span).to(span_of(expr_if.cond.span()));
+        let condition = self.expr(&expr_if.cond);
+        let body = self.block(&expr_if.then_branch);
+
+        let mut else_body = Vec::new();
+        match expr_if.else_branch.as_ref().map(|(_, branch)| &**branch) {
+            None => {}
+            Some(syn::Expr::Block(block)) => else_body = self.block(&block.block),
+            Some(syn::Expr::If(nested)) => self.if_stmt(nested, &mut else_body),
+            Some(other) => self.unsupported("this `else` branch", other),
+        }
+
+        if let Some(condition) = condition {
+            out.push(Stmt::if_else(condition, body, else_body).with_span(header));
+        }
+    }
+
+    /// Lowers a `match` whose patterns are integer or `bool` literals or
+    /// `_`. An arm's body may be a block or a single statement.
+    fn match_stmt(&mut self, expr_match: &syn::ExprMatch, out: &mut Vec<Stmt>) {
+        let header = span_of(expr_match.match_token.span).to(span_of(expr_match.expr.span()));
+        let scrutinee = self.expr(&expr_match.expr);
+
+        let mut arms = Vec::new();
+        for arm in &expr_match.arms {
+            if let Some((_, guard)) = &arm.guard {
+                self.unsupported("a match guard", guard);
+                continue;
+            }
+            let pattern = match &arm.pat {
+                syn::Pat::Wild(_) => Pattern::Wildcard,
+                syn::Pat::Lit(literal) => match &literal.lit {
+                    syn::Lit::Int(int) => match int.base10_parse::<i32>() {
+                        Ok(value) => Pattern::Number(value),
+                        Err(error) => {
+                            self.diagnostics.push(TranspilerError::with_span(
+                                error.to_string(),
+                                span_of(int.span()),
+                            ));
+                            continue;
+                        }
+                    },
+                    syn::Lit::Bool(boolean) => Pattern::Number(boolean.value.into()),
+                    _ => {
+                        self.unsupported("this pattern", &arm.pat);
+                        continue;
+                    }
+                },
+                pat => {
+                    self.unsupported("this pattern", pat);
+                    continue;
+                }
+            };
+            let body = match &*arm.body {
+                syn::Expr::Block(block) if block.label.is_none() => self.block(&block.block),
+                body => {
+                    let mut stmts = Vec::new();
+                    self.expr_stmt(body, span_of(body.span()), &mut stmts);
+                    stmts
+                }
+            };
+            arms.push(MatchArm::new(pattern, body).with_span(span_of(arm.pat.span())));
+        }
+
+        if let Some(scrutinee) = scrutinee {
+            out.push(Stmt::match_stmt(scrutinee, arms).with_span(header));
+        }
+    }
+
+    /// Lowers `print!("{}", e)`, `println!("{}", e)` and `println!()`, and
+    /// the same macros with only text.
+    fn print_macro(&mut self, mac: &syn::Macro, span: Span, out: &mut Vec<Stmt>) {
+        let newline = if mac.path.is_ident("println") {
+            true
+        } else if mac.path.is_ident("print") {
+            false
+        } else {
+            return self.unsupported("this macro", mac);
+        };
+
+        let args =
+            match mac.parse_body_with(Punctuated::<syn::Expr, syn::Token![,]>::parse_terminated) {
+                Ok(args) => args,
+                Err(error) => {
+                    return self
+                        .diagnostics
+                        .push(TranspilerError::with_span(error.to_string(), span));
+                }
+            };
+
+        let mut args = args.iter();
+        match (args.next(), args.next(), args.next()) {
+            (None, ..) if newline => {}
+            (
+                Some(syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(format),
+                    ..
+                })),
+                Some(value),
+                None,
+            ) if format.value() == "{}" => {
+                if let Some(value) = self.expr(value) {
+                    out.push(Stmt::print_num(value).with_span(span));
+                }
+            }
+            (
+                Some(syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(text),
+                    ..
+                })),
+                None,
+                None,
+            ) if !text.value().contains(['{', '}']) => {
+                out.push(Stmt::print(Expr::string(text.value())).with_span(span));
+            }
+            _ => {
+                return self
+                    .unsupported("a format string other than `\"{}\"` with one argument", mac);
+            }
+        }
+
+        if newline {
+            out.push(Stmt::print(Expr::number(10)).with_span(span));
+        }
+    }
+
+    /// The variable assigned to by `target = ...`.
+    fn target(&mut self, target: &syn::Expr) -> Option<String> {
+        match target {
+            syn::Expr::Path(path) if path.qself.is_none() => match path.path.get_ident() {
+                Some(ident) => Some(ident.to_string()),
+                None => {
+                    self.unsupported("assigning to a path", target);
+                    None
+                }
+            },
+            _ => {
+                self.unsupported("this assignment target", target);
+                None
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &syn::Expr) -> Option<Expr> {
+        match expr {
+            syn::Expr::Lit(literal) => match &literal.lit {
+                syn::Lit::Int(int) => match int.base10_parse::<i32>() {
+                    Ok(value) => Some(Expr::number(value)),
+                    Err(error) => {
+                        self.diagnostics.push(TranspilerError::with_span(
+                            error.to_string(),
+                            span_of(int.span()),
+                        ));
+                        None
+                    }
+                },
+                syn::Lit::Bool(boolean) => Some(Expr::Bool(boolean.value)),
+                _ => {
+                    self.unsupported("this literal", expr);
+                    None
+                }
+            },
+            syn::Expr::Path(path) if path.qself.is_none() => match path.path.get_ident() {
+                Some(ident) => match self.calls.last() {
+                    Some((function, values)) => match values.get(&ident.to_string()) {
+                        Some(value) => Some(Expr::number(*value as i32)),
+                        None => {
+                            self.diagnostics.push(TranspilerError::with_span(
+                                format!("`{}` is not a parameter of `{}`", ident, function),
+                                span_of(ident.span()),
+                            ));
+                            None
+                        }
+                    },
+                    None => Some(Expr::variable(ident.to_string())),
+                },
+                None => {
+                    self.unsupported("a path expression", expr);
+                    None
+                }
+            },
+            syn::Expr::Call(call) => self.call(call),
+            syn::Expr::Paren(paren) => self.expr(&paren.expr),
+            syn::Expr::Unary(syn::ExprUnary {
+                op: syn::UnOp::Not(_),
+                expr: operand,
+                ..
+            }) => {
+                let operand = self.expr(operand)?;
+                Some(Expr::unary(UnaryOp::Not, operand))
+            }
+            syn::Expr::Binary(binary) => {
+                let left = self.expr(&binary.left);
+                let right = self.expr(&binary.right);
+                let (left, right) = (left?, right?);
+                let operator = match binary.op {
+                    syn::BinOp::Add(_) => BinaryOp::Add,
+                    syn::BinOp::Sub(_) => BinaryOp::Sub,
+                    syn::BinOp::Mul(_) => BinaryOp::Mul,
+                    syn::BinOp::Div(_) => BinaryOp::Div,
+                    syn::BinOp::Rem(_) => BinaryOp::Mod,
+                    syn::BinOp::Eq(_) => BinaryOp::Equal,
+                    syn::BinOp::Ne(_) => BinaryOp::NotEqual,
+                    syn::BinOp::Lt(_) => BinaryOp::Less,
+                    syn::BinOp::Gt(_) => BinaryOp::Greater,
+                    syn::BinOp::Le(_) => BinaryOp::LessEqual,
+                    syn::BinOp::Ge(_) => BinaryOp::GreaterEqual,
+                    syn::BinOp::And(_) => BinaryOp::And,
+                    syn::BinOp::Or(_) => BinaryOp::Or,
+                    _ => {
+                        self.unsupported("this operator", &binary.op);
+                        return None;
+                    }
+                };
+                Some(Expr::binary(left, operator, right))
+            }
+            _ => {
+                self.unsupported("this expression", expr);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "interpreter")]
+    use crate::{analysis::Analyzer, bf::Interpreter, codegen::BrainfuckGenerator};
+
+    #[cfg(feature = "interpreter")]
+    fn run(source: &str) -> Vec<u8> {
+        let program = Rust.parse(source).unwrap();
+        assert!(!Analyzer::new().analyze(&program).has_errors());
+        let code = BrainfuckGenerator::new().generate(&program).unwrap();
+        Interpreter::new(code.as_str()).run().unwrap().output
+    }
+
+    fn errors(source: &str) -> Vec<String> {
+        Rust.parse(source)
+            .unwrap_err()
+            .into_iter()
+            .map(|e| e.message)
+            .collect()
+    }
+
+    #[test]
+    fn test_lowers_main_body() {
+        let program = Rust
+            .parse("fn main() {\n    let mut x: u8 = 2;\n    x += 3;\n    print!(\"{}\", x);\n}\n")
+            .unwrap();
+
+        assert_eq!(
+            program,
+            vec![
+                Stmt::let_stmt("x", true, Expr::number(2)).with_span(Span::new(16, 34)),
+                Stmt::assign(
+                    "x",
+                    Expr::binary(Expr::variable("x"), BinaryOp::Add, Expr::number(3))
+                )
+                .with_span(Span::new(39, 46)),
+                Stmt::print_num(Expr::variable("x")).with_span(Span::new(51, 67)),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_if_else_chain() {
+        let source = "fn main() {
+            let x = 2;
+            if x == 1 { print!(\"A\"); }
+            else if x == 2 { print!(\"B\"); }
+            else { print!(\"C\"); }
+            println!();
+        }";
+        assert_eq!(run(source), b"B\n");
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_negation() {
+        let source = "fn main() { let n = 0; if !(n == 1) { println!(\"{}\", 65); } }";
+        assert_eq!(run(source), b"65\n");
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_prints_decimal() {
+        let source = "fn main() { let x = 7; println!(\"{}\", x); print!(\"{}\", x * 30); }";
+        assert_eq!(run(source), b"7\n210");
+        // Printing a small number is what rustc does, not a mistake.
+        let program = Rust.parse(source).unwrap();
+        assert!(Analyzer::new().analyze(&program).is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_prints_text() {
+        let source = "fn main() { print!(\"n = \"); println!(\"{}\", 55); println!(\"done\"); }";
+        assert_eq!(run(source), b"n = 55\ndone\n");
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_loop_break_continue() {
+        let source = "fn main() {
+            let mut n = 0;
+            loop {
+                n += 1;
+                if n == 2 { continue; }
+                if n == 4 { break; }
+                print!(\"{}\", n);
+            }
+        }";
+        assert_eq!(run(source), b"13");
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_match() {
+        let source = "fn main() {
+            let mut n: u8 = 0;
+            while n < 4 {
+                match n {
+                    0 => print!(\"A\"),
+                    2 => {
+                        n += 1;
+                        continue;
+                    }
+                    _ => {}
+                }
+                match n == 1 {
+                    true => print!(\"B\"),
+                    false => {}
+                }
+                n += 1;
+            }
+        }";
+        assert_eq!(run(source), b"AB");
+        assert_eq!(
+            errors("fn main() { match 1 { n if n > 0 => {} 1..=3 => {} _ => {} } }"),
+            vec![
+                "a match guard is not supported",
+                "this pattern is not supported",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lowers_while() {
+        let program = Rust
+            .parse("fn main() { let mut n = 3; while n != 0 { n -= 1; } }")
+            .unwrap();
+        assert_eq!(
+            program[1],
+            Stmt::while_stmt(
+                Expr::binary(Expr::variable("n"), BinaryOp::NotEqual, Expr::number(0)),
+                vec![
+                    Stmt::assign(
+                        "n",
+                        Expr::binary(Expr::variable("n"), BinaryOp::Sub, Expr::number(1))
+                    )
+                    .with_span(Span::new(42, 49))
+                ]
+            )
+            .with_span(Span::new(27, 39))
+        );
+    }
+
+    #[test]
+    fn test_lowers_non_strict_comparisons() {
+        let program = Rust.parse("fn main() { let b = 1 <= 2; }").unwrap();
+        let Stmt::Let { value, .. } = &program[0] else {
+            panic!("expected a let");
+        };
+        assert_eq!(
+            *value,
+            Expr::binary(Expr::number(1), BinaryOp::LessEqual, Expr::number(2))
+        );
+    }
+
+    #[test]
+    fn test_reports_unsupported_constructs() {
+        assert_eq!(
+            errors(
+                "fn helper() {}\nfn main() { let s = \"hi\"; 'a: loop {} println!(\"{} {}\", 1, 2); }"
+            ),
+            vec![
+                "an item other than `fn main()` or a `const fn` is not supported",
+                "this literal is not supported",
+                "a loop label is not supported",
+                "a format string other than `\"{}\"` with one argument is not supported",
+            ]
+        );
+        assert_eq!(errors(""), vec!["expected a `fn main()`"]);
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_evaluates_const_fn_calls() {
+        let source = "
+            const fn square(n: u8) -> u8 { n * n }
+            const fn glyph(n: u8) -> u8 { sum_of_squares(n, 1) % 10 }
+            const fn sum_of_squares(a: u8, b: u8) -> u8 { square(a) + square(b) }
+            const fn is_even(n: u8) -> bool { n % 2 == 0 }
+            fn main() {
+                print!(\"{}\", glyph(2));
+                if is_even(square(3)) { print!(\"E\"); } else { print!(\"O\"); }
+                print!(\"{}\", square(15));
+            }";
+        assert_eq!(run(source), b"5O225");
+
+        let program = Rust
+            .parse("const fn f(n: u8) -> u8 { n + 1 } fn main() { let x = f(f(1)); }")
+            .unwrap();
+        let Stmt::Let { value, .. } = &program[0] else {
+            panic!("expected a let");
+        };
+        assert_eq!(*value, Expr::number(3));
+    }
+
+    #[test]
+    fn test_const_fn_errors() {
+        assert_eq!(
+            errors(
+                "const fn f(n: u8) -> u8 { n / 0 }
+                 const fn g(n: u8) -> u8 { g(n) + x }
+                 fn main() { let x = 1; let a = f(x); let b = f(2); let c = g(1); let d = h(1); }"
+            ),
+            vec![
+                "the arguments of `f` must be constants",
+                "evaluating this call to `f` divides by zero",
+                "`g` calls itself, so it cannot be evaluated",
+                "`x` is not a parameter of `g`",
+                "a call to anything but a `const fn` is not supported",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_syntax_error_has_span() {
+        let diagnostics = Rust.parse("fn main() { let = 1; }").unwrap_err();
+        let error = diagnostics.iter().next().unwrap();
+        assert_eq!(error.span, Some(Span::new(16, 17)));
+    }
+
+    #[test]
+    fn test_byte_order_mark() {
+        let diagnostics = Rust.parse("\u{feff}fn main() { let = 1; }").unwrap_err();
+        let error = diagnostics.iter().next().unwrap();
+        assert_eq!(error.span, Some(Span::new(19, 20)));
+    }
+}
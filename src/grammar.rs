@@ -0,0 +1,735 @@
+//! The grammar of mini-rust, as data.
+//!
+//! [`rules`] lists one rule per parsing function of the
+//! [`Parser`](crate::parser::Parser), named after it, with the expression
+//! levels taken from [`BINARY_OPERATORS`], the table the parser itself
+//! climbs. [`ebnf`] writes the rules out in the W3C notation most grammar
+//! tools read, and [`railroad`] draws them as railroad diagrams in an HTML
+//! page. The tests derive programs from the rules and parse them, so the
+//! two cannot drift apart unnoticed.
+
+use crate::parser::BINARY_OPERATORS;
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+/// Part of the right-hand side of a rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Item {
+    /// Text written as is, such as a keyword or `;`.
+    Terminal(&'static str),
+    /// Any token of a class, such as `IDENTIFIER`.
+    Token(&'static str),
+    /// Another rule, by name.
+    Rule(&'static str),
+    Sequence(Vec<Item>),
+    /// One of the items; the parser tries them in order.
+    Choice(Vec<Item>),
+    Optional(Box<Item>),
+    /// The item any number of times, including none.
+    Repeat(Box<Item>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub name: &'static str,
+    pub item: Item,
+}
+
+/// What the token classes match, as the lexer reads them.
+pub const TOKENS: &[(&str, &str)] = &[
+    (
+        "IDENTIFIER",
+        "a letter or `_`, then letters, digits and `_`; not a keyword",
+    ),
+    ("NUMBER", "decimal digits"),
+    (
+        "STRING",
+        "text in double quotes, with the escapes \\n \\r \\t \\0 \\\\ \\\" and \\x00 to \\x7f",
+    ),
+];
+
+fn t(text: &'static str) -> Item {
+    Item::Terminal(text)
+}
+
+fn r(name: &'static str) -> Item {
+    Item::Rule(name)
+}
+
+fn seq(items: impl Into<Vec<Item>>) -> Item {
+    Item::Sequence(items.into())
+}
+
+fn choice(items: impl Into<Vec<Item>>) -> Item {
+    Item::Choice(items.into())
+}
+
+fn opt(item: Item) -> Item {
+    Item::Optional(Box::new(item))
+}
+
+fn many(item: Item) -> Item {
+    Item::Repeat(Box::new(item))
+}
+
+/// `item (separator item)*`.
+fn list(item: Item, separator: &'static str) -> Item {
+    seq([item.clone(), many(seq([t(separator), item]))])
+}
+
+const IDENTIFIER: Item = Item::Token("IDENTIFIER");
+const NUMBER: Item = Item::Token("NUMBER");
+const STRING: Item = Item::Token("STRING");
+
+/// Every rule of the language, starting with `program`.
+pub fn rules() -> Vec<Rule> {
+    let semicolon = || opt(t(";"));
+    let rule = |name, item| Rule { name, item };
+    let mut rules = vec![
+        rule("program", many(r("statement"))),
+        rule(
+            "statement",
+            choice([
+                r("print_statement"),
+                r("let_statement"),
+                r("if_statement"),
+                r("match_statement"),
+                r("while_statement"),
+                r("loop_statement"),
+                r("jump_statement"),
+                r("for_statement"),
+                r("function_definition"),
+                r("return_statement"),
+                r("call_statement"),
+                r("assignment_statement"),
+            ]),
+        ),
+        rule(
+            "print_statement",
+            seq([
+                choice([
+                    seq([t("print"), t("("), list(r("expression"), ",")]),
+                    seq([t("println"), t("("), opt(list(r("expression"), ","))]),
+                    seq([t("print"), t("!"), t("("), r("format_arguments")]),
+                    seq([t("println"), t("!"), t("("), opt(r("format_arguments"))]),
+                    seq([t("print_num"), t("("), r("expression")]),
+                ]),
+                t(")"),
+                semicolon(),
+            ]),
+        ),
+        // The text may not contain `{` or `}`.
+        rule(
+            "format_arguments",
+            choice([seq([t("\"{}\""), t(","), r("expression")]), STRING]),
+        ),
+        rule(
+            "let_statement",
+            seq([
+                t("let"),
+                opt(t("mut")),
+                IDENTIFIER,
+                opt(seq([t(":"), r("type_name")])),
+                t("="),
+                r("expression"),
+                semicolon(),
+            ]),
+        ),
+        rule(
+            "if_statement",
+            seq([
+                t("if"),
+                r("expression"),
+                r("block"),
+                opt(seq([t("else"), choice([r("if_statement"), r("block")])])),
+            ]),
+        ),
+        rule(
+            "match_statement",
+            seq([
+                t("match"),
+                r("expression"),
+                t("{"),
+                many(seq([r("match_arm"), opt(t(","))])),
+                t("}"),
+            ]),
+        ),
+        rule(
+            "match_arm",
+            seq([
+                choice([seq([opt(t("-")), NUMBER]), t("_")]),
+                t("=>"),
+                r("block"),
+            ]),
+        ),
+        rule(
+            "while_statement",
+            seq([t("while"), r("expression"), r("block")]),
+        ),
+        rule(
+            "loop_statement",
+            seq([
+                t("loop"),
+                r("block"),
+                opt(seq([t("while"), r("expression"), semicolon()])),
+            ]),
+        ),
+        rule(
+            "jump_statement",
+            seq([choice([t("break"), t("continue")]), semicolon()]),
+        ),
+        rule(
+            "for_statement",
+            seq([t("for"), IDENTIFIER, t("in"), r("expression"), r("block")]),
+        ),
+        rule(
+            "function_definition",
+            seq([
+                opt(r("inline_attribute")),
+                t("fn"),
+                IDENTIFIER,
+                t("("),
+                opt(seq([
+                    list(seq([IDENTIFIER, opt(seq([t(":"), r("type_name")]))]), ","),
+                    opt(t(",")),
+                ])),
+                t(")"),
+                opt(seq([t("->"), r("type_name")])),
+                r("block"),
+            ]),
+        ),
+        rule(
+            "inline_attribute",
+            seq([
+                t("#"),
+                t("["),
+                t("inline"),
+                opt(seq([t("("), choice([t("always"), t("never")]), t(")")])),
+                t("]"),
+            ]),
+        ),
+        rule("type_name", choice([t("u8"), t("bool")])),
+        rule(
+            "return_statement",
+            seq([t("return"), opt(r("expression")), semicolon()]),
+        ),
+        rule(
+            "call_statement",
+            seq([
+                choice([
+                    seq([IDENTIFIER, t("("), r("arguments")]),
+                    seq([t("input"), t("(")]),
+                ]),
+                t(")"),
+                semicolon(),
+            ]),
+        ),
+        rule(
+            "assignment_statement",
+            seq([IDENTIFIER, t("="), r("expression"), semicolon()]),
+        ),
+        rule(
+            "arguments",
+            opt(seq([list(r("expression"), ","), opt(t(","))])),
+        ),
+        rule("block", seq([t("{"), many(r("statement")), t("}")])),
+        rule("expression", r(BINARY_OPERATORS[0].0)),
+    ];
+
+    // One rule per precedence level, each joining operands of the next.
+    let levels = BINARY_OPERATORS.iter().map(|&(name, _)| name);
+    let operands = levels.skip(1).chain(["unary"]);
+    for (&(name, operators), operand) in BINARY_OPERATORS.iter().zip(operands) {
+        let symbols: Vec<Item> = operators.iter().map(|(_, op)| t(op.symbol())).collect();
+        let operator = match <[Item; 1]>::try_from(symbols) {
+            Ok([symbol]) => symbol,
+            Err(symbols) => choice(symbols),
+        };
+        rules.push(rule(
+            name,
+            seq([r(operand), many(seq([operator, r(operand)]))]),
+        ));
+    }
+
+    rules.push(rule("unary", seq([many(t("!")), r("primary")])));
+    rules.push(rule(
+        "primary",
+        choice([
+            NUMBER,
+            STRING,
+            t("true"),
+            t("false"),
+            seq([IDENTIFIER, opt(seq([t("("), r("arguments"), t(")")]))]),
+            seq([t("input"), t("("), t(")")]),
+            seq([t("("), r("expression"), t(")")]),
+        ]),
+    ));
+    rules
+}
+
+/// The grammar in W3C EBNF, one `name ::= ...` rule per line, with the
+/// names padded so the definitions line up.
+///
+/// ```
+/// let grammar = rusted_brains::grammar::ebnf();
+/// assert!(grammar.contains(" ::= \"{\" statement* \"}\"\n"));
+/// ```
+pub fn ebnf() -> String {
+    let rules = rules();
+    let width = rules.iter().map(|rule| rule.name.len()).max().unwrap_or(0);
+    let mut text = String::from("/* Grammar of mini-rust. Tokens:\n");
+    for (name, description) in TOKENS {
+        text.push_str(&format!(" * {}: {}\n", name, description));
+    }
+    text.push_str(" * Whitespace and // comments may go between any two tokens. */\n\n");
+    for rule in &rules {
+        text.push_str(&format!(
+            "{:width$} ::= {}\n",
+            rule.name,
+            ebnf_item(&rule.item, false),
+            width = width
+        ));
+    }
+    text
+}
+
+/// `item` in EBNF, in parentheses if `nested` in something that binds
+/// tighter than a choice or, for postfix operators, a sequence.
+fn ebnf_item(item: &Item, nested: bool) -> String {
+    let text = match item {
+        Item::Terminal(text) if text.contains('"') => return format!("'{}'", text),
+        Item::Terminal(text) => return format!("\"{}\"", text),
+        Item::Token(name) | Item::Rule(name) => return name.to_string(),
+        Item::Optional(item) => return format!("{}?", ebnf_item(item, true)),
+        Item::Repeat(item) => return format!("{}*", ebnf_item(item, true)),
+        Item::Sequence(items) if items.len() == 1 => return ebnf_item(&items[0], nested),
+        Item::Sequence(items) => {
+            // Sequences within sequences need no parentheses.
+            let items: Vec<String> = items
+                .iter()
+                .map(|item| ebnf_item(item, !matches!(item, Item::Sequence(_))))
+                .collect();
+            items.join(" ")
+        }
+        Item::Choice(items) => {
+            let items: Vec<String> = items.iter().map(|item| ebnf_item(item, false)).collect();
+            items.join(" | ")
+        }
+    };
+    if nested {
+        format!("( {} )", text)
+    } else {
+        text
+    }
+}
+
+/// Spacing of the diagrams, in pixels.
+const ARC: u32 = 10;
+const GAP: u32 = 10;
+const BOX_HEIGHT: u32 = 22;
+const CHAR_WIDTH: u32 = 8;
+const MARGIN: u32 = 20;
+
+/// What a diagram is drawn from: [`Item`] with optional and repeated parts
+/// broken down into the shapes that draw them.
+enum Shape<'a> {
+    /// A box, rounded for text written as is, linking to `link` if set.
+    Box {
+        text: &'a str,
+        rounded: bool,
+        link: bool,
+    },
+    /// A plain line, for an optional part not taken.
+    Skip,
+    Sequence(Vec<Shape<'a>>),
+    /// The first branch on the line, the others below it.
+    Choice(Vec<Shape<'a>>),
+    /// The shape, then a line back to its start below it.
+    Loop(Box<Shape<'a>>),
+}
+
+impl<'a> Shape<'a> {
+    fn of(item: &'a Item) -> Self {
+        match item {
+            Item::Terminal(text) => Shape::Box {
+                text,
+                rounded: true,
+                link: false,
+            },
+            Item::Token(text) => Shape::Box {
+                text,
+                rounded: false,
+                link: false,
+            },
+            Item::Rule(text) => Shape::Box {
+                text,
+                rounded: false,
+                link: true,
+            },
+            Item::Sequence(items) => Shape::Sequence(items.iter().map(Shape::of).collect()),
+            Item::Choice(items) => Shape::Choice(items.iter().map(Shape::of).collect()),
+            Item::Optional(item) => Shape::Choice(vec![Shape::Skip, Shape::of(item)]),
+            Item::Repeat(item) => {
+                Shape::Choice(vec![Shape::Skip, Shape::Loop(Box::new(Shape::of(item)))])
+            }
+        }
+    }
+
+    /// Width, and height above and below the line through it.
+    fn size(&self) -> (u32, u32, u32) {
+        match self {
+            Shape::Box { text, .. } => (
+                text.chars().count() as u32 * CHAR_WIDTH + 2 * GAP,
+                BOX_HEIGHT / 2,
+                BOX_HEIGHT / 2,
+            ),
+            Shape::Skip => (0, 0, 0),
+            Shape::Sequence(shapes) => {
+                let sizes: Vec<_> = shapes.iter().map(Shape::size).collect();
+                let width = sizes.iter().map(|size| size.0).sum::<u32>()
+                    + GAP * (sizes.len() as u32).saturating_sub(1);
+                let up = sizes.iter().map(|size| size.1).max().unwrap_or(0);
+                let down = sizes.iter().map(|size| size.2).max().unwrap_or(0);
+                (width, up, down)
+            }
+            Shape::Choice(shapes) => {
+                let width = shapes.iter().map(|shape| shape.size().0).max().unwrap_or(0);
+                let up = shapes.first().map_or(0, |shape| shape.size().1);
+                let down = self
+                    .branch_offsets()
+                    .last()
+                    .map_or(0, |&(offset, shape)| offset + shape.size().2);
+                (width + 4 * ARC, up, down)
+            }
+            Shape::Loop(shape) => {
+                let (width, up, down) = shape.size();
+                (width + 2 * ARC, up, down + GAP)
+            }
+        }
+    }
+
+    /// How far below the line each branch of a choice runs.
+    fn branch_offsets(&self) -> Vec<(u32, &Shape<'a>)> {
+        let Shape::Choice(shapes) = self else {
+            return Vec::new();
+        };
+        let mut offsets = Vec::new();
+        let mut offset = 0;
+        let mut below = 0;
+        for (i, shape) in shapes.iter().enumerate() {
+            let (_, up, down) = shape.size();
+            if i > 0 {
+                offset = (offset + below + GAP + up).max(offset + 2 * ARC);
+            }
+            offsets.push((offset, shape));
+            below = down;
+        }
+        offsets
+    }
+
+    /// Draws the shape with its line entering at `(x, y)`.
+    fn draw(&self, x: u32, y: u32, svg: &mut String) {
+        let (width, _, _) = self.size();
+        match self {
+            Shape::Box {
+                text,
+                rounded,
+                link,
+            } => {
+                let top = y - BOX_HEIGHT / 2;
+                let radius = if *rounded { BOX_HEIGHT / 2 } else { 0 };
+                let text = escape(text);
+                let label = format!(
+                    "<text x=\"{}\" y=\"{}\">{}</text>",
+                    x + width / 2,
+                    y + 4,
+                    text
+                );
+                let label = if *link {
+                    format!("<a href=\"#{}\">{}</a>", text, label)
+                } else {
+                    label
+                };
+                svg.push_str(&format!(
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"{}\"/>{}\n",
+                    x, top, width, BOX_HEIGHT, radius, label
+                ));
+            }
+            Shape::Skip => {}
+            Shape::Sequence(shapes) => {
+                let mut x = x;
+                for (i, shape) in shapes.iter().enumerate() {
+                    if i > 0 {
+                        line(svg, x, y, GAP);
+                        x += GAP;
+                    }
+                    shape.draw(x, y, svg);
+                    x += shape.size().0;
+                }
+            }
+            Shape::Choice(_) => {
+                for (offset, shape) in self.branch_offsets() {
+                    let branch = shape.size().0;
+                    if offset == 0 {
+                        line(svg, x, y, 2 * ARC);
+                    } else {
+                        let drop = offset - 2 * ARC;
+                        svg.push_str(&format!(
+                            "<path d=\"M{} {}a{r} {r} 0 0 1 {r} {r}v{}a{r} {r} 0 0 0 {r} {r}\"/>\n",
+                            x,
+                            y,
+                            drop,
+                            r = ARC
+                        ));
+                        svg.push_str(&format!(
+                            "<path d=\"M{} {}a{r} {r} 0 0 0 {r} -{r}v-{}a{r} {r} 0 0 1 {r} -{r}\"/>\n",
+                            x + width - 2 * ARC,
+                            y + offset,
+                            drop,
+                            r = ARC
+                        ));
+                    }
+                    shape.draw(x + 2 * ARC, y + offset, svg);
+                    let end = x + 2 * ARC + branch;
+                    line(svg, end, y + offset, x + width - 2 * ARC - end);
+                    if offset == 0 {
+                        line(svg, x + width - 2 * ARC, y, 2 * ARC);
+                    }
+                }
+            }
+            Shape::Loop(shape) => {
+                let (inner, _, down) = shape.size();
+                line(svg, x, y, ARC);
+                shape.draw(x + ARC, y, svg);
+                line(svg, x + ARC + inner, y, ARC);
+                let back = down + GAP;
+                svg.push_str(&format!(
+                    "<path d=\"M{} {}a{r} {r} 0 0 1 {r} {r}v{}a{r} {r} 0 0 1 -{r} {r}h-{}a{r} {r} 0 0 1 -{r} -{r}v-{}a{r} {r} 0 0 1 {r} -{r}\"/>\n",
+                    x + ARC + inner,
+                    y,
+                    back.saturating_sub(2 * ARC),
+                    inner,
+                    back.saturating_sub(2 * ARC),
+                    r = ARC
+                ));
+            }
+        }
+    }
+}
+
+fn line(svg: &mut String, x: u32, y: u32, length: u32) {
+    if length > 0 {
+        svg.push_str(&format!("<path d=\"M{} {}h{}\"/>\n", x, y, length));
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The grammar as an HTML page with a railroad diagram for each rule.
+pub fn railroad() -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Grammar of mini-rust</title>\n<style>\n\
+         svg { display: block; margin-bottom: 1em; }\n\
+         path { fill: none; stroke: black; stroke-width: 2; }\n\
+         rect { fill: #f4f4ff; stroke: black; stroke-width: 2; }\n\
+         text { font: 13px monospace; text-anchor: middle; }\n\
+         a text { fill: #00c; }\n\
+         </style>\n</head>\n<body>\n<h1>Grammar of mini-rust</h1>\n<dl>\n",
+    );
+    for (name, description) in TOKENS {
+        html.push_str(&format!(
+            "<dt>{}</dt><dd>{}</dd>\n",
+            name,
+            escape(description)
+        ));
+    }
+    html.push_str("</dl>\n");
+    for rule in rules() {
+        let shape = Shape::of(&rule.item);
+        let (width, up, down) = shape.size();
+        let y = MARGIN + up;
+        let mut svg = String::new();
+        // The ends of the line, marked as in most railroad diagrams.
+        svg.push_str(&format!(
+            "<path d=\"M{} {}v20m10 -20v20m-10 -10h{}\"/>\n",
+            MARGIN - 20,
+            y - 10,
+            20
+        ));
+        shape.draw(MARGIN, y, &mut svg);
+        svg.push_str(&format!(
+            "<path d=\"M{} {}h20m-10 -10v20m10 -20v20\"/>\n",
+            MARGIN + width,
+            y
+        ));
+        html.push_str(&format!(
+            "<h2 id=\"{}\">{}</h2>\n<svg width=\"{}\" height=\"{}\">\n{}</svg>\n",
+            rule.name,
+            rule.name,
+            width + 2 * MARGIN + 20,
+            up + down + 2 * MARGIN,
+            svg
+        ));
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{KEYWORDS, Lexer};
+    use crate::parser::Parser;
+    use alloc::collections::BTreeSet;
+
+    fn collect<'a>(item: &'a Item, terminals: &mut BTreeSet<&'a str>, rules: &mut Vec<&'a str>) {
+        match item {
+            Item::Terminal(text) => {
+                terminals.insert(text);
+            }
+            Item::Token(_) => {}
+            Item::Rule(name) => rules.push(name),
+            Item::Sequence(items) | Item::Choice(items) => {
+                for item in items {
+                    collect(item, terminals, rules);
+                }
+            }
+            Item::Optional(item) | Item::Repeat(item) => collect(item, terminals, rules),
+        }
+    }
+
+    #[test]
+    fn test_rules_are_complete() {
+        let rules = rules();
+        let names: BTreeSet<&str> = rules.iter().map(|rule| rule.name).collect();
+        assert_eq!(names.len(), rules.len(), "a rule is defined twice");
+        let mut terminals = BTreeSet::new();
+        let mut used = Vec::new();
+        for rule in &rules {
+            collect(&rule.item, &mut terminals, &mut used);
+        }
+        for name in &used {
+            assert!(names.contains(name), "`{}` is used but not defined", name);
+        }
+        for rule in &rules[1..] {
+            assert!(used.contains(&rule.name), "`{}` is never used", rule.name);
+        }
+        for keyword in KEYWORDS {
+            assert!(
+                terminals.contains(keyword),
+                "`{}` is not in the grammar",
+                keyword
+            );
+        }
+    }
+
+    /// Writes out a random program the grammar allows. Past `depth` nested
+    /// rules, it takes the first branch of every choice and leaves out
+    /// everything optional, which always ends.
+    struct Deriver<'a> {
+        rules: &'a [Rule],
+        seed: u64,
+        depth: usize,
+    }
+
+    impl Deriver<'_> {
+        fn next(&mut self, bound: usize) -> usize {
+            self.seed = self
+                .seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (self.seed >> 33) as usize % bound
+        }
+
+        fn derive(&mut self, item: &Item, out: &mut String) {
+            let deep = self.depth > 6;
+            match item {
+                Item::Terminal(text) => {
+                    out.push_str(text);
+                    out.push(' ');
+                }
+                Item::Token("IDENTIFIER") => out.push_str("x "),
+                Item::Token("NUMBER") => out.push_str("7 "),
+                Item::Token(_) => out.push_str("\"hi\" "),
+                Item::Rule(name) => {
+                    let rule = self.rules.iter().find(|rule| rule.name == *name).unwrap();
+                    self.depth += 1;
+                    self.derive(&rule.item.clone(), out);
+                    self.depth -= 1;
+                }
+                Item::Sequence(items) => {
+                    for item in items {
+                        self.derive(item, out);
+                    }
+                }
+                Item::Choice(items) => {
+                    let i = if deep { 0 } else { self.next(items.len()) };
+                    self.derive(&items[i], out);
+                }
+                // The parser reads as much of an expression as it can, so a
+                // `;` left out may let the next statement join it.
+                Item::Optional(item) if **item == Item::Terminal(";") => self.derive(item, out),
+                Item::Optional(item) => {
+                    if !deep && self.next(2) == 0 {
+                        self.derive(item, out);
+                    }
+                }
+                Item::Repeat(item) => {
+                    let times = if deep { 0 } else { self.next(3) };
+                    for _ in 0..times {
+                        self.derive(item, out);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_parser_accepts_derived_programs() {
+        let rules = rules();
+        for seed in 0..300 {
+            let mut deriver = Deriver {
+                rules: &rules,
+                seed,
+                depth: 0,
+            };
+            let mut source = String::new();
+            deriver.derive(&Item::Rule("program"), &mut source);
+            let tokens = Lexer::new(&source).tokenize().unwrap();
+            let result = Parser::new(tokens).parse();
+            assert!(result.is_ok(), "{}\n{:?}", source, result.err());
+        }
+    }
+
+    #[test]
+    fn test_precedence_levels_follow_the_parser() {
+        let grammar = ebnf();
+        assert!(grammar.contains("\nexpression           ::= or\n"));
+        assert!(grammar.contains("\nor                   ::= and ( \"||\" and )*\n"));
+        assert!(
+            grammar.contains(
+                "\nproduct              ::= unary ( ( \"*\" | \"/\" | \"%\" ) unary )*\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_railroad() {
+        let html = railroad();
+        assert!(html.contains("<h2 id=\"let_statement\">let_statement</h2>"));
+        assert!(html.contains("<a href=\"#expression\">"));
+        assert!(html.contains(">&lt;=</text>"));
+        assert_eq!(html.matches("<svg").count(), rules().len());
+    }
+}
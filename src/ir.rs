@@ -0,0 +1,131 @@
+//! A structured form of Brainfuck used between code generation and output.
+//!
+//! Runs of `+`/`-` and `<`/`>` are collapsed into single operations and loops
+//! are nested, which makes the program easy to analyze and rewrite. Emitting
+//! and re-parsing a program is lossless apart from comments and run lengths.
+
+use crate::error::TranspilerError;
+use alloc::{format, string::String, vec::Vec};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    /// Add to the current cell, modulo 256.
+    Add(u8),
+    /// Move the pointer by this many cells (negative is left).
+    Move(isize),
+    /// Set the current cell to zero (`[-]`).
+    Clear,
+    Output,
+    Input,
+    Loop(Vec<Op>),
+}
+
+/// Parses Brainfuck source, ignoring non-command characters.
+pub fn parse(code: &str) -> Result<Vec<Op>, TranspilerError> {
+    let mut stack: Vec<(usize, Vec<Op>)> = Vec::new();
+    let mut current = Vec::new();
+
+    for (position, ch) in code.char_indices() {
+        match ch {
+            '+' => current.push(Op::Add(1)),
+            '-' => current.push(Op::Add(255)),
+            '>' => current.push(Op::Move(1)),
+            '<' => current.push(Op::Move(-1)),
+            '.' => current.push(Op::Output),
+            ',' => current.push(Op::Input),
+            '[' => stack.push((position, core::mem::take(&mut current))),
+            ']' => {
+                let (_, outer) = stack.pop().ok_or_else(|| {
+                    TranspilerError::new(format!("Unmatched ']' at position {}", position))
+                })?;
+                let body = core::mem::replace(&mut current, outer);
+                current.push(Op::Loop(body));
+            }
+            _ => {}
+        }
+    }
+
+    match stack.pop() {
+        Some((position, _)) => Err(TranspilerError::new(format!(
+            "Unmatched '[' at position {}",
+            position
+        ))),
+        None => Ok(current),
+    }
+}
+
+/// Renders the program as plain Brainfuck.
+pub fn emit(ops: &[Op]) -> String {
+    let mut out = String::new();
+    emit_into(ops, &mut out);
+    out
+}
+
+fn emit_into(ops: &[Op], out: &mut String) {
+    for op in ops {
+        match op {
+            // Wrapping makes `+` * n and `-` * (256 - n) equivalent; use the
+            // shorter one.
+            Op::Add(n) if *n <= 128 => out.push_str(&"+".repeat(*n as usize)),
+            Op::Add(n) => out.push_str(&"-".repeat(256 - *n as usize)),
+            Op::Move(n) if *n >= 0 => out.push_str(&">".repeat(*n as usize)),
+            Op::Move(n) => out.push_str(&"<".repeat(n.unsigned_abs())),
+            Op::Clear => out.push_str("[-]"),
+            Op::Output => out.push('.'),
+            Op::Input => out.push(','),
+            Op::Loop(body) => {
+                out.push('[');
+                emit_into(body, out);
+                out.push(']');
+            }
+        }
+    }
+}
+
+/// Number of Brainfuck commands the program emits to.
+pub fn size(ops: &[Op]) -> usize {
+    ops.iter()
+        .map(|op| match op {
+            Op::Add(n) => (*n as usize).min(256 - *n as usize),
+            Op::Move(n) => n.unsigned_abs(),
+            Op::Clear => 3,
+            Op::Output | Op::Input => 1,
+            Op::Loop(body) => 2 + size(body),
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_nests_loops() {
+        assert_eq!(
+            parse("+[>-]x.").unwrap(),
+            vec![
+                Op::Add(1),
+                Op::Loop(vec![Op::Move(1), Op::Add(255)]),
+                Op::Output,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unmatched_brackets() {
+        assert!(parse("[").is_err());
+        assert!(parse("]").is_err());
+    }
+
+    #[test]
+    fn test_emit_uses_shorter_direction() {
+        let ops = vec![
+            Op::Add(250),
+            Op::Move(-3),
+            Op::Clear,
+            Op::Loop(vec![Op::Input]),
+        ];
+        assert_eq!(emit(&ops), "------<<<[-][,]");
+        assert_eq!(size(&ops), emit(&ops).len());
+    }
+}
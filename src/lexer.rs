@@ -1,12 +1,45 @@
-use crate::ast::Token;
-use crate::error::{TranspilerError, TranspilerResult};
-use std::iter::Peekable;
-use std::str::Chars;
+use crate::ast::{SpannedToken, Token};
+use crate::error::{Diagnostics, TranspilerError, TranspilerResult};
+use crate::span::Span;
+use crate::trace;
+use alloc::{format, string::String, vec::Vec};
+use core::iter::Peekable;
+use core::str::Chars;
+
+/// Words that lex as keywords rather than identifiers.
+pub const KEYWORDS: &[&str] = &[
+    "let",
+    "mut",
+    "print",
+    "println",
+    "print_num",
+    "input",
+    "if",
+    "else",
+    "match",
+    "while",
+    "loop",
+    "break",
+    "continue",
+    "for",
+    "in",
+    "fn",
+    "return",
+    "true",
+    "false",
+];
+
+/// Rust keywords that still lex as identifiers but are kept free for the
+/// language to grow into.
+pub const RESERVED: &[&str] = &[
+    "as", "const", "enum", "impl", "mod", "static", "struct", "trait", "type", "use",
+];
 
 pub struct Lexer<'a> {
     input: Peekable<Chars<'a>>,
     position: usize,
     current_char: Option<char>,
+    diagnostics: Diagnostics,
 }
 
 impl<'a> Lexer<'a> {
@@ -14,30 +47,56 @@ impl<'a> Lexer<'a> {
         let mut chars = input.chars().peekable();
         let current_char = chars.next();
 
-        Self {
+        let mut lexer = Self {
             input: chars,
             position: 0,
             current_char,
+            diagnostics: Diagnostics::new(),
+        };
+        // Editors on Windows often start files with a byte order mark. Skip
+        // it without rebasing, so spans still index into `input`.
+        if lexer.current_char == Some('\u{feff}') {
+            lexer.advance();
         }
+        lexer
     }
 
-    pub fn tokenize(&mut self) -> TranspilerResult<Vec<Token>> {
-        let mut tokens = Vec::new();
+    pub fn tokenize(&mut self) -> Result<Vec<SpannedToken>, Diagnostics> {
+        self.tokenize_into()
+    }
 
-        while let Some(token) = self.next_token()? {
-            if matches!(token, Token::Eof) {
-                break;
+    /// Like [`tokenize`](Self::tokenize), pushing each token into `T` as it
+    /// is read, e.g. straight into [`Tokens`](crate::tokens::Tokens).
+    pub fn tokenize_into<T: Default + Extend<SpannedToken>>(&mut self) -> Result<T, Diagnostics> {
+        let _span = trace::span!("lex");
+        let mut tokens = T::default();
+
+        loop {
+            self.skip_whitespace_and_comments();
+            let start = self.position;
+            match self.next_token() {
+                Ok(Some(Token::Eof)) | Ok(None) => break,
+                Ok(Some(token)) => tokens.extend(Some(SpannedToken::new(
+                    token,
+                    Span::new(start, self.position),
+                ))),
+                // Skip the offending character so the rest of the file is
+                // still checked.
+                Err(error) => {
+                    self.diagnostics.push(error);
+                    self.advance();
+                }
             }
-            tokens.push(token);
         }
 
-        tokens.push(Token::Eof);
-        Ok(tokens)
+        tokens.extend(Some(SpannedToken::new(
+            Token::Eof,
+            Span::new(self.position, self.position),
+        )));
+        core::mem::take(&mut self.diagnostics).into_result(tokens)
     }
 
     fn next_token(&mut self) -> TranspilerResult<Option<Token>> {
-        self.skip_whitespace();
-
         match self.current_char {
             None => Ok(Some(Token::Eof)),
             Some(ch) => {
@@ -50,7 +109,12 @@ impl<'a> Lexer<'a> {
                     }
                     '-' => {
                         self.advance();
-                        Token::Minus
+                        if self.current_char == Some('>') {
+                            self.advance();
+                            Token::Arrow
+                        } else {
+                            Token::Minus
+                        }
                     }
                     '*' => {
                         self.advance();
@@ -60,14 +124,14 @@ impl<'a> Lexer<'a> {
                         self.advance();
                         Token::Divide
                     }
-                    '<' => {
+                    '&' => self.handle_doubled('&', Token::And)?,
+                    '|' => self.handle_doubled('|', Token::Or)?,
+                    '%' => {
                         self.advance();
-                        Token::Less
-                    }
-                    '>' => {
-                        self.advance();
-                        Token::Greater
+                        Token::Percent
                     }
+                    '<' => self.handle_angle(Token::Less, Token::LessEqual),
+                    '>' => self.handle_angle(Token::Greater, Token::GreaterEqual),
                     ';' => {
                         self.advance();
                         Token::Semicolon
@@ -88,12 +152,33 @@ impl<'a> Lexer<'a> {
                         self.advance();
                         Token::RightParen
                     }
-                    c if c.is_ascii_digit() => self.read_number()?,
+                    ',' => {
+                        self.advance();
+                        Token::Comma
+                    }
+                    ':' => {
+                        self.advance();
+                        Token::Colon
+                    }
+                    '#' => {
+                        self.advance();
+                        Token::Hash
+                    }
+                    '[' => {
+                        self.advance();
+                        Token::LeftBracket
+                    }
+                    ']' => {
+                        self.advance();
+                        Token::RightBracket
+                    }
+                    '"' => self.read_string(),
+                    c if c.is_ascii_digit() => self.read_number(),
                     c if c.is_alphabetic() || c == '_' => self.read_identifier(),
                     c => {
-                        return Err(TranspilerError::with_position(
+                        return Err(TranspilerError::with_span(
                             format!("Unexpected character: '{}'", c),
-                            self.position,
+                            Span::new(self.position, self.position + c.len_utf8()),
                         ));
                     }
                 };
@@ -107,6 +192,9 @@ impl<'a> Lexer<'a> {
         if self.current_char == Some('=') {
             self.advance();
             Token::Equal
+        } else if self.current_char == Some('>') {
+            self.advance();
+            Token::FatArrow
         } else {
             Token::Assign
         }
@@ -122,7 +210,34 @@ impl<'a> Lexer<'a> {
         }
     }
 
-    fn read_number(&mut self) -> TranspilerResult<Token> {
+    /// `<` or `>`, or `<=` or `>=` if followed by `=`.
+    fn handle_angle(&mut self, strict: Token, or_equal: Token) -> Token {
+        self.advance();
+        if self.current_char == Some('=') {
+            self.advance();
+            or_equal
+        } else {
+            strict
+        }
+    }
+
+    /// `&&` or `||`. There are no bitwise operators, so `ch` on its own is
+    /// an error.
+    fn handle_doubled(&mut self, ch: char, token: Token) -> TranspilerResult<Token> {
+        if self.input.peek() != Some(&ch) {
+            return Err(TranspilerError::with_span(
+                format!("Unexpected character: '{}'", ch),
+                Span::new(self.position, self.position + 1),
+            )
+            .with_note(format!("help: use `{}{}` for a logical operator", ch, ch)));
+        }
+        self.advance();
+        self.advance();
+        Ok(token)
+    }
+
+    fn read_number(&mut self) -> Token {
+        let start = self.position;
         let mut number = String::new();
 
         while let Some(ch) = self.current_char {
@@ -134,9 +249,115 @@ impl<'a> Lexer<'a> {
             }
         }
 
-        number.parse::<i32>().map(Token::Number).map_err(|_| {
-            TranspilerError::with_position(format!("Invalid number: {}", number), self.position)
-        })
+        // A malformed literal still has well-defined boundaries, so record the
+        // error and keep lexing with a placeholder value.
+        let span = Span::new(start, self.position);
+        number
+            .parse::<i32>()
+            .map(Token::Number)
+            .unwrap_or_else(|_| {
+                self.diagnostics.push(TranspilerError::with_span(
+                    format!("Invalid number: {}", number),
+                    span,
+                ));
+                Token::Number(0)
+            })
+    }
+
+    /// A `"..."` literal with the escapes `\n`, `\r`, `\t`, `\0`, `\\`, `\"`
+    /// and `\x7f`-style hex, so `"\x1b[31m"` turns terminal output red. Like
+    /// numbers, malformed strings are reported and lexing goes on.
+    fn read_string(&mut self) -> Token {
+        let start = self.position;
+        self.advance();
+        let mut text = String::new();
+
+        loop {
+            match self.current_char {
+                None => {
+                    self.diagnostics.push(TranspilerError::with_span(
+                        "Unterminated string",
+                        Span::new(start, self.position),
+                    ));
+                    break;
+                }
+                Some('"') => {
+                    self.advance();
+                    break;
+                }
+                Some('\\') => {
+                    let escape = self.position;
+                    self.advance();
+                    let ch = match self.current_char {
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('0') => '\0',
+                        Some('\\') => '\\',
+                        Some('"') => '"',
+                        Some('x') => match self.read_hex_escape(escape) {
+                            Some(ch) => ch,
+                            None => continue,
+                        },
+                        other => {
+                            let end = self.position + other.map_or(0, char::len_utf8);
+                            self.diagnostics.push(TranspilerError::with_span(
+                                "Unknown escape in string",
+                                Span::new(escape, end),
+                            ));
+                            if other.is_none() {
+                                continue;
+                            }
+                            '\\'
+                        }
+                    };
+                    text.push(ch);
+                    self.advance();
+                }
+                Some(ch) => {
+                    text.push(ch);
+                    self.advance();
+                }
+            }
+        }
+        Token::Str(text)
+    }
+
+    /// The character a `\x` escape stands for, with the `x` current. The
+    /// last digit is left current; if the digits are missing, the character
+    /// after the bad one is, and the error has been reported. As in Rust,
+    /// only ASCII can be written this way.
+    fn read_hex_escape(&mut self, escape: usize) -> Option<char> {
+        let mut value = 0;
+        for _ in 0..2 {
+            match self.input.peek().and_then(|ch| ch.to_digit(16)) {
+                Some(digit) => {
+                    self.advance();
+                    value = value * 16 + digit;
+                }
+                None => {
+                    self.advance();
+                    self.diagnostics.push(
+                        TranspilerError::with_span(
+                            "Invalid hex escape in string",
+                            Span::new(escape, self.position),
+                        )
+                        .with_note("help: write two hex digits, as in `\\x1b`"),
+                    );
+                    return None;
+                }
+            }
+        }
+        if value > 0x7f {
+            self.diagnostics.push(
+                TranspilerError::with_span(
+                    "Out of range hex escape in string",
+                    Span::new(escape, self.position + 1),
+                )
+                .with_note("help: `\\x` escapes go up to `\\x7f`"),
+            );
+        }
+        char::from_u32(value)
     }
 
     fn read_identifier(&mut self) -> Token {
@@ -155,25 +376,47 @@ impl<'a> Lexer<'a> {
             "let" => Token::Let,
             "mut" => Token::Mut,
             "print" => Token::Print,
+            "println" => Token::Println,
+            "print_num" => Token::PrintNum,
+            "input" => Token::Input,
             "if" => Token::If,
+            "else" => Token::Else,
+            "match" => Token::Match,
             "while" => Token::While,
+            "loop" => Token::Loop,
+            "break" => Token::Break,
+            "continue" => Token::Continue,
+            "for" => Token::For,
+            "in" => Token::In,
+            "fn" => Token::Fn,
+            "return" => Token::Return,
+            "true" => Token::True,
+            "false" => Token::False,
             _ => Token::Identifier(identifier),
         }
     }
 
-    fn skip_whitespace(&mut self) {
-        while let Some(ch) = self.current_char {
-            if ch.is_whitespace() {
-                self.advance();
-            } else {
-                break;
+    /// Skips whitespace and `//` comments, which run to the end of the
+    /// line.
+    fn skip_whitespace_and_comments(&mut self) {
+        loop {
+            match self.current_char {
+                Some(ch) if ch.is_whitespace() => self.advance(),
+                Some('/') if self.input.peek() == Some(&'/') => {
+                    while self.current_char.is_some_and(|ch| ch != '\n') {
+                        self.advance();
+                    }
+                }
+                _ => break,
             }
         }
     }
 
     fn advance(&mut self) {
+        if let Some(ch) = self.current_char {
+            self.position += ch.len_utf8();
+        }
         self.current_char = self.input.next();
-        self.position += 1;
     }
 }
 
@@ -184,7 +427,12 @@ mod tests {
     #[test]
     fn test_tokenize_simple() {
         let mut lexer = Lexer::new("let x = 42;");
-        let tokens = lexer.tokenize().unwrap();
+        let tokens: Vec<Token> = lexer
+            .tokenize()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token)
+            .collect();
 
         assert_eq!(
             tokens,
@@ -201,8 +449,13 @@ mod tests {
 
     #[test]
     fn test_tokenize_operators() {
-        let mut lexer = Lexer::new("== != < > + - * /");
-        let tokens = lexer.tokenize().unwrap();
+        let mut lexer = Lexer::new("== != < > <= >= <> && || + - * / % => -> :");
+        let tokens: Vec<Token> = lexer
+            .tokenize()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token)
+            .collect();
 
         assert_eq!(
             tokens,
@@ -211,12 +464,144 @@ mod tests {
                 Token::NotEqual,
                 Token::Less,
                 Token::Greater,
+                Token::LessEqual,
+                Token::GreaterEqual,
+                Token::Less,
+                Token::Greater,
+                Token::And,
+                Token::Or,
                 Token::Plus,
                 Token::Minus,
                 Token::Multiply,
                 Token::Divide,
+                Token::Percent,
+                Token::FatArrow,
+                Token::Arrow,
+                Token::Colon,
+                Token::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_skips_comments() {
+        let tokens: Vec<Token> =
+            Lexer::new("// header\nprint(8 / 2); // halved\n//! expect: \\x04")
+                .tokenize()
+                .unwrap()
+                .into_iter()
+                .map(|t| t.token)
+                .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Print,
+                Token::LeftParen,
+                Token::Number(8),
+                Token::Divide,
+                Token::Number(2),
+                Token::RightParen,
+                Token::Semicolon,
+                Token::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_reports_every_invalid_number() {
+        let mut lexer = Lexer::new("let a = 99999999999; let b = 88888888888;");
+        let errors = lexer.tokenize().unwrap_err();
+
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn test_tokenize_reports_every_unexpected_character() {
+        let mut lexer = Lexer::new("let a = 1 @ 2;\nprint(a ? $);\nprint(a & a);");
+        let errors = lexer.tokenize().unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|e| e.message.as_str()).collect();
+
+        assert_eq!(
+            messages,
+            vec![
+                "Unexpected character: '@'",
+                "Unexpected character: '?'",
+                "Unexpected character: '$'",
+                "Unexpected character: '&'",
+            ]
+        );
+        assert_eq!(errors.iter().nth(2).unwrap().span, Some(Span::new(25, 26)));
+    }
+
+    #[test]
+    fn test_tokenize_strings() {
+        let tokens: Vec<Token> = Lexer::new(r#"for c in "a\"b\n" {"#)
+            .tokenize()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::For,
+                Token::Identifier("c".to_string()),
+                Token::In,
+                Token::Str("a\"b\n".to_string()),
+                Token::LeftBrace,
                 Token::Eof,
             ]
         );
+
+        let tokens: Vec<Token> = Lexer::new(r#""\x1b[1mA\x7F""#)
+            .tokenize()
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token)
+            .collect();
+        assert_eq!(tokens[0], Token::Str("\x1b[1mA\x7f".to_string()));
+
+        let errors = Lexer::new(r#""\x8f" "\xg" "\x"#).tokenize().unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Out of range hex escape in string",
+                "Invalid hex escape in string",
+                "Invalid hex escape in string",
+                "Unterminated string"
+            ]
+        );
+        assert_eq!(errors.iter().next().unwrap().span, Some(Span::new(1, 5)));
+
+        let errors = Lexer::new(r#"let s = "\q"; let t = "open"#)
+            .tokenize()
+            .unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec!["Unknown escape in string", "Unterminated string"]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_windows_source() {
+        let mut lexer = Lexer::new("\u{feff}let x = 1;\r\n\tprint(x);\r\n");
+        let tokens = lexer.tokenize().unwrap();
+
+        assert_eq!(tokens[0].token, Token::Let);
+        assert_eq!(tokens[0].span, Span::new(3, 6));
+        assert_eq!(tokens[5].token, Token::Print);
+        assert_eq!(tokens.len(), 11);
+    }
+
+    #[test]
+    fn test_token_spans_are_byte_ranges() {
+        let mut lexer = Lexer::new("let é = 10;");
+        let tokens = lexer.tokenize().unwrap();
+
+        assert_eq!(tokens[1].span, Span::new(4, 6));
+        assert_eq!(tokens[3].span, Span::new(9, 11));
+        assert_eq!(tokens.last().unwrap().span, Span::new(12, 12));
     }
 }
@@ -0,0 +1,58 @@
+//! RustedBrains: a transpiler from a small subset of Rust to Brainfuck.
+//!
+//! The pipeline is [`lexer`] → [`parser`] → [`analysis`] → [`codegen`], with
+//! [`bf`] available to run the generated programs and [`eval`] defining what
+//! they should do. [`verify`] proves generated programs stay on the tape, and
+//! [`ranges`] works out which values each variable can hold.
+//! Parsing and code generation go through the [`frontend::Frontend`] and
+//! [`backend::CodeGenerator`] traits so other source languages and targets
+//! can be added.
+//!
+//! Optional parts sit behind cargo features: `interpreter` ([`bf`]), `cli`
+//! (the command-line tool), `ffi` (C bindings), `python`, `node`, `tracing`,
+//! `arbitrary` and `rust-frontend`. The rest works without the standard
+//! library; with `default-features = false` the crate is `no_std` + `alloc`,
+//! e.g. for WASM or embedded hosts.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+pub mod analysis;
+pub mod ast;
+pub mod backend;
+#[cfg(feature = "interpreter")]
+pub mod bf;
+pub mod bytecode;
+pub mod charset;
+pub mod codegen;
+pub mod embed;
+pub mod error;
+pub mod eval;
+pub mod expect;
+pub mod explain;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod frontend;
+pub mod grammar;
+pub mod ir;
+pub mod lexer;
+pub mod link;
+#[cfg(feature = "node")]
+pub mod node;
+pub mod optimizer;
+pub mod parser;
+pub mod pipeline;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod ranges;
+pub mod render;
+pub mod rle;
+pub mod span;
+pub mod suggest;
+pub mod symbol_table;
+pub mod tokens;
+mod trace;
+pub mod verify;
+#[cfg(feature = "interpreter")]
+pub mod watch;
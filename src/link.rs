@@ -0,0 +1,117 @@
+//! Joining separately compiled Brainfuck programs into one that runs them
+//! in turn.
+//!
+//! Each program is given cells of its own: it starts on the first cell past
+//! every cell the programs before it can reach, so it finds the fresh, zeroed
+//! tape it was compiled for. The cells a program uses and where it leaves
+//! the pointer are read off its code with [`verify`], which is why every
+//! program has to stay within a bounded part of the tape.
+
+use crate::error::{TranspilerError, TranspilerResult};
+use crate::ir::Op;
+use crate::verify;
+use alloc::{format, vec::Vec};
+
+/// `programs`, each named for error messages, run one after the other.
+///
+/// ```
+/// use rusted_brains::{ir, link::link};
+///
+/// let first = ir::parse("++++++++[>++++++++<-]>+.").unwrap();
+/// let second = ir::parse(">++++++++[<++++++++>-]<++.").unwrap();
+/// let linked = link([("a.bf", first), ("b.bf", second)]).unwrap();
+/// // The second program's cells start at cell 2, past the first's.
+/// assert!(ir::emit(&linked).starts_with("++++++++[>++++++++<-]>+.>>++++++++["));
+/// ```
+pub fn link<'a>(
+    programs: impl IntoIterator<Item = (&'a str, Vec<Op>)>,
+) -> TranspilerResult<Vec<Op>> {
+    let mut linked = Vec::new();
+    // First cell no program so far can reach, and where the pointer is.
+    let mut base = 0;
+    let mut pointer = 0;
+    for (name, mut ops) in programs {
+        verify::check_pointer(&ops).map_err(|e| in_program(name, e))?;
+        let cells = verify::tape_cells(&ops).ok_or_else(|| {
+            TranspilerError::new(format!("Cannot tell which cells '{}' uses", name))
+                .with_note("a loop may move the pointer further right on every pass")
+        })?;
+        let exit = verify::exit_cell(&ops).ok_or_else(|| {
+            TranspilerError::new(format!("Cannot tell where '{}' leaves the pointer", name))
+                .with_note("a loop moves the pointer by an amount that depends on the data")
+        })?;
+
+        // Compiled programs open with a comment in a loop that never runs
+        // on a zero cell, which every program here starts on.
+        let start = ops
+            .iter()
+            .take_while(|op| matches!(op, Op::Loop(_)))
+            .count();
+        ops.drain(..start);
+
+        if base != pointer {
+            linked.push(Op::Move(base as isize - pointer as isize));
+        }
+        linked.extend(ops);
+        pointer = base + exit;
+        base += cells;
+    }
+    Ok(linked)
+}
+
+fn in_program(name: &str, error: TranspilerError) -> TranspilerError {
+    TranspilerError {
+        message: format!("In '{}': {}", name, error.message),
+        ..error
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir;
+
+    fn link_code(programs: &[&str]) -> TranspilerResult<alloc::string::String> {
+        let programs = programs
+            .iter()
+            .enumerate()
+            .map(|(i, code)| (["a.bf", "b.bf", "c.bf"][i], ir::parse(code).unwrap()));
+        link(programs).map(|ops| ir::emit(&ops))
+    }
+
+    #[test]
+    fn test_programs_get_cells_of_their_own() {
+        assert_eq!(
+            link_code(&["+>+", "[comment]+", ">>+<"]).unwrap(),
+            "+>+>+>>>+<"
+        );
+        // Ending left of where it started still moves past every cell used.
+        assert_eq!(link_code(&[">>+<<", "+"]).unwrap(), ">>+<<>>>+");
+    }
+
+    #[test]
+    fn test_unbounded_programs_are_rejected() {
+        let error = link_code(&["+[>+]", "+"]).unwrap_err();
+        assert_eq!(error.message, "Cannot tell which cells 'a.bf' uses");
+        let error = link_code(&["+", ">+[<]"]).unwrap_err();
+        assert!(error.message.starts_with("In 'b.bf': Loop at position 2"));
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_linked_programs_run_in_turn() {
+        use crate::pipeline::compile;
+
+        let first = compile("let x = 72; print(x); let y = x + 1; print(y);").unwrap();
+        let second = compile("let z = 33; print(z);").unwrap();
+        let linked = link([
+            ("first", ir::parse(&first.code).unwrap()),
+            ("second", ir::parse(&second.code).unwrap()),
+        ])
+        .unwrap();
+        let run = crate::bf::Interpreter::new(ir::emit(&linked))
+            .run()
+            .unwrap();
+        assert_eq!(run.output, b"HI!");
+    }
+}
@@ -1,60 +1,1389 @@
-mod ast;
-mod codegen;
-mod error;
-mod lexer;
-mod parser;
-
-use codegen::BrainfuckGenerator;
-use error::TranspilerResult;
-use lexer::Lexer;
-use parser::Parser;
+mod batch;
+mod build_db;
+mod cli;
+mod conformance;
+mod examples;
+mod report;
+mod sha256;
+
+use cli::{Command, Emit, GrammarFormat, Options, Session};
+use report::{FileReport, OutputStats, Stats, Timings};
+use rusted_brains::analysis::Analyzer;
+use rusted_brains::ast::{self, Program, Stmt};
+use rusted_brains::backend::{self, CodeGenerator, rust::RustGenerator};
+use rusted_brains::bf::{Interpreter, Overflow, RuntimeError, Transcript, Vm};
+use rusted_brains::bytecode;
+use rusted_brains::charset::Charset;
+use rusted_brains::codegen::{BrainfuckGenerator, CallStrategy};
+use rusted_brains::embed;
+use rusted_brains::error::{Diagnostics, TranspilerError};
+use rusted_brains::eval::Evaluator;
+use rusted_brains::expect;
+use rusted_brains::explain;
+use rusted_brains::frontend::{self, Frontend};
+use rusted_brains::grammar;
+use rusted_brains::ir::{self, Op};
+use rusted_brains::link;
+use rusted_brains::optimizer::PassManager;
+use rusted_brains::parser::Dialect;
+use rusted_brains::pipeline;
+use rusted_brains::render;
+use rusted_brains::rle;
+use rusted_brains::verify;
+use rusted_brains::watch::Watcher;
+use std::collections::BTreeMap;
 use std::env;
+use std::fmt;
 use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::thread;
+use std::time::Instant;
 
 fn main() {
-    if let Err(e) = run() {
-        eprintln!("Error: {}", e);
+    // Diagnostics for the transpiler itself, e.g. `RUST_LOG=debug`.
+    #[cfg(feature = "tracing")]
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_writer(std::io::stderr)
+        .init();
+
+    let options = match Command::parse(env::args().skip(1)) {
+        Ok(Command::Compile(options)) => *options,
+        Ok(Command::Example { name, output }) => {
+            if !example(name.as_deref(), output.as_deref()) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        Ok(Command::Run {
+            program,
+            session,
+            trap_overflow,
+        }) => {
+            if !execute(&program, &session, trap_overflow) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        Ok(Command::Link { programs, output }) => {
+            if !link(&programs, &output) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        Ok(Command::Grammar(format)) => {
+            match format {
+                GrammarFormat::Ebnf => print!("{}", grammar::ebnf()),
+                GrammarFormat::Railroad => print!("{}", grammar::railroad()),
+            }
+            return;
+        }
+        Ok(Command::Conformance { programs }) => {
+            if !conformance::conformance(&programs) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        Ok(Command::Verify {
+            programs,
+            trap_overflow,
+        }) => {
+            // Check every program even after one fails.
+            let results: Vec<bool> = programs
+                .iter()
+                .map(|program| verify(program, trap_overflow))
+                .collect();
+            if !results.into_iter().all(|ok| ok) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        Err(message) => {
+            eprintln!("Error: {}", message);
+            std::process::exit(1);
+        }
+    };
+
+    let ok = if options.eval {
+        // Evaluate every file even after one fails.
+        let results: Vec<bool> = options
+            .inputs
+            .iter()
+            .map(|filename| evaluate(&options, filename))
+            .collect();
+        results.into_iter().all(|ok| ok)
+    } else if !options.watch.is_empty() {
+        watch(&options, &options.inputs[0])
+    } else if options.inputs.len() == 1 {
+        run(&options, &options.inputs[0])
+    } else {
+        run_batch(&options)
+    };
+    if !ok {
         std::process::exit(1);
     }
 }
 
-fn run() -> TranspilerResult<()> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        return Err("Usage: rust2bf <filename>\nExample: rust2bf example.rs".into());
+/// Lists the built-in examples, or prints the one called `name` or writes
+/// it to `output`.
+fn example(name: Option<&str>, output: Option<&str>) -> bool {
+    let Some(name) = name else {
+        for (name, _) in examples::EXAMPLES {
+            println!("{}", name);
+        }
+        return true;
+    };
+    let Some(source) = examples::find(name) else {
+        eprintln!(
+            "Error: No example named '{}' (available: {})",
+            name,
+            examples::names()
+        );
+        return false;
+    };
+
+    match output {
+        None => {
+            print!("{}", source);
+            true
+        }
+        Some(path) => match fs::write(path, source) {
+            Ok(()) => {
+                println!("Wrote {} to {}", name, path);
+                true
+            }
+            Err(e) => {
+                eprintln!("Error: Could not write to '{}': {}", path, e);
+                false
+            }
+        },
+    }
+}
+
+/// What a cell wrapping around does under `--trap-overflow` or without it.
+fn overflow(trap_overflow: bool) -> Overflow {
+    if trap_overflow {
+        Overflow::Trap
+    } else {
+        Overflow::Wrap
+    }
+}
+
+/// Runs a Brainfuck program, or one saved with `--emit bfc` on the bytecode
+/// VM, reading its input from stdin, if it has any `,`, or, when replaying,
+/// from the saved session. With `trap_overflow`,
+/// the program must have been compiled with `--checked-arithmetic`, and a
+/// cell wrapping around is reported where the source's arithmetic did.
+fn execute(program: &str, session: &Session, trap_overflow: bool) -> bool {
+    let code = match fs::read(program) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: Could not read file '{}': {}", program, e);
+            return false;
+        }
+    };
+    let decoded = if !code.starts_with(bytecode::MAGIC) {
+        None
+    } else if trap_overflow {
+        eprintln!(
+            "Error: '{}' is bytecode, which --trap-overflow cannot check; run the \
+             Brainfuck compiled with --checked-arithmetic instead",
+            program
+        );
+        return false;
+    } else {
+        match bytecode::decode(&code) {
+            Ok(ops) => Some(ops),
+            Err(e) => {
+                eprintln!("Error: In '{}': {}", program, e.message);
+                return false;
+            }
+        }
+    };
+    let reads_input = match &decoded {
+        Some(ops) => ir::emit(ops).contains(','),
+        None => code.contains(&b','),
+    };
+    if trap_overflow && !compiled_checked(&code) {
+        eprintln!(
+            "Error: '{}' was not compiled with --checked-arithmetic; other code lets \
+             cells wrap around on purpose, so --trap-overflow would stop it anyway",
+            program
+        );
+        return false;
+    }
+    let recorded = match session {
+        Session::Replay(path) => match fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|json| Transcript::from_json(&json).map_err(|e| e.message))
+        {
+            Ok(transcript) => Some(transcript),
+            Err(e) => {
+                eprintln!("Error: Could not load session '{}': {}", path, e);
+                return false;
+            }
+        },
+        _ => None,
+    };
+    let input = match &recorded {
+        Some(transcript) => transcript.input.clone(),
+        // A program without `,` never reads, so it need not wait for stdin.
+        None if !reads_input => Vec::new(),
+        None => {
+            let mut input = Vec::new();
+            if let Err(e) = io::Read::read_to_end(&mut io::stdin(), &mut input) {
+                eprintln!("Error: Could not read input: {}", e);
+                return false;
+            }
+            input
+        }
+    };
+
+    let result = match &decoded {
+        Some(ops) => Vm::new(ops).with_input(input.clone()).run(),
+        None => Interpreter::new(code.clone())
+            .with_input(input.clone())
+            .with_overflow(overflow(trap_overflow))
+            .run(),
+    };
+    let execution = match result {
+        Ok(execution) => execution,
+        Err(RuntimeError::CellOverflow { position }) if trap_overflow => {
+            match overflow_source(program, &code, position) {
+                Some(report) => eprintln!("{}", report),
+                None => eprintln!(
+                    "Error: Cell value wrapped around at position {}; the source '{}' was \
+                     compiled from was not found, so the line is unknown",
+                    position, program
+                ),
+            }
+            return false;
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return false;
+        }
+    };
+    let mut stdout = io::stdout();
+    if stdout
+        .write_all(&execution.output)
+        .and_then(|_| stdout.flush())
+        .is_err()
+    {
+        return false;
     }
 
-    let filename = &args[1];
-    let contents = fs::read_to_string(filename)
-        .map_err(|e| format!("Could not read file '{}': {}", filename, e))?;
+    match session {
+        Session::Live => true,
+        Session::Record(path) => {
+            let transcript = Transcript {
+                input,
+                output: execution.output,
+            };
+            match fs::write(path, transcript.to_json()) {
+                Ok(()) => true,
+                Err(e) => {
+                    eprintln!("Error: Could not write to '{}': {}", path, e);
+                    false
+                }
+            }
+        }
+        Session::Replay(path) => {
+            let expected = recorded
+                .map(|transcript| transcript.output)
+                .unwrap_or_default();
+            match expected
+                .iter()
+                .zip(&execution.output)
+                .position(|(a, b)| a != b)
+            {
+                None if expected.len() == execution.output.len() => true,
+                differs => {
+                    eprintln!(
+                        "Error: Output differs from the session recorded in '{}' at byte {}",
+                        path,
+                        differs.unwrap_or(expected.len().min(execution.output.len()))
+                    );
+                    false
+                }
+            }
+        }
+    }
+}
+
+/// Joins compiled Brainfuck programs into `output`, which runs them in the
+/// order given.
+fn link(programs: &[String], output: &str) -> bool {
+    let mut parsed = Vec::new();
+    for program in programs {
+        let ops = fs::read_to_string(program)
+            .map_err(|e| format!("Could not read file '{}': {}", program, e))
+            .and_then(|code| ir::parse(&code).map_err(|e| format!("In '{}': {}", program, e)));
+        match ops {
+            Ok(ops) => parsed.push((program.as_str(), ops)),
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return false;
+            }
+        }
+    }
+    let linked = match link::link(parsed) {
+        Ok(linked) => linked,
+        Err(e) => {
+            eprintln!("{}", render::render_file(output, "", &e));
+            return false;
+        }
+    };
+
+    let header = BrainfuckGenerator::new().comment(&format!(
+        "Linked by rust2bf {} from {}",
+        env!("CARGO_PKG_VERSION"),
+        programs.join(" ")
+    ));
+    let code = header.unwrap_or_default() + &ir::emit(&linked);
+    match fs::write(output, code) {
+        Ok(()) => {
+            println!("Output saved to: {}", output);
+            true
+        }
+        Err(e) => {
+            eprintln!("Error: Could not write to '{}': {}", output, e);
+            false
+        }
+    }
+}
+
+/// Whether `code` starts with the comment `--checked-arithmetic` leaves.
+fn compiled_checked(code: &[u8]) -> bool {
+    let header = code.split(|&byte| byte == b'\n').next().unwrap_or_default();
+    header.starts_with(b"[Generated by rust2bf ")
+        && header
+            .split(|&byte| byte == b' ' || byte == b']')
+            .any(|word| word == b"--checked-arithmetic")
+}
+
+/// The statement whose arithmetic wrapped a cell at byte `position` of
+/// `code`, compiled with `--checked-arithmetic` from `program`'s source.
+/// The comment at its start gives the options but names no file, so the
+/// files [`output_path`] could have named `program` after are compiled
+/// until one gives back the same code.
+fn overflow_source(program: &str, code: &[u8], position: usize) -> Option<String> {
+    let code = std::str::from_utf8(code).ok()?;
+    let (header, body) = code.split_once('\n')?;
+    let (_, flags) = header.trim_end_matches(']').split_once(" with ")?;
+    let position = position.checked_sub(header.len() + 1)?;
+    let stem = program.strip_suffix(".bf")?;
+    for filename in [format!("{}.rs", stem), stem.to_string()] {
+        let Ok(contents) = fs::read_to_string(&filename) else {
+            continue;
+        };
+        let args = flags
+            .split_whitespace()
+            .map(str::to_string)
+            .chain([filename.clone()]);
+        let Ok(options) = Options::parse(args) else {
+            continue;
+        };
+        let Ok(mut ast) = create_frontend(&options).and_then(|frontend| frontend.parse(&contents))
+        else {
+            continue;
+        };
+        eliminate_dead_functions(&options, &mut ast);
+        let mut generator = brainfuck_generator(&options);
+        if generator.generate(&ast).ok().as_deref() != Some(body) {
+            continue;
+        }
+        let error = match generator.source_map().span_at(position) {
+            Some(span) => TranspilerError::with_span("arithmetic wrapped around", span),
+            None => TranspilerError::new("arithmetic wrapped around"),
+        };
+        return Some(render::render_file(&filename, &contents, &error));
+    }
+    None
+}
+
+/// Most Brainfuck commands `verify` runs a program for.
+const VERIFY_MAX_STEPS: u64 = 100_000_000;
+
+/// Compiles `filename` and runs it on what its `//! input:` comments hold,
+/// checking it prints what its `//! expect:` comments say. With
+/// `trap_overflow` it runs the unoptimized code with checked arithmetic
+/// instead, failing at the statement whose arithmetic wraps.
+fn verify(filename: &str, trap_overflow: bool) -> bool {
+    let contents = match fs::read_to_string(filename) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("Error: Could not read file '{}': {}", filename, e);
+            return false;
+        }
+    };
+    let expected = match expect::expected_output(&contents) {
+        Ok(Some(expected)) => expected,
+        Ok(None) => {
+            eprintln!(
+                "Error: '{}' has no `{}` comments to check",
+                filename,
+                expect::MARKER
+            );
+            return false;
+        }
+        Err(e) => {
+            eprintln!("{}", render::render_file(filename, &contents, &e));
+            return false;
+        }
+    };
+    let input = match expect::input(&contents) {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("{}", render::render_file(filename, &contents, &e));
+            return false;
+        }
+    };
+    let compiled = match pipeline::compile(&contents) {
+        Ok(compiled) => compiled,
+        Err(errors) => {
+            for e in errors.iter() {
+                eprintln!("{}", render::render_file(filename, &contents, e));
+            }
+            return false;
+        }
+    };
+    let mut generator = BrainfuckGenerator::new().with_checked_arithmetic();
+    let code = if trap_overflow {
+        // It compiled above, so it parses and generates.
+        let program = frontend::MiniRust.parse(&contents).unwrap_or_default();
+        match generator.generate(&program) {
+            Ok(code) => code,
+            Err(e) => {
+                eprintln!("{}", render::render_file(filename, &contents, &e));
+                return false;
+            }
+        }
+    } else {
+        compiled.code
+    };
+    let output = match Interpreter::new(code)
+        .with_input(input)
+        .with_overflow(overflow(trap_overflow))
+        .with_max_steps(VERIFY_MAX_STEPS)
+        .run()
+    {
+        Ok(execution) => execution.output,
+        Err(RuntimeError::CellOverflow { position }) if trap_overflow => {
+            eprintln!("FAILED {}", filename);
+            let error = match generator.source_map().span_at(position) {
+                Some(span) => TranspilerError::with_span("arithmetic wrapped around", span),
+                None => TranspilerError::new("arithmetic wrapped around"),
+            };
+            eprintln!("{}", render::render_file(filename, &contents, &error));
+            return false;
+        }
+        Err(e) => {
+            eprintln!("FAILED {}: {}", filename, e);
+            return false;
+        }
+    };
+    if output != expected {
+        eprintln!("FAILED {}", filename);
+        eprintln!("  expected: \"{}\"", expect::escape(&expected));
+        eprintln!("  actual:   \"{}\"", expect::escape(&output));
+        return false;
+    }
+    println!("ok {}", filename);
+    true
+}
+
+/// Outputs longer than this are saved but not printed as well.
+const SHOWN_BYTES: usize = 64 * 1024;
+
+/// Compiles a single file, printing every stage along the way.
+fn run(options: &Options, filename: &str) -> bool {
+    let contents = match fs::read_to_string(filename) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("Error: Could not read file '{}': {}", filename, e);
+            return false;
+        }
+    };
 
     println!("=== Source Code ===");
     println!("{}\n", contents);
 
+    let result = compile(options, &contents, Some(filename)).and_then(|compilation| {
+        for warning in &compilation.warnings {
+            eprintln!("{}", render::render_file(filename, &contents, warning));
+        }
+
+        for output in &compilation.outputs {
+            if output.target == "bf" {
+                println!("=== Generated Brainfuck ===");
+            } else {
+                println!("=== Generated {} ===", output.target);
+            }
+            if output.saved.is_some() {
+                println!("(written to the saved file as it was generated)\n");
+            } else if output.code.len() > SHOWN_BYTES {
+                println!(
+                    "({} bytes, too long to show here; see the saved file)\n",
+                    output.code.len()
+                );
+            } else {
+                println!("{}\n", output.code);
+            }
+            if let Some(bytecode) = &output.bytecode {
+                println!("=== Bytecode ===");
+                println!("{} bytes\n", bytecode.len());
+            }
+        }
+
+        for output in &compilation.outputs {
+            let output_filename = match &output.saved {
+                Some(path) => path.clone(),
+                None => save(filename, output, io::stderr().is_terminal())?,
+            };
+            println!("Output saved to: {}", output_filename);
+        }
+        Ok((compilation.stats, compilation.timings))
+    });
+
+    let reports = match result {
+        Ok((stats, timings)) => {
+            if options.stats {
+                println!("\n=== Statistics ===\n{}", stats);
+            }
+            if options.timings {
+                println!("\n=== Timings ===\n{}", timings);
+            }
+            Some((stats, timings))
+        }
+        Err(errors) => {
+            for e in &errors {
+                eprintln!("{}", render::render_file(filename, &contents, e));
+            }
+            None
+        }
+    };
+    let report = FileReport {
+        input: filename,
+        stats: reports.as_ref().map(|(stats, _)| stats),
+        timings: reports.as_ref().map(|(_, timings)| timings),
+    };
+    save_report(options, &[report]) && reports.is_some()
+}
+
+/// Writes the `--report-json` file, if one was asked for.
+fn save_report(options: &Options, files: &[FileReport]) -> bool {
+    let Some(path) = &options.report_json else {
+        return true;
+    };
+    let json = report::to_json(files, options.stats, options.timings);
+    match fs::write(path, json) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("Error: Could not write to '{}': {}", path, e);
+            false
+        }
+    }
+}
+
+/// Compiles several files in parallel, reporting each one's outcome in the
+/// order given.
+fn run_batch(options: &Options) -> bool {
+    let jobs = options
+        .jobs
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |jobs| jobs.get()));
+
+    let results = batch::parallel_map(&options.inputs, jobs, |filename| {
+        let contents = match fs::read_to_string(filename) {
+            Ok(contents) => contents,
+            Err(e) => {
+                return (
+                    None,
+                    Err(format!("Error: Could not read file '{}': {}", filename, e)),
+                );
+            }
+        };
+        let rendered = |diagnostics: &Diagnostics| -> String {
+            diagnostics
+                .iter()
+                .map(|d| render::render_file(filename, &contents, d))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let saved = compile(options, &contents, None).and_then(|compilation| {
+            let outputs = compilation
+                .outputs
+                .iter()
+                .map(|output| {
+                    Ok(build_db::Output {
+                        target: output.target,
+                        // Outputs are saved in parallel, so progress lines
+                        // would interleave.
+                        path: save(filename, output, false)?,
+                        sha256: sha256::hex_digest(saved_bytes(output)),
+                    })
+                })
+                .collect::<Result<Vec<_>, Diagnostics>>()?;
+            Ok((outputs, compilation))
+        });
+        let result = match saved {
+            Ok((outputs, compilation)) => Ok((
+                outputs,
+                rendered(&compilation.warnings),
+                compilation.stats,
+                compilation.timings,
+            )),
+            Err(errors) => Err(rendered(&errors)),
+        };
+        (Some(sha256::hex_digest(contents.as_bytes())), result)
+    });
+
+    let mut ok = true;
+    for (filename, (_, result)) in options.inputs.iter().zip(&results) {
+        match result {
+            Ok((outputs, warnings, stats, timings)) => {
+                if !warnings.is_empty() {
+                    eprintln!("{}:\n{}", filename, warnings);
+                }
+                let paths: Vec<&str> = outputs.iter().map(|output| output.path.as_str()).collect();
+                println!("{} -> {}", filename, paths.join(", "));
+                if options.stats {
+                    println!("{}\n", stats);
+                }
+                if options.timings {
+                    println!("{}\n", timings);
+                }
+            }
+            Err(errors) => {
+                eprintln!("{}:\n{}", filename, errors);
+                ok = false;
+            }
+        }
+    }
+    let reports: Vec<FileReport> = options
+        .inputs
+        .iter()
+        .zip(&results)
+        .map(|(filename, (_, result))| FileReport {
+            input: filename,
+            stats: result.as_ref().ok().map(|(_, _, stats, _)| stats),
+            timings: result.as_ref().ok().map(|(_, _, _, timings)| timings),
+        })
+        .collect();
+    let ok = save_report(options, &reports) && ok;
+
+    let entries: Vec<build_db::Entry> = options
+        .inputs
+        .iter()
+        .zip(results)
+        .map(|(filename, (input_sha256, result))| {
+            let mut arguments = vec!["rust2bf".to_string()];
+            arguments.extend(options.arguments(filename));
+            build_db::Entry {
+                input: filename,
+                input_sha256,
+                arguments,
+                outputs: result.ok().map(|(outputs, ..)| outputs),
+            }
+        })
+        .collect();
+    save_build_db(options, &entries) && ok
+}
+
+/// Writes the compilation database for a batch build.
+fn save_build_db(options: &Options, entries: &[build_db::Entry]) -> bool {
+    let directory = env::current_dir()
+        .map(|dir| dir.display().to_string())
+        .unwrap_or_default();
+    let json = build_db::to_json(&directory, entries);
+    match fs::write(&options.build_db, json) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("Error: Could not write to '{}': {}", options.build_db, e);
+            false
+        }
+    }
+}
+
+/// Runs a file with the AST evaluator, writing what it prints to stdout.
+fn evaluate(options: &Options, filename: &str) -> bool {
+    let contents = match fs::read_to_string(filename) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("Error: Could not read file '{}': {}", filename, e);
+            return false;
+        }
+    };
+
+    match eval_source(options, &contents) {
+        Ok((output, warnings)) => {
+            for warning in &warnings {
+                eprintln!("{}", render::render_file(filename, &contents, warning));
+            }
+            let mut stdout = io::stdout();
+            stdout
+                .write_all(&output)
+                .and_then(|_| stdout.flush())
+                .is_ok()
+        }
+        Err(errors) => {
+            for e in &errors {
+                eprintln!("{}", render::render_file(filename, &contents, e));
+            }
+            false
+        }
+    }
+}
+
+/// Parses, checks and evaluates `contents`, returning what it printed along
+/// with any warnings. A program that reads input gets stdin.
+fn eval_source(options: &Options, contents: &str) -> Result<(Vec<u8>, Diagnostics), Diagnostics> {
+    let (ast, warnings) = check(options, contents)?;
+    let mut evaluator = Evaluator::new();
+    if let Some(depth) = options.stack_depth {
+        evaluator = evaluator.with_stack_depth(depth);
+    }
+    if ast.iter().any(Stmt::reads_input) {
+        let mut input = Vec::new();
+        io::Read::read_to_end(&mut io::stdin(), &mut input)
+            .map_err(|e| TranspilerError::new(format!("Could not read input: {}", e)))?;
+        evaluator = evaluator.with_input(input);
+    }
+    let evaluation = evaluator.run(&ast).map_err(TranspilerError::from)?;
+    Ok((evaluation.output, warnings))
+}
+
+/// Parses and analyzes `contents`, returning the program along with any
+/// warnings.
+fn check(options: &Options, contents: &str) -> Result<(Program, Diagnostics), Diagnostics> {
+    let ast = create_frontend(options)?.parse(contents)?;
+
+    let mut warnings = analyzer(options).analyze(&ast);
+    if options.deny_warnings {
+        warnings.deny_warnings();
+    }
+    if warnings.has_errors() {
+        return Err(warnings);
+    }
+    Ok((ast, warnings))
+}
+
+/// Runs `filename` as Brainfuck, reporting each statement that changes a
+/// variable named with `--watch`. On a terminal it waits for Enter after
+/// each one.
+fn watch(options: &Options, filename: &str) -> bool {
+    let contents = match fs::read_to_string(filename) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("Error: Could not read file '{}': {}", filename, e);
+            return false;
+        }
+    };
+    let names: Vec<&str> = options.watch.iter().map(String::as_str).collect();
+    let watcher = check(options, &contents)
+        .and_then(|(ast, warnings)| Ok((Watcher::new(&ast, &names)?, warnings)));
+    let mut watcher = match watcher {
+        Ok((watcher, warnings)) => {
+            for warning in &warnings {
+                eprintln!("{}", render::render_file(filename, &contents, warning));
+            }
+            watcher
+        }
+        Err(errors) => {
+            for e in &errors {
+                eprintln!("{}", render::render_file(filename, &contents, e));
+            }
+            return false;
+        }
+    };
+
+    let interactive = io::stdin().is_terminal();
+    let mut stdout = io::stdout();
+    let mut printed = 0;
+    loop {
+        let change = watcher.next_change();
+        // What the program printed before the change comes first.
+        let output = &watcher.output()[printed..];
+        printed += output.len();
+        if stdout
+            .write_all(output)
+            .and_then(|_| stdout.flush())
+            .is_err()
+        {
+            return false;
+        }
+
+        let change = match change {
+            Ok(Some(change)) => change,
+            Ok(None) => return true,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return false;
+            }
+        };
+        // Reports start on a line of their own.
+        if watcher.output().last().is_some_and(|&byte| byte != b'\n') {
+            eprintln!();
+        }
+        eprintln!(
+            "{} (cell {}): {} -> {}",
+            change.name, change.cell, change.old, change.new
+        );
+        if let Some(span) = change.span {
+            eprint!("{}", render::render_span(filename, &contents, span));
+        }
+        if interactive {
+            eprint!("Press Enter to continue, or q to quit: ");
+            let mut line = String::new();
+            if io::stdin().read_line(&mut line).is_err() || line.trim() == "q" {
+                return true;
+            }
+        }
+    }
+}
+
+/// What compiling one file produced.
+struct Compilation {
+    /// One per target, in the order given.
+    outputs: Vec<Output>,
+    warnings: Diagnostics,
+    stats: Stats,
+    timings: Timings,
+}
+
+/// The code generated for one target.
+struct Output {
+    code: String,
+    /// With `--emit bfc`, the serialized program, which is what gets saved.
+    bytecode: Option<Vec<u8>>,
+    target: &'static str,
+    extension: &'static str,
+    /// Where the output was written while it was generated, leaving `code`
+    /// empty.
+    saved: Option<String>,
+}
+
+/// Runs the pipeline on `contents`, parsing and checking it once for all
+/// targets. Given the `input` file, each intermediate stage is printed, and
+/// an output that can be is written next to it as it is generated.
+fn compile(
+    options: &Options,
+    contents: &str,
+    input: Option<&str>,
+) -> Result<Compilation, Diagnostics> {
+    let verbose = input.is_some();
+    #[cfg(feature = "tracing")]
+    let _span = tracing::info_span!("compile", targets = ?options.targets).entered();
+
+    let frontend = create_frontend(options)?;
+    let mut timings = Timings::default();
+    let mut stats = Stats {
+        source_bytes: contents.len(),
+        outputs: Vec::new(),
+    };
+
     // Lexical analysis
-    let mut lexer = Lexer::new(&contents);
-    let tokens = lexer.tokenize()?;
-    println!("=== Tokens ===");
-    println!("{:?}\n", tokens);
+    if verbose && let Some(tokens) = frontend.tokens(contents) {
+        println!("=== Tokens ===");
+        println!("{}\n", tokens);
+    }
 
     // Syntax analysis
-    let mut parser = Parser::new(tokens);
-    let ast = parser.parse()?;
-    println!("=== AST ===");
-    println!("{:#?}\n", ast);
+    let start = Instant::now();
+    let mut ast = frontend.parse(contents)?;
+    timings.record("parse", start.elapsed());
+    if verbose {
+        println!("=== AST ===");
+        println!("{:#?}\n", ast);
+    }
+
+    // Semantic analysis
+    let start = Instant::now();
+    let mut warnings = analyzer(options).analyze(&ast);
+    timings.record("analyze", start.elapsed());
+    if options.deny_warnings {
+        warnings.deny_warnings();
+    }
+    if warnings.has_errors() {
+        return Err(warnings);
+    }
+
+    // Dead-function elimination, after analysis has warned about them
+    let dead_functions = eliminate_dead_functions(options, &mut ast);
+    if verbose && options.opt_report {
+        println!("=== Dead Functions ===");
+        if dead_functions.is_empty() {
+            println!("none\n");
+        } else {
+            println!("{}\n", dead_functions.join(", "));
+        }
+    }
 
     // Code generation
-    let mut generator = BrainfuckGenerator::new();
-    let brainfuck_code = generator.generate(&ast)?;
+    let mut outputs = Vec::new();
+    for target in &options.targets {
+        let mut backend = match target.as_str() {
+            "bf" => Box::new(brainfuck_generator(options)),
+            _ => backend::create(target).ok_or_else(|| {
+                format!(
+                    "Unknown target '{}' (available: {})",
+                    target,
+                    backend::TARGETS.join(", ")
+                )
+            })?,
+        };
+        if let Some(input) = input
+            && backend.target() == "bf"
+            && streams(options)
+        {
+            let path = output_path(input, backend.extension());
+            let header = match options.entry_cell {
+                _ if options.no_metadata => None,
+                Some(_) => None,
+                None => backend.comment(&metadata(options, backend.target())),
+            };
+            let start = Instant::now();
+            let bytes = stream(&mut *backend, &ast, header.as_deref(), &path)?;
+            timings.record(format!("generate {}", backend.target()), start.elapsed());
+            stats.outputs.push(OutputStats {
+                target: backend.target(),
+                bytes,
+                generated_bytes: bytes - header.map_or(0, |header| header.len()),
+                tape_cells: None,
+            });
+            outputs.push(Output {
+                code: String::new(),
+                bytecode: None,
+                target: backend.target(),
+                extension: backend.extension(),
+                saved: Some(path),
+            });
+            continue;
+        }
+        let start = Instant::now();
+        let code = backend.generate(&ast)?;
+        timings.record(format!("generate {}", backend.target()), start.elapsed());
+        let generated_bytes = code.len();
+
+        // Optimization works on Brainfuck, so it only applies to that target.
+        let mut bytecode = None;
+        let mut tape_cells = None;
+        let code = if backend.target() == "bf" {
+            let start = Instant::now();
+            let program = optimize(options, &code, &backend.cell_names(), verbose)?;
+            timings.record("optimize bf", start.elapsed());
+            tape_cells = verify::tape_cells_from(&program, options.entry_cell.unwrap_or(0));
+            check_budgets(options, &program)?;
+            if options.emit == Some(Emit::Bytecode) {
+                bytecode = Some(rusted_brains::bytecode::encode(&program));
+            }
+            let code = ir::emit(&program);
+            match &options.charset {
+                Some(path) => load_charset(path)?.encode(&code),
+                None if options.emit == Some(Emit::RustEmbed) => {
+                    embed::rust_source(&code, options.embed_interpreter)
+                }
+                None if options.emit == Some(Emit::Rle) => {
+                    if verbose {
+                        println!("=== Run-Length Encoding ===");
+                        println!("{}\n", rle::RunReport::of(&code));
+                    }
+                    rle::encode(&code)
+                }
+                None => code,
+            }
+        } else {
+            code
+        };
+        let embedded = backend.target() == "bf" && options.emit == Some(Emit::RustEmbed);
+        let run_length = backend.target() == "bf" && options.emit == Some(Emit::Rle);
+        let metadata = metadata(options, backend.target());
+        let header = if embedded {
+            RustGenerator::new().comment(&metadata)
+        } else if backend.target() == "bf" && options.entry_cell.is_some() {
+            // The comment is a loop that only stays shut on a zero cell,
+            // which a used tape need not have under the pointer.
+            None
+        } else {
+            backend.comment(&metadata)
+        };
+        // Charsets and bytecode have no way to mark text as a comment, and
+        // the digits in one would be counts once run-length encoded.
+        let code = match header {
+            Some(header)
+                if !options.no_metadata
+                    && options.charset.is_none()
+                    && bytecode.is_none()
+                    && !run_length =>
+            {
+                header + &code
+            }
+            _ => code,
+        };
+
+        let size = bytecode.as_ref().map_or(code.len(), Vec::len);
+        if let Some(max) = options.max_size
+            && size > max
+        {
+            return Err(TranspilerError::new(format!(
+                "Generated {} output is {} bytes, over the --max-size budget of {}",
+                backend.target(),
+                size,
+                max
+            ))
+            .into());
+        }
+
+        stats.outputs.push(OutputStats {
+            target: backend.target(),
+            bytes: size,
+            generated_bytes,
+            tape_cells,
+        });
+        outputs.push(Output {
+            code,
+            extension: if bytecode.is_some() {
+                "bfc"
+            } else if embedded {
+                "embed.rs"
+            } else if run_length {
+                "rle"
+            } else {
+                backend.extension()
+            },
+            bytecode,
+            target: backend.target(),
+            saved: None,
+        });
+    }
+
+    Ok(Compilation {
+        outputs,
+        warnings,
+        stats,
+        timings,
+    })
+}
+
+/// Fails if the Brainfuck may need more tape cells or steps than allowed.
+fn check_budgets(options: &Options, program: &[Op]) -> Result<(), Diagnostics> {
+    if let Some(max) = options.max_cells {
+        match verify::tape_cells_from(program, options.entry_cell.unwrap_or(0)) {
+            Some(cells) if cells <= max => {}
+            Some(cells) => {
+                return Err(format!(
+                    "Program uses up to {} tape cells, over the --max-cells budget of {}",
+                    cells, max
+                )
+                .into());
+            }
+            None => {
+                return Err(TranspilerError::new(format!(
+                    "Cannot prove the program stays within the --max-cells budget of {}",
+                    max
+                ))
+                .with_note("a loop may move the pointer further right on every pass")
+                .into());
+            }
+        }
+    }
+    if let Some(max) = options.max_est_steps {
+        // Programs read no input, so one run shows how long every run takes.
+        let entry = ">".repeat(options.entry_cell.unwrap_or(0));
+        let run = Interpreter::new(entry + &ir::emit(program))
+            .with_max_steps(max)
+            .run();
+        match run {
+            Ok(_) => {}
+            Err(RuntimeError::StepLimitExceeded { .. }) => {
+                return Err(format!(
+                    "Program runs for more than the --max-est-steps budget of {} steps",
+                    max
+                )
+                .into());
+            }
+            Err(e) => return Err(TranspilerError::from(e).into()),
+        }
+    }
+    Ok(())
+}
+
+/// Drops the functions `ast` never calls, unless `options` skip
+/// optimizing, returning their names.
+fn eliminate_dead_functions(options: &Options, ast: &mut Program) -> Vec<String> {
+    if options.no_optimize {
+        return Vec::new();
+    }
+    ast::eliminate_dead_functions(ast)
+}
+
+/// The Brainfuck generator `options` ask for.
+fn brainfuck_generator(options: &Options) -> BrainfuckGenerator {
+    let mut generator = BrainfuckGenerator::new()
+        .with_guard(options.guard_cells)
+        .with_entry_cell(options.entry_cell.unwrap_or(0));
+    if let Some(cell) = options.exit_cell {
+        generator = generator.with_exit_cell(cell);
+    }
+    if options.lookup_tables {
+        generator = generator.with_lookup_tables();
+    }
+    if options.constant_pool {
+        generator = generator.with_constant_pool();
+    }
+    if let Some(depth) = options.stack_depth {
+        generator = generator.with_stack_depth(depth);
+    }
+    if options.checked_arithmetic {
+        generator = generator.with_checked_arithmetic();
+    }
+    generator.with_call_strategy(options.calls)
+}
+
+/// What produced a file, for the comment at its start. It names no paths,
+/// times or hosts, so the same input and options give the same bytes on
+/// any machine.
+fn metadata(options: &Options, target: &str) -> String {
+    let mut text = format!(
+        "Generated by rust2bf {} with --frontend {} --target {}",
+        env!("CARGO_PKG_VERSION"),
+        options.frontend,
+        target
+    );
+    for pass in &options.disabled_passes {
+        text.push_str(&format!(" --disable-pass {}", pass));
+    }
+    if target == "bf" {
+        if options.guard_cells > 0 {
+            text.push_str(&format!(" --guard-cells {}", options.guard_cells));
+        }
+        if let Some(cell) = options.exit_cell {
+            text.push_str(&format!(" --exit-cell {}", cell));
+        }
+        if options.lookup_tables {
+            text.push_str(" --lookup-tables");
+        }
+        if options.constant_pool {
+            text.push_str(" --constant-pool");
+        }
+        if options.calls == CallStrategy::Dispatch {
+            text.push_str(" --calls dispatch");
+        }
+        if let Some(depth) = options.stack_depth {
+            text.push_str(&format!(" --stack-depth {}", depth));
+        }
+        if options.checked_arithmetic {
+            text.push_str(" --checked-arithmetic");
+        }
+        if options.emit == Some(Emit::RustEmbed) {
+            text.push_str(" --emit rust-embed");
+            if options.embed_interpreter {
+                text.push_str(" --embed-interpreter");
+            }
+        }
+    }
+    text
+}
 
-    println!("=== Generated Brainfuck ===");
-    println!("{}\n", brainfuck_code);
+fn create_frontend(options: &Options) -> Result<Box<dyn Frontend>, Diagnostics> {
+    if options.dialect != Dialect::MiniRust {
+        return Ok(Box::new(frontend::MiniRustDialect(options.dialect)));
+    }
+    let frontend = frontend::create(&options.frontend).ok_or_else(|| {
+        format!(
+            "Unknown frontend '{}' (available: {})",
+            options.frontend,
+            frontend::FRONTENDS.join(", ")
+        )
+    })?;
+    Ok(frontend)
+}
+
+fn analyzer(options: &Options) -> Analyzer {
+    let analyzer = Analyzer::new();
+    if options.dialect == Dialect::StrictRust {
+        analyzer.with_strict_rust()
+    } else {
+        analyzer
+    }
+}
 
-    // Save output
-    let output_filename = format!("{}.bf", filename.trim_end_matches(".rs"));
-    fs::write(&output_filename, &brainfuck_code)
+/// Optimizes generated Brainfuck; `names` labels cells in the
+/// `--explain-codegen` narration.
+fn optimize(
+    options: &Options,
+    code: &str,
+    names: &BTreeMap<usize, String>,
+    verbose: bool,
+) -> Result<Vec<Op>, Diagnostics> {
+    let mut passes = match options.entry_cell {
+        Some(_) => PassManager::for_used_tape(),
+        None => PassManager::new(),
+    };
+    // Optimizing may trade `+` for `-` across a wrap, which checked code
+    // has to keep where the generator put it.
+    if options.no_optimize || options.checked_arithmetic {
+        let names: Vec<_> = passes.pass_names().collect();
+        for name in names {
+            passes.set_enabled(name, false);
+        }
+    }
+    for name in &options.disabled_passes {
+        if !passes.set_enabled(name, false) {
+            let known: Vec<_> = passes.pass_names().collect();
+            return Err(format!(
+                "Unknown optimization pass '{}' (available: {})",
+                name,
+                known.join(", ")
+            )
+            .into());
+        }
+    }
+    let mut program = ir::parse(code)?;
+    let report = passes.run(&mut program);
+    verify::check_pointer_from(&program, options.entry_cell.unwrap_or(0))?;
+    if verbose && options.opt_report {
+        println!("=== Optimization Report ===");
+        println!("{}\n", report);
+    }
+    if verbose && options.explain_codegen {
+        println!("=== Codegen Explanation ===");
+        println!("{}", explain::explain_with_names(&program, names));
+    }
+    Ok(program)
+}
+
+fn load_charset(path: &str) -> Result<Charset, Diagnostics> {
+    let table = fs::read_to_string(path)
+        .map_err(|e| format!("Could not read charset '{}': {}", path, e))?;
+    let charset =
+        Charset::parse(&table).map_err(|e| format!("Invalid charset '{}': {}", path, e.message))?;
+    Ok(charset)
+}
+
+/// What `save` writes for `output`.
+fn saved_bytes(output: &Output) -> &[u8] {
+    output.bytecode.as_deref().unwrap_or(output.code.as_bytes())
+}
+
+/// Writes the output next to `filename`, returning the path written. With
+/// `progress`, a large output reports how much has been written as it goes.
+fn save(filename: &str, output: &Output, progress: bool) -> Result<String, Diagnostics> {
+    let output_filename = output_path(filename, output.extension);
+    write_in_chunks(&output_filename, saved_bytes(output), progress)
         .map_err(|e| format!("Could not write to '{}': {}", output_filename, e))?;
+    Ok(output_filename)
+}
+
+/// Where the output with `extension` of compiling `filename` is saved.
+fn output_path(filename: &str, extension: &str) -> String {
+    let stem = filename.trim_end_matches(".rs");
+    let path = format!("{}.{}", stem, extension);
+    // `--target rust` would otherwise overwrite a `.rs` input.
+    if path == filename {
+        format!("{}.out.{}", stem, extension)
+    } else {
+        path
+    }
+}
 
-    println!("Brainfuck code saved to: {}", output_filename);
+/// Whether `--no-optimize` Brainfuck can be written out as it is
+/// generated: nothing else asked for needs the whole program first.
+fn streams(options: &Options) -> bool {
+    options.no_optimize
+        && options.charset.is_none()
+        && options.emit.is_none()
+        && !options.explain_codegen
+        && options.max_size.is_none()
+        && options.max_cells.is_none()
+        && options.max_est_steps.is_none()
+}
+
+/// Generates `program` with `backend` straight into the file at `path`,
+/// after `header`, returning how many bytes were written. When stderr is
+/// a terminal, a large output shows how much has been written as it goes.
+fn stream(
+    backend: &mut dyn CodeGenerator,
+    program: &Program,
+    header: Option<&str>,
+    path: &str,
+) -> Result<usize, Diagnostics> {
+    let failed = |e: io::Error| format!("Could not write to '{}': {}", path, e);
+    let mut sink = FileSink {
+        file: io::BufWriter::new(fs::File::create(path).map_err(failed)?),
+        path,
+        written: 0,
+        progress: io::stderr().is_terminal(),
+        error: None,
+    };
+    let result = match fmt::Write::write_str(&mut sink, header.unwrap_or("")) {
+        // The sink keeps the error, which is reported below.
+        Err(fmt::Error) => Ok(()),
+        Ok(()) => backend.generate_into(program, &mut sink),
+    };
+    let result = match (result, sink.error.take()) {
+        (_, Some(e)) => Err(failed(e).into()),
+        (Err(e), None) => Err(e.into()),
+        (Ok(()), None) => sink.file.flush().map_err(|e| failed(e).into()),
+    };
+    if sink.progress && sink.written >= PROGRESS_BYTES {
+        eprintln!();
+    }
+    match result {
+        Ok(()) => Ok(sink.written),
+        Err(errors) => {
+            // Half a program is no use to anyone.
+            let _ = fs::remove_file(path);
+            Err(errors)
+        }
+    }
+}
+
+/// Passes generated code on to a file, keeping the first error to report.
+struct FileSink<'a> {
+    file: io::BufWriter<fs::File>,
+    path: &'a str,
+    written: usize,
+    progress: bool,
+    error: Option<io::Error>,
+}
+
+impl fmt::Write for FileSink<'_> {
+    fn write_str(&mut self, code: &str) -> fmt::Result {
+        if let Err(e) = self.file.write_all(code.as_bytes()) {
+            self.error = Some(e);
+            return Err(fmt::Error);
+        }
+        let before = self.written / PROGRESS_BYTES;
+        self.written += code.len();
+        if self.progress && self.written / PROGRESS_BYTES > before {
+            eprint!("\rWriting {}: {} MiB", self.path, self.written >> 20);
+        }
+        Ok(())
+    }
+}
+
+/// Saving an output at least this long shows its progress.
+const PROGRESS_BYTES: usize = 4 * 1024 * 1024;
+
+/// How much of an output is written at a time.
+const CHUNK_BYTES: usize = 256 * 1024;
+
+/// Writes `bytes` to `path` [`CHUNK_BYTES`] at a time, so a large output
+/// can show its progress on stderr as it goes.
+fn write_in_chunks(path: &str, bytes: &[u8], progress: bool) -> io::Result<()> {
+    let progress = progress && bytes.len() >= PROGRESS_BYTES;
+    let mut file = fs::File::create(path)?;
+    let mut written = 0;
+    for chunk in bytes.chunks(CHUNK_BYTES) {
+        file.write_all(chunk)?;
+        written += chunk.len();
+        if progress {
+            eprint!("\rWriting {}: {}%", path, written * 100 / bytes.len());
+        }
+    }
+    if progress {
+        eprintln!();
+    }
     Ok(())
 }
@@ -0,0 +1,101 @@
+//! Node.js bindings.
+//!
+//! Built as a native addon exporting `compile(source)` and
+//! `run(code, input?, maxSteps?)`:
+//!
+//! ```js
+//! const { compile, run } = require('./rusted_brains.node');
+//!
+//! const { code, diagnostics } = compile('let x = 72; print(x);');
+//! run(code).toString(); // 'H'
+//! ```
+//!
+//! `compile` does not throw on invalid programs: `code` is absent and the
+//! errors are in `diagnostics`. `run` throws on Brainfuck runtime errors.
+
+use crate::bf::Interpreter;
+use crate::error::{Severity, TranspilerError};
+use crate::pipeline;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use napi::bindgen_prelude::Buffer;
+use napi_derive::napi;
+
+#[napi(object)]
+pub struct Diagnostic {
+    pub message: String,
+    /// `"error"` or `"warning"`.
+    pub severity: String,
+    /// Byte offsets into the source, absent if there is no location.
+    pub start: Option<u32>,
+    pub end: Option<u32>,
+    /// 1-based line and column of `start`, absent if there is no location.
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    fn new(source: &str, error: &TranspilerError) -> Self {
+        let location = error.span.map(|span| span.location(source));
+        Self {
+            message: error.message.clone(),
+            severity: match error.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            }
+            .to_string(),
+            start: error.span.map(|span| span.start as u32),
+            end: error.span.map(|span| span.end as u32),
+            line: location.map(|location| location.line as u32),
+            column: location.map(|location| location.column as u32),
+            notes: error.notes.clone(),
+        }
+    }
+}
+
+#[napi(object)]
+pub struct CompileResult {
+    /// The generated Brainfuck, absent if compilation failed.
+    pub code: Option<String>,
+    /// Errors (on failure) or warnings (on success).
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Compiles mini-rust `source` to Brainfuck.
+#[napi]
+pub fn compile(source: String) -> CompileResult {
+    match pipeline::compile(&source) {
+        Ok(compiled) => CompileResult {
+            code: Some(compiled.code),
+            diagnostics: compiled
+                .warnings
+                .iter()
+                .map(|error| Diagnostic::new(&source, error))
+                .collect(),
+        },
+        Err(errors) => CompileResult {
+            code: None,
+            diagnostics: errors
+                .iter()
+                .map(|error| Diagnostic::new(&source, error))
+                .collect(),
+        },
+    }
+}
+
+/// Runs a Brainfuck program and returns its output.
+#[napi]
+pub fn run(code: String, input: Option<Buffer>, max_steps: Option<i64>) -> napi::Result<Buffer> {
+    let mut interpreter = Interpreter::new(code);
+    if let Some(input) = input {
+        interpreter = interpreter.with_input(input.to_vec());
+    }
+    if let Some(max_steps) = max_steps {
+        interpreter = interpreter.with_max_steps(max_steps.max(0) as u64);
+    }
+    let execution = interpreter
+        .run()
+        .map_err(|error| napi::Error::from_reason(error.to_string()))?;
+    Ok(execution.output.into())
+}
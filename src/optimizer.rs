@@ -0,0 +1,379 @@
+//! Rewrites of the [`ir`](crate::ir) that shrink programs without changing
+//! what they do.
+//!
+//! Each rewrite is an [`OptimizationPass`]; a [`PassManager`] runs an ordered
+//! list of them until none makes further progress and reports what each one
+//! achieved.
+
+use crate::ir::{self, Op};
+use crate::trace;
+use alloc::{boxed::Box, vec::Vec};
+use core::fmt;
+
+pub trait OptimizationPass: Send + Sync {
+    /// Stable identifier used to enable or disable the pass.
+    fn name(&self) -> &'static str;
+
+    /// Rewrites `program` in place, returning whether anything changed.
+    fn run(&self, program: &mut Vec<Op>) -> bool;
+}
+
+/// What a single pass achieved over a whole optimization run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassReport {
+    pub name: &'static str,
+    pub enabled: bool,
+    /// Number of times the pass changed the program.
+    pub changes: usize,
+    /// Brainfuck commands removed by the pass, summed over all iterations.
+    pub bytes_saved: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct OptReport {
+    pub size_before: usize,
+    pub size_after: usize,
+    pub iterations: usize,
+    pub passes: Vec<PassReport>,
+}
+
+impl fmt::Display for OptReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<16} {:>8} {:>8}", "pass", "changes", "saved")?;
+        for pass in &self.passes {
+            if pass.enabled {
+                writeln!(
+                    f,
+                    "{:<16} {:>8} {:>8}",
+                    pass.name, pass.changes, pass.bytes_saved
+                )?;
+            } else {
+                writeln!(f, "{:<16} {:>8} {:>8}", pass.name, "-", "disabled")?;
+            }
+        }
+        write!(
+            f,
+            "total: {} -> {} bytes ({} saved) in {} iteration(s)",
+            self.size_before,
+            self.size_after,
+            self.size_before.saturating_sub(self.size_after),
+            self.iterations
+        )
+    }
+}
+
+/// Upper bound on full rounds through the pass list, in case two passes keep
+/// undoing each other's work.
+const MAX_ITERATIONS: usize = 16;
+
+pub struct PassManager {
+    passes: Vec<(Box<dyn OptimizationPass>, bool)>,
+}
+
+impl Default for PassManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PassManager {
+    /// A manager with the built-in passes, in their default order.
+    pub fn new() -> Self {
+        Self::with_builtin_passes(true)
+    }
+
+    /// The built-in passes for a program that may start on a tape other
+    /// code has already used, where no cell is known to be zero.
+    pub fn for_used_tape() -> Self {
+        Self::with_builtin_passes(false)
+    }
+
+    fn with_builtin_passes(fresh_tape: bool) -> Self {
+        let mut manager = Self::empty();
+        manager.add(Box::new(FoldRuns));
+        manager.add(Box::new(ClearLoops));
+        manager.add(Box::new(DeadLoops { fresh_tape }));
+        manager
+    }
+
+    pub fn empty() -> Self {
+        Self { passes: Vec::new() }
+    }
+
+    /// Appends a pass; passes run in the order they were added.
+    pub fn add(&mut self, pass: Box<dyn OptimizationPass>) {
+        self.passes.push((pass, true));
+    }
+
+    /// Inserts a pass directly before the pass called `before`, or at the end
+    /// if there is no such pass.
+    pub fn add_before(&mut self, before: &str, pass: Box<dyn OptimizationPass>) {
+        let index = self
+            .passes
+            .iter()
+            .position(|(p, _)| p.name() == before)
+            .unwrap_or(self.passes.len());
+        self.passes.insert(index, (pass, true));
+    }
+
+    /// Enables or disables a pass by name. Returns `false` if there is no pass
+    /// with that name.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        match self.passes.iter_mut().find(|(p, _)| p.name() == name) {
+            Some((_, state)) => {
+                *state = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn pass_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.passes.iter().map(|(p, _)| p.name())
+    }
+
+    pub fn run(&self, program: &mut Vec<Op>) -> OptReport {
+        let _span = trace::span!("optimize");
+        let mut report = OptReport {
+            size_before: ir::size(program),
+            passes: self
+                .passes
+                .iter()
+                .map(|(pass, enabled)| PassReport {
+                    name: pass.name(),
+                    enabled: *enabled,
+                    changes: 0,
+                    bytes_saved: 0,
+                })
+                .collect(),
+            ..OptReport::default()
+        };
+
+        while report.iterations < MAX_ITERATIONS {
+            report.iterations += 1;
+            let mut changed = false;
+
+            for ((pass, enabled), entry) in self.passes.iter().zip(&mut report.passes) {
+                if !enabled {
+                    continue;
+                }
+                let _span = trace::span!("pass", name = pass.name());
+                let before = ir::size(program);
+                if pass.run(program) {
+                    let after = ir::size(program);
+                    trace::debug!(before, after, "pass changed the program");
+                    changed = true;
+                    entry.changes += 1;
+                    entry.bytes_saved += before.saturating_sub(after);
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+
+        report.size_after = ir::size(program);
+        report
+    }
+}
+
+/// Merges adjacent additions and moves and drops the ones that cancel out.
+pub struct FoldRuns;
+
+impl OptimizationPass for FoldRuns {
+    fn name(&self) -> &'static str {
+        "fold-runs"
+    }
+
+    fn run(&self, program: &mut Vec<Op>) -> bool {
+        let mut changed = false;
+        let mut folded: Vec<Op> = Vec::with_capacity(program.len());
+
+        for mut op in program.drain(..) {
+            if let Op::Loop(body) = &mut op {
+                changed |= self.run(body);
+            }
+
+            match (folded.last_mut(), &op) {
+                (Some(Op::Add(a)), Op::Add(b)) => {
+                    *a = a.wrapping_add(*b);
+                    changed = true;
+                }
+                (Some(Op::Move(a)), Op::Move(b)) => {
+                    *a += b;
+                    changed = true;
+                }
+                _ => folded.push(op),
+            }
+
+            if matches!(folded.last(), Some(Op::Add(0) | Op::Move(0))) {
+                folded.pop();
+                changed = true;
+            }
+        }
+
+        *program = folded;
+        changed
+    }
+}
+
+/// Recognizes `[-]` and `[+]` as a single clear operation.
+pub struct ClearLoops;
+
+impl OptimizationPass for ClearLoops {
+    fn name(&self) -> &'static str {
+        "clear-loops"
+    }
+
+    fn run(&self, program: &mut Vec<Op>) -> bool {
+        let mut changed = false;
+        for op in program.iter_mut() {
+            if let Op::Loop(body) = op {
+                if matches!(body.as_slice(), [Op::Add(1)] | [Op::Add(255)]) {
+                    *op = Op::Clear;
+                    changed = true;
+                } else {
+                    changed |= self.run(body);
+                }
+            }
+        }
+        changed
+    }
+}
+
+/// Removes work on a cell that is known to be zero: loops that can never be
+/// entered and clears of an already clear cell. A cell is known to be zero at
+/// the start of the program and right after a loop or clear, until the
+/// pointer moves or the cell is written.
+pub struct DeadLoops {
+    /// Whether the program starts on an all-zero tape; if not, nothing is
+    /// known about the first cell.
+    pub fresh_tape: bool,
+}
+
+impl DeadLoops {
+    fn run_block(&self, program: &mut Vec<Op>, mut known_zero: bool) -> bool {
+        let mut changed = false;
+        let mut kept = Vec::with_capacity(program.len());
+
+        for mut op in program.drain(..) {
+            let dead = known_zero && matches!(op, Op::Loop(_) | Op::Clear);
+            if dead {
+                changed = true;
+                continue;
+            }
+
+            if let Op::Loop(body) = &mut op {
+                // The body is only entered with a nonzero current cell.
+                changed |= self.run_block(body, false);
+            }
+            known_zero = matches!(op, Op::Loop(_) | Op::Clear);
+            kept.push(op);
+        }
+
+        *program = kept;
+        changed
+    }
+}
+
+impl OptimizationPass for DeadLoops {
+    fn name(&self) -> &'static str {
+        "dead-loops"
+    }
+
+    fn run(&self, program: &mut Vec<Op>) -> bool {
+        self.run_block(program, self.fresh_tape)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "interpreter")]
+    use crate::bf::Interpreter;
+
+    fn optimize(code: &str) -> (String, OptReport) {
+        let mut program = ir::parse(code).unwrap();
+        let report = PassManager::new().run(&mut program);
+        (ir::emit(&program), report)
+    }
+
+    #[test]
+    fn test_fold_runs() {
+        assert_eq!(optimize("+++--><<>>.").0, "+>.");
+        assert_eq!(optimize("+-<>.").0, ".");
+    }
+
+    #[test]
+    fn test_dead_loops() {
+        assert_eq!(optimize("[-]+[>]").0, "+[>]");
+        assert_eq!(optimize("+[-][-][>+<-].").0, "+[-].");
+        assert_eq!(optimize("+[-]>[-]+.").0, "+[-]>[-]+.");
+
+        let mut program = ir::parse("[-]+[-][>]").unwrap();
+        PassManager::for_used_tape().run(&mut program);
+        assert_eq!(ir::emit(&program), "[-]+[-]");
+    }
+
+    #[test]
+    fn test_report_accounts_for_savings() {
+        let (code, report) = optimize("[-]++--+[-][-]>");
+        assert_eq!(code, "+[-]>");
+        assert_eq!(report.size_before, 15);
+        assert_eq!(report.size_after, 5);
+        let saved: usize = report.passes.iter().map(|p| p.bytes_saved).sum();
+        assert_eq!(saved, 10);
+    }
+
+    #[test]
+    fn test_disable_pass_by_name() {
+        let mut manager = PassManager::new();
+        assert!(manager.set_enabled("dead-loops", false));
+        assert!(!manager.set_enabled("no-such-pass", false));
+
+        let mut program = ir::parse("[-]+").unwrap();
+        manager.run(&mut program);
+        assert_eq!(ir::emit(&program), "[-]+");
+    }
+
+    struct StripOutput;
+
+    impl OptimizationPass for StripOutput {
+        fn name(&self) -> &'static str {
+            "strip-output"
+        }
+
+        fn run(&self, program: &mut Vec<Op>) -> bool {
+            let before = program.len();
+            program.retain(|op| *op != Op::Output);
+            program.len() != before
+        }
+    }
+
+    #[test]
+    fn test_custom_pass_ordering() {
+        let mut manager = PassManager::new();
+        manager.add_before("fold-runs", Box::new(StripOutput));
+        assert_eq!(
+            manager.pass_names().collect::<Vec<_>>(),
+            vec!["strip-output", "fold-runs", "clear-loops", "dead-loops"]
+        );
+
+        let mut program = ir::parse("+.+.").unwrap();
+        manager.run(&mut program);
+        assert_eq!(program, vec![Op::Add(2)]);
+    }
+
+    #[test]
+    #[cfg(feature = "interpreter")]
+    fn test_optimized_program_behaves_the_same() {
+        let code = "[-]++++++[>++++++++<-]>+.[-]<><+++[->+<]>.";
+        let (optimized, _) = optimize(code);
+        assert!(optimized.len() < code.len());
+        assert_eq!(
+            Interpreter::new(code).run().unwrap().output,
+            Interpreter::new(optimized).run().unwrap().output
+        );
+    }
+}
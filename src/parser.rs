@@ -1,41 +1,279 @@
-use crate::ast::{BinaryOp, Expr, Program, Stmt, Token};
-use crate::error::{TranspilerError, TranspilerResult};
+use crate::ast::{
+    BinaryOp, Expr, Function, Inline, MatchArm, Pattern, Program, SpannedToken, Stmt, Token,
+    UnaryOp,
+};
+use crate::error::{Diagnostics, TranspilerError, TranspilerResult};
+use crate::lexer::KEYWORDS;
+use crate::span::Span;
+use crate::suggest;
+use crate::symbol_table::Type;
+use crate::tokens::{TokenKind, Tokens};
+use crate::trace;
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+/// Byte printed between the values of a `print` or `println` with several
+/// arguments.
+pub const PRINT_SEPARATOR: u8 = b' ';
+
+/// The variations of the language the parser can be configured to read.
+/// They share one grammar; a dialect only relaxes or tightens a few rules.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Dialect {
+    /// The language as the README describes it.
+    #[default]
+    MiniRust,
+    /// For classroom use: `print` and `println` also take their values
+    /// without parentheses, as in `print x, y`. A bare `println` prints a
+    /// newline when the statement ends there.
+    Teaching,
+    /// Only what rustc would also accept, for programs meant to be copied
+    /// back into a Rust project. Everything else is reported as an error:
+    /// bare `print`, statements outside `fn main()`, parameters and results
+    /// without types, and the other liberties mini-rust takes.
+    StrictRust,
+}
+
+impl Dialect {
+    /// Every dialect, by the name `--dialect` selects it with.
+    pub const ALL: &[(&str, Dialect)] = &[
+        ("mini-rust", Dialect::MiniRust),
+        ("teaching", Dialect::Teaching),
+        ("strict-rust", Dialect::StrictRust),
+    ];
+
+    pub fn name(self) -> &'static str {
+        Self::ALL
+            .iter()
+            .find(|&&(_, dialect)| dialect == self)
+            .map_or("mini-rust", |&(name, _)| name)
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|&&(known, _)| known == name)
+            .map(|&(_, dialect)| dialect)
+    }
+}
 
 pub struct Parser {
-    tokens: Vec<Token>,
+    tokens: Tokens,
     current: usize,
+    dialect: Dialect,
+    diagnostics: Diagnostics,
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+    pub fn new(tokens: Vec<SpannedToken>) -> Self {
+        Self::from_tokens(tokens.into())
     }
 
-    pub fn parse(&mut self) -> TranspilerResult<Program> {
+    /// A parser over tokens already in compact form, skipping the copy
+    /// [`Parser::new`] makes.
+    pub fn from_tokens(tokens: Tokens) -> Self {
+        Self {
+            tokens,
+            current: 0,
+            dialect: Dialect::default(),
+            diagnostics: Diagnostics::new(),
+        }
+    }
+
+    /// Parses the given variation of the language; see [`Dialect`].
+    pub fn with_dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+
+    pub fn parse(&mut self) -> Result<Program, Diagnostics> {
+        let (program, diagnostics) = self.parse_partial();
+        diagnostics.into_result(program)
+    }
+
+    /// Parses as much of the input as possible, returning every statement
+    /// that could be recovered together with the errors that were skipped.
+    pub fn parse_partial(&mut self) -> (Program, Diagnostics) {
+        let _span = trace::span!("parse");
+        let mut statements = self.statement_list();
+
+        // A `}` only ends a statement list inside a block; at the top level
+        // it is unmatched, so report it and keep going.
+        while matches!(self.peek(), Token::RightBrace) {
+            self.diagnostics.push(TranspilerError::with_span(
+                "Unexpected token: RightBrace",
+                self.current_span(),
+            ));
+            self.advance();
+            statements.extend(self.statement_list());
+        }
+
+        if self.dialect == Dialect::StrictRust {
+            self.check_main(&statements);
+        }
+        // Like Rust, a program made of nothing but functions runs `main`.
+        if let Some(main) = entry_point(&statements) {
+            let call = Stmt::expr(Expr::call("main", Vec::new())).with_span(main.span);
+            statements.push(call);
+        }
+
+        (statements, core::mem::take(&mut self.diagnostics))
+    }
+
+    /// Reports top-level statements other than functions, and a missing
+    /// or malformed `fn main()`.
+    fn check_main(&mut self, statements: &[Stmt]) {
+        if let Some(stmt) = statements
+            .iter()
+            .find(|stmt| !matches!(stmt, Stmt::Function(_)))
+        {
+            self.diagnostics.push(
+                TranspilerError::with_span("Rust has no statements outside functions", stmt.span())
+                    .with_note("help: move the program's statements into `fn main() { ... }`"),
+            );
+        }
+        match statements.iter().find_map(|stmt| match stmt {
+            Stmt::Function(function) if function.name == "main" => Some(function),
+            _ => None,
+        }) {
+            None => self.diagnostics.push(
+                TranspilerError::new("A Rust program needs a `fn main()`")
+                    .with_note("help: the program starts by running `main`"),
+            ),
+            Some(main) if !main.params.is_empty() => {
+                self.diagnostics.push(TranspilerError::with_span(
+                    "`main` cannot take parameters in Rust",
+                    main.span,
+                ));
+            }
+            Some(_) => {}
+        }
+    }
+
+    /// Reports `message` at `span` in the strict Rust dialect, with `help`
+    /// on how Rust would write it.
+    fn not_rust(&mut self, message: &str, span: Span, help: &str) {
+        if self.dialect == Dialect::StrictRust {
+            self.diagnostics.push(
+                TranspilerError::with_span(message.to_string(), span)
+                    .with_note(format!("help: {}", help)),
+            );
+        }
+    }
+
+    /// Parses statements up to the closing brace of the enclosing block (or
+    /// end of input), recovering from errors at statement boundaries.
+    fn statement_list(&mut self) -> Vec<Stmt> {
         let mut statements = Vec::new();
 
-        while !self.is_at_end() && !matches!(self.peek(), Token::Eof) {
-            statements.push(self.statement()?);
+        while !self.is_at_end() && !matches!(self.peek(), Token::RightBrace | Token::Eof) {
+            let start = self.current;
+            match self.statement() {
+                Ok(stmts) => statements.extend(stmts),
+                Err(error) => {
+                    self.diagnostics.push(error);
+                    self.synchronize();
+                    // Always make progress, even if the error was raised on a
+                    // token that is itself a statement boundary.
+                    if self.current == start {
+                        self.advance();
+                    }
+                }
+            }
         }
 
-        Ok(statements)
+        statements
+    }
+
+    /// Skips tokens until the start of the next statement: just past a `;`,
+    /// just past a balanced `{ ... }` block, or right before a keyword that
+    /// begins a statement or a `}` closing the enclosing block.
+    fn synchronize(&mut self) {
+        while !self.is_at_end() {
+            match self.peek() {
+                Token::Semicolon => {
+                    self.advance();
+                    return;
+                }
+                Token::LeftBrace => {
+                    self.skip_block();
+                    return;
+                }
+                Token::Let
+                | Token::Print
+                | Token::Println
+                | Token::PrintNum
+                | Token::If
+                | Token::Match
+                | Token::While
+                | Token::Loop
+                | Token::Break
+                | Token::Continue
+                | Token::For
+                | Token::Fn
+                | Token::Hash
+                | Token::Return
+                | Token::RightBrace
+                | Token::Eof => {
+                    return;
+                }
+                _ => {
+                    self.advance();
+                }
+            }
+        }
+    }
+
+    fn skip_block(&mut self) {
+        let mut depth = 0usize;
+        while !self.is_at_end() && !matches!(self.peek(), Token::Eof) {
+            match self.advance() {
+                Token::LeftBrace => depth += 1,
+                Token::RightBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return;
+                    }
+                }
+                _ => {}
+            }
+        }
     }
 
-    fn statement(&mut self) -> TranspilerResult<Stmt> {
+    /// Parses one statement, which `print` may desugar into several.
+    fn statement(&mut self) -> TranspilerResult<Vec<Stmt>> {
         match self.peek() {
-            Token::Let => self.let_statement(),
-            Token::Print => self.print_statement(),
-            Token::If => self.if_statement(),
-            Token::While => self.while_statement(),
-            Token::Identifier(_) => self.assignment_statement(),
-            _ => Err(TranspilerError::with_position(
+            Token::Print | Token::Println => self.print_statement(),
+            Token::PrintNum => self.print_num_statement().map(|stmt| vec![stmt]),
+            Token::Input => self.call_statement().map(|stmt| vec![stmt]),
+            Token::Let => self.let_statement().map(|stmt| vec![stmt]),
+            Token::If => self.if_statement().map(|stmt| vec![stmt]),
+            Token::Match => self.match_statement().map(|stmt| vec![stmt]),
+            Token::While => self.while_statement().map(|stmt| vec![stmt]),
+            Token::Loop => self.loop_statement().map(|stmt| vec![stmt]),
+            Token::Break | Token::Continue => Ok(vec![self.jump_statement()]),
+            Token::For => self.for_statement().map(|stmt| vec![stmt]),
+            Token::Fn | Token::Hash => self.function_definition().map(|stmt| vec![stmt]),
+            Token::Return => self.return_statement().map(|stmt| vec![stmt]),
+            Token::Identifier(_)
+                if matches!(self.tokens.get(self.current + 1), Some(Token::LeftParen)) =>
+            {
+                self.call_statement().map(|stmt| vec![stmt])
+            }
+            Token::Identifier(_) => self.assignment_statement().map(|stmt| vec![stmt]),
+            _ => Err(TranspilerError::with_span(
                 format!("Unexpected token: {:?}", self.peek()),
-                self.current,
+                self.current_span(),
             )),
         }
     }
 
     fn let_statement(&mut self) -> TranspilerResult<Stmt> {
+        let start = self.current_span();
         self.consume(Token::Let, "Expected 'let'")?;
 
         let mutable = if matches!(self.peek(), Token::Mut) {
@@ -46,128 +284,505 @@ impl Parser {
         };
 
         let name = self.consume_identifier("Expected variable name")?;
+        let ty = if self.consume_if_present(Token::Colon) {
+            Some(self.type_name()?)
+        } else {
+            None
+        };
         self.consume(Token::Assign, "Expected '=' after variable name")?;
         let value = self.expression()?;
-        self.consume_if_present(Token::Semicolon);
+        if !self.consume_if_present(Token::Semicolon) {
+            let span = self.previous_span();
+            self.not_rust(
+                "Expected ';' after `let`",
+                span,
+                "Rust ends every `let` with `;`",
+            );
+        }
 
-        Ok(Stmt::let_stmt(name, mutable, value))
+        let stmt = match ty {
+            Some(ty) => Stmt::typed_let(name, mutable, ty, value),
+            None => Stmt::let_stmt(name, mutable, value),
+        };
+        Ok(stmt.with_span(start.to(self.previous_span())))
     }
 
     fn assignment_statement(&mut self) -> TranspilerResult<Stmt> {
+        let start = self.current_span();
         let name = self.consume_identifier("Expected variable name")?;
-        self.consume(Token::Assign, "Expected '=' in assignment")?;
+        // A statement starting with a name that is not followed by `=` is
+        // most likely a misspelled keyword, e.g. `whlie x { ... }`.
+        self.consume(Token::Assign, "Expected '=' in assignment")
+            .map_err(
+                |error| match suggest::best_match(&name, KEYWORDS.iter().copied()) {
+                    Some(keyword) => error.with_note(format!("help: did you mean `{}`?", keyword)),
+                    None => error,
+                },
+            )?;
         let value = self.expression()?;
         self.consume_if_present(Token::Semicolon);
 
-        Ok(Stmt::assign(name, value))
+        Ok(Stmt::assign(name, value).with_span(start.to(self.previous_span())))
     }
 
-    fn print_statement(&mut self) -> TranspilerResult<Stmt> {
-        self.consume(Token::Print, "Expected 'print'")?;
-        self.consume(Token::LeftParen, "Expected '(' after 'print'")?;
-        let expr = self.expression()?;
+    /// `print(a, b)` becomes one print per value with [`PRINT_SEPARATOR`]
+    /// printed between them; `println` adds a newline at the end and may
+    /// have no arguments at all. The macros `print!("{}", a)` and
+    /// `println!("{}", a)` take exactly one value, or none for `println!()`,
+    /// and `print!("text")` prints text with no `{}` in it.
+    fn print_statement(&mut self) -> TranspilerResult<Vec<Stmt>> {
+        let start = self.current_span();
+        let newline = matches!(self.advance(), Token::Println);
+        let keyword = if newline { "println" } else { "print" };
+        let is_macro = self.consume_if_present(Token::Exclamation);
+        let parenthesized = is_macro
+            || self.dialect != Dialect::Teaching
+            || matches!(self.peek(), Token::LeftParen);
+        if parenthesized {
+            self.consume(
+                Token::LeftParen,
+                &format!("Expected '(' after '{}'", keyword),
+            )
+            .map_err(|error| {
+                if is_macro {
+                    error
+                } else {
+                    error.with_note("help: `--dialect teaching` accepts values without parentheses")
+                }
+            })?;
+        }
+
+        let mut values = Vec::new();
+        if is_macro {
+            match self.peek() {
+                Token::RightParen if newline => {}
+                Token::Str(format) if format == "{}" => {
+                    self.advance();
+                    self.consume(Token::Comma, "Expected ',' after the format string")?;
+                    values.push(self.expression()?);
+                }
+                // Text without placeholders is printed as it is.
+                Token::Str(text) if !text.contains(['{', '}']) => {
+                    values.push(Expr::string(text.clone()));
+                    self.advance();
+                }
+                _ => {
+                    return Err(TranspilerError::with_span(
+                        format!("Expected \"{{}}\" as the format string of `{}!`", keyword),
+                        self.current_span(),
+                    )
+                    .with_note(format!(
+                        "help: `{}!` prints text, or one value as a byte: `{}!(\"{{}}\", x)`",
+                        keyword, keyword
+                    )));
+                }
+            }
+        } else {
+            let empty = if parenthesized {
+                matches!(self.peek(), Token::RightParen)
+            } else {
+                self.at_statement_end()
+            };
+            if !(newline && empty) {
+                values.push(self.expression()?);
+                while self.consume_if_present(Token::Comma) {
+                    values.push(self.expression()?);
+                }
+            }
+            self.not_rust(
+                &format!("`{}(...)` is not Rust", keyword),
+                start,
+                &format!(
+                    "use the macro, one value at a time: `{}!(\"{{}}\", x)`",
+                    keyword
+                ),
+            );
+        }
+        if parenthesized {
+            self.consume(Token::RightParen, "Expected ')' after expression")?;
+        }
+        self.consume_if_present(Token::Semicolon);
+
+        let span = start.to(self.previous_span());
+        let mut stmts = Vec::new();
+        for (i, value) in values.into_iter().enumerate() {
+            if i > 0 {
+                stmts.push(Stmt::print(Expr::number(PRINT_SEPARATOR.into())).with_span(span));
+            }
+            stmts.push(Stmt::print(value).with_span(span));
+        }
+        if newline {
+            stmts.push(Stmt::print(Expr::number(b'\n'.into())).with_span(span));
+        }
+        Ok(stmts)
+    }
+
+    /// `print_num(x)` prints one value as decimal digits.
+    fn print_num_statement(&mut self) -> TranspilerResult<Stmt> {
+        let start = self.current_span();
+        self.consume(Token::PrintNum, "Expected 'print_num'")?;
+        self.consume(Token::LeftParen, "Expected '(' after 'print_num'")?;
+        let value = self.expression()?;
         self.consume(Token::RightParen, "Expected ')' after expression")?;
         self.consume_if_present(Token::Semicolon);
+        self.not_rust(
+            "`print_num(...)` is not Rust",
+            start,
+            "Rust prints numbers in decimal with `print!(\"{}\", x)`",
+        );
 
-        Ok(Stmt::print(expr))
+        Ok(Stmt::print_num(value).with_span(start.to(self.previous_span())))
     }
 
     fn if_statement(&mut self) -> TranspilerResult<Stmt> {
+        let start = self.current_span();
         self.consume(Token::If, "Expected 'if'")?;
         let condition = self.expression()?;
+        let span = start.to(self.previous_span());
+        let body = self.block()?;
+
+        let else_body = if !self.consume_if_present(Token::Else) {
+            Vec::new()
+        } else if matches!(self.peek(), Token::If) {
+            vec![self.if_statement()?]
+        } else {
+            self.block()?
+        };
+
+        Ok(Stmt::if_else(condition, body, else_body).with_span(span))
+    }
+
+    /// `match value { pattern => { ... } ... }`, where commas between arms
+    /// are optional. An arm that fails to parse is skipped and the rest are
+    /// still read.
+    fn match_statement(&mut self) -> TranspilerResult<Stmt> {
+        let start = self.current_span();
+        self.consume(Token::Match, "Expected 'match'")?;
+        let scrutinee = self.expression()?;
+        let span = start.to(self.previous_span());
+        self.consume(Token::LeftBrace, "Expected '{' after match value")?;
+
+        let mut arms = Vec::new();
+        while !matches!(self.peek(), Token::RightBrace | Token::Eof) {
+            let arm_start = self.current;
+            match self.match_arm() {
+                Ok(arm) => arms.push(arm),
+                Err(error) => {
+                    self.diagnostics.push(error);
+                    self.synchronize();
+                    if self.current == arm_start {
+                        self.advance();
+                    }
+                }
+            }
+            self.consume_if_present(Token::Comma);
+        }
+        self.consume(Token::RightBrace, "Expected '}'")?;
+
+        if !arms.iter().any(|arm| arm.pattern == Pattern::Wildcard) {
+            self.not_rust(
+                "`match` does not cover every value",
+                span,
+                "Rust needs every value matched: add a `_ => {}` arm",
+            );
+        }
+        Ok(Stmt::match_stmt(scrutinee, arms).with_span(span))
+    }
+
+    fn match_arm(&mut self) -> TranspilerResult<MatchArm> {
+        let start = self.current_span();
+        let pattern = match self.peek() {
+            Token::Number(n) => Pattern::Number(*n),
+            Token::Minus if let Some(&Token::Number(n)) = self.tokens.get(self.current + 1) => {
+                let minus = self.current_span();
+                self.advance();
+                self.not_rust(
+                    "Negative patterns do not match a `u8`",
+                    minus.to(self.current_span()),
+                    &format!(
+                        "match the value it wraps around to: `{}`",
+                        (-n).rem_euclid(256)
+                    ),
+                );
+                Pattern::Number(-n)
+            }
+            Token::Identifier(name) if name == "_" => Pattern::Wildcard,
+            token => {
+                return Err(TranspilerError::with_span(
+                    format!("Expected a number or '_' as a pattern, got {:?}", token),
+                    self.current_span(),
+                ));
+            }
+        };
+        self.advance();
+        let span = start.to(self.previous_span());
+        self.consume(Token::FatArrow, "Expected '=>' after pattern")?;
         let body = self.block()?;
 
-        Ok(Stmt::if_stmt(condition, body))
+        Ok(MatchArm::new(pattern, body).with_span(span))
     }
 
     fn while_statement(&mut self) -> TranspilerResult<Stmt> {
+        let start = self.current_span();
         self.consume(Token::While, "Expected 'while'")?;
         let condition = self.expression()?;
+        let span = start.to(self.previous_span());
         let body = self.block()?;
 
-        Ok(Stmt::while_stmt(condition, body))
+        Ok(Stmt::while_stmt(condition, body).with_span(span))
     }
 
-    fn block(&mut self) -> TranspilerResult<Vec<Stmt>> {
-        self.consume(Token::LeftBrace, "Expected '{'")?;
+    fn loop_statement(&mut self) -> TranspilerResult<Stmt> {
+        let span = self.current_span();
+        self.consume(Token::Loop, "Expected 'loop'")?;
+        let body = self.block()?;
 
-        let mut statements = Vec::new();
-        while !matches!(self.peek(), Token::RightBrace) && !self.is_at_end() {
-            statements.push(self.statement()?);
+        // `loop { ... } while condition;` tests the condition after each
+        // pass, but a `while` with a block of its own is the next loop.
+        if matches!(self.peek(), Token::While) {
+            let next = self.current;
+            let start = self.current_span();
+            self.advance();
+            if let Ok(condition) = self.expression()
+                && !matches!(self.peek(), Token::LeftBrace)
+            {
+                let span = start.to(self.previous_span());
+                self.consume_if_present(Token::Semicolon);
+                self.not_rust(
+                    "`loop { ... } while condition` is not Rust",
+                    span,
+                    "end the loop's body with `if !(condition) { break; }`",
+                );
+                return Ok(Stmt::do_while(body, condition).with_span(span));
+            }
+            self.current = next;
         }
 
-        self.consume(Token::RightBrace, "Expected '}'")?;
-        Ok(statements)
+        Ok(Stmt::loop_stmt(body).with_span(span))
     }
 
-    fn expression(&mut self) -> TranspilerResult<Expr> {
-        self.equality()
+    /// `break` or `continue`.
+    fn jump_statement(&mut self) -> Stmt {
+        let start = self.current_span();
+        let stmt = match self.advance() {
+            Token::Break => Stmt::break_stmt(),
+            _ => Stmt::continue_stmt(),
+        };
+        self.consume_if_present(Token::Semicolon);
+
+        stmt.with_span(start.to(self.previous_span()))
     }
 
-    fn equality(&mut self) -> TranspilerResult<Expr> {
-        let mut expr = self.comparison()?;
+    fn for_statement(&mut self) -> TranspilerResult<Stmt> {
+        let start = self.current_span();
+        self.consume(Token::For, "Expected 'for'")?;
+        let name = self.consume_identifier("Expected variable name after 'for'")?;
+        self.consume(Token::In, "Expected 'in' after variable name")?;
+        let iterable = self.expression()?;
+        let span = start.to(self.previous_span());
+        self.not_rust(
+            "Rust cannot loop over a string directly",
+            span,
+            "Rust loops over a string's bytes with `.bytes()`, which mini-rust does not have",
+        );
+        let body = self.block()?;
 
-        while matches!(self.peek(), Token::Equal | Token::NotEqual) {
-            let op = match self.advance() {
-                Token::Equal => BinaryOp::Equal,
-                Token::NotEqual => BinaryOp::NotEqual,
-                _ => unreachable!(),
-            };
-            let right = self.comparison()?;
-            expr = Expr::binary(expr, op, right);
+        Ok(Stmt::for_stmt(name, iterable, body).with_span(span))
+    }
+
+    /// `fn name(a, b) { ... }`. Parameters need no types, as every value
+    /// fits in a cell, but may have them, as may the result:
+    /// `fn name(a: u8, b: u8) -> u8 { ... }`. An `#[inline(...)]`
+    /// attribute may come first.
+    fn function_definition(&mut self) -> TranspilerResult<Stmt> {
+        let inline = self.inline_attribute()?;
+        let start = self.current_span();
+        self.consume(Token::Fn, "Expected 'fn'")?;
+        let name = self.consume_identifier("Expected function name after 'fn'")?;
+        self.consume(Token::LeftParen, "Expected '(' after function name")?;
+        let mut params = Vec::new();
+        let mut param_types = Vec::new();
+        while !matches!(self.peek(), Token::RightParen) {
+            let param_span = self.current_span();
+            let param = self.consume_identifier("Expected parameter name")?;
+            if self.consume_if_present(Token::Colon) {
+                param_types.push(Some(self.type_name()?));
+            } else {
+                self.not_rust(
+                    &format!("Parameter `{}` needs a type in Rust", param),
+                    param_span,
+                    &format!("write `{}: u8`", param),
+                );
+                param_types.push(None);
+            }
+            params.push(param);
+            if !self.consume_if_present(Token::Comma) {
+                break;
+            }
         }
+        self.consume(Token::RightParen, "Expected ')' after parameters")?;
+        let return_type = if self.consume_if_present(Token::Arrow) {
+            Some(self.type_name()?)
+        } else {
+            None
+        };
+        let span = start.to(self.previous_span());
+        let body = self.block()?;
 
-        Ok(expr)
+        if return_type.is_none() && body.iter().any(Stmt::returns_value) {
+            self.not_rust(
+                &format!("Function `{}` returns a value but has no return type", name),
+                span,
+                "write `-> u8` after the parameters",
+            );
+        }
+        Ok(Stmt::Function(Function {
+            name,
+            params,
+            param_types,
+            return_type,
+            inline,
+            body,
+            span,
+        }))
     }
 
-    fn comparison(&mut self) -> TranspilerResult<Expr> {
-        let mut expr = self.term()?;
-
-        while matches!(self.peek(), Token::Less | Token::Greater) {
-            let op = match self.advance() {
-                Token::Less => BinaryOp::Less,
-                Token::Greater => BinaryOp::Greater,
-                _ => unreachable!(),
+    /// `#[inline]`, `#[inline(always)]` or `#[inline(never)]` before a
+    /// function, if there is one.
+    fn inline_attribute(&mut self) -> TranspilerResult<Inline> {
+        if !self.consume_if_present(Token::Hash) {
+            return Ok(Inline::Auto);
+        }
+        self.consume(Token::LeftBracket, "Expected '[' after '#'")?;
+        let span = self.current_span();
+        let name = self.consume_identifier("Expected an attribute name")?;
+        if name != "inline" {
+            return Err(
+                TranspilerError::with_span(format!("Unknown attribute `{}`", name), span)
+                    .with_note(
+                        "functions may have `#[inline]`, `#[inline(always)]` or `#[inline(never)]`",
+                    ),
+            );
+        }
+        let inline = if self.consume_if_present(Token::LeftParen) {
+            let span = self.current_span();
+            let inline = match self
+                .consume_identifier("Expected `always` or `never`")?
+                .as_str()
+            {
+                "always" => Inline::Always,
+                "never" => Inline::Never,
+                other => {
+                    return Err(TranspilerError::with_span(
+                        format!("Unknown inline hint `{}`", other),
+                        span,
+                    )
+                    .with_note("write `#[inline(always)]` or `#[inline(never)]`"));
+                }
             };
-            let right = self.term()?;
-            expr = Expr::binary(expr, op, right);
+            self.consume(Token::RightParen, "Expected ')' after inline hint")?;
+            inline
+        } else {
+            Inline::Auto
+        };
+        self.consume(Token::RightBracket, "Expected ']' after attribute")?;
+        if !matches!(self.peek(), Token::Fn) {
+            return Err(TranspilerError::with_span(
+                "Only functions may have an `#[inline]` attribute",
+                self.current_span(),
+            ));
         }
+        Ok(inline)
+    }
 
-        Ok(expr)
+    /// A type after `:` or `->`, which the analyzer checks values against.
+    fn type_name(&mut self) -> TranspilerResult<Type> {
+        let span = self.current_span();
+        let name = self.consume_identifier("Expected a type")?;
+        match name.as_str() {
+            "u8" => Ok(Type::Int),
+            "bool" => Ok(Type::Bool),
+            _ => Err(
+                TranspilerError::with_span(format!("Unknown type `{}`", name), span)
+                    .with_note("mini-rust values are `u8` or `bool`"),
+            ),
+        }
     }
 
-    fn term(&mut self) -> TranspilerResult<Expr> {
-        let mut expr = self.factor()?;
+    /// `return value;`, or `return;` in a function with nothing to return.
+    fn return_statement(&mut self) -> TranspilerResult<Stmt> {
+        let start = self.current_span();
+        self.consume(Token::Return, "Expected 'return'")?;
+        let value = if matches!(self.peek(), Token::Semicolon | Token::RightBrace) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume_if_present(Token::Semicolon);
 
-        while matches!(self.peek(), Token::Plus | Token::Minus) {
-            let op = match self.advance() {
-                Token::Plus => BinaryOp::Add,
-                Token::Minus => BinaryOp::Sub,
-                _ => unreachable!(),
-            };
-            let right = self.factor()?;
-            expr = Expr::binary(expr, op, right);
+        Ok(Stmt::return_stmt(value).with_span(start.to(self.previous_span())))
+    }
+
+    /// A call on its own, run for what the function does: `name(args);`.
+    fn call_statement(&mut self) -> TranspilerResult<Stmt> {
+        let start = self.current_span();
+        let value = self.primary()?;
+        self.consume_if_present(Token::Semicolon);
+
+        Ok(Stmt::expr(value).with_span(start.to(self.previous_span())))
+    }
+
+    /// The arguments of a call, after its `(`.
+    fn arguments(&mut self) -> TranspilerResult<Vec<Expr>> {
+        let mut args = Vec::new();
+        while !matches!(self.peek(), Token::RightParen) {
+            args.push(self.expression()?);
+            if !self.consume_if_present(Token::Comma) {
+                break;
+            }
         }
+        self.consume(Token::RightParen, "Expected ')' after arguments")?;
+        Ok(args)
+    }
 
-        Ok(expr)
+    fn block(&mut self) -> TranspilerResult<Vec<Stmt>> {
+        self.consume(Token::LeftBrace, "Expected '{'")?;
+        let statements = self.statement_list();
+        self.consume(Token::RightBrace, "Expected '}'")?;
+        Ok(statements)
     }
 
-    fn factor(&mut self) -> TranspilerResult<Expr> {
-        let mut expr = self.primary()?;
+    fn expression(&mut self) -> TranspilerResult<Expr> {
+        self.binary_expression(0)
+    }
 
-        while matches!(self.peek(), Token::Multiply | Token::Divide) {
-            let op = match self.advance() {
-                Token::Multiply => BinaryOp::Mul,
-                Token::Divide => BinaryOp::Div,
-                _ => unreachable!(),
-            };
-            let right = self.primary()?;
+    /// Precedence climbing: parses operands joined by operators that bind
+    /// tighter than `min_precedence`. All binary operators are
+    /// left-associative.
+    fn binary_expression(&mut self, min_precedence: u8) -> TranspilerResult<Expr> {
+        let mut expr = self.unary()?;
+
+        while let Some((op, precedence)) = binary_operator(self.peek_kind())
+            && precedence > min_precedence
+        {
+            self.advance();
+            let right = self.binary_expression(precedence)?;
             expr = Expr::binary(expr, op, right);
         }
 
         Ok(expr)
     }
 
+    /// A primary expression, after any number of `!`s, which bind tighter
+    /// than every binary operator.
+    fn unary(&mut self) -> TranspilerResult<Expr> {
+        if self.consume_if_present(Token::Exclamation) {
+            let operand = self.unary()?;
+            return Ok(Expr::unary(UnaryOp::Not, operand));
+        }
+        self.primary()
+    }
+
     fn primary(&mut self) -> TranspilerResult<Expr> {
         match self.peek() {
             Token::Number(n) => {
@@ -175,9 +790,22 @@ impl Parser {
                 self.advance();
                 Ok(Expr::number(num))
             }
+            Token::Str(text) => {
+                let text = text.clone();
+                self.advance();
+                Ok(Expr::string(text))
+            }
+            Token::True | Token::False => {
+                let value = matches!(self.advance(), Token::True);
+                Ok(Expr::Bool(value))
+            }
             Token::Identifier(name) => {
                 let var_name = name.clone();
                 self.advance();
+                if self.consume_if_present(Token::LeftParen) {
+                    let args = self.arguments()?;
+                    return Ok(Expr::call(var_name, args));
+                }
                 Ok(Expr::variable(var_name))
             }
             Token::LeftParen => {
@@ -186,9 +814,21 @@ impl Parser {
                 self.consume(Token::RightParen, "Expected ')' after expression")?;
                 Ok(expr)
             }
-            _ => Err(TranspilerError::with_position(
+            Token::Input => {
+                let start = self.current_span();
+                self.advance();
+                self.consume(Token::LeftParen, "Expected '(' after 'input'")?;
+                self.consume(Token::RightParen, "`input()` takes no arguments")?;
+                self.not_rust(
+                    "`input()` is not Rust",
+                    start.to(self.previous_span()),
+                    "Rust reads input through `std::io::stdin()`",
+                );
+                Ok(Expr::Input)
+            }
+            _ => Err(TranspilerError::with_span(
                 format!("Unexpected token in expression: {:?}", self.peek()),
-                self.current,
+                self.current_span(),
             )),
         }
     }
@@ -198,6 +838,10 @@ impl Parser {
         self.tokens.get(self.current).unwrap_or(&Token::Eof)
     }
 
+    fn peek_kind(&self) -> TokenKind {
+        self.tokens.kind(self.current).unwrap_or(TokenKind::Eof)
+    }
+
     fn advance(&mut self) -> &Token {
         if !self.is_at_end() {
             self.current += 1;
@@ -205,24 +849,50 @@ impl Parser {
         self.tokens.get(self.current - 1).unwrap_or(&Token::Eof)
     }
 
+    /// Span of the most recently consumed token.
+    fn previous_span(&self) -> Span {
+        self.current
+            .checked_sub(1)
+            .and_then(|i| self.tokens.span(i))
+            .unwrap_or_default()
+    }
+
+    /// Span of the token about to be consumed, used to locate errors.
+    fn current_span(&self) -> Span {
+        self.tokens
+            .span(self.current)
+            .or_else(|| self.tokens.span(self.tokens.len().checked_sub(1)?))
+            .unwrap_or_default()
+    }
+
+    /// Whether the current token cannot continue a statement: a `;`, the
+    /// end of the block or input, or a keyword starting the next one.
+    fn at_statement_end(&self) -> bool {
+        match self.peek() {
+            Token::Semicolon | Token::RightBrace | Token::Eof => true,
+            Token::True | Token::False | Token::Input => false,
+            token => token.keyword().is_some(),
+        }
+    }
+
     fn is_at_end(&self) -> bool {
         self.current >= self.tokens.len()
     }
 
     fn consume(&mut self, expected: Token, message: &str) -> TranspilerResult<()> {
-        if std::mem::discriminant(self.peek()) == std::mem::discriminant(&expected) {
+        if self.peek_kind() == TokenKind::of(&expected) {
             self.advance();
             Ok(())
         } else {
-            Err(TranspilerError::with_position(
+            Err(TranspilerError::with_span(
                 format!("{}, got {:?}", message, self.peek()),
-                self.current,
+                self.current_span(),
             ))
         }
     }
 
     fn consume_if_present(&mut self, token: Token) -> bool {
-        if std::mem::discriminant(self.peek()) == std::mem::discriminant(&token) {
+        if self.peek_kind() == TokenKind::of(&token) {
             self.advance();
             true
         } else {
@@ -237,14 +907,92 @@ impl Parser {
                 self.advance();
                 Ok(result)
             }
-            _ => Err(TranspilerError::with_position(
+            // Consume the keyword so recovery does not restart at it, which
+            // would report `let print = 5;` a second time as a bad `print`.
+            token if let Some(keyword) = token.keyword() => {
+                let span = self.current_span();
+                self.advance();
+                Err(TranspilerError::with_span(
+                    format!("{}, found keyword `{}`", message, keyword),
+                    span,
+                )
+                .with_note(format!(
+                    "`{}` is a keyword and cannot be used as a name",
+                    keyword
+                )))
+            }
+            _ => Err(TranspilerError::with_span(
                 message.to_string(),
-                self.current,
+                self.current_span(),
             )),
         }
     }
 }
 
+/// The `fn main()` to run after the definitions of a program that is only
+/// function definitions, as a Rust program would.
+fn entry_point(statements: &[Stmt]) -> Option<&Function> {
+    let mut main = None;
+    for stmt in statements {
+        match stmt {
+            Stmt::Function(function) if function.name == "main" => main = Some(function),
+            Stmt::Function(_) => {}
+            _ => return None,
+        }
+    }
+    main.filter(|main| main.params.is_empty())
+}
+
+/// The binary operators by precedence, loosest first, each level named as
+/// in [`grammar`](crate::grammar).
+pub const BINARY_OPERATORS: &[(&str, &[(TokenKind, BinaryOp)])] = &[
+    ("or", &[(TokenKind::Or, BinaryOp::Or)]),
+    ("and", &[(TokenKind::And, BinaryOp::And)]),
+    (
+        "equality",
+        &[
+            (TokenKind::Equal, BinaryOp::Equal),
+            (TokenKind::NotEqual, BinaryOp::NotEqual),
+        ],
+    ),
+    (
+        "comparison",
+        &[
+            (TokenKind::Less, BinaryOp::Less),
+            (TokenKind::Greater, BinaryOp::Greater),
+            (TokenKind::LessEqual, BinaryOp::LessEqual),
+            (TokenKind::GreaterEqual, BinaryOp::GreaterEqual),
+        ],
+    ),
+    (
+        "sum",
+        &[
+            (TokenKind::Plus, BinaryOp::Add),
+            (TokenKind::Minus, BinaryOp::Sub),
+        ],
+    ),
+    (
+        "product",
+        &[
+            (TokenKind::Multiply, BinaryOp::Mul),
+            (TokenKind::Divide, BinaryOp::Div),
+            (TokenKind::Percent, BinaryOp::Mod),
+        ],
+    ),
+];
+
+/// The operator a token denotes between two operands, with its precedence
+/// from [`BINARY_OPERATORS`]; higher binds tighter.
+fn binary_operator(kind: TokenKind) -> Option<(BinaryOp, u8)> {
+    BINARY_OPERATORS
+        .iter()
+        .zip(1..)
+        .find_map(|(&(_, operators), precedence)| {
+            let (_, operator) = operators.iter().find(|(token, _)| *token == kind)?;
+            Some((operator.clone(), precedence))
+        })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -274,4 +1022,584 @@ mod tests {
             matches!(value, Expr::Binary { .. });
         }
     }
+
+    #[test]
+    fn test_precedence_and_associativity() {
+        let tokens = Lexer::new("let x = 1 - 2 - 3 * 4 / 5 % 6 <= 6 == 7 || 8 > 9 && 1 == 1;")
+            .tokenize()
+            .unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+
+        let n = Expr::number;
+        let product = Expr::binary(Expr::binary(n(3), BinaryOp::Mul, n(4)), BinaryOp::Div, n(5));
+        let product = Expr::binary(product, BinaryOp::Mod, n(6));
+        let difference = Expr::binary(
+            Expr::binary(n(1), BinaryOp::Sub, n(2)),
+            BinaryOp::Sub,
+            product,
+        );
+        let equality = Expr::binary(
+            Expr::binary(difference, BinaryOp::LessEqual, n(6)),
+            BinaryOp::Equal,
+            n(7),
+        );
+        let conjunction = Expr::binary(
+            Expr::binary(n(8), BinaryOp::Greater, n(9)),
+            BinaryOp::And,
+            Expr::binary(n(1), BinaryOp::Equal, n(1)),
+        );
+        let expected = Expr::binary(equality, BinaryOp::Or, conjunction);
+        assert!(matches!(&ast[0], Stmt::Let { value, .. } if *value == expected));
+    }
+
+    #[test]
+    fn test_bool_literals() {
+        let tokens = Lexer::new("let done = false; while !done == true { }")
+            .tokenize()
+            .unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        assert_eq!(
+            program,
+            vec![
+                Stmt::let_stmt("done", false, Expr::Bool(false)).with_span(Span::new(0, 17)),
+                Stmt::while_stmt(
+                    Expr::binary(
+                        Expr::unary(UnaryOp::Not, Expr::variable("done")),
+                        BinaryOp::Equal,
+                        Expr::Bool(true)
+                    ),
+                    vec![]
+                )
+                .with_span(Span::new(18, 37)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unary_not() {
+        let tokens = Lexer::new("let x = !a == !!(b < 1);").tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+
+        let not = |operand| Expr::unary(UnaryOp::Not, operand);
+        let expected = Expr::binary(
+            not(Expr::variable("a")),
+            BinaryOp::Equal,
+            not(not(Expr::binary(
+                Expr::variable("b"),
+                BinaryOp::Less,
+                Expr::number(1),
+            ))),
+        );
+        assert!(matches!(&ast[0], Stmt::Let { value, .. } if *value == expected));
+    }
+
+    #[test]
+    fn test_else_branches() {
+        let source = "if a { print(1); } else if b { } else { print(3); print(4); } if c { }";
+        let ast = Parser::new(Lexer::new(source).tokenize().unwrap())
+            .parse()
+            .unwrap();
+
+        assert_eq!(ast.len(), 2);
+        let Stmt::If {
+            body, else_body, ..
+        } = &ast[0]
+        else {
+            panic!("expected an if");
+        };
+        assert_eq!(body.len(), 1);
+        assert!(matches!(
+            else_body.as_slice(),
+            [Stmt::If { condition, body, else_body, .. }]
+                if *condition == Expr::variable("b") && body.is_empty() && else_body.len() == 2
+        ));
+        assert!(matches!(&ast[1], Stmt::If { else_body, .. } if else_body.is_empty()));
+    }
+
+    #[test]
+    fn test_loop_statement() {
+        let tokens = Lexer::new("loop { if a { break; } continue }")
+            .tokenize()
+            .unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        assert_eq!(
+            program,
+            vec![
+                Stmt::loop_stmt(vec![
+                    Stmt::if_stmt(
+                        Expr::variable("a"),
+                        vec![Stmt::break_stmt().with_span(Span::new(14, 20))]
+                    )
+                    .with_span(Span::new(7, 11)),
+                    Stmt::continue_stmt().with_span(Span::new(23, 31)),
+                ])
+                .with_span(Span::new(0, 4))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_do_while() {
+        let tokens = Lexer::new("loop { } while a; loop { } while a { }")
+            .tokenize()
+            .unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        assert_eq!(
+            program,
+            vec![
+                Stmt::do_while(vec![], Expr::variable("a")).with_span(Span::new(9, 16)),
+                Stmt::loop_stmt(vec![]).with_span(Span::new(18, 22)),
+                Stmt::while_stmt(Expr::variable("a"), vec![]).with_span(Span::new(27, 34)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_match_statement() {
+        let tokens = Lexer::new("match x { 0 => { print(1); }, -2 => {} _ => { break; } }")
+            .tokenize()
+            .unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        assert_eq!(
+            program,
+            vec![
+                Stmt::match_stmt(
+                    Expr::variable("x"),
+                    vec![
+                        MatchArm::new(
+                            Pattern::Number(0),
+                            vec![Stmt::print(Expr::number(1)).with_span(Span::new(17, 26))]
+                        )
+                        .with_span(Span::new(10, 11)),
+                        MatchArm::new(Pattern::Number(-2), vec![]).with_span(Span::new(30, 32)),
+                        MatchArm::new(
+                            Pattern::Wildcard,
+                            vec![Stmt::break_stmt().with_span(Span::new(46, 52))]
+                        )
+                        .with_span(Span::new(39, 40)),
+                    ]
+                )
+                .with_span(Span::new(0, 7))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_function_definition_and_calls() {
+        let tokens = Lexer::new(
+            "fn add(a, b) { return a + b; } fn f() { return; } f(); print(add(1, f()));",
+        )
+        .tokenize()
+        .unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        assert_eq!(
+            program,
+            vec![
+                Stmt::function(
+                    "add",
+                    vec!["a".to_string(), "b".to_string()],
+                    vec![
+                        Stmt::return_stmt(Some(Expr::binary(
+                            Expr::variable("a"),
+                            BinaryOp::Add,
+                            Expr::variable("b")
+                        )))
+                        .with_span(Span::new(15, 28))
+                    ]
+                )
+                .with_span(Span::new(0, 12)),
+                Stmt::function(
+                    "f",
+                    vec![],
+                    vec![Stmt::return_stmt(None).with_span(Span::new(40, 47))]
+                )
+                .with_span(Span::new(31, 37)),
+                Stmt::expr(Expr::call("f", vec![])).with_span(Span::new(50, 54)),
+                Stmt::print(Expr::call(
+                    "add",
+                    vec![Expr::number(1), Expr::call("f", vec![])]
+                ))
+                .with_span(Span::new(55, 74)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inline_attributes() {
+        let parse = |source| Parser::new(Lexer::new(source).tokenize().unwrap()).parse_partial();
+        let (program, errors) = parse(
+            "#[inline(always)] fn a() {} #[inline] fn b() {} #[inline(never)] fn c() {} fn d() {}",
+        );
+        assert!(errors.is_empty());
+        let hints: Vec<_> = program
+            .iter()
+            .map(|stmt| match stmt {
+                Stmt::Function(function) => function.inline,
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            hints,
+            [Inline::Always, Inline::Auto, Inline::Never, Inline::Auto]
+        );
+        // The span still starts at `fn`.
+        assert_eq!(program[0].span(), Span::new(18, 24));
+
+        for (source, message) in [
+            ("#[cold] fn f() {}", "Unknown attribute `cold`"),
+            (
+                "#[inline(sometimes)] fn f() {}",
+                "Unknown inline hint `sometimes`",
+            ),
+            (
+                "#[inline] let x = 1;",
+                "Only functions may have an `#[inline]` attribute",
+            ),
+        ] {
+            let (program, errors) = parse(source);
+            let messages: Vec<_> = errors.iter().map(|e| e.message.as_str()).collect();
+            assert_eq!(messages, [message], "{}", source);
+            // Parsing picks up again at the `fn` or `let` after it.
+            assert_eq!(program.len(), 1, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_recovers_after_bad_match_arm() {
+        let tokens = Lexer::new("match x { y => { print(1); } 2 => { print(2); } } print(3);")
+            .tokenize()
+            .unwrap();
+        let (ast, errors) = Parser::new(tokens).parse_partial();
+
+        assert_eq!(
+            errors
+                .iter()
+                .map(|e| e.message.as_str())
+                .collect::<Vec<_>>(),
+            vec!["Expected a number or '_' as a pattern, got Identifier(\"y\")"]
+        );
+        assert_eq!(ast.len(), 2);
+        assert!(matches!(&ast[0], Stmt::Match { arms, .. } if arms.len() == 1));
+    }
+
+    #[test]
+    fn test_recovers_after_statement_error() {
+        let mut lexer = Lexer::new("let = 1; print(2); let y 3; while x { print(); } print(4);");
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let (ast, errors) = parser.parse_partial();
+
+        assert_eq!(errors.len(), 3);
+        assert_eq!(ast.len(), 3);
+        assert!(matches!(
+            ast[0],
+            Stmt::Print {
+                value: Expr::Number(2),
+                ..
+            }
+        ));
+        assert!(matches!(ast[1], Stmt::While { ref body, .. } if body.is_empty()));
+        assert!(matches!(
+            ast[2],
+            Stmt::Print {
+                value: Expr::Number(4),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_recovery_skips_block_of_broken_header() {
+        let mut lexer = Lexer::new("if x == { print(1); } print(2);");
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let (ast, errors) = parser.parse_partial();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            ast,
+            vec![Stmt::print(Expr::number(2)).with_span(Span::new(22, 31))]
+        );
+    }
+
+    #[test]
+    fn test_misspelled_keyword_suggestion() {
+        let notes = |source: &str| {
+            let tokens = Lexer::new(source).tokenize().unwrap();
+            let errors = Parser::new(tokens).parse().unwrap_err();
+            errors.iter().next().unwrap().notes.clone()
+        };
+
+        assert_eq!(notes("whlie x { }"), vec!["help: did you mean `while`?"]);
+        assert_eq!(notes("retrun 1;"), vec!["help: did you mean `return`?"]);
+        assert!(notes("count 1;").is_empty());
+    }
+
+    #[test]
+    fn test_keyword_as_name() {
+        let tokens = Lexer::new("let print = 5; print(1);").tokenize().unwrap();
+        let (ast, errors) = Parser::new(tokens).parse_partial();
+        let error = errors.iter().next().unwrap();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(ast.len(), 1);
+
+        assert_eq!(
+            error.message,
+            "Expected variable name, found keyword `print`"
+        );
+        assert_eq!(error.span, Some(Span::new(4, 9)));
+    }
+
+    #[test]
+    fn test_for_statement() {
+        let tokens = Lexer::new("let s = \"hi\"; for c in s { print(c); }")
+            .tokenize()
+            .unwrap();
+        let program = Parser::new(tokens).parse().unwrap();
+        assert_eq!(
+            program[1],
+            Stmt::for_stmt(
+                "c",
+                Expr::variable("s"),
+                vec![Stmt::print(Expr::variable("c")).with_span(Span::new(27, 36))]
+            )
+            .with_span(Span::new(14, 24))
+        );
+        assert_eq!(
+            program[0],
+            Stmt::let_stmt("s", false, Expr::string("hi")).with_span(Span::new(0, 13))
+        );
+
+        let tokens = Lexer::new("for c s { }").tokenize().unwrap();
+        assert!(Parser::new(tokens).parse().is_err());
+    }
+
+    #[test]
+    fn test_print_several_values() {
+        let parse = |source: &str| {
+            let tokens = Lexer::new(source).tokenize().unwrap();
+            Parser::new(tokens).parse().unwrap()
+        };
+        let printed = |program: Vec<Stmt>| -> Vec<Expr> {
+            program
+                .into_iter()
+                .map(|stmt| match stmt {
+                    Stmt::Print { value, .. } => value,
+                    other => panic!("expected a print, got {:?}", other),
+                })
+                .collect()
+        };
+
+        let (a, b) = (Expr::variable("a"), Expr::variable("b"));
+        assert_eq!(
+            printed(parse("print(a, b);")),
+            vec![a.clone(), Expr::number(32), b.clone()]
+        );
+        assert_eq!(
+            printed(parse("println(a, b)")),
+            vec![a.clone(), Expr::number(32), b, Expr::number(10)]
+        );
+        assert_eq!(printed(parse("println();")), vec![Expr::number(10)]);
+        assert_eq!(parse("print(a, b);")[0].span(), Span::new(0, 12));
+
+        let tokens = Lexer::new("print();").tokenize().unwrap();
+        assert!(Parser::new(tokens).parse().is_err());
+    }
+
+    #[test]
+    fn test_rust_spellings() {
+        let tokens = Lexer::new(
+            "fn add(a: u8, b: u8) -> u8 { return a + b; } \
+             fn main() { let x: u8 = add(1, 2); print!(\"{}\", x); println!(); }",
+        )
+        .tokenize()
+        .unwrap();
+        let program = Parser::new(tokens)
+            .with_dialect(Dialect::StrictRust)
+            .parse()
+            .unwrap();
+        // A program of only functions runs `main`.
+        assert_eq!(program.len(), 3);
+        assert!(matches!(
+            &program[2],
+            Stmt::Expr { value: Expr::Call { name, args }, .. } if name == "main" && args.is_empty()
+        ));
+        let Stmt::Function(add) = &program[0] else {
+            panic!("expected `add`, got {:?}", program[0]);
+        };
+        assert_eq!(add.param_types, vec![Some(Type::Int); 2]);
+        assert_eq!(add.return_type, Some(Type::Int));
+        let Stmt::Function(main) = &program[1] else {
+            panic!("expected `main`, got {:?}", program[1]);
+        };
+        assert!(matches!(
+            main.body[0],
+            Stmt::Let {
+                ty: Some(Type::Int),
+                ..
+            }
+        ));
+        assert_eq!(
+            main.body[1..],
+            [
+                Stmt::print(Expr::variable("x")).with_span(Span::new(80, 96)),
+                Stmt::print(Expr::number(10)).with_span(Span::new(97, 108)),
+            ]
+        );
+
+        let parse = |source: &str| Parser::new(Lexer::new(source).tokenize().unwrap()).parse();
+        assert!(parse("fn main() { } main();").unwrap().len() == 2);
+        assert!(parse("let x: i32 = 1;").is_err());
+        assert!(parse("print!(\"{} {}\", 1, 2);").is_err());
+        assert!(parse("println!(\"{{}}\");").is_err());
+        let printed = |source: &str| -> Vec<Expr> {
+            parse(source)
+                .unwrap()
+                .into_iter()
+                .map(|stmt| match stmt {
+                    Stmt::Print { value, .. } => value,
+                    other => panic!("expected a print, got {:?}", other),
+                })
+                .collect()
+        };
+        assert_eq!(
+            printed("print!(\"hi\"); println!(\"there\");"),
+            vec![Expr::string("hi"), Expr::string("there"), Expr::number(10)]
+        );
+    }
+
+    #[test]
+    fn test_teaching_dialect() {
+        let parse = |source: &str| {
+            let tokens = Lexer::new(source).tokenize().unwrap();
+            Parser::new(tokens).with_dialect(Dialect::Teaching).parse()
+        };
+        let values = |source: &str| -> Vec<Expr> {
+            parse(source)
+                .unwrap()
+                .into_iter()
+                .map(|stmt| match stmt {
+                    Stmt::Print { value, .. } => value,
+                    other => panic!("expected a print, got {:?}", other),
+                })
+                .collect()
+        };
+
+        let (a, b) = (Expr::variable("a"), Expr::variable("b"));
+        assert_eq!(
+            values("print a, b println"),
+            vec![a.clone(), Expr::number(32), b.clone(), Expr::number(10)]
+        );
+        assert_eq!(values("println b; print(a)"), vec![b, Expr::number(10), a]);
+        assert!(parse("if 1 { println } print 1").is_ok());
+        assert!(parse("print").is_err());
+
+        let tokens = Lexer::new("print a").tokenize().unwrap();
+        let errors = Parser::new(tokens).parse().unwrap_err();
+        assert!(
+            errors
+                .iter()
+                .all(|error| error.notes[0].contains("--dialect teaching"))
+        );
+    }
+
+    #[test]
+    fn test_strict_rust_rejects_dialect() {
+        let errors = |source: &str| -> Vec<String> {
+            let tokens = Lexer::new(source).tokenize().unwrap();
+            match Parser::new(tokens)
+                .with_dialect(Dialect::StrictRust)
+                .parse()
+            {
+                Ok(_) => Vec::new(),
+                Err(errors) => errors.into_iter().map(|error| error.message).collect(),
+            }
+        };
+
+        assert_eq!(
+            errors(
+                "fn f(a) { return a; } \
+                 fn main() { let x = 1 print(x); match x { -1 => {} } loop {} while x; }"
+            ),
+            vec![
+                "Parameter `a` needs a type in Rust",
+                "Function `f` returns a value but has no return type",
+                "Expected ';' after `let`",
+                "`print(...)` is not Rust",
+                "Negative patterns do not match a `u8`",
+                "`match` does not cover every value",
+                "`loop { ... } while condition` is not Rust",
+            ]
+        );
+        assert_eq!(
+            errors("print!(\"{}\", 1);"),
+            vec![
+                "Rust has no statements outside functions",
+                "A Rust program needs a `fn main()`",
+            ]
+        );
+        assert_eq!(
+            errors("fn main(a: u8) { }"),
+            vec!["`main` cannot take parameters in Rust"]
+        );
+        assert!(errors("fn main() { let x = 1; print!(\"{}\", x); }").is_empty());
+        assert_eq!(
+            errors("fn main() { print_num(1); }"),
+            vec!["`print_num(...)` is not Rust"]
+        );
+        assert_eq!(
+            errors("fn main() { let c = input(); }"),
+            vec!["`input()` is not Rust"]
+        );
+    }
+
+    #[test]
+    fn test_print_num() {
+        let parse = |source: &str| Parser::new(Lexer::new(source).tokenize().unwrap()).parse();
+        assert_eq!(
+            parse("print_num(a + 1); print_num(2)").unwrap(),
+            vec![
+                Stmt::print_num(Expr::binary(
+                    Expr::variable("a"),
+                    BinaryOp::Add,
+                    Expr::number(1)
+                ))
+                .with_span(Span::new(0, 17)),
+                Stmt::print_num(Expr::number(2)).with_span(Span::new(18, 30)),
+            ]
+        );
+        // One value at a time.
+        assert!(parse("print_num(1, 2);").is_err());
+        assert!(parse("print_num();").is_err());
+    }
+
+    #[test]
+    fn test_input() {
+        let parse = |source: &str| Parser::new(Lexer::new(source).tokenize().unwrap()).parse();
+        assert_eq!(
+            parse("let c = input() + 1; input();").unwrap(),
+            vec![
+                Stmt::let_stmt(
+                    "c",
+                    false,
+                    Expr::binary(Expr::Input, BinaryOp::Add, Expr::number(1))
+                )
+                .with_span(Span::new(0, 20)),
+                Stmt::expr(Expr::Input).with_span(Span::new(21, 29)),
+            ]
+        );
+        assert!(parse("let c = input(1);").is_err());
+        assert!(parse("let c = input;").is_err());
+    }
+
+    #[test]
+    fn test_stray_closing_brace_does_not_hang() {
+        let mut lexer = Lexer::new("} print(1);");
+        let tokens = lexer.tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+
+        let errors = parser.parse().unwrap_err();
+        assert_eq!(errors.len(), 1);
+    }
 }
@@ -0,0 +1,207 @@
+//! The whole compiler behind a single call.
+//!
+//! The CLI drives each stage itself so it can print them; embedding
+//! applications and language bindings that only want the result use
+//! [`compile`], or [`compile_with`] to rewrite the program with their own
+//! [`Folder`]s first, e.g. to add builtins of their own, or its tokens
+//! with their own preprocessors.
+
+use crate::analysis::Analyzer;
+use crate::ast::{self, Folder};
+use crate::codegen::BrainfuckGenerator;
+use crate::error::Diagnostics;
+use crate::ir;
+use crate::optimizer::PassManager;
+use crate::parser::Parser;
+use crate::tokens::{TokenStream, Tokens};
+use crate::trace;
+use crate::verify;
+use alloc::{boxed::Box, string::String, vec::Vec};
+
+/// A successfully compiled program.
+#[derive(Debug)]
+pub struct Compiled {
+    /// The optimized Brainfuck program.
+    pub code: String,
+    /// Warnings from semantic analysis.
+    pub warnings: Diagnostics,
+}
+
+/// Edits the tokens of a program before it is parsed; see
+/// [`Options::with_token_pass`].
+pub type TokenPass = Box<dyn FnMut(&mut TokenStream) + Send>;
+
+/// How [`compile_with`] compiles a program.
+#[derive(Default)]
+pub struct Options {
+    token_passes: Vec<TokenPass>,
+    ast_passes: Vec<Box<dyn Folder + Send>>,
+}
+
+impl Options {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Edits the tokens with `pass` after lexing, before they are parsed.
+    /// Passes run in the order they were added.
+    pub fn with_token_pass(mut self, pass: TokenPass) -> Self {
+        self.token_passes.push(pass);
+        self
+    }
+
+    /// Rewrites the program with `pass` after parsing, before it is checked
+    /// and optimized. Passes run in the order they were added.
+    pub fn with_ast_pass(mut self, pass: Box<dyn Folder + Send>) -> Self {
+        self.ast_passes.push(pass);
+        self
+    }
+}
+
+/// Compiles mini-rust `source` to Brainfuck with the default optimization
+/// passes, returning every error found if it does not compile.
+pub fn compile(source: &str) -> Result<Compiled, Diagnostics> {
+    compile_with(source, &mut Options::new())
+}
+
+/// Like [`compile`], running the token and AST passes of `options`.
+pub fn compile_with(source: &str, options: &mut Options) -> Result<Compiled, Diagnostics> {
+    let _span = trace::span!("compile");
+    let tokens = if options.token_passes.is_empty() {
+        Tokens::lex(source)?
+    } else {
+        let mut stream = TokenStream::lex(source)?;
+        for pass in &mut options.token_passes {
+            pass(&mut stream);
+        }
+        stream.into()
+    };
+    let mut program = Parser::from_tokens(tokens).parse()?;
+    for pass in &mut options.ast_passes {
+        program = pass.fold_program(program);
+    }
+
+    let warnings = Analyzer::new().analyze(&program);
+    if warnings.has_errors() {
+        return Err(warnings);
+    }
+
+    ast::eliminate_dead_functions(&mut program);
+    let code = BrainfuckGenerator::new().generate(&program)?;
+    let mut ops = ir::parse(&code)?;
+    PassManager::new().run(&mut ops);
+    verify::check_pointer(&ops)?;
+
+    Ok(Compiled {
+        code: ir::emit(&ops),
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{BinaryOp, Expr, Stmt, fold_expr_children, fold_stmt_children};
+
+    #[test]
+    fn test_compile_reports_warnings() {
+        let compiled = compile("let x = 65; let y = 1; print(x);").unwrap();
+        assert!(compiled.code.ends_with('.'));
+        assert_eq!(compiled.warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_public_types_are_thread_safe() {
+        fn send_sync<T: Send + Sync>() {}
+        fn send<T: Send>() {}
+
+        send_sync::<crate::ast::Program>();
+        send_sync::<crate::ast::SpannedToken>();
+        send_sync::<crate::tokens::Tokens>();
+        send_sync::<Diagnostics>();
+        send_sync::<Compiled>();
+        send_sync::<PassManager>();
+        send_sync::<crate::tokens::TokenStream>();
+        send_sync::<Box<dyn crate::frontend::Frontend>>();
+        send_sync::<crate::symbol_table::SymbolTable>();
+        send_sync::<crate::ir::Op>();
+        send::<Analyzer>();
+        send::<BrainfuckGenerator>();
+        send::<Options>();
+        send::<Box<dyn crate::backend::CodeGenerator>>();
+    }
+
+    /// A builtin `beep()` that prints the bell character, and `twice(x)`
+    /// for `x + x`.
+    struct Builtins;
+
+    impl Folder for Builtins {
+        fn fold_stmt(&mut self, stmt: Stmt) -> Vec<Stmt> {
+            match stmt {
+                Stmt::Expr {
+                    value: Expr::Call { name, .. },
+                    span,
+                } if name == "beep" => vec![Stmt::print(Expr::number(7)).with_span(span)],
+                stmt => vec![fold_stmt_children(self, stmt)],
+            }
+        }
+
+        fn fold_expr(&mut self, expr: Expr) -> Expr {
+            match fold_expr_children(self, expr) {
+                Expr::Call { name, mut args } if name == "twice" && args.len() == 1 => {
+                    let arg = args.pop().unwrap();
+                    Expr::binary(arg.clone(), BinaryOp::Add, arg)
+                }
+                expr => expr,
+            }
+        }
+    }
+
+    #[test]
+    fn test_ast_passes() {
+        let source = "beep(); if 1 == 1 { print(twice(twice(8))); }";
+        assert!(compile(source).is_err());
+
+        let mut options = Options::new().with_ast_pass(Box::new(Builtins));
+        let compiled = compile_with(source, &mut options).unwrap();
+        assert!(compiled.code.contains('.'));
+        #[cfg(feature = "interpreter")]
+        assert_eq!(
+            crate::bf::Interpreter::new(compiled.code)
+                .run()
+                .unwrap()
+                .output,
+            vec![7, 32]
+        );
+    }
+
+    #[test]
+    fn test_token_passes() {
+        use crate::ast::Token;
+
+        // `NL` stands for a newline.
+        let newline = |tokens: &mut TokenStream| {
+            tokens.replace_all(&[Token::Identifier("NL".into())], &[Token::Number(10)]);
+        };
+        let source = "print(NL); print(NL + 1);";
+        assert!(compile(source).is_err());
+
+        let mut options = Options::new().with_token_pass(Box::new(newline));
+        let compiled = compile_with(source, &mut options).unwrap();
+        assert!(compiled.code.contains('.'));
+        #[cfg(feature = "interpreter")]
+        assert_eq!(
+            crate::bf::Interpreter::new(compiled.code)
+                .run()
+                .unwrap()
+                .output,
+            vec![10, 11]
+        );
+    }
+
+    #[test]
+    fn test_compile_collects_errors() {
+        let errors = compile("print(a); print(b);").unwrap_err();
+        assert_eq!(errors.len(), 2);
+    }
+}
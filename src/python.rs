@@ -0,0 +1,181 @@
+//! Python bindings.
+//!
+//! Built as the `rusted_brains` extension module:
+//!
+//! ```python
+//! import rusted_brains
+//!
+//! compiled = rusted_brains.compile("let x = 72; print(x);")
+//! rusted_brains.run(compiled.code)  # b"H"
+//! ```
+//!
+//! Compilation failures raise `CompileError`, whose `diagnostics` attribute
+//! holds a [`Diagnostic`] per problem; Brainfuck runtime failures raise
+//! `BrainfuckError`.
+
+use crate::bf::Interpreter;
+use crate::error::{Severity, TranspilerError};
+use crate::pipeline;
+use crate::render;
+use alloc::string::String;
+use alloc::vec::Vec;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+pyo3::create_exception!(rusted_brains, CompileError, PyException);
+pyo3::create_exception!(rusted_brains, BrainfuckError, PyException);
+
+/// A diagnostic reported while compiling.
+#[pyclass(module = "rusted_brains", frozen, get_all, skip_from_py_object)]
+#[derive(Clone)]
+pub struct Diagnostic {
+    pub message: String,
+    /// `"error"` or `"warning"`.
+    pub severity: &'static str,
+    /// Byte offsets into the source, or `None` if there is no location.
+    pub start: Option<usize>,
+    pub end: Option<usize>,
+    /// 1-based line and column of `start`, or `None` if there is no
+    /// location.
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub notes: Vec<String>,
+}
+
+#[pymethods]
+impl Diagnostic {
+    fn __repr__(&self) -> String {
+        format!("<Diagnostic {}: {}>", self.severity, self.message)
+    }
+}
+
+impl Diagnostic {
+    fn new(source: &str, error: &TranspilerError) -> Self {
+        let location = error.span.map(|span| span.location(source));
+        Self {
+            message: error.message.clone(),
+            severity: match error.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            },
+            start: error.span.map(|span| span.start),
+            end: error.span.map(|span| span.end),
+            line: location.map(|location| location.line),
+            column: location.map(|location| location.column),
+            notes: error.notes.clone(),
+        }
+    }
+}
+
+/// The result of a successful [`compile`].
+#[pyclass(module = "rusted_brains", frozen, get_all)]
+pub struct Compiled {
+    pub code: String,
+    pub warnings: Vec<Diagnostic>,
+}
+
+/// Compiles mini-rust `source` to Brainfuck.
+#[pyfunction]
+fn compile(py: Python<'_>, source: &str) -> PyResult<Compiled> {
+    match pipeline::compile(source) {
+        Ok(compiled) => Ok(Compiled {
+            code: compiled.code,
+            warnings: compiled
+                .warnings
+                .iter()
+                .map(|error| Diagnostic::new(source, error))
+                .collect(),
+        }),
+        Err(errors) => {
+            let rendered: Vec<String> = errors
+                .iter()
+                .map(|error| render::render(source, error))
+                .collect();
+            let exception = CompileError::new_err(rendered.join("\n"));
+            let diagnostics: Vec<Diagnostic> = errors
+                .iter()
+                .map(|error| Diagnostic::new(source, error))
+                .collect();
+            exception.value(py).setattr("diagnostics", diagnostics)?;
+            Err(exception)
+        }
+    }
+}
+
+/// Runs a Brainfuck program and returns its output.
+#[pyfunction]
+#[pyo3(signature = (code, input = None, max_steps = None))]
+fn run<'py>(
+    py: Python<'py>,
+    code: &str,
+    input: Option<&[u8]>,
+    max_steps: Option<u64>,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let mut interpreter = Interpreter::new(code).with_input(input.unwrap_or_default());
+    if let Some(max_steps) = max_steps {
+        interpreter = interpreter.with_max_steps(max_steps);
+    }
+    let execution = interpreter
+        .run()
+        .map_err(|error| BrainfuckError::new_err(error.to_string()))?;
+    Ok(PyBytes::new(py, &execution.output))
+}
+
+#[pymodule]
+fn rusted_brains(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(compile, m)?)?;
+    m.add_function(wrap_pyfunction!(run, m)?)?;
+    m.add_class::<Diagnostic>()?;
+    m.add_class::<Compiled>()?;
+    m.add("CompileError", m.py().get_type::<CompileError>())?;
+    m.add("BrainfuckError", m.py().get_type::<BrainfuckError>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compile_and_run() {
+        Python::initialize();
+        Python::attach(|py| {
+            let compiled = compile(py, "let x = 72; let y = 1; print(x);").unwrap();
+            assert_eq!(compiled.warnings.len(), 1);
+            assert_eq!(compiled.warnings[0].severity, "warning");
+
+            let output = run(py, &compiled.code, None, None).unwrap();
+            assert_eq!(output.as_bytes(), b"H");
+        });
+    }
+
+    #[test]
+    fn test_compile_error_carries_diagnostics() {
+        Python::initialize();
+        Python::attach(|py| {
+            let error = compile(py, "print(y);").err().unwrap();
+            assert!(error.is_instance_of::<CompileError>(py));
+
+            let diagnostics: Vec<Bound<'_, Diagnostic>> = error
+                .value(py)
+                .getattr("diagnostics")
+                .unwrap()
+                .extract()
+                .unwrap();
+            let diagnostic = diagnostics[0].get();
+            assert_eq!(diagnostic.message, "undefined variable `y`");
+            assert_eq!((diagnostic.start, diagnostic.end), (Some(0), Some(9)));
+            assert_eq!((diagnostic.line, diagnostic.column), (Some(1), Some(1)));
+        });
+    }
+
+    #[test]
+    fn test_runtime_error() {
+        Python::initialize();
+        Python::attach(|py| {
+            let error = run(py, "+[]", None, Some(100)).err().unwrap();
+            assert!(error.is_instance_of::<BrainfuckError>(py));
+        });
+    }
+}
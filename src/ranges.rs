@@ -0,0 +1,549 @@
+//! Value-range analysis: the interval of cell values each variable and
+//! expression can take at a point in the program.
+//!
+//! [`Ranges`] is carried through a program in the order its statements
+//! run, narrowed by the conditions that guard each branch and widened again
+//! where control flow meets. A loop body may run any number of times, so
+//! every variable it assigns is taken to hold any value on entry, rather
+//! than iterating to a fixed point. The result over-approximates what the
+//! program can do: a value outside its interval never occurs, but not
+//! every value inside one need occur either.
+//!
+//! The analyzer uses it to report arithmetic that always overflows, and
+//! checked code generation to leave out guards against wrapping that can
+//! never trigger.
+
+use crate::ast::{BinaryOp, Expr, Stmt, UnaryOp};
+use alloc::{collections::BTreeMap, string::String, vec, vec::Vec};
+use core::fmt;
+
+/// An inclusive range of cell values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval {
+    pub min: u8,
+    pub max: u8,
+}
+
+impl Interval {
+    /// Every value a cell can hold.
+    pub const FULL: Interval = Interval { min: 0, max: 255 };
+    /// The values of a `bool`.
+    pub const BOOL: Interval = Interval { min: 0, max: 1 };
+
+    pub fn new(min: u8, max: u8) -> Self {
+        debug_assert!(min <= max, "empty interval {}..={}", min, max);
+        Self { min, max }
+    }
+
+    pub fn exact(value: u8) -> Self {
+        Self::new(value, value)
+    }
+
+    /// The one value in the interval, if there is only one.
+    pub fn value(self) -> Option<u8> {
+        (self.min == self.max).then_some(self.min)
+    }
+
+    /// The smallest interval holding both.
+    pub fn hull(self, other: Self) -> Self {
+        Self::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    fn intersect(self, other: Self) -> Option<Self> {
+        let (min, max) = (self.min.max(other.min), self.max.min(other.max));
+        (min <= max).then(|| Self::new(min, max))
+    }
+
+    /// Whether every value is nonzero (`Some(true)`) or zero
+    /// (`Some(false)`), as a condition sees them.
+    fn truth(self) -> Option<bool> {
+        if self.min > 0 {
+            Some(true)
+        } else if self.max == 0 {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    fn of_bool(truth: Option<bool>) -> Self {
+        match truth {
+            Some(truth) => Self::exact(truth.into()),
+            None => Self::BOOL,
+        }
+    }
+
+    /// From bounds that may lie outside the cell's range, wrapping them the
+    /// way a cell does when that keeps them contiguous.
+    fn wrapped(min: i32, max: i32) -> Self {
+        if min.div_euclid(256) == max.div_euclid(256) {
+            Self::new(min.rem_euclid(256) as u8, max.rem_euclid(256) as u8)
+        } else {
+            Self::FULL
+        }
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.value() {
+            Some(value) => write!(f, "{}", value),
+            None => write!(f, "{}..={}", self.min, self.max),
+        }
+    }
+}
+
+/// The least and greatest results of `left operator right` before they
+/// wrap, for `+`, `-` and `*`; `None` for the operators that cannot wrap.
+pub fn unwrapped(left: Interval, operator: &BinaryOp, right: Interval) -> Option<(i32, i32)> {
+    let (left_min, left_max) = (i32::from(left.min), i32::from(left.max));
+    let (right_min, right_max) = (i32::from(right.min), i32::from(right.max));
+    match operator {
+        BinaryOp::Add => Some((left_min + right_min, left_max + right_max)),
+        BinaryOp::Sub => Some((left_min - right_max, left_max - right_min)),
+        BinaryOp::Mul => Some((left_min * right_min, left_max * right_max)),
+        _ => None,
+    }
+}
+
+/// The values `left operator right` can take.
+pub fn binary(left: Interval, operator: &BinaryOp, right: Interval) -> Interval {
+    if let Some((min, max)) = unwrapped(left, operator, right) {
+        return Interval::wrapped(min, max);
+    }
+    match operator {
+        // Dividing by zero stops the program, so only a nonzero divisor
+        // says anything about the result.
+        BinaryOp::Div if right.min > 0 => Interval::new(left.min / right.max, left.max / right.min),
+        BinaryOp::Mod if right.min > 0 => Interval::new(0, left.max.min(right.max - 1)),
+        BinaryOp::Div | BinaryOp::Mod => Interval::FULL,
+        BinaryOp::Less => Interval::of_bool(less(left, right, false)),
+        BinaryOp::LessEqual => Interval::of_bool(less(left, right, true)),
+        BinaryOp::Greater => Interval::of_bool(less(right, left, false)),
+        BinaryOp::GreaterEqual => Interval::of_bool(less(right, left, true)),
+        BinaryOp::Equal => Interval::of_bool(equal(left, right)),
+        BinaryOp::NotEqual => Interval::of_bool(equal(left, right).map(|equal| !equal)),
+        BinaryOp::And => Interval::of_bool(match (left.truth(), right.truth()) {
+            (Some(false), _) | (_, Some(false)) => Some(false),
+            (Some(true), Some(true)) => Some(true),
+            _ => None,
+        }),
+        BinaryOp::Or => Interval::of_bool(match (left.truth(), right.truth()) {
+            (Some(true), _) | (_, Some(true)) => Some(true),
+            (Some(false), Some(false)) => Some(false),
+            _ => None,
+        }),
+        BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul => unreachable!("handled above"),
+    }
+}
+
+/// Whether `left < right` (or `<=` if `or_equal`) always or never holds.
+fn less(left: Interval, right: Interval, or_equal: bool) -> Option<bool> {
+    let (always, never) = if or_equal {
+        (left.max <= right.min, left.min > right.max)
+    } else {
+        (left.max < right.min, left.min >= right.max)
+    };
+    if always {
+        Some(true)
+    } else if never {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+fn equal(left: Interval, right: Interval) -> Option<bool> {
+    match (left.value(), right.value()) {
+        (Some(left), Some(right)) => Some(left == right),
+        _ if left.intersect(right).is_none() => Some(false),
+        _ => None,
+    }
+}
+
+/// The comparison that holds when `operator` does not.
+fn negated(operator: &BinaryOp) -> Option<BinaryOp> {
+    Some(match operator {
+        BinaryOp::Less => BinaryOp::GreaterEqual,
+        BinaryOp::LessEqual => BinaryOp::Greater,
+        BinaryOp::Greater => BinaryOp::LessEqual,
+        BinaryOp::GreaterEqual => BinaryOp::Less,
+        BinaryOp::Equal => BinaryOp::NotEqual,
+        BinaryOp::NotEqual => BinaryOp::Equal,
+        _ => return None,
+    })
+}
+
+/// The comparison that holds for `b operator' a` exactly when `a operator
+/// b` does.
+fn flipped(operator: &BinaryOp) -> Option<BinaryOp> {
+    Some(match operator {
+        BinaryOp::Less => BinaryOp::Greater,
+        BinaryOp::LessEqual => BinaryOp::GreaterEqual,
+        BinaryOp::Greater => BinaryOp::Less,
+        BinaryOp::GreaterEqual => BinaryOp::LessEqual,
+        BinaryOp::Equal | BinaryOp::NotEqual => operator.clone(),
+        _ => return None,
+    })
+}
+
+/// The values a `for` over `iterable` gives its variable.
+pub fn bytes(iterable: &Expr) -> Interval {
+    match iterable {
+        Expr::Str(text) => text
+            .bytes()
+            .map(Interval::exact)
+            .reduce(Interval::hull)
+            .unwrap_or(Interval::FULL),
+        _ => Interval::FULL,
+    }
+}
+
+/// The interval of each variable in scope, innermost scope last. A name
+/// with no interval, such as a function's result, may hold any value.
+#[derive(Debug, Clone)]
+pub struct Ranges {
+    scopes: Vec<BTreeMap<String, Interval>>,
+    /// Cleared for code that does not run in the order it is written.
+    tracking: bool,
+    /// Set once a condition that cannot hold is assumed, as the code after
+    /// it never runs.
+    unreachable: bool,
+}
+
+impl Default for Ranges {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Ranges {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![BTreeMap::new()],
+            tracking: true,
+            unreachable: false,
+        }
+    }
+
+    /// Ranges that know nothing and learn nothing, where each variable may
+    /// hold any value.
+    pub fn unknown() -> Self {
+        Self {
+            tracking: false,
+            ..Self::new()
+        }
+    }
+
+    pub fn enter_scope(&mut self) {
+        self.scopes.push(BTreeMap::new());
+    }
+
+    pub fn exit_scope(&mut self) {
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
+    }
+
+    /// Binds `name` in the innermost scope, shadowing any outer binding.
+    pub fn declare(&mut self, name: &str, interval: Interval) {
+        if !self.tracking {
+            return;
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.into(), interval);
+        }
+    }
+
+    /// Sets the innermost binding of `name`, if there is one.
+    pub fn assign(&mut self, name: &str, interval: Interval) {
+        if let Some(current) = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+        {
+            *current = interval;
+        }
+    }
+
+    /// Whether the code being visited can run at all.
+    pub fn reachable(&self) -> bool {
+        !self.unreachable
+    }
+
+    pub fn get(&self, name: &str) -> Interval {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .copied()
+            .unwrap_or(Interval::FULL)
+    }
+
+    /// The values `expr` can take here.
+    pub fn of(&self, expr: &Expr) -> Interval {
+        match expr {
+            Expr::Number(value) => Interval::exact(value.rem_euclid(256) as u8),
+            Expr::Bool(value) => Interval::exact((*value).into()),
+            Expr::Variable(name) => self.get(name),
+            Expr::Unary {
+                operator: UnaryOp::Not,
+                operand,
+            } => Interval::of_bool(self.of(operand).truth().map(|truth| !truth)),
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => binary(self.of(left), operator, self.of(right)),
+            Expr::Str(_) | Expr::Call { .. } | Expr::Input => Interval::FULL,
+        }
+    }
+
+    /// Forgets what is known of every variable `body` assigns, so that
+    /// what is left holds on every pass through a loop around it.
+    pub fn widen(&mut self, body: &[Stmt]) {
+        for scope in &mut self.scopes {
+            for (name, interval) in scope.iter_mut() {
+                if body.iter().any(|stmt| stmt.assigns(name)) {
+                    *interval = Interval::FULL;
+                }
+            }
+        }
+    }
+
+    /// Narrows the variables `condition` tests to the values for which it
+    /// is true, or false if not `holds`. A condition that cannot hold at
+    /// all makes what follows unreachable.
+    pub fn assume(&mut self, condition: &Expr, holds: bool) {
+        match condition {
+            Expr::Unary {
+                operator: UnaryOp::Not,
+                operand,
+            } => self.assume(operand, !holds),
+            Expr::Binary {
+                left,
+                operator: BinaryOp::And,
+                right,
+            } if holds => {
+                self.assume(left, true);
+                self.assume(right, true);
+            }
+            Expr::Binary {
+                left,
+                operator: BinaryOp::Or,
+                right,
+            } if !holds => {
+                self.assume(left, false);
+                self.assume(right, false);
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                if let Expr::Variable(name) = &**left {
+                    self.narrow(name, operator, self.of(right), holds);
+                }
+                if let (Expr::Variable(name), Some(operator)) = (&**right, flipped(operator)) {
+                    self.narrow(name, &operator, self.of(left), holds);
+                }
+            }
+            Expr::Variable(name) => {
+                self.narrow(name, &BinaryOp::NotEqual, Interval::exact(0), holds);
+            }
+            _ => {}
+        }
+    }
+
+    /// Narrows `name` to the values for which `name operator other` holds,
+    /// or does not if not `holds`.
+    fn narrow(&mut self, name: &str, operator: &BinaryOp, other: Interval, holds: bool) {
+        if !self.tracking {
+            return;
+        }
+        let Some(operator) = (if holds {
+            Some(operator.clone())
+        } else {
+            negated(operator)
+        }) else {
+            return;
+        };
+        let current = self.get(name);
+        let bound = match operator {
+            BinaryOp::Less => other.max.checked_sub(1).map(|max| Interval::new(0, max)),
+            BinaryOp::LessEqual => Some(Interval::new(0, other.max)),
+            BinaryOp::Greater => other.min.checked_add(1).map(|min| Interval::new(min, 255)),
+            BinaryOp::GreaterEqual => Some(Interval::new(other.min, 255)),
+            BinaryOp::Equal => Some(other),
+            // Only a value at either end can be cut off.
+            BinaryOp::NotEqual => match other.value() {
+                Some(value) if current.value() == Some(value) => {
+                    self.unreachable = true;
+                    return;
+                }
+                Some(value) if value == current.min && value < current.max => {
+                    Some(Interval::new(value + 1, current.max))
+                }
+                Some(value) if value == current.max && value > current.min => {
+                    Some(Interval::new(current.min, value - 1))
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        match bound.map(|bound| current.intersect(bound)) {
+            Some(Some(narrowed)) => self.assign(name, narrowed),
+            Some(None) => self.unreachable = true,
+            None => {}
+        }
+    }
+
+    /// Widens each variable to also cover the values it has in `other`,
+    /// where two paths through the program meet.
+    pub fn join(&mut self, other: &Ranges) {
+        if other.unreachable {
+            return;
+        }
+        if self.unreachable {
+            self.unreachable = false;
+            for (scope, theirs) in self.scopes.iter_mut().zip(&other.scopes) {
+                scope.clone_from(theirs);
+            }
+            return;
+        }
+        for (scope, other) in self.scopes.iter_mut().zip(&other.scopes) {
+            for (name, interval) in scope.iter_mut() {
+                *interval = match other.get(name) {
+                    Some(&theirs) => interval.hull(theirs),
+                    None => Interval::FULL,
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn expr(source: &str) -> Expr {
+        let tokens = Lexer::new(&format!("print({});", source))
+            .tokenize()
+            .unwrap();
+        match Parser::new(tokens).parse().unwrap().remove(0) {
+            Stmt::Print { value, .. } => value,
+            other => panic!("expected a print, got {:?}", other),
+        }
+    }
+
+    fn with(variables: &[(&str, u8, u8)]) -> Ranges {
+        let mut ranges = Ranges::new();
+        for &(name, min, max) in variables {
+            ranges.declare(name, Interval::new(min, max));
+        }
+        ranges
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let ranges = with(&[("x", 10, 20), ("y", 0, 5)]);
+        let of = |source| ranges.of(&expr(source)).to_string();
+        assert_eq!(of("x + y"), "10..=25");
+        assert_eq!(of("x - y"), "5..=20");
+        assert_eq!(of("y - x"), "236..=251");
+        assert_eq!(of("x * 12"), "120..=240");
+        assert_eq!(of("x * 13"), "0..=255");
+        assert_eq!(of("x / (y + 1)"), "1..=20");
+        assert_eq!(of("x / y"), "0..=255");
+        assert_eq!(of("x % 7"), "0..=6");
+        assert_eq!(of("250 + 10"), "4");
+        assert_eq!(of("x < 21"), "1");
+        assert_eq!(of("x > y"), "1");
+        assert_eq!(of("x == y"), "0");
+        assert_eq!(of("x <= 15"), "0..=1");
+        assert_eq!(of("!(y < 10) || x == 20"), "0..=1");
+        assert_eq!(of("true && x != y"), "1");
+        assert_eq!(of("z + 1"), "0..=255");
+    }
+
+    #[test]
+    fn test_overflow_bounds() {
+        let x = Interval::new(200, 210);
+        assert_eq!(
+            unwrapped(x, &BinaryOp::Add, Interval::exact(100)),
+            Some((300, 310))
+        );
+        assert_eq!(
+            unwrapped(Interval::exact(3), &BinaryOp::Sub, x),
+            Some((-207, -197))
+        );
+        assert_eq!(unwrapped(x, &BinaryOp::Div, x), None);
+    }
+
+    #[test]
+    fn test_conditions_narrow() {
+        let narrowed = |condition: &str, holds: bool| {
+            let mut ranges = with(&[("x", 0, 255), ("y", 5, 9)]);
+            ranges.assume(&expr(condition), holds);
+            (ranges.get("x").to_string(), ranges.get("y").to_string())
+        };
+        assert_eq!(narrowed("x < 10", true), ("0..=9".into(), "5..=9".into()));
+        assert_eq!(
+            narrowed("x < 10", false),
+            ("10..=255".into(), "5..=9".into())
+        );
+        assert_eq!(narrowed("y >= x", true), ("0..=9".into(), "5..=9".into()));
+        assert_eq!(narrowed("x == y", true), ("5..=9".into(), "5..=9".into()));
+        assert_eq!(narrowed("x", true), ("1..=255".into(), "5..=9".into()));
+        assert_eq!(narrowed("x", false), ("0".into(), "5..=9".into()));
+        assert_eq!(narrowed("y != 9", true), ("0..=255".into(), "5..=8".into()));
+        assert_eq!(
+            narrowed("x > 3 && !(y == 5)", true),
+            ("4..=255".into(), "6..=9".into())
+        );
+        assert_eq!(
+            narrowed("x > 3 || y > 6", false),
+            ("0..=3".into(), "5..=6".into())
+        );
+        // Cannot be true, so nothing is learned.
+        assert_eq!(narrowed("y > 20", true), ("0..=255".into(), "5..=9".into()));
+    }
+
+    #[test]
+    fn test_loops_and_joins() {
+        let body = |source: &str| {
+            Parser::new(Lexer::new(source).tokenize().unwrap())
+                .parse()
+                .unwrap()
+        };
+        let mut ranges = with(&[("i", 0, 0), ("n", 3, 3)]);
+        ranges.widen(&body("while i < n { i = i + 1; }"));
+        assert_eq!(ranges.get("i"), Interval::FULL);
+        assert_eq!(ranges.get("n"), Interval::exact(3));
+
+        let mut left = with(&[("x", 1, 1)]);
+        let mut right = left.clone();
+        right.assign("x", Interval::exact(7));
+        left.enter_scope();
+        left.declare("x", Interval::exact(100));
+        left.exit_scope();
+        left.join(&right);
+        assert_eq!(left.get("x"), Interval::new(1, 7));
+
+        // A branch that cannot run adds nothing where the paths meet.
+        let mut dead = with(&[("x", 0, 0)]);
+        let live = dead.clone();
+        dead.assume(&expr("x > 0"), true);
+        assert!(!dead.reachable());
+        dead.join(&live);
+        assert!(dead.reachable());
+        assert_eq!(dead.get("x"), Interval::exact(0));
+
+        let mut unknown = Ranges::unknown();
+        unknown.declare("x", Interval::exact(1));
+        assert_eq!(unknown.get("x"), Interval::FULL);
+    }
+}
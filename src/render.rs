@@ -0,0 +1,238 @@
+use crate::error::{Severity, TranspilerError};
+use crate::span::{Location, Span, TAB_WIDTH, display_width};
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+/// Formats a diagnostic in the style of rustc: the message, its
+/// `line:column`, then the offending source line(s) with the reported span
+/// underlined by carets. Related locations follow as `note:` entries with
+/// their own excerpts.
+///
+/// Diagnostics without a span are rendered as the message alone.
+pub fn render(source: &str, error: &TranspilerError) -> String {
+    render_inner(None, source, error)
+}
+
+/// Like [`render`], with locations written as `filename:line:column`.
+pub fn render_file(filename: &str, source: &str, error: &TranspilerError) -> String {
+    render_inner(Some(filename), source, error)
+}
+
+/// The source lines `span` covers with carets underneath, after its
+/// `filename:line:column`, for pointing at code outside a diagnostic.
+pub fn render_span(filename: &str, source: &str, span: Span) -> String {
+    let mut out = String::new();
+    excerpt(Some(filename), source, span, &mut out);
+    out
+}
+
+fn render_inner(filename: Option<&str>, source: &str, error: &TranspilerError) -> String {
+    let label = match error.severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+    };
+    let mut out = format!("{}: {}\n", label, error.message);
+
+    let gutter = match error.span {
+        Some(span) => excerpt(filename, source, span, &mut out),
+        None => "  ".to_string(),
+    };
+    for note in &error.notes {
+        out.push_str(&format!("{} = {}\n", gutter, note));
+    }
+
+    for (message, span) in &error.related {
+        out.push_str(&format!("note: {}\n", message));
+        excerpt(filename, source, *span, &mut out);
+    }
+
+    out
+}
+
+/// Appends where `span` starts and the lines it covers with carets
+/// underneath, returning the blank gutter used so follow-up lines can align
+/// with it.
+fn excerpt(filename: Option<&str>, source: &str, span: Span, out: &mut String) -> String {
+    let start = span.start.min(source.len());
+    // Empty spans (e.g. end of input) still get a single caret.
+    let end = span.end.clamp(start + 1, source.len().max(start + 1));
+
+    let lines: Vec<(usize, &str)> = line_starts(source)
+        .into_iter()
+        .enumerate()
+        .filter(|&(_, (line_start, line))| {
+            let line_end = line_start + line.len();
+            line_start < end && start <= line_end
+        })
+        .map(|(number, (line_start, line))| {
+            (number + 1, &source[line_start..line_start + line.len()])
+        })
+        .collect();
+
+    let width = lines
+        .last()
+        .map_or(1, |&(number, _)| number.to_string().len());
+    let gutter = " ".repeat(width);
+    let location = Location::of(source, start);
+    match filename {
+        Some(filename) => out.push_str(&format!("{}--> {}:{}\n", gutter, filename, location)),
+        None => out.push_str(&format!("{}--> {}\n", gutter, location)),
+    }
+    out.push_str(&format!("{} |\n", gutter));
+
+    for (number, line) in lines {
+        let line_start = line.as_ptr() as usize - source.as_ptr() as usize;
+        let from = start.max(line_start) - line_start;
+        let to = end.min(line_start + line.len()).max(line_start + from) - line_start;
+
+        let padding = display_width(&line[..from]);
+        let carets = (display_width(&line[..to]) - padding).max(1);
+
+        out.push_str(&format!(
+            "{:>width$} | {}\n",
+            number,
+            printable(line),
+            width = width
+        ));
+        out.push_str(&format!(
+            "{} | {}{}\n",
+            gutter,
+            " ".repeat(padding),
+            "^".repeat(carets)
+        ));
+    }
+
+    gutter
+}
+
+/// `line` as it should be printed: tabs expanded to the columns
+/// [`display_width`] counts, and carriage returns and byte order marks
+/// dropped.
+fn printable(line: &str) -> String {
+    let mut out = String::new();
+    for ch in line.chars() {
+        match ch {
+            '\t' => {
+                let width = display_width(&out);
+                out.push_str(&" ".repeat((width / TAB_WIDTH + 1) * TAB_WIDTH - width));
+            }
+            '\r' | '\u{feff}' => {}
+            ch => out.push(ch),
+        }
+    }
+    out
+}
+
+/// Splits `source` into lines, pairing each with its starting byte offset.
+fn line_starts(source: &str) -> Vec<(usize, &str)> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    for line in source.split('\n') {
+        lines.push((offset, line));
+        offset += line.len() + 1;
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_underlines_span() {
+        let source = "let x = 1;\nlet y 3;\n";
+        let error = TranspilerError::with_span("Expected '='", Span::new(17, 18));
+
+        assert_eq!(
+            render(source, &error),
+            "error: Expected '='\n --> 2:7\n  |\n2 | let y 3;\n  |       ^\n"
+        );
+    }
+
+    #[test]
+    fn test_render_multiline_span() {
+        let source = "while x {\n}";
+        let error = TranspilerError::with_span("Loop", Span::new(6, 11));
+
+        assert_eq!(
+            render(source, &error),
+            "error: Loop\n --> 1:7\n  |\n1 | while x {\n  |       ^^^\n2 | }\n  | ^\n"
+        );
+    }
+
+    #[test]
+    fn test_render_span() {
+        let source = "let x = 1;\nx = 2;\n";
+        assert_eq!(
+            render_span("a.rs", source, Span::new(11, 17)),
+            " --> a.rs:2:1\n  |\n2 | x = 2;\n  | ^^^^^^\n"
+        );
+    }
+
+    #[test]
+    fn test_render_at_end_of_input() {
+        let source = "print(1";
+        let error = TranspilerError::with_span("Expected ')'", Span::new(7, 7));
+
+        assert_eq!(
+            render(source, &error),
+            "error: Expected ')'\n --> 1:8\n  |\n1 | print(1\n  |        ^\n"
+        );
+    }
+
+    #[test]
+    fn test_render_warning_label() {
+        let error = TranspilerError::warning("unused variable: `x`", Span::new(4, 5));
+        assert!(render("let x = 1;", &error).starts_with("warning: unused variable"));
+    }
+
+    #[test]
+    fn test_render_notes() {
+        let error = TranspilerError::with_span("undefined variable `cuont`", Span::new(6, 11))
+            .with_note("help: did you mean `count`?");
+
+        assert_eq!(
+            render("print(cuont);", &error),
+            "error: undefined variable `cuont`\n --> 1:7\n  |\n1 | print(cuont);\n  |       ^^^^^\n  = help: did you mean `count`?\n"
+        );
+    }
+
+    #[test]
+    fn test_render_related_location() {
+        let error = TranspilerError::warning("`x` declared twice", Span::new(11, 12))
+            .with_related("first declared here", Span::new(4, 5));
+
+        assert_eq!(
+            render("let x = 1;\nx", &error),
+            "warning: `x` declared twice\n --> 2:1\n  |\n2 | x\n  | ^\nnote: first declared here\n --> 1:5\n  |\n1 | let x = 1;\n  |     ^\n"
+        );
+    }
+
+    #[test]
+    fn test_render_windows_source() {
+        let source = "\u{feff}let x = 1;\r\n\tprint(\ty);\r\n";
+        let error = TranspilerError::with_span("undefined variable `y`", Span::new(23, 24));
+
+        assert_eq!(
+            render(source, &error),
+            "error: undefined variable `y`\n --> 2:13\n  |\n2 |     print(  y);\n  |             ^\n"
+        );
+    }
+
+    #[test]
+    fn test_render_file() {
+        let source = "let x = 1;\nlet y 3;\n";
+        let error = TranspilerError::with_span("Expected '='", Span::new(17, 18));
+
+        assert!(render_file("main.rs", source, &error).contains("\n --> main.rs:2:7\n"));
+    }
+
+    #[test]
+    fn test_render_without_span() {
+        let error = TranspilerError::new("Something went wrong");
+        assert_eq!(render("", &error), "error: Something went wrong\n");
+    }
+}
@@ -0,0 +1,240 @@
+//! The `--stats` and `--timings` reports, printed as tables or, with
+//! `--report-json`, saved as one JSON document covering every input.
+
+use std::fmt::{self, Write};
+use std::time::Duration;
+
+/// Sizes of what one compilation read and wrote.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    pub source_bytes: usize,
+    /// One per target, in the order given.
+    pub outputs: Vec<OutputStats>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputStats {
+    pub target: &'static str,
+    /// Size of the saved file.
+    pub bytes: usize,
+    /// Size of the code as the backend generated it, before optimizing
+    /// and before the metadata comment.
+    pub generated_bytes: usize,
+    /// Tape cells the Brainfuck may use, when that can be bounded; always
+    /// `None` for other targets.
+    pub tape_cells: Option<usize>,
+}
+
+/// How long each stage of one compilation took, in the order they ran.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Timings {
+    pub phases: Vec<(String, Duration)>,
+}
+
+impl Timings {
+    pub fn record(&mut self, phase: impl Into<String>, duration: Duration) {
+        self.phases.push((phase.into(), duration));
+    }
+
+    pub fn total(&self) -> Duration {
+        self.phases.iter().map(|(_, duration)| *duration).sum()
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Source: {} bytes", self.source_bytes)?;
+        write!(
+            f,
+            "{:<8} {:>10} {:>10} {:>6}",
+            "target", "bytes", "generated", "cells"
+        )?;
+        for output in &self.outputs {
+            let cells = output
+                .tape_cells
+                .map_or("-".to_string(), |cells| cells.to_string());
+            write!(
+                f,
+                "\n{:<8} {:>10} {:>10} {:>6}",
+                output.target, output.bytes, output.generated_bytes, cells
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (phase, duration) in &self.phases {
+            writeln!(f, "{:<16} {:>10.3} ms", phase, millis(*duration))?;
+        }
+        write!(f, "{:<16} {:>10.3} ms", "total", millis(self.total()))
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// One input's entry in the JSON report.
+pub struct FileReport<'a> {
+    pub input: &'a str,
+    /// `None` if the file did not compile.
+    pub stats: Option<&'a Stats>,
+    pub timings: Option<&'a Timings>,
+}
+
+/// The JSON report for a whole run. Only the sections that were asked for
+/// are included; durations are whole microseconds.
+pub fn to_json(files: &[FileReport], stats: bool, timings: bool) -> String {
+    let mut out = format!(
+        "{{\n  \"version\": {},\n  \"files\": [",
+        quote(env!("CARGO_PKG_VERSION"))
+    );
+    for (i, file) in files.iter().enumerate() {
+        let separator = if i == 0 { "" } else { "," };
+        let _ = write!(
+            out,
+            "{}\n    {{\n      \"input\": {},\n      \"ok\": {}",
+            separator,
+            quote(file.input),
+            file.stats.is_some()
+        );
+        if let Some(report) = file.stats.filter(|_| stats) {
+            let _ = write!(
+                out,
+                ",\n      \"stats\": {{\"source_bytes\": {}, \"outputs\": [",
+                report.source_bytes
+            );
+            for (i, output) in report.outputs.iter().enumerate() {
+                let _ = write!(
+                    out,
+                    "{}{{\"target\": {}, \"bytes\": {}, \"generated_bytes\": {}, \"tape_cells\": {}}}",
+                    if i == 0 { "" } else { ", " },
+                    quote(output.target),
+                    output.bytes,
+                    output.generated_bytes,
+                    output
+                        .tape_cells
+                        .map_or("null".to_string(), |cells| cells.to_string())
+                );
+            }
+            out.push_str("]}");
+        }
+        if let Some(report) = file.timings.filter(|_| timings) {
+            out.push_str(",\n      \"timings_us\": {");
+            for (phase, duration) in &report.phases {
+                let _ = write!(out, "{}: {}, ", quote(phase), duration.as_micros());
+            }
+            let _ = write!(out, "\"total\": {}}}", report.total().as_micros());
+        }
+        out.push_str("\n    }");
+    }
+    if !files.is_empty() {
+        out.push_str("\n  ");
+    }
+    out.push_str("]\n}\n");
+    out
+}
+
+/// `text` as a JSON string.
+pub fn quote(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if u32::from(c) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", u32::from(c));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example() -> (Stats, Timings) {
+        let stats = Stats {
+            source_bytes: 42,
+            outputs: vec![
+                OutputStats {
+                    target: "bf",
+                    bytes: 120,
+                    generated_bytes: 300,
+                    tape_cells: Some(104),
+                },
+                OutputStats {
+                    target: "c",
+                    bytes: 500,
+                    generated_bytes: 450,
+                    tape_cells: None,
+                },
+            ],
+        };
+        let mut timings = Timings::default();
+        timings.record("parse", Duration::from_micros(15));
+        timings.record("generate bf", Duration::from_micros(250));
+        (stats, timings)
+    }
+
+    #[test]
+    fn test_tables() {
+        let (stats, timings) = example();
+        assert_eq!(
+            stats.to_string(),
+            "Source: 42 bytes
+target        bytes  generated  cells
+bf              120        300    104
+c               500        450      -"
+        );
+        assert_eq!(
+            timings.to_string(),
+            "parse                 0.015 ms
+generate bf           0.250 ms
+total                 0.265 ms"
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let (stats, timings) = example();
+        let files = [
+            FileReport {
+                input: "dir/\"a\".rs",
+                stats: Some(&stats),
+                timings: Some(&timings),
+            },
+            FileReport {
+                input: "b.rs",
+                stats: None,
+                timings: None,
+            },
+        ];
+        let json = to_json(&files, true, true);
+        assert!(json.contains("\"input\": \"dir/\\\"a\\\".rs\",\n      \"ok\": true"));
+        assert!(json.contains(
+            "\"outputs\": [{\"target\": \"bf\", \"bytes\": 120, \"generated_bytes\": 300, \"tape_cells\": 104}, "
+        ));
+        assert!(json.contains("\"tape_cells\": null}]}"));
+        assert!(
+            json.contains("\"timings_us\": {\"parse\": 15, \"generate bf\": 250, \"total\": 265}")
+        );
+        assert!(json.contains("\"input\": \"b.rs\",\n      \"ok\": false\n    }"));
+
+        let json = to_json(&files[..1], false, true);
+        assert!(!json.contains("\"stats\""));
+        assert_eq!(
+            to_json(&[], true, true),
+            format!(
+                "{{\n  \"version\": \"{}\",\n  \"files\": []\n}}\n",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+    }
+}
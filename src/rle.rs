@@ -0,0 +1,137 @@
+//! Run-length-encoded Brainfuck.
+//!
+//! Some interpreters and Brainfuck derivatives accept a count before a
+//! command, so that `15+` means fifteen `+`. Generated code is mostly runs
+//! of moves and additions, so [`encode`] shrinks it a good deal; a
+//! [`RunReport`] tells by how much before committing to such a dialect.
+
+use alloc::{format, string::String};
+use core::fmt;
+
+/// Runs shorter than this are left alone, since `2+` is no shorter than
+/// `++`.
+pub const MIN_RUN: usize = 3;
+
+/// The commands a count may precede. Repeated `.` and `,` are left
+/// alone, as dialects disagree on whether they can be counted.
+const COUNTED: [char; 4] = ['+', '-', '>', '<'];
+
+/// Writes every run of at least [`MIN_RUN`] identical `+ - > <` commands
+/// as its length followed by the command. Everything else is copied as it
+/// is.
+///
+/// ```
+/// use rusted_brains::rle;
+///
+/// assert_eq!(rle::encode("+++++[->>>+<<<]++"), "5+[-3>+3<]++");
+/// ```
+pub fn encode(code: &str) -> String {
+    let mut out = String::new();
+    for_each_run(code, |command, length| {
+        if COUNTED.contains(&command) && length >= MIN_RUN {
+            out.push_str(&format!("{}{}", length, command));
+        } else {
+            out.extend(core::iter::repeat_n(command, length));
+        }
+    });
+    out
+}
+
+/// How much of a program's size is runs that [`encode`] shortens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RunReport {
+    /// Size of the plain program.
+    pub plain_bytes: usize,
+    /// How many runs get a count.
+    pub runs: usize,
+    /// Plain bytes those runs take up.
+    pub run_bytes: usize,
+    /// Size of the encoded program.
+    pub encoded_bytes: usize,
+}
+
+impl RunReport {
+    pub fn of(code: &str) -> Self {
+        let mut report = RunReport {
+            plain_bytes: code.len(),
+            ..RunReport::default()
+        };
+        for_each_run(code, |command, length| {
+            if COUNTED.contains(&command) && length >= MIN_RUN {
+                report.runs += 1;
+                report.run_bytes += length;
+                report.encoded_bytes += length.ilog10() as usize + 2;
+            } else {
+                report.encoded_bytes += length * command.len_utf8();
+            }
+        });
+        report
+    }
+}
+
+impl fmt::Display for RunReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let share = match self.plain_bytes {
+            0 => 0.0,
+            total => self.run_bytes as f64 * 100.0 / total as f64,
+        };
+        writeln!(
+            f,
+            "{} runs of {} or more commands: {} of {} bytes ({:.1}%)",
+            self.runs, MIN_RUN, self.run_bytes, self.plain_bytes, share
+        )?;
+        write!(
+            f,
+            "run-length encoded: {} -> {} bytes ({} saved)",
+            self.plain_bytes,
+            self.encoded_bytes,
+            self.plain_bytes - self.encoded_bytes
+        )
+    }
+}
+
+/// Calls `run` with each maximal run of one character and its length.
+fn for_each_run(code: &str, mut run: impl FnMut(char, usize)) {
+    let mut chars = code.chars().peekable();
+    while let Some(command) = chars.next() {
+        let mut length = 1;
+        while chars.next_if_eq(&command).is_some() {
+            length += 1;
+        }
+        run(command, length);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(encode(""), "");
+        assert_eq!(encode("++--><"), "++--><");
+        assert_eq!(encode("...,,,[[[-]]]"), "...,,,[[[-]]]");
+        let code = format!("{}.{}", "+".repeat(72), "<".repeat(100));
+        assert_eq!(encode(&code), "72+.100<");
+    }
+
+    #[test]
+    fn test_report() {
+        let code = "+++++[->>>+<<<]++";
+        let report = RunReport::of(code);
+        assert_eq!(
+            report,
+            RunReport {
+                plain_bytes: 17,
+                runs: 3,
+                run_bytes: 11,
+                encoded_bytes: encode(code).len(),
+            }
+        );
+        assert_eq!(
+            report.to_string(),
+            "3 runs of 3 or more commands: 11 of 17 bytes (64.7%)\n\
+             run-length encoded: 17 -> 12 bytes (5 saved)"
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use core::fmt;
+
+/// A half-open byte range `start..end` into the source text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// Returns the smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+
+    pub fn len(&self) -> usize {
+        self.end.saturating_sub(self.start)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Where the span starts, as a line and column in `source`.
+    pub fn location(&self, source: &str) -> Location {
+        Location::of(source, self.start)
+    }
+}
+
+/// Columns between tab stops when reporting positions and printing source.
+pub const TAB_WIDTH: usize = 4;
+
+/// A 1-based line and column, as shown to users. Columns count characters,
+/// not bytes, with tabs advancing to the next multiple of [`TAB_WIDTH`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    /// The location of byte `offset` in `source`; offsets past the end
+    /// refer to the end.
+    pub fn of(source: &str, offset: usize) -> Self {
+        let mut offset = offset.min(source.len());
+        while !source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let before = &source[..offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Self {
+            line: before.matches('\n').count() + 1,
+            column: display_width(&before[line_start..]) + 1,
+        }
+    }
+}
+
+/// How many columns `line` takes up when printed from the start of a line.
+/// Tabs expand to the next tab stop; carriage returns and byte order marks
+/// take no space.
+pub fn display_width(line: &str) -> usize {
+    line.chars().fold(0, |width, ch| match ch {
+        '\t' => (width / TAB_WIDTH + 1) * TAB_WIDTH,
+        '\r' | '\u{feff}' => width,
+        _ => width + 1,
+    })
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_location() {
+        let source = "let x = 1;\nlet é = 2;\n";
+        assert_eq!(Span::new(4, 5).location(source).to_string(), "1:5");
+        assert_eq!(Location::of(source, 11), Location { line: 2, column: 1 });
+        // Columns count the two-byte `é` once.
+        assert_eq!(Location::of(source, 18), Location { line: 2, column: 7 });
+        assert_eq!(Location::of(source, 100), Location { line: 3, column: 1 });
+    }
+
+    #[test]
+    fn test_location_in_windows_source() {
+        let source = "\u{feff}let x = 1;\r\n\tprint(\tx);\r\n";
+        assert_eq!(Location::of(source, 3), Location { line: 1, column: 1 });
+        assert_eq!(
+            Location::of(source, 13),
+            Location {
+                line: 1,
+                column: 11
+            }
+        );
+        assert_eq!(Location::of(source, 16), Location { line: 2, column: 5 });
+        // The second tab only pads to the next tab stop.
+        assert_eq!(
+            Location::of(source, 23),
+            Location {
+                line: 2,
+                column: 13
+            }
+        );
+    }
+}
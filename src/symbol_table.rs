@@ -0,0 +1,223 @@
+use crate::span::Span;
+use alloc::{collections::BTreeMap, string::String, vec, vec::Vec};
+use core::fmt;
+use core::ops::Range;
+
+/// The type of a value as far as the transpiler is concerned.
+///
+/// `Int` and `Bool` are stored in a single cell, and `Bool` values are
+/// always 0 or 1. A `Str` takes one cell per byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub enum Type {
+    Int,
+    Bool,
+    Str,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Bool => write!(f, "bool"),
+            Type::Str => write!(f, "str"),
+        }
+    }
+}
+
+/// Handle to a symbol, stable for the lifetime of the table even after the
+/// scope that declared it has been exited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SymbolId(usize);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub mutable: bool,
+    pub ty: Type,
+    /// Tape cells holding the value; empty until codegen assigns storage.
+    pub cells: Range<usize>,
+    pub span: Span,
+}
+
+impl Symbol {
+    pub fn new(name: impl Into<String>, mutable: bool, span: Span) -> Self {
+        Self {
+            name: name.into(),
+            mutable,
+            ty: Type::Int,
+            cells: 0..0,
+            span,
+        }
+    }
+
+    pub fn with_type(mut self, ty: Type) -> Self {
+        self.ty = ty;
+        self
+    }
+
+    pub fn with_cells(mut self, cells: Range<usize>) -> Self {
+        self.cells = cells;
+        self
+    }
+
+    /// First cell of the value's storage.
+    pub fn addr(&self) -> usize {
+        self.cells.start
+    }
+}
+
+/// Lexically scoped name resolution.
+///
+/// Declaring a name that is already visible shadows it until the scope that
+/// declared the new symbol is exited. Symbols are never removed, so passes
+/// can keep per-symbol data indexed by `SymbolId`.
+#[derive(Debug, Clone)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+    scopes: Vec<BTreeMap<String, SymbolId>>,
+    /// Index of the outermost scope each enclosing function body can see,
+    /// innermost last.
+    functions: Vec<usize>,
+}
+
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self {
+            symbols: Vec::new(),
+            scopes: vec![BTreeMap::new()],
+            functions: Vec::new(),
+        }
+    }
+
+    pub fn enter_scope(&mut self) {
+        self.scopes.push(BTreeMap::new());
+    }
+
+    pub fn exit_scope(&mut self) {
+        // The outermost scope lives as long as the table.
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
+    }
+
+    /// Enters a function body, where nothing declared outside it is
+    /// visible until [`exit_function`](Self::exit_function).
+    pub fn enter_function(&mut self) {
+        self.functions.push(self.scopes.len());
+        self.enter_scope();
+    }
+
+    pub fn exit_function(&mut self) {
+        if let Some(start) = self.functions.pop() {
+            self.scopes.truncate(start.max(1));
+        }
+    }
+
+    /// The scopes whose declarations are visible, outermost first.
+    fn visible_scopes(&self) -> &[BTreeMap<String, SymbolId>] {
+        &self.scopes[self.functions.last().copied().unwrap_or(0)..]
+    }
+
+    pub fn declare(&mut self, symbol: Symbol) -> SymbolId {
+        let id = SymbolId(self.symbols.len());
+        self.scopes
+            .last_mut()
+            .expect("symbol table always has a scope")
+            .insert(symbol.name.clone(), id);
+        self.symbols.push(symbol);
+        id
+    }
+
+    /// Resolves `name` to the innermost visible declaration.
+    pub fn resolve(&self, name: &str) -> Option<SymbolId> {
+        self.visible_scopes()
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+    }
+
+    /// Resolves `name` only among declarations in the innermost scope.
+    pub fn resolve_in_current_scope(&self, name: &str) -> Option<SymbolId> {
+        self.scopes
+            .last()
+            .and_then(|scope| scope.get(name).copied())
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<&Symbol> {
+        self.resolve(name).map(|id| self.get(id))
+    }
+
+    pub fn get(&self, id: SymbolId) -> &Symbol {
+        &self.symbols[id.0]
+    }
+
+    /// Names currently in scope, innermost scope first.
+    pub fn visible_names(&self) -> impl Iterator<Item = &str> {
+        self.visible_scopes()
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.keys().map(String::as_str))
+    }
+
+    /// Every symbol ever declared, in declaration order.
+    pub fn iter(&self) -> impl Iterator<Item = (SymbolId, &Symbol)> {
+        self.symbols
+            .iter()
+            .enumerate()
+            .map(|(i, symbol)| (SymbolId(i), symbol))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_function_bodies_only_see_their_own_names() {
+        let mut table = SymbolTable::new();
+        let outer = table.declare(Symbol::new("x", false, Span::default()));
+
+        table.enter_function();
+        assert_eq!(table.resolve("x"), None);
+        let param = table.declare(Symbol::new("y", false, Span::default()));
+        table.enter_scope();
+        assert_eq!(table.resolve("y"), Some(param));
+        table.exit_function();
+
+        assert_eq!(table.resolve("x"), Some(outer));
+        assert_eq!(table.resolve("y"), None);
+    }
+
+    #[test]
+    fn test_shadowing_is_undone_on_scope_exit() {
+        let mut table = SymbolTable::new();
+        let outer = table.declare(Symbol::new("x", false, Span::default()));
+
+        table.enter_scope();
+        let inner = table.declare(Symbol::new("x", true, Span::default()));
+        assert_eq!(table.resolve("x"), Some(inner));
+        table.exit_scope();
+
+        assert_eq!(table.resolve("x"), Some(outer));
+        assert!(!table.get(outer).mutable);
+    }
+
+    #[test]
+    fn test_inner_declarations_are_not_visible_outside() {
+        let mut table = SymbolTable::new();
+        table.enter_scope();
+        table.declare(Symbol::new("y", false, Span::default()).with_cells(3..4));
+        assert_eq!(table.lookup("y").map(Symbol::addr), Some(3));
+        table.exit_scope();
+
+        assert!(table.lookup("y").is_none());
+        assert_eq!(table.iter().count(), 1);
+    }
+}
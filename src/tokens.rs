@@ -0,0 +1,359 @@
+//! Token streams, for editing between the lexer and the parser and in
+//! compact form for the parser to walk.
+//!
+//! A [`SpannedToken`] is 40 bytes, most of it room for the `String` that only
+//! identifiers and strings carry. [`Tokens`] instead keeps one byte per token
+//! for its kind and its span in a parallel array, and moves the payloads of
+//! identifiers, numbers and strings to a side table, so the parser's
+//! `peek`/`advance` loop touches far less memory on large inputs. The lexer
+//! fills it directly ([`Tokens::lex`]), so the tokens are never all held
+//! whole at once.
+//! [`TokenStream`] keeps the tokens whole, so they are easy to change.
+
+use crate::ast::{SpannedToken, Token};
+use crate::error::Diagnostics;
+use crate::lexer::Lexer;
+use crate::span::Span;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+macro_rules! token_kinds {
+    ($($name:ident),* $(,)?) => {
+        /// The variant of a [`Token`], without its payload.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        #[repr(u8)]
+        pub enum TokenKind {
+            Identifier,
+            Number,
+            Str,
+            $($name,)*
+        }
+
+        impl TokenKind {
+            pub fn of(token: &Token) -> Self {
+                match token {
+                    Token::Identifier(_) => TokenKind::Identifier,
+                    Token::Number(_) => TokenKind::Number,
+                    Token::Str(_) => TokenKind::Str,
+                    $(Token::$name => TokenKind::$name,)*
+                }
+            }
+
+            /// The token of this kind, unless it carries a payload.
+            fn fixed(self) -> Option<&'static Token> {
+                match self {
+                    TokenKind::Identifier | TokenKind::Number | TokenKind::Str => None,
+                    $(TokenKind::$name => Some(&Token::$name),)*
+                }
+            }
+        }
+    };
+}
+
+token_kinds! {
+    Let, Mut, Print, Println, PrintNum, Input, If, Else, Match, While, Loop, Break, Continue, For, In,
+    Fn, Return, True, False,
+    Assign, FatArrow, Arrow, Plus, Minus, Multiply, Divide, Percent, Equal, NotEqual, Less,
+    Greater, LessEqual, GreaterEqual, And, Or,
+    Semicolon, LeftBrace, RightBrace, LeftParen, RightParen, Comma, Colon, Exclamation,
+    Hash, LeftBracket, RightBracket,
+    Eof,
+}
+
+/// A token stream stored as parallel arrays of kinds and spans, with the
+/// tokens that carry a payload kept in a side table.
+#[derive(Debug, Clone, Default)]
+pub struct Tokens {
+    kinds: Vec<TokenKind>,
+    spans: Vec<Span>,
+    /// Where each token's payload is in `payloads`, or 0 for tokens
+    /// without one.
+    slots: Vec<u32>,
+    payloads: Vec<Token>,
+}
+
+impl Tokens {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lexes `source` straight into compact form.
+    pub fn lex(source: &str) -> Result<Self, Diagnostics> {
+        Lexer::new(source).tokenize_into()
+    }
+
+    pub fn push(&mut self, token: SpannedToken) {
+        let kind = TokenKind::of(&token.token);
+        let slot = if kind.fixed().is_none() {
+            let slot = u32::try_from(self.payloads.len()).expect("more than u32::MAX payloads");
+            self.payloads.push(token.token);
+            slot
+        } else {
+            0
+        };
+        self.kinds.push(kind);
+        self.spans.push(token.span);
+        self.slots.push(slot);
+    }
+
+    pub fn len(&self) -> usize {
+        self.kinds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kinds.is_empty()
+    }
+
+    pub fn kind(&self, index: usize) -> Option<TokenKind> {
+        self.kinds.get(index).copied()
+    }
+
+    pub fn span(&self, index: usize) -> Option<Span> {
+        self.spans.get(index).copied()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Token> {
+        let kind = self.kind(index)?;
+        kind.fixed()
+            .or_else(|| Some(&self.payloads[self.slots[index] as usize]))
+    }
+}
+
+impl Extend<SpannedToken> for Tokens {
+    fn extend<I: IntoIterator<Item = SpannedToken>>(&mut self, iter: I) {
+        for token in iter {
+            self.push(token);
+        }
+    }
+}
+
+impl FromIterator<SpannedToken> for Tokens {
+    fn from_iter<I: IntoIterator<Item = SpannedToken>>(iter: I) -> Self {
+        let mut tokens = Tokens::new();
+        tokens.extend(iter);
+        tokens
+    }
+}
+
+impl From<Vec<SpannedToken>> for Tokens {
+    fn from(tokens: Vec<SpannedToken>) -> Self {
+        tokens.into_iter().collect()
+    }
+}
+
+/// Lexed tokens that can be changed before they are parsed, e.g. by a
+/// preprocessor expanding a name into the tokens it stands for.
+///
+/// Every token keeps the span it was read from wherever it is moved, so
+/// errors in the parsed program still point into the source. A token put
+/// in by [`splice`](Self::splice) or [`replace`](Self::replace) takes the
+/// span of what it replaces.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TokenStream {
+    tokens: Vec<SpannedToken>,
+}
+
+impl TokenStream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn lex(source: &str) -> Result<Self, Diagnostics> {
+        Lexer::new(source).tokenize().map(Self::from)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&SpannedToken> {
+        self.tokens.get(index)
+    }
+
+    pub fn iter(&self) -> core::slice::Iter<'_, SpannedToken> {
+        self.tokens.iter()
+    }
+
+    /// The index of the first run of tokens equal to `pattern` that starts
+    /// at or after `from`.
+    pub fn find(&self, pattern: &[Token], from: usize) -> Option<usize> {
+        if pattern.is_empty() {
+            return None;
+        }
+        let rest = self.tokens.get(from..)?;
+        rest.windows(pattern.len())
+            .position(|window| window.iter().map(|t| &t.token).eq(pattern))
+            .map(|i| from + i)
+    }
+
+    /// Replaces the tokens in `range` with `tokens`, returning the ones
+    /// taken out. The new tokens all span the tokens they replace, or the
+    /// point where they go in if `range` is empty.
+    ///
+    /// Panics if `range` is out of bounds, like [`Vec::splice`].
+    pub fn splice(
+        &mut self,
+        range: Range<usize>,
+        tokens: impl IntoIterator<Item = Token>,
+    ) -> Vec<SpannedToken> {
+        let span = self.span_of(range.clone());
+        let tokens = tokens
+            .into_iter()
+            .map(|token| SpannedToken::new(token, span));
+        self.tokens.splice(range, tokens).collect()
+    }
+
+    /// Like [`splice`](Self::splice), but the new tokens keep the spans
+    /// they come with, e.g. when moving tokens from elsewhere in the stream.
+    pub fn splice_spanned(
+        &mut self,
+        range: Range<usize>,
+        tokens: impl IntoIterator<Item = SpannedToken>,
+    ) -> Vec<SpannedToken> {
+        self.tokens.splice(range, tokens).collect()
+    }
+
+    /// Replaces the token at `index`, keeping its span, and returns the one
+    /// it replaced.
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn replace(&mut self, index: usize, token: Token) -> Token {
+        core::mem::replace(&mut self.tokens[index].token, token)
+    }
+
+    /// Replaces every run of tokens equal to `pattern` with `replacement`,
+    /// as [`splice`](Self::splice) does, and returns how many were
+    /// replaced. Tokens put in are not searched again, so a replacement
+    /// may contain its own pattern.
+    pub fn replace_all(&mut self, pattern: &[Token], replacement: &[Token]) -> usize {
+        let mut count = 0;
+        let mut from = 0;
+        while let Some(start) = self.find(pattern, from) {
+            self.splice(start..start + pattern.len(), replacement.iter().cloned());
+            from = start + replacement.len();
+            count += 1;
+        }
+        count
+    }
+
+    /// The span covering the tokens in `range`, or if there are none, the
+    /// empty one where they would go.
+    fn span_of(&self, range: Range<usize>) -> Span {
+        let replaced = &self.tokens[range.clone()];
+        if let (Some(first), Some(last)) = (replaced.first(), replaced.last()) {
+            return first.span.to(last.span);
+        }
+        let point = match self.tokens.get(range.start) {
+            Some(next) => next.span.start,
+            None => self.tokens.last().map_or(0, |last| last.span.end),
+        };
+        Span::new(point, point)
+    }
+}
+
+impl From<Vec<SpannedToken>> for TokenStream {
+    fn from(tokens: Vec<SpannedToken>) -> Self {
+        Self { tokens }
+    }
+}
+
+impl From<TokenStream> for Vec<SpannedToken> {
+    fn from(stream: TokenStream) -> Self {
+        stream.tokens
+    }
+}
+
+impl From<TokenStream> for Tokens {
+    fn from(stream: TokenStream) -> Self {
+        stream.tokens.into_iter().collect()
+    }
+}
+
+impl IntoIterator for TokenStream {
+    type Item = SpannedToken;
+    type IntoIter = alloc::vec::IntoIter<SpannedToken>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.tokens.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    #[test]
+    fn test_round_trip() {
+        let spanned = Lexer::new("let x = \"hi\" + 42; print(x);")
+            .tokenize()
+            .unwrap();
+        let tokens = Tokens::from(spanned.clone());
+
+        assert_eq!(tokens.len(), spanned.len());
+        for (i, expected) in spanned.iter().enumerate() {
+            assert_eq!(tokens.get(i), Some(&expected.token));
+            assert_eq!(tokens.span(i), Some(expected.span));
+            assert_eq!(tokens.kind(i), Some(TokenKind::of(&expected.token)));
+        }
+        assert_eq!(tokens.get(spanned.len()), None);
+        assert_eq!(tokens.payloads.len(), 4);
+        assert_eq!(tokens.slots[5], 2);
+        let lexed = Tokens::lex("let x = \"hi\" + 42; print(x);").unwrap();
+        assert_eq!(lexed.kinds, tokens.kinds);
+        assert_eq!(lexed.spans, tokens.spans);
+        assert_eq!(lexed.payloads, tokens.payloads);
+        assert_eq!(core::mem::size_of::<TokenKind>(), 1);
+    }
+
+    #[test]
+    fn test_token_stream_editing() {
+        let name = |name: &str| Token::Identifier(name.into());
+
+        // `NL` stands for 10, and takes its span.
+        let mut stream = TokenStream::lex("print(NL); print(NL);").unwrap();
+        assert_eq!(stream.replace_all(&[name("NL")], &[Token::Number(10)]), 2);
+        assert_eq!(stream.find(&[name("NL")], 0), None);
+        assert_eq!(stream.find(&[Token::Number(10)], 3), Some(7));
+        assert_eq!(
+            stream.get(7),
+            Some(&SpannedToken::new(Token::Number(10), Span::new(17, 19)))
+        );
+
+        // What is put in is not searched again.
+        let mut stream = TokenStream::lex("x").unwrap();
+        let doubled = [name("x"), Token::Plus, name("x")];
+        assert_eq!(stream.replace_all(&[name("x")], &doubled), 1);
+        assert_eq!(stream.len(), 4);
+
+        let mut stream = TokenStream::lex("print(1);").unwrap();
+        let removed = stream.splice(2..3, [Token::Number(2), Token::Plus, Token::Number(3)]);
+        assert_eq!(
+            removed,
+            vec![SpannedToken::new(Token::Number(1), Span::new(6, 7))]
+        );
+        assert_eq!(stream.replace(0, Token::Println), Token::Print);
+        let end = stream.len() - 1;
+        let print = [
+            Token::Print,
+            Token::LeftParen,
+            Token::Number(0),
+            Token::RightParen,
+        ];
+        stream.splice(end..end, print);
+        assert_eq!(stream.get(end).unwrap().span, Span::new(9, 9));
+        // Moved tokens keep their spans.
+        let moved = stream.splice_spanned(end..end + 4, []);
+        stream.splice_spanned(0..0, moved);
+
+        let program = crate::parser::Parser::from_tokens(stream.into())
+            .parse()
+            .unwrap();
+        assert_eq!(program[0].span(), Span::new(9, 9));
+        assert_eq!(program[1].span(), Span::new(0, 9));
+    }
+}
@@ -0,0 +1,35 @@
+//! Optional `tracing` instrumentation.
+//!
+//! The macros here forward to `tracing` when the feature is enabled and
+//! compile to nothing otherwise, so instrumented code needs no `cfg`s.
+
+/// Enters an info-level span for the rest of the enclosing scope:
+/// `let _span = span!("codegen");`.
+#[cfg(feature = "tracing")]
+macro_rules! span {
+    ($($args:tt)*) => {
+        tracing::info_span!($($args)*).entered()
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! span {
+    ($($args:tt)*) => {
+        ()
+    };
+}
+
+/// Emits a debug-level event, with the same syntax as `tracing::debug!`.
+#[cfg(feature = "tracing")]
+macro_rules! debug {
+    ($($args:tt)*) => {
+        tracing::debug!($($args)*)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! debug {
+    ($($args:tt)*) => {};
+}
+
+pub(crate) use {debug, span};
@@ -0,0 +1,202 @@
+//! Static checks on generated Brainfuck.
+//!
+//! Many interpreters treat moving left of cell 0 as a crash rather than
+//! wrapping around, so [`check_pointer`] proves the program never does,
+//! however its loops run. [`tape_cells`] bounds the other end of the tape
+//! the same way, which [`check_tape_size`] holds against a fixed size, and
+//! [`exit_cell`] finds where the pointer ends up.
+
+use crate::error::{TranspilerError, TranspilerResult};
+use crate::ir::{self, Op};
+use alloc::format;
+
+/// Proves the pointer, starting at cell 0, never moves below it.
+///
+/// The check tracks the lowest cell the pointer can be on. A loop is
+/// accepted when one pass through its body cannot leave the pointer further
+/// left than where it started, since then no number of passes can either.
+///
+/// ```
+/// use rusted_brains::{ir, verify::check_pointer};
+///
+/// assert!(check_pointer(&ir::parse(">+[->+<]<").unwrap()).is_ok());
+/// assert!(check_pointer(&ir::parse(">+[<]").unwrap()).is_err());
+/// ```
+pub fn check_pointer(ops: &[Op]) -> TranspilerResult<()> {
+    check_pointer_from(ops, 0)
+}
+
+/// [`check_pointer`] for a program that starts with the pointer on `cell`.
+///
+/// ```
+/// use rusted_brains::{ir, verify::check_pointer_from};
+///
+/// assert!(check_pointer_from(&ir::parse("<<+").unwrap(), 2).is_ok());
+/// assert!(check_pointer_from(&ir::parse("<<<+").unwrap(), 2).is_err());
+/// ```
+pub fn check_pointer_from(ops: &[Op], cell: usize) -> TranspilerResult<()> {
+    block(ops, cell as isize, 0).map(|_| ())
+}
+
+/// Checks `ops`, emitted from `position` on with the pointer at or right
+/// of `lowest`, and returns the lowest cell it can end on.
+fn block(ops: &[Op], mut lowest: isize, mut position: usize) -> TranspilerResult<isize> {
+    for op in ops {
+        match op {
+            Op::Move(n) => {
+                lowest += n;
+                if lowest < 0 {
+                    return Err(TranspilerError::new(format!(
+                        "Pointer may move left of cell 0 at position {}",
+                        position + ir::size(core::slice::from_ref(op)) - 1
+                    ))
+                    .with_note("strict interpreters stop with an error there"));
+                }
+            }
+            Op::Loop(body) if block(body, lowest, position + 1)? < lowest => {
+                return Err(TranspilerError::new(format!(
+                    "Loop at position {} may move the pointer further left on every pass",
+                    position
+                ))
+                .with_note("so it could end up left of cell 0"));
+            }
+            _ => {}
+        }
+        position += ir::size(core::slice::from_ref(op));
+    }
+    Ok(lowest)
+}
+
+/// How many cells, counting from cell 0, the program can reach, or `None`
+/// if a loop may carry the pointer further right on every pass.
+///
+/// ```
+/// use rusted_brains::{ir, verify::tape_cells};
+///
+/// assert_eq!(tape_cells(&ir::parse(">>+[-<+>]").unwrap()), Some(3));
+/// assert_eq!(tape_cells(&ir::parse("+[>+]").unwrap()), None);
+/// ```
+pub fn tape_cells(ops: &[Op]) -> Option<usize> {
+    tape_cells_from(ops, 0)
+}
+
+/// [`tape_cells`] for a program that starts with the pointer on `cell`.
+///
+/// ```
+/// use rusted_brains::{ir, verify::tape_cells_from};
+///
+/// assert_eq!(tape_cells_from(&ir::parse("<+").unwrap(), 4), Some(5));
+/// ```
+pub fn tape_cells_from(ops: &[Op], cell: usize) -> Option<usize> {
+    let mut reach = cell as isize;
+    extent(ops, cell as isize, &mut reach)?;
+    Some(reach as usize + 1)
+}
+
+/// Mirrors [`block`] for the right end: with the pointer at or left of
+/// `highest`, raises `reach` to the furthest cell `ops` can visit and
+/// returns the highest cell it can end on.
+fn extent(ops: &[Op], mut highest: isize, reach: &mut isize) -> Option<isize> {
+    for op in ops {
+        match op {
+            Op::Move(n) => {
+                highest += n;
+                *reach = (*reach).max(highest);
+            }
+            Op::Loop(body) if extent(body, highest, reach)? > highest => return None,
+            _ => {}
+        }
+    }
+    Some(highest)
+}
+
+/// Checks that the program, starting at cell 0, needs no more than `size`
+/// cells. One whose loops may carry the pointer right without bound, which
+/// generated programs never do, cannot be checked and passes.
+///
+/// ```
+/// use rusted_brains::{ir, verify::check_tape_size};
+///
+/// assert!(check_tape_size(&ir::parse(">>+").unwrap(), 3).is_ok());
+/// assert!(check_tape_size(&ir::parse(">>>+").unwrap(), 3).is_err());
+/// ```
+pub fn check_tape_size(ops: &[Op], size: usize) -> TranspilerResult<()> {
+    match tape_cells(ops) {
+        Some(cells) if cells > size => Err(TranspilerError::new(format!(
+            "Program needs {} cells, more than the {} on the tape",
+            cells, size
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// The cell the pointer ends on, or `None` if a loop can leave it
+/// somewhere that depends on how often it runs.
+///
+/// ```
+/// use rusted_brains::{ir, verify::exit_cell};
+///
+/// assert_eq!(exit_cell(&ir::parse(">>+[->+<]>").unwrap()), Some(3));
+/// assert_eq!(exit_cell(&ir::parse("+[>+]").unwrap()), None);
+/// ```
+pub fn exit_cell(ops: &[Op]) -> Option<usize> {
+    usize::try_from(offset(ops)?).ok()
+}
+
+/// How far `ops` moves the pointer, if every loop in it is balanced.
+fn offset(ops: &[Op]) -> Option<isize> {
+    let mut total = 0;
+    for op in ops {
+        match op {
+            Op::Move(n) => total += n,
+            Op::Loop(body) if offset(body)? != 0 => return None,
+            _ => {}
+        }
+    }
+    Some(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(code: &str) -> TranspilerResult<()> {
+        check_pointer(&ir::parse(code).unwrap())
+    }
+
+    #[test]
+    fn test_pointer_stays_on_tape() {
+        assert!(check("+>>++<<[->+<]>.").is_ok());
+        // Drifting right is always safe.
+        assert!(check("+[>+]>").is_ok());
+    }
+
+    #[test]
+    fn test_move_below_zero() {
+        let error = check("+.>+<<").unwrap_err();
+        assert_eq!(
+            error.message,
+            "Pointer may move left of cell 0 at position 5"
+        );
+    }
+
+    #[test]
+    fn test_loop_drifting_left() {
+        let error = check(">>+[-<]").unwrap_err();
+        assert_eq!(
+            error.message,
+            "Loop at position 3 may move the pointer further left on every pass"
+        );
+        // The same loop inside another is reported too.
+        assert!(check(">>+[>+[<<]]").is_err());
+    }
+
+    #[test]
+    fn test_tape_cells() {
+        let cells = |code: &str| tape_cells(&ir::parse(code).unwrap());
+        assert_eq!(cells("+."), Some(1));
+        assert_eq!(cells(">>>><<<<>"), Some(5));
+        assert_eq!(cells("+[>>>+<<<-]"), Some(4));
+        assert_eq!(cells("+[>[>]<]"), None);
+    }
+}
@@ -0,0 +1,248 @@
+//! Watching a program's variables change as it runs.
+//!
+//! A [`Watcher`] compiles the program without optimizing it, so the
+//! [`SourceMap`] still describes the code, and runs it on a [`Debugger`]
+//! watching the cells of the chosen variables. Reading a variable drains its
+//! cell and fills it back up, so writes are added up per statement and only
+//! what each statement leaves behind is reported.
+
+use crate::ast::Program;
+use crate::bf::{CellWrite, Debugger, Execution, Interpreter, RuntimeError, Stop};
+use crate::codegen::{BrainfuckGenerator, SourceMap};
+use crate::error::TranspilerError;
+use crate::span::Span;
+use crate::suggest;
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    format,
+    string::String,
+    vec,
+    vec::Vec,
+};
+
+/// A statement changing a watched variable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub name: String,
+    pub cell: usize,
+    /// The value before the statement ran.
+    pub old: u8,
+    /// The value the statement left behind.
+    pub new: u8,
+    /// The innermost statement that made the change; `None` for code that
+    /// belongs to no statement.
+    pub span: Option<Span>,
+}
+
+/// A program run a statement at a time, pausing whenever one changes a
+/// watched variable.
+///
+/// ```
+/// use rusted_brains::frontend::{Frontend, MiniRust};
+/// use rusted_brains::span::Span;
+/// use rusted_brains::watch::Watcher;
+///
+/// let source = "let mut x = 1; let y = x + 1; x = y * 3; print(x);";
+/// let program = MiniRust.parse(source).unwrap();
+/// let mut watcher = Watcher::new(&program, &["x"]).unwrap();
+///
+/// let change = watcher.next_change().unwrap().unwrap();
+/// assert_eq!((change.old, change.new), (0, 1));
+/// let change = watcher.next_change().unwrap().unwrap();
+/// assert_eq!((change.old, change.new), (1, 6));
+/// assert_eq!(change.span, Some(Span::new(30, 40)));
+/// assert_eq!(watcher.next_change().unwrap(), None);
+/// assert_eq!(watcher.output(), [6]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Watcher {
+    debugger: Debugger,
+    names: BTreeMap<usize, String>,
+    source_map: SourceMap,
+    /// Whether the code of some statement starts at each offset.
+    starts: Vec<bool>,
+    /// What the statements run since the last one started have changed, in
+    /// the order they first changed each cell.
+    pending: Vec<Change>,
+    ready: VecDeque<Change>,
+    finished: bool,
+}
+
+impl Watcher {
+    /// Compiles `program`, which must have passed analysis, and watches
+    /// every variable called one of `names`; a shadowed name watches each
+    /// variable of that name. A cell watched under two names, as a
+    /// parameter shares its argument's, is reported under the first.
+    pub fn new(program: &Program, names: &[&str]) -> Result<Self, TranspilerError> {
+        let mut generator = BrainfuckGenerator::new();
+        let code = generator.generate(program)?;
+        let mut debugger = Interpreter::new(code.as_bytes()).debugger()?;
+        let mut watched = BTreeMap::new();
+        for &name in names {
+            let cells = generator.cells_of(name);
+            if cells.is_empty() {
+                let mut error =
+                    TranspilerError::new(format!("cannot find variable `{}` to watch", name));
+                let cell_names = generator.cell_names();
+                let candidates = cell_names.values().map(String::as_str);
+                if let Some(candidate) = suggest::best_match(name, candidates) {
+                    error = error.with_note(format!("help: did you mean `{}`?", candidate));
+                }
+                return Err(error);
+            }
+            for cell in cells {
+                debugger.watch(cell);
+                watched.entry(cell).or_insert_with(|| name.into());
+            }
+        }
+
+        let source_map = generator.source_map().clone();
+        let mut starts = vec![false; code.len()];
+        for (range, _) in source_map.statements() {
+            // A statement with no code may start where the program ends.
+            if let Some(start) = starts.get_mut(range.start) {
+                *start = true;
+            }
+        }
+
+        Ok(Self {
+            debugger,
+            names: watched,
+            source_map,
+            starts,
+            pending: Vec::new(),
+            ready: VecDeque::new(),
+            finished: false,
+        })
+    }
+
+    /// Runs until a statement has changed a watched variable, returning
+    /// `None` once the program has finished.
+    pub fn next_change(&mut self) -> Result<Option<Change>, RuntimeError> {
+        loop {
+            if let Some(change) = self.ready.pop_front() {
+                return Ok(Some(change));
+            }
+            if self.finished {
+                return Ok(None);
+            }
+            if self.starts.get(self.debugger.position()) == Some(&true) {
+                self.flush();
+            }
+            match self.debugger.step()? {
+                None => {}
+                Some(Stop::Write(write)) => self.record(write),
+                Some(Stop::Finished) => {
+                    self.flush();
+                    self.finished = true;
+                }
+            }
+        }
+    }
+
+    /// What the program has printed so far.
+    pub fn output(&self) -> &[u8] {
+        self.debugger.output()
+    }
+
+    /// The state the program has reached, as if it ended here.
+    pub fn into_execution(self) -> Execution {
+        self.debugger.into_execution()
+    }
+
+    fn record(&mut self, write: CellWrite) {
+        let span = self.source_map.span_at(write.position);
+        match self
+            .pending
+            .iter_mut()
+            .find(|change| change.cell == write.cell && change.span == span)
+        {
+            Some(change) => change.new = write.new,
+            None => self.pending.push(Change {
+                name: self.names[&write.cell].clone(),
+                cell: write.cell,
+                old: write.old,
+                new: write.new,
+                span,
+            }),
+        }
+    }
+
+    /// Reports what the statements run since the last flush changed.
+    fn flush(&mut self) {
+        let changes = self.pending.drain(..);
+        self.ready
+            .extend(changes.filter(|change| change.old != change.new));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::{Frontend, MiniRust};
+
+    fn changes(source: &str, names: &[&str]) -> Vec<String> {
+        let program = MiniRust.parse(source).unwrap();
+        let mut watcher = Watcher::new(&program, names).unwrap();
+        let mut changes = Vec::new();
+        while let Some(change) = watcher.next_change().unwrap() {
+            let span = change.span.unwrap();
+            changes.push(format!(
+                "{}: {} -> {} in `{}`",
+                change.name,
+                change.old,
+                change.new,
+                &source[span.start..span.end]
+            ));
+        }
+        changes
+    }
+
+    #[test]
+    fn test_loops_report_each_pass() {
+        let source = "let mut i = 0; while i < 3 { i = i + 1; } print(i);";
+        assert_eq!(
+            changes(source, &["i"]),
+            [
+                "i: 0 -> 1 in `i = i + 1;`",
+                "i: 1 -> 2 in `i = i + 1;`",
+                "i: 2 -> 3 in `i = i + 1;`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reads_are_not_changes() {
+        let source = "let mut a = 5; let b = a * a; if a > 2 { a = b - a; } print(a);";
+        assert_eq!(
+            changes(source, &["a", "b"]),
+            [
+                "a: 0 -> 5 in `let mut a = 5;`",
+                "b: 0 -> 25 in `let b = a * a;`",
+                "a: 5 -> 20 in `a = b - a;`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parameters() {
+        let source = "fn twice(n) { n = n * 2; return n; } let mut x = 3; x = twice(x); print(x);";
+        assert_eq!(
+            changes(source, &["n", "x"]),
+            [
+                "x: 0 -> 3 in `let mut x = 3;`",
+                "n: 0 -> 3 in `x = twice(x);`",
+                "n: 3 -> 6 in `n = n * 2;`",
+                "x: 3 -> 6 in `x = twice(x);`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_variables() {
+        let program = MiniRust.parse("let count = 1; print(count);").unwrap();
+        let error = Watcher::new(&program, &["cuont"]).unwrap_err();
+        assert_eq!(error.message, "cannot find variable `cuont` to watch");
+        assert_eq!(error.notes, ["help: did you mean `count`?"]);
+    }
+}
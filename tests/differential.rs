@@ -0,0 +1,160 @@
+//! Differential testing: small random programs must print the same bytes
+//! when evaluated directly by `eval::Evaluator` and when compiled to
+//! Brainfuck and run by `bf::Interpreter`.
+
+use proptest::prelude::*;
+use proptest::sample::select;
+use rusted_brains::analysis::Analyzer;
+use rusted_brains::ast::{BinaryOp, Expr, MatchArm, Pattern, Program, Stmt, UnaryOp};
+use rusted_brains::bf::Interpreter;
+use rusted_brains::codegen::BrainfuckGenerator;
+use rusted_brains::eval::Evaluator;
+
+const VARIABLES: &[&str] = &["a", "b", "c"];
+
+// Operators and statements are limited to what the Brainfuck generator
+// implements correctly; extend these as codegen support lands.
+fn arithmetic() -> impl Strategy<Value = Expr> {
+    let leaf = prop_oneof![
+        (0..60).prop_map(Expr::number),
+        select(VARIABLES).prop_map(Expr::variable),
+    ];
+    leaf.prop_recursive(2, 6, 2, |inner| {
+        prop_oneof![
+            (
+                inner.clone(),
+                select(vec![BinaryOp::Add, BinaryOp::Sub]),
+                inner.clone(),
+            )
+                .prop_map(|(left, op, right)| Expr::binary(left, op, right)),
+            // Multiplying and dividing take time proportional to both
+            // operands, so small constants keep runs within the step limit.
+            (
+                inner,
+                select(vec![BinaryOp::Mul, BinaryOp::Div, BinaryOp::Mod]),
+                0..5
+            )
+                .prop_map(|(left, op, right)| Expr::binary(
+                    left,
+                    op,
+                    Expr::number(right)
+                )),
+        ]
+    })
+}
+
+fn condition() -> impl Strategy<Value = Expr> {
+    prop_oneof![
+        (
+            comparison(),
+            select(vec![BinaryOp::And, BinaryOp::Or]),
+            comparison(),
+        )
+            .prop_map(|(left, op, right)| Expr::binary(left, op, right)),
+        comparison(),
+        comparison().prop_map(|operand| Expr::unary(UnaryOp::Not, operand)),
+        // Comparing with a literal only works if comparisons leave exactly
+        // 1 or 0 behind.
+        (
+            comparison(),
+            select(vec![BinaryOp::Equal, BinaryOp::NotEqual]),
+            any::<bool>(),
+        )
+            .prop_map(|(left, op, right)| Expr::binary(left, op, Expr::Bool(right))),
+        any::<bool>().prop_map(Expr::Bool),
+        select(VARIABLES).prop_map(Expr::variable),
+    ]
+}
+
+fn comparison() -> impl Strategy<Value = Expr> {
+    (
+        arithmetic(),
+        select(vec![
+            BinaryOp::Equal,
+            BinaryOp::NotEqual,
+            BinaryOp::Less,
+            BinaryOp::Greater,
+            BinaryOp::LessEqual,
+            BinaryOp::GreaterEqual,
+        ]),
+        arithmetic(),
+    )
+        .prop_map(|(left, op, right)| Expr::binary(left, op, right))
+}
+
+fn statement() -> impl Strategy<Value = Stmt> {
+    let leaf = prop_oneof![
+        (select(VARIABLES), arithmetic()).prop_map(|(name, value)| Stmt::assign(name, value)),
+        arithmetic().prop_map(Stmt::print),
+        arithmetic().prop_map(Stmt::print_num),
+        "[\\x00-\\x7f]{0,6}".prop_map(|text| Stmt::print(Expr::string(text))),
+    ];
+    leaf.prop_recursive(2, 12, 3, |inner| {
+        let pattern =
+            prop_oneof![4 => (0..60).prop_map(Pattern::Number), 1 => Just(Pattern::Wildcard)];
+        let arm = (pattern, prop::collection::vec(inner.clone(), 0..2))
+            .prop_map(|(pattern, body)| MatchArm::new(pattern, body));
+        prop_oneof![
+            (
+                condition(),
+                prop::collection::vec(inner.clone(), 0..3),
+                prop::collection::vec(inner, 0..2),
+            )
+                .prop_map(|(condition, body, else_body)| Stmt::if_else(condition, body, else_body)),
+            (arithmetic(), prop::collection::vec(arm, 0..4))
+                .prop_map(|(scrutinee, arms)| Stmt::match_stmt(scrutinee, arms)),
+        ]
+    })
+}
+
+/// A `for` loop whose body may `break` or `continue` from inside an `if`.
+/// Loops over a string are unrolled, so unlike `while` it always ends.
+fn for_loop() -> impl Strategy<Value = Stmt> {
+    let jump = prop_oneof![Just(Stmt::break_stmt()), Just(Stmt::continue_stmt())];
+    let body = prop::collection::vec(
+        prop_oneof![
+            statement(),
+            (condition(), jump).prop_map(|(condition, jump)| Stmt::if_stmt(condition, vec![jump])),
+        ],
+        1..4,
+    );
+    body.prop_map(|body| Stmt::for_stmt("_byte", Expr::string("xyz"), body))
+}
+
+fn program() -> impl Strategy<Value = Program> {
+    (
+        prop::collection::vec(0..60, VARIABLES.len()),
+        prop::collection::vec(prop_oneof![4 => statement(), 1 => for_loop()], 1..6),
+    )
+        .prop_map(|(initial, body)| {
+            let mut program: Program = VARIABLES
+                .iter()
+                .zip(initial)
+                .map(|(name, value)| Stmt::let_stmt(*name, true, Expr::number(value)))
+                .collect();
+            program.extend(body);
+            program
+        })
+}
+
+proptest! {
+    #[test]
+    fn generated_code_matches_reference(program in program()) {
+        prop_assert!(!Analyzer::new().analyze(&program).has_errors());
+
+        // Brainfuck cannot fail, so division by zero has no counterpart.
+        let expected = Evaluator::new().run(&program);
+        prop_assume!(expected.is_ok());
+        let expected = expected.unwrap();
+        let generators = [
+            BrainfuckGenerator::new(),
+            BrainfuckGenerator::new().with_checked_arithmetic(),
+            BrainfuckGenerator::new().with_constant_pool(),
+        ];
+        for mut generator in generators {
+            let code = generator.generate(&program).unwrap();
+            let execution = Interpreter::new(code).with_max_steps(10_000_000).run();
+            prop_assert_eq!(&execution.unwrap().output, &expected.output);
+        }
+    }
+}
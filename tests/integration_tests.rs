@@ -1,37 +1,506 @@
+//! End-to-end tests of the command-line tool. What it generates for each
+//! example is covered by the snapshot tests in `snapshots.rs`.
+
+use std::env;
 use std::fs;
-use std::process::Command;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+fn rust2bf(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_RustedBrains"))
+        .args(args)
+        .output()
+        .expect("Failed to execute command")
+}
+
+/// Copies an example into a scratch directory of its own so tests can run
+/// in parallel without sharing output files.
+fn scratch_copy(example: &str, test: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("rust2bf-{}-{}", test, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(example);
+    fs::copy(
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("examples")
+            .join(example),
+        &path,
+    )
+    .unwrap();
+    path
+}
 
 #[test]
 fn test_compile_simple_example() {
-    let output = Command::new("cargo")
-        .args(&["run", "examples/simple.txt"])
-        .output()
-        .expect("Failed to execute command");
+    let input = scratch_copy("simple.txt", "compile");
+    let output = rust2bf(&[input.to_str().unwrap()]);
 
     assert!(output.status.success());
     assert!(String::from_utf8_lossy(&output.stdout).contains("Generated Brainfuck"));
+    let _ = fs::remove_dir_all(input.parent().unwrap());
 }
 
 #[test]
 fn test_output_file_creation() {
-    let _output = Command::new("cargo")
-        .args(&["run", "examples/simple.txt"])
-        .output()
-        .expect("Failed to execute command");
+    let input = scratch_copy("simple.txt", "output");
+    let output = rust2bf(&[input.to_str().unwrap()]);
+
+    assert!(output.status.success());
+    assert!(fs::metadata(input.with_extension("txt.bf")).is_ok());
+    let _ = fs::remove_dir_all(input.parent().unwrap());
+}
+
+#[test]
+fn test_multiple_targets() {
+    let input = scratch_copy("simple.txt", "targets");
+    let output = rust2bf(&[
+        "--target",
+        "bf,c",
+        "--target",
+        "wat",
+        input.to_str().unwrap(),
+    ]);
+
+    assert!(output.status.success());
+    for extension in ["txt.bf", "txt.c", "txt.wat"] {
+        assert!(fs::metadata(input.with_extension(extension)).is_ok());
+    }
+    let _ = fs::remove_dir_all(input.parent().unwrap());
+}
+
+#[test]
+fn test_dead_functions_are_dropped() {
+    let dir = env::temp_dir().join(format!("rust2bf-dead-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("dead.rs");
+    fs::write(
+        &input,
+        "fn helper(x) { print(x); }\nfn used() { helper(66); }\n\
+         fn unused() { helper(1); }\nused();\n",
+    )
+    .unwrap();
+    let output = rust2bf(&["--target", "c", "--opt-report", input.to_str().unwrap()]);
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("=== Dead Functions ===\nunused\n"));
+    let c = fs::read_to_string(dir.join("dead.c")).unwrap();
+    assert!(c.contains("helper"));
+    assert!(!c.contains("unused"));
+    let _ = fs::remove_dir_all(dir);
+}
 
-    assert!(fs::metadata("examples/simple.txt.bf").is_ok());
+#[test]
+fn test_metadata_is_reproducible() {
+    let first = scratch_copy("simple.txt", "metadata-a");
+    let second = scratch_copy("simple.txt", "metadata-b");
+    assert!(rust2bf(&[first.to_str().unwrap()]).status.success());
+    assert!(rust2bf(&[second.to_str().unwrap()]).status.success());
+
+    let code = fs::read_to_string(first.with_extension("txt.bf")).unwrap();
+    assert!(code.starts_with("[Generated by rust2bf "));
+    assert_eq!(
+        code,
+        fs::read_to_string(second.with_extension("txt.bf")).unwrap()
+    );
 
-    // Cleanup
-    let _ = fs::remove_file("examples/simple.txt.bf");
+    assert!(
+        rust2bf(&["--no-metadata", first.to_str().unwrap()])
+            .status
+            .success()
+    );
+    let bare = fs::read_to_string(first.with_extension("txt.bf")).unwrap();
+    assert!(code.ends_with(&bare) && !bare.contains("Generated"));
+
+    let _ = fs::remove_dir_all(first.parent().unwrap());
+    let _ = fs::remove_dir_all(second.parent().unwrap());
 }
 
 #[test]
 fn test_error_handling() {
-    let output = Command::new("cargo")
-        .args(&["run", "nonexistent.rs"])
-        .output()
-        .expect("Failed to execute command");
+    let output = rust2bf(&["nonexistent.rs"]);
 
     assert!(!output.status.success());
     assert!(String::from_utf8_lossy(&output.stderr).contains("Error"));
 }
+
+#[test]
+fn test_example_subcommand() {
+    let listed = rust2bf(&["example"]);
+    assert!(listed.status.success());
+    assert!(String::from_utf8_lossy(&listed.stdout).contains("fizzbuzz"));
+
+    let printed = rust2bf(&["example", "counter"]);
+    assert!(printed.status.success());
+    assert_eq!(
+        printed.stdout,
+        fs::read(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("examples/counter.txt")).unwrap()
+    );
+
+    let missing = rust2bf(&["example", "nope"]);
+    assert!(!missing.status.success());
+    assert!(String::from_utf8_lossy(&missing.stderr).contains("hello_world"));
+}
+
+#[test]
+fn test_record_and_replay_session() {
+    let dir = env::temp_dir().join(format!("rust2bf-session-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let program = dir.join("upcase.bf");
+    let session = dir.join("session.json");
+    // Prints each byte read, minus 32, until the input runs out.
+    fs::write(&program, ",[--------------------------------.,]").unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_RustedBrains"))
+        .args(["run", program.to_str().unwrap(), "--record"])
+        .arg(&session)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"abc").unwrap();
+    let recorded = child.wait_with_output().unwrap();
+    assert!(recorded.status.success());
+    assert_eq!(recorded.stdout, b"ABC");
+    assert!(
+        fs::read_to_string(&session)
+            .unwrap()
+            .contains("\"input\": \"abc\"")
+    );
+
+    let replayed = rust2bf(&[
+        "run",
+        program.to_str().unwrap(),
+        "--replay",
+        session.to_str().unwrap(),
+    ]);
+    assert!(replayed.status.success());
+    assert_eq!(replayed.stdout, b"ABC");
+
+    // A program that no longer does the same thing fails the replay.
+    fs::write(&program, ",[-------------------------------.,]").unwrap();
+    let changed = rust2bf(&[
+        "run",
+        program.to_str().unwrap(),
+        "--replay",
+        session.to_str().unwrap(),
+    ]);
+    assert!(!changed.status.success());
+    assert!(String::from_utf8_lossy(&changed.stderr).contains("at byte 0"));
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn test_budgets() {
+    let input = scratch_copy("simple.txt", "budgets");
+    let input = input.to_str().unwrap();
+
+    let within = rust2bf(&["--max-size", "100000", "--max-cells", "1000", input]);
+    assert!(within.status.success());
+
+    for budget in ["--max-size", "--max-cells", "--max-est-steps"] {
+        let over = rust2bf(&[budget, "3", input]);
+        assert!(!over.status.success());
+        let stderr = String::from_utf8_lossy(&over.stderr);
+        assert!(stderr.contains(&format!("the {} budget of 3", budget)));
+    }
+    let _ = fs::remove_dir_all(PathBuf::from(input).parent().unwrap());
+}
+
+#[test]
+fn test_emit_rust_embed() {
+    let input = scratch_copy("simple.txt", "embed");
+    let output = rust2bf(&["--emit=rust-embed", input.to_str().unwrap()]);
+    assert!(output.status.success());
+
+    let embedded = fs::read_to_string(input.with_extension("txt.embed.rs")).unwrap();
+    assert!(embedded.starts_with("// Generated by rust2bf"));
+    assert!(embedded.contains("pub const PROGRAM: &str = \""));
+    assert!(!embedded.contains("pub fn run"));
+    let _ = fs::remove_dir_all(input.parent().unwrap());
+}
+
+#[test]
+fn test_run_bytecode() {
+    let input = scratch_copy("simple.txt", "run-bfc");
+    let output = rust2bf(&["--emit", "bfc", input.to_str().unwrap()]);
+    assert!(output.status.success());
+    let program = input.with_extension("txt.bfc");
+    assert!(fs::read(&program).unwrap().starts_with(b"BFC"));
+
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_RustedBrains"))
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .unwrap()
+    };
+    let bf = input.with_extension("txt.bf");
+    assert!(rust2bf(&[input.to_str().unwrap()]).status.success());
+    let expected = run(&["run", bf.to_str().unwrap()]);
+    let output = run(&["run", program.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert!(!output.stdout.is_empty());
+    assert_eq!(output.stdout, expected.stdout);
+
+    let output = run(&["run", program.to_str().unwrap(), "--trap-overflow"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is bytecode"));
+    let _ = fs::remove_dir_all(input.parent().unwrap());
+}
+
+#[test]
+fn test_emit_rle() {
+    let input = scratch_copy("counter.txt", "rle");
+    let input = input.to_str().unwrap();
+    assert!(rust2bf(&["--no-metadata", input]).status.success());
+    let output = rust2bf(&["--emit", "rle", input]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("=== Run-Length Encoding ==="));
+
+    let plain = fs::read_to_string(format!("{}.bf", input)).unwrap();
+    let encoded = fs::read_to_string(format!("{}.rle", input)).unwrap();
+    assert_eq!(encoded, rusted_brains::rle::encode(&plain));
+    assert!(encoded.len() < plain.len());
+    let _ = fs::remove_dir_all(PathBuf::from(input).parent().unwrap());
+}
+
+#[test]
+fn test_large_output_is_saved_not_shown() {
+    let dir = env::temp_dir().join(format!("rust2bf-large-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("large.txt");
+    // Each byte of the string is set and printed in its own code.
+    let source = format!("for c in \"{}\" {{ print(c); }}", "~".repeat(2000));
+    fs::write(&input, source).unwrap();
+
+    let output = rust2bf(&["--no-metadata", input.to_str().unwrap()]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let code = fs::read_to_string(input.with_extension("txt.bf")).unwrap();
+    assert!(code.len() > 64 * 1024);
+    assert!(stdout.contains(&format!(
+        "({} bytes, too long to show here; see the saved file)",
+        code.len()
+    )));
+    assert!(!stdout.contains(&code));
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn test_no_optimize_writes_while_generating() {
+    let input = scratch_copy("fizzbuzz.txt", "no-optimize");
+    let path = input.to_str().unwrap();
+    let saved = input.with_extension("txt.bf");
+
+    let output = rust2bf(&["--no-optimize", path]);
+    assert!(output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stdout)
+            .contains("(written to the saved file as it was generated)")
+    );
+    let streamed = fs::read_to_string(&saved).unwrap();
+
+    // A budget needs the whole program, so this one is built in memory.
+    let output = rust2bf(&["--no-optimize", "--max-size", "10000000", path]);
+    assert!(output.status.success());
+    assert_eq!(fs::read_to_string(&saved).unwrap(), streamed);
+
+    let output = rust2bf(&[path]);
+    assert!(output.status.success());
+    assert!(fs::read_to_string(&saved).unwrap().len() < streamed.len());
+    let _ = fs::remove_dir_all(input.parent().unwrap());
+}
+
+#[test]
+fn test_verify_expectations() {
+    let input = scratch_copy("hello_world.txt", "verify");
+    let dir = input.parent().unwrap().to_path_buf();
+    let output = rust2bf(&["verify", input.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("ok "));
+
+    let wrong = dir.join("wrong.txt");
+    fs::write(&wrong, "print(65);\n//! expect: B\n").unwrap();
+    let untested = dir.join("untested.txt");
+    fs::write(&untested, "print(65);\n").unwrap();
+    let output = rust2bf(&[
+        "verify",
+        wrong.to_str().unwrap(),
+        untested.to_str().unwrap(),
+        input.to_str().unwrap(),
+    ]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("expected: \"B\"\n  actual:   \"A\""));
+    assert!(stderr.contains("has no `//! expect:` comments"));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("hello_world.txt"));
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn test_verify_trap_overflow() {
+    let dir = env::temp_dir().join(format!("rust2bf-trap-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let wraps = dir.join("wraps.txt");
+    fs::write(
+        &wraps,
+        "let x = input();\nprint(x + 200);\n//! input: A\n//! expect: \\x09\n",
+    )
+    .unwrap();
+    assert!(
+        rust2bf(&["verify", wraps.to_str().unwrap()])
+            .status
+            .success()
+    );
+
+    let output = rust2bf(&["verify", "--trap-overflow", wraps.to_str().unwrap()]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("arithmetic wrapped around"));
+    assert!(stderr.contains("wraps.txt:2:1"));
+
+    let program = dir.join("wraps.bf");
+    fs::write(&program, "-.").unwrap();
+    let run = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_RustedBrains"))
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .unwrap()
+    };
+    assert_eq!(run(&["run", program.to_str().unwrap()]).stdout, [255]);
+    let output = run(&["run", program.to_str().unwrap(), "--trap-overflow"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--checked-arithmetic"));
+
+    assert!(
+        rust2bf(&["--checked-arithmetic", wraps.to_str().unwrap()])
+            .status
+            .success()
+    );
+    let mut child = Command::new(env!("CARGO_BIN_EXE_RustedBrains"))
+        .args([
+            "run",
+            dir.join("wraps.txt.bf").to_str().unwrap(),
+            "--trap-overflow",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"A").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("arithmetic wrapped around"));
+    assert!(stderr.contains("wraps.txt:2:1"));
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn test_stats_report_json() {
+    let input = scratch_copy("simple.txt", "report");
+    let dir = input.parent().unwrap().to_path_buf();
+    let report = dir.join("report.json");
+    let output = rust2bf(&[
+        "--stats",
+        "--timings",
+        "--report-json",
+        report.to_str().unwrap(),
+        input.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("=== Statistics ==="));
+    assert!(stdout.contains("=== Timings ==="));
+
+    let json = fs::read_to_string(&report).unwrap();
+    assert!(json.contains("\"ok\": true"));
+    assert!(json.contains("\"outputs\": [{\"target\": \"bf\", \"bytes\": "));
+    assert!(json.contains("\"timings_us\": {\"parse\": "));
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn test_batch_writes_build_db() {
+    let first = scratch_copy("simple.txt", "build-db");
+    let second = scratch_copy("hello_world.txt", "build-db");
+    let dir = first.parent().unwrap().to_path_buf();
+    let db = dir.join("db.json");
+    let output = rust2bf(&[
+        "--target",
+        "bf,c",
+        "--build-db",
+        db.to_str().unwrap(),
+        first.to_str().unwrap(),
+        second.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+
+    let json = fs::read_to_string(&db).unwrap();
+    assert_eq!(json.matches("\"ok\": true").count(), 2);
+    assert!(json.contains(&format!(
+        "\"arguments\": [\"rust2bf\", \"--frontend\", \"mini-rust\", \"--target\", \"bf,c\", \"{}\"]",
+        first.display()
+    )));
+    assert!(json.contains(&format!(
+        "\"path\": \"{}\", \"sha256\": \"",
+        first.with_extension("txt.bf").display()
+    )));
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn test_link_runs_programs_in_turn() {
+    let first = scratch_copy("hello_world.txt", "link");
+    let second = scratch_copy("counter.txt", "link");
+    let dir = first.parent().unwrap().to_path_buf();
+    let programs = [
+        first.with_extension("txt.bf"),
+        second.with_extension("txt.bf"),
+    ];
+    let mut expected = Vec::new();
+    for (input, program) in [&first, &second].iter().zip(&programs) {
+        assert!(rust2bf(&[input.to_str().unwrap()]).status.success());
+        expected.extend(rust2bf(&["run", program.to_str().unwrap()]).stdout);
+    }
+
+    let linked = dir.join("linked.bf");
+    let output = rust2bf(&[
+        "link",
+        programs[0].to_str().unwrap(),
+        programs[1].to_str().unwrap(),
+        "-o",
+        linked.to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    let run = rust2bf(&["run", linked.to_str().unwrap()]);
+    assert!(run.status.success());
+    assert_eq!(run.stdout, expected);
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+fn test_watch_reports_changes() {
+    let input = scratch_copy("counter.txt", "watch");
+    let output = Command::new(env!("CARGO_BIN_EXE_RustedBrains"))
+        .args(["--watch", "digit", input.to_str().unwrap()])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"0\n1\n2\n3\n4\n5\n6\n7\n8\n9\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("digit (cell 0): 0 -> 48\n --> "));
+    assert!(stderr.contains("digit (cell 0): 57 -> 58\n"));
+    assert_eq!(stderr.matches("digit = digit + 1;").count(), 10);
+    // Nothing is compiled.
+    assert!(!input.with_extension("txt.bf").exists());
+
+    let output = rust2bf(&["--watch", "digits", input.to_str().unwrap()]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("did you mean `digit`?"));
+    let _ = fs::remove_dir_all(input.parent().unwrap());
+}
@@ -0,0 +1,138 @@
+//! Golden tests: every program in `examples/` is compiled with the default
+//! options and run on its `//! input:`, and both the Brainfuck and what it
+//! prints are compared
+//! against the files checked in under `tests/snapshots/`. What it prints
+//! must also match the example's own `//! expect:` comments, if it has
+//! any.
+//!
+//! After an intended change to the generated code, regenerate them with
+//! `UPDATE_SNAPSHOTS=1 cargo test --test snapshots` and review the diff.
+
+use rusted_brains::bf::Interpreter;
+use rusted_brains::{expect, pipeline};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const MAX_STEPS: u64 = 10_000_000;
+
+/// What running the program on `input` produced, with non-printable bytes
+/// escaped.
+fn describe_run(code: &str, input: &[u8]) -> String {
+    match Interpreter::new(code)
+        .with_input(input)
+        .with_max_steps(MAX_STEPS)
+        .run()
+    {
+        Ok(execution) => {
+            let output: String = execution
+                .output
+                .iter()
+                .flat_map(|&byte| std::ascii::escape_default(byte))
+                .map(char::from)
+                .collect();
+            format!("output: \"{}\"\n", output)
+        }
+        Err(e) => format!("error: {}\n", e),
+    }
+}
+
+fn check(path: &Path, actual: &str, failures: &mut Vec<String>) {
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::write(path, actual).unwrap();
+        return;
+    }
+    match fs::read_to_string(path) {
+        Ok(expected) if expected == actual => {}
+        Ok(expected) => failures.push(format!(
+            "{} differs\n--- expected\n{}\n--- actual\n{}",
+            path.display(),
+            expected,
+            actual
+        )),
+        Err(_) => failures.push(format!("{} is missing", path.display())),
+    }
+}
+
+/// Compares what `code` prints on `input` with the `//! expect:` comments
+/// in `source`.
+fn check_expectations(
+    name: &str,
+    source: &str,
+    code: &str,
+    input: &[u8],
+    failures: &mut Vec<String>,
+) {
+    let expected = match expect::expected_output(source) {
+        Ok(Some(expected)) => expected,
+        Ok(None) => return,
+        Err(e) => {
+            failures.push(format!("{} has a malformed expectation: {}", name, e));
+            return;
+        }
+    };
+    let output = Interpreter::new(code)
+        .with_input(input)
+        .with_max_steps(MAX_STEPS)
+        .run()
+        .map(|execution| execution.output);
+    if output.as_ref() != Ok(&expected) {
+        failures.push(format!(
+            "{} does not print what it expects\n--- expected\n\"{}\"\n--- actual\n{}",
+            name,
+            expect::escape(&expected),
+            describe_run(code, input)
+        ));
+    }
+}
+
+#[test]
+fn examples_match_snapshots() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let snapshots = root.join("tests/snapshots");
+    let mut examples: Vec<PathBuf> = fs::read_dir(root.join("examples"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "txt"))
+        .collect();
+    examples.sort();
+    assert!(!examples.is_empty());
+
+    let mut failures = Vec::new();
+    for example in &examples {
+        let name = example.file_stem().unwrap().to_str().unwrap();
+        let source = fs::read_to_string(example).unwrap();
+        let input = match expect::input(&source) {
+            Ok(input) => input,
+            Err(e) => {
+                failures.push(format!("{} has malformed input: {}", name, e));
+                continue;
+            }
+        };
+        let compiled = match pipeline::compile(&source) {
+            Ok(compiled) => compiled,
+            Err(errors) => {
+                failures.push(format!("{} does not compile: {:?}", name, errors));
+                continue;
+            }
+        };
+
+        check(
+            &snapshots.join(format!("{}.bf", name)),
+            &format!("{}\n", compiled.code),
+            &mut failures,
+        );
+        check(
+            &snapshots.join(format!("{}.out", name)),
+            &describe_run(&compiled.code, &input),
+            &mut failures,
+        );
+        check_expectations(name, &source, &compiled.code, &input, &mut failures);
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{}\n\nrun with UPDATE_SNAPSHOTS=1 to accept the new output",
+        failures.join("\n\n")
+    );
+}